@@ -9,23 +9,38 @@ use axum::{
 };
 use axum_extra::extract::CookieJar;
 use serde::Serialize;
-use tower_http::services::ServeDir;
+use tower_http::{
+    services::ServeDir,
+    trace::{DefaultMakeSpan, TraceLayer},
+};
+use tracing::{Instrument, Level};
+
+mod telemetry;
 
 #[tokio::main]
 async fn main() {
+    let tracer_provider = telemetry::init_tracing();
+
     let app = Router::new()
         .nest_service("/assets", ServeDir::new("assets"))
         .route("/", get(root))
         .route("/login", get(login))
-        .route("/protected", get(protected));
+        .route("/protected", get(protected))
+        .layer(
+            TraceLayer::new_for_http().make_span_with(DefaultMakeSpan::new().level(Level::INFO)),
+        );
 
     let listener = tokio::net::TcpListener::bind("0.0.0.0:8000").await.unwrap();
 
-    println!(
+    tracing::info!(
         "App service listening on http://{}",
         listener.local_addr().unwrap()
     );
     axum::serve(listener, app).await.unwrap();
+
+    if let Some(tracer_provider) = tracer_provider {
+        tracer_provider.shutdown().ok();
+    }
 }
 
 
@@ -83,7 +98,14 @@ async fn protected(jar: CookieJar) -> impl IntoResponse {
     let auth_hostname = env::var("AUTH_SERVICE_HOST_NAME").unwrap_or("0.0.0.0".to_owned());
     let url = format!("http://{}:3000/verify-token", auth_hostname);
 
-    let response = match api_client.post(&url).json(&verify_token_body).send().await {
+    // Sent with this span's trace context, so auth-service's spans join the trace
+    let span = tracing::info_span!("verify_token");
+    let mut request = api_client.post(&url).json(&verify_token_body);
+    for (name, value) in telemetry::trace_headers(&span) {
+        request = request.header(name, value);
+    }
+
+    let response = match request.send().instrument(span).await {
         Ok(response) => response,
         Err(_) => {
             return StatusCode::INTERNAL_SERVER_ERROR.into_response();
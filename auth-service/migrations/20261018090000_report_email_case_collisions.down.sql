@@ -0,0 +1 @@
+DROP TABLE IF EXISTS email_case_collisions;
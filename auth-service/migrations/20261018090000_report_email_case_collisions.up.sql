@@ -0,0 +1,33 @@
+-- Emails are now compared case-insensitively. Before enforcing that, record
+-- every existing account whose address collides with another one once
+-- surrounding whitespace and case are ignored, so they can be merged by hand.
+CREATE TABLE IF NOT EXISTS email_case_collisions(
+   canonical_email TEXT NOT NULL,
+   email TEXT NOT NULL,
+   detected_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+   PRIMARY KEY (canonical_email, email)
+);
+
+INSERT INTO email_case_collisions (canonical_email, email)
+SELECT LOWER(BTRIM(email)), email
+FROM users
+WHERE LOWER(BTRIM(email)) IN (
+   SELECT LOWER(BTRIM(email))
+   FROM users
+   GROUP BY LOWER(BTRIM(email))
+   HAVING COUNT(*) > 1
+)
+ON CONFLICT DO NOTHING;
+
+DO $$
+DECLARE
+   collision RECORD;
+BEGIN
+   FOR collision IN
+      SELECT canonical_email, STRING_AGG(email, ', ' ORDER BY email) AS emails
+      FROM email_case_collisions
+      GROUP BY canonical_email
+   LOOP
+      RAISE WARNING 'Email collision for %: %', collision.canonical_email, collision.emails;
+   END LOOP;
+END $$;
@@ -0,0 +1,34 @@
+-- Runs in its own transaction after the collision report, so the report is
+-- kept even when this migration refuses to continue.
+DO $$
+DECLARE
+   collisions INTEGER;
+BEGIN
+   SELECT COUNT(*) INTO collisions FROM (
+      SELECT LOWER(BTRIM(email))
+      FROM users
+      GROUP BY LOWER(BTRIM(email))
+      HAVING COUNT(*) > 1
+   ) AS remaining;
+   IF collisions > 0 THEN
+      RAISE EXCEPTION '% email address(es) differ only by case, resolve the rows listed in email_case_collisions first', collisions;
+   END IF;
+END $$;
+
+-- Mirror `Email::parse`: trim and lowercase the domain.
+UPDATE users
+SET email = SUBSTRING(BTRIM(email) FROM '^(.*)@') || '@' || LOWER(SUBSTRING(BTRIM(email) FROM '@([^@]*)$'))
+WHERE email LIKE '%@%';
+
+-- Internationalized domains can't be converted to punycode in SQL, so those
+-- accounts won't match a parsed email until they're updated by hand.
+DO $$
+DECLARE
+   row RECORD;
+BEGIN
+   FOR row IN SELECT email FROM users WHERE email ~ '[^[:ascii:]]' LOOP
+      RAISE WARNING 'Email needs punycode conversion: %', row.email;
+   END LOOP;
+END $$;
+
+CREATE UNIQUE INDEX IF NOT EXISTS users_email_lower_idx ON users (LOWER(email));
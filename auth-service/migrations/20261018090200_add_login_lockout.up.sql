@@ -0,0 +1,5 @@
+-- Consecutive failed logins, for exponential backoff and temporary account locks
+ALTER TABLE users
+   ADD COLUMN IF NOT EXISTS failed_login_attempts INTEGER NOT NULL DEFAULT 0,
+   ADD COLUMN IF NOT EXISTS last_failed_login_at TIMESTAMPTZ,
+   ADD COLUMN IF NOT EXISTS locked_until TIMESTAMPTZ;
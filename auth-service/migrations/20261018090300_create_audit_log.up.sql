@@ -0,0 +1,24 @@
+-- Append-only log of authentication events (signups, logins, 2FA, logouts)
+CREATE TABLE IF NOT EXISTS audit_log (
+   id BIGSERIAL PRIMARY KEY,
+   occurred_at TIMESTAMPTZ NOT NULL,
+   event_type TEXT NOT NULL,
+   outcome TEXT NOT NULL,
+   subject TEXT,
+   ip INET,
+   user_agent TEXT,
+   detail TEXT
+);
+
+CREATE INDEX IF NOT EXISTS audit_log_subject_idx ON audit_log (lower(subject), occurred_at);
+CREATE INDEX IF NOT EXISTS audit_log_occurred_at_idx ON audit_log (occurred_at);
+
+CREATE OR REPLACE FUNCTION audit_log_append_only() RETURNS trigger AS $$
+BEGIN
+   RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only
+   BEFORE UPDATE OR DELETE OR TRUNCATE ON audit_log
+   FOR EACH STATEMENT EXECUTE FUNCTION audit_log_append_only();
@@ -0,0 +1,6 @@
+DROP TABLE IF EXISTS audit_checkpoints;
+DROP INDEX IF EXISTS audit_log_hash_idx;
+
+ALTER TABLE audit_log
+   DROP COLUMN IF EXISTS prev_hash,
+   DROP COLUMN IF EXISTS hash;
@@ -0,0 +1,22 @@
+-- Each record carries the hash of the one before it, see `AuditRecord`.
+-- Records written before this migration stay unchained and are skipped by
+-- `audit verify`; the chain starts at the first record with a hash.
+ALTER TABLE audit_log
+   ADD COLUMN IF NOT EXISTS prev_hash TEXT,
+   ADD COLUMN IF NOT EXISTS hash TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS audit_log_hash_idx ON audit_log (hash);
+
+-- Signed heads of the chain, made periodically for off-site storage
+CREATE TABLE IF NOT EXISTS audit_checkpoints (
+   id BIGSERIAL PRIMARY KEY,
+   created_at TIMESTAMPTZ NOT NULL,
+   last_record_id BIGINT NOT NULL,
+   last_hash TEXT NOT NULL,
+   public_key TEXT NOT NULL,
+   signature TEXT NOT NULL
+);
+
+CREATE TRIGGER audit_checkpoints_append_only
+   BEFORE UPDATE OR DELETE OR TRUNCATE ON audit_checkpoints
+   FOR EACH STATEMENT EXECUTE FUNCTION audit_log_append_only();
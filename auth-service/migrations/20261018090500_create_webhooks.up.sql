@@ -0,0 +1,25 @@
+-- Outbound webhook subscriptions; an empty event_types list means all events
+CREATE TABLE IF NOT EXISTS webhook_subscriptions (
+   id UUID PRIMARY KEY,
+   url TEXT NOT NULL,
+   secret TEXT NOT NULL,
+   event_types TEXT[] NOT NULL DEFAULT '{}',
+   created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Durable delivery queue. Failed deliveries are retried with backoff and end
+-- up with status 'dead' once they run out of attempts.
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+   id UUID PRIMARY KEY,
+   subscription_id UUID NOT NULL REFERENCES webhook_subscriptions (id) ON DELETE CASCADE,
+   event_type TEXT NOT NULL,
+   payload TEXT NOT NULL,
+   status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'delivered', 'dead')),
+   attempts INTEGER NOT NULL DEFAULT 0,
+   next_attempt_at TIMESTAMPTZ NOT NULL,
+   last_error TEXT,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_due_idx
+   ON webhook_deliveries (next_attempt_at) WHERE status = 'pending';
@@ -0,0 +1,28 @@
+-- Role-based access control. Users get roles, roles grant permissions, and
+-- the roles a user has when logging in are embedded in their token.
+CREATE TABLE IF NOT EXISTS roles (
+   name TEXT NOT NULL PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS permissions (
+   name TEXT NOT NULL PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS role_permissions (
+   role TEXT NOT NULL REFERENCES roles (name) ON DELETE CASCADE,
+   permission TEXT NOT NULL REFERENCES permissions (name) ON DELETE CASCADE,
+   PRIMARY KEY (role, permission)
+);
+
+CREATE TABLE IF NOT EXISTS user_roles (
+   email TEXT NOT NULL REFERENCES users (email) ON DELETE CASCADE ON UPDATE CASCADE,
+   role TEXT NOT NULL REFERENCES roles (name) ON DELETE CASCADE,
+   PRIMARY KEY (email, role)
+);
+
+-- Keep in sync with `DEFAULT_ROLES`
+INSERT INTO roles (name) VALUES ('admin') ON CONFLICT DO NOTHING;
+INSERT INTO permissions (name) VALUES ('users:read'), ('users:write') ON CONFLICT DO NOTHING;
+INSERT INTO role_permissions (role, permission)
+VALUES ('admin', 'users:read'), ('admin', 'users:write')
+ON CONFLICT DO NOTHING;
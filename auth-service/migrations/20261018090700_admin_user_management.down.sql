@@ -0,0 +1,7 @@
+ALTER TABLE audit_log DROP COLUMN IF EXISTS actor;
+
+ALTER TABLE users
+   DROP COLUMN IF EXISTS password_reset_expires_at,
+   DROP COLUMN IF EXISTS password_reset_token_hash,
+   DROP COLUMN IF EXISTS created_at,
+   DROP COLUMN IF EXISTS disabled;
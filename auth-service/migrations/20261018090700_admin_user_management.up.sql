@@ -0,0 +1,10 @@
+-- Accounts ops can switch off without deleting them
+ALTER TABLE users
+   ADD COLUMN IF NOT EXISTS disabled BOOLEAN NOT NULL DEFAULT FALSE,
+   ADD COLUMN IF NOT EXISTS created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+   -- SHA-256 of the emailed reset token; the token itself is never stored
+   ADD COLUMN IF NOT EXISTS password_reset_token_hash TEXT,
+   ADD COLUMN IF NOT EXISTS password_reset_expires_at TIMESTAMPTZ;
+
+-- Who performed an action on someone else's account, e.g. an admin
+ALTER TABLE audit_log ADD COLUMN IF NOT EXISTS actor TEXT;
@@ -0,0 +1,15 @@
+ALTER TABLE audit_log DROP COLUMN IF EXISTS tenant;
+
+-- Subscriptions and roles outside the default tenant have no equivalent
+DELETE FROM webhook_subscriptions WHERE tenant_id <> 'default';
+ALTER TABLE webhook_subscriptions DROP COLUMN IF EXISTS tenant_id;
+
+DELETE FROM user_roles WHERE tenant_id <> 'default';
+ALTER TABLE user_roles DROP CONSTRAINT IF EXISTS user_roles_member_fkey;
+ALTER TABLE user_roles DROP CONSTRAINT IF EXISTS user_roles_pkey;
+ALTER TABLE user_roles DROP COLUMN IF EXISTS tenant_id;
+ALTER TABLE user_roles ADD PRIMARY KEY (email, role);
+
+DROP TABLE IF EXISTS tenant_members;
+DROP TABLE IF EXISTS tenant_hosts;
+DROP TABLE IF EXISTS tenants;
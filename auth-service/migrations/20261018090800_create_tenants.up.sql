@@ -0,0 +1,45 @@
+-- Organizations sharing this auth server. A user is one identity that can be
+-- a member of several tenants, with roles granted per tenant.
+CREATE TABLE IF NOT EXISTS tenants (
+   id TEXT NOT NULL PRIMARY KEY,
+   name TEXT NOT NULL,
+   min_password_length INTEGER NOT NULL DEFAULT 8 CHECK (min_password_length >= 8),
+   requires_2fa BOOLEAN NOT NULL DEFAULT FALSE,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Hosts that resolve to a tenant, lowercase and without a port
+CREATE TABLE IF NOT EXISTS tenant_hosts (
+   host TEXT NOT NULL PRIMARY KEY,
+   tenant_id TEXT NOT NULL REFERENCES tenants (id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS tenant_members (
+   tenant_id TEXT NOT NULL REFERENCES tenants (id) ON DELETE CASCADE,
+   email TEXT NOT NULL REFERENCES users (email) ON DELETE CASCADE ON UPDATE CASCADE,
+   PRIMARY KEY (tenant_id, email)
+);
+
+-- Keep in sync with `DEFAULT_TENANT_ID`
+INSERT INTO tenants (id, name) VALUES ('default', 'Default') ON CONFLICT DO NOTHING;
+
+-- Everyone registered so far belongs to the default tenant, and so do the
+-- roles they were granted
+INSERT INTO tenant_members (tenant_id, email)
+SELECT 'default', email FROM users
+ON CONFLICT DO NOTHING;
+
+ALTER TABLE user_roles ADD COLUMN IF NOT EXISTS tenant_id TEXT NOT NULL DEFAULT 'default';
+ALTER TABLE user_roles ALTER COLUMN tenant_id DROP DEFAULT;
+ALTER TABLE user_roles DROP CONSTRAINT IF EXISTS user_roles_pkey;
+ALTER TABLE user_roles ADD PRIMARY KEY (tenant_id, email, role);
+-- Leaving a tenant takes its roles with it
+ALTER TABLE user_roles ADD CONSTRAINT user_roles_member_fkey
+   FOREIGN KEY (tenant_id, email) REFERENCES tenant_members (tenant_id, email)
+   ON DELETE CASCADE ON UPDATE CASCADE;
+
+ALTER TABLE webhook_subscriptions
+   ADD COLUMN IF NOT EXISTS tenant_id TEXT NOT NULL DEFAULT 'default' REFERENCES tenants (id) ON DELETE CASCADE;
+ALTER TABLE webhook_subscriptions ALTER COLUMN tenant_id DROP DEFAULT;
+
+ALTER TABLE audit_log ADD COLUMN IF NOT EXISTS tenant TEXT;
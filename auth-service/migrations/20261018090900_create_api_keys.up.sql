@@ -0,0 +1,17 @@
+-- Personal API keys. Only a SHA-256 hash of each key is kept, and a key
+-- belongs to one tenant membership, so it goes when the membership does.
+CREATE TABLE IF NOT EXISTS api_keys (
+   id UUID NOT NULL PRIMARY KEY,
+   tenant_id TEXT NOT NULL,
+   email TEXT NOT NULL,
+   name TEXT NOT NULL,
+   key_hash TEXT NOT NULL UNIQUE,
+   scopes TEXT[] NOT NULL DEFAULT '{}',
+   created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+   expires_at TIMESTAMPTZ NOT NULL,
+   last_used_at TIMESTAMPTZ,
+   FOREIGN KEY (tenant_id, email) REFERENCES tenant_members (tenant_id, email)
+      ON DELETE CASCADE ON UPDATE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS api_keys_owner_idx ON api_keys (tenant_id, email);
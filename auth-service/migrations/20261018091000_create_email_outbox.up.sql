@@ -0,0 +1,20 @@
+-- Emails waiting to be sent, and a record of those that were. Failed sends are
+-- retried with backoff and end up with status 'failed' once they run out of
+-- attempts. The body is cleared once an email leaves the outbox, as it may
+-- hold 2FA codes and password reset tokens.
+CREATE TABLE IF NOT EXISTS email_outbox (
+   id UUID PRIMARY KEY,
+   idempotency_key TEXT NOT NULL UNIQUE,
+   recipient TEXT NOT NULL,
+   subject TEXT NOT NULL,
+   text_body TEXT NOT NULL,
+   html_body TEXT,
+   status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'sent', 'failed')),
+   attempts INTEGER NOT NULL DEFAULT 0,
+   next_attempt_at TIMESTAMPTZ NOT NULL,
+   last_error TEXT,
+   created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX IF NOT EXISTS email_outbox_due_idx
+   ON email_outbox (next_attempt_at) WHERE status = 'pending';
@@ -0,0 +1,8 @@
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_two_fa_channel_phone;
+
+ALTER TABLE users
+   DROP COLUMN IF EXISTS phone_code_expires_at,
+   DROP COLUMN IF EXISTS phone_code_hash,
+   DROP COLUMN IF EXISTS pending_phone,
+   DROP COLUMN IF EXISTS two_fa_channel,
+   DROP COLUMN IF EXISTS phone;
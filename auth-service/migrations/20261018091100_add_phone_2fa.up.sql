@@ -0,0 +1,13 @@
+-- Verified phone number and where 2FA codes are sent
+ALTER TABLE users
+   ADD COLUMN IF NOT EXISTS phone TEXT,
+   ADD COLUMN IF NOT EXISTS two_fa_channel TEXT NOT NULL DEFAULT 'email'
+      CHECK (two_fa_channel IN ('email', 'sms', 'voice')),
+   -- The number being verified, and SHA-256 of the code texted to it
+   ADD COLUMN IF NOT EXISTS pending_phone TEXT,
+   ADD COLUMN IF NOT EXISTS phone_code_hash TEXT,
+   ADD COLUMN IF NOT EXISTS phone_code_expires_at TIMESTAMPTZ;
+
+-- Codes can't be sent to a phone the user hasn't verified
+ALTER TABLE users ADD CONSTRAINT users_two_fa_channel_phone
+   CHECK (two_fa_channel = 'email' OR phone IS NOT NULL);
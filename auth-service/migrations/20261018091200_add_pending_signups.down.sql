@@ -0,0 +1,3 @@
+ALTER TABLE users
+   DROP COLUMN IF EXISTS signup_expires_at,
+   DROP COLUMN IF EXISTS signup_token_hash;
@@ -0,0 +1,5 @@
+-- Accounts made by enumeration-resistant signup can't log in until the owner
+-- of the address confirms it. SHA-256 of the emailed token, NULL once confirmed.
+ALTER TABLE users
+   ADD COLUMN IF NOT EXISTS signup_token_hash TEXT,
+   ADD COLUMN IF NOT EXISTS signup_expires_at TIMESTAMPTZ;
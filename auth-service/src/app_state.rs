@@ -1,32 +1,170 @@
 use std::sync::Arc;
 use tokio::sync::RwLock;
 
-use crate::domain::{BannedTokenStore, EmailClient, TwoFACodeStore, UserStore};
+use crate::config::Config;
+use crate::domain::{
+    ApiKeyStore, AuditSink, BannedTokenStore, Email, EmailClient, EmailOutboxStore, PasswordHasher,
+    RateLimiter, SmsClient, TenantStore, TwoFACodeStore, UserStore, WebhookStore,
+};
+use crate::services::data_stores::hashmap_api_key_store::HashmapApiKeyStore;
+use crate::services::data_stores::hashmap_email_outbox_store::HashmapEmailOutboxStore;
+use crate::services::data_stores::hashmap_rate_limiter::HashmapRateLimiter;
+use crate::services::data_stores::hashmap_tenant_store::HashmapTenantStore;
+use crate::services::data_stores::hashmap_webhook_store::HashmapWebhookStore;
+use crate::services::data_stores::vec_audit_sink::VecAuditSink;
+use crate::services::mock_sms_client::MockSmsClient;
+use crate::utils::email_templates::EmailTemplates;
+use crate::utils::health::HealthChecks;
+use crate::utils::metrics::Metrics;
+use crate::utils::shutdown::ShutdownHandle;
 
 pub type UserStoreType = Arc<RwLock<dyn UserStore + Send + Sync>>;
 pub type BannedTokenStoreType = Arc<RwLock<dyn BannedTokenStore + Send + Sync>>;
 pub type TwoFACodeStoreType = Arc<RwLock<dyn TwoFACodeStore + Send + Sync>>;
 pub type EmailClientType = Arc<RwLock<dyn EmailClient + Send + Sync>>;
+pub type EmailOutboxStoreType = Arc<RwLock<dyn EmailOutboxStore + Send + Sync>>;
+pub type SmsClientType = Arc<RwLock<dyn SmsClient + Send + Sync>>;
+// Hashers are stateless and shared with the user stores, so they don't need a lock
+pub type PasswordHasherType = Arc<dyn PasswordHasher + Send + Sync>;
+pub type RateLimiterType = Arc<RwLock<dyn RateLimiter + Send + Sync>>;
+// Sinks only append, so they take `&self` and synchronize internally
+pub type AuditSinkType = Arc<dyn AuditSink + Send + Sync>;
+pub type WebhookStoreType = Arc<RwLock<dyn WebhookStore + Send + Sync>>;
+pub type TenantStoreType = Arc<RwLock<dyn TenantStore + Send + Sync>>;
+pub type ApiKeyStoreType = Arc<RwLock<dyn ApiKeyStore + Send + Sync>>;
 #[derive(Clone)]
 pub struct AppState {
+    pub config: Arc<Config>,
     pub user_store: UserStoreType,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
     pub email_client: EmailClientType,
+    pub email_templates: Arc<EmailTemplates>,
+    // Emails are queued here and sent by the outbox worker
+    pub email_outbox_store: EmailOutboxStoreType,
+    // For users who get their 2FA codes by text or voice call
+    pub sms_client: SmsClientType,
+    pub password_hasher: PasswordHasherType,
+    // Counts requests against `config.rate_limits`
+    pub rate_limiter: RateLimiterType,
+    pub audit_sink: AuditSinkType,
+    pub webhook_store: WebhookStoreType,
+    pub tenant_store: TenantStoreType,
+    pub api_key_store: ApiKeyStoreType,
+    // Operator secret for role changes without an admin session and /metrics
+    pub admin_api_token: Option<Arc<String>>,
+    // Shared with the instrumented stores, so their timings are served too
+    pub metrics: Arc<Metrics>,
+    // What /health/ready checks
+    pub health_checks: Arc<HealthChecks>,
+    // Triggered on shutdown, which fails readiness while requests drain
+    pub shutdown: ShutdownHandle,
 }
 
 impl AppState {
+    // The admin token starts out as `config` has it
     pub fn new(
+        config: Arc<Config>,
         user_store: UserStoreType,
         banned_token_store: BannedTokenStoreType,
         two_fa_code_store: TwoFACodeStoreType,
         email_client: EmailClientType,
+        password_hasher: PasswordHasherType,
     ) -> Self {
+        let admin_api_token = config.auth.admin_api_token.clone();
         Self {
+            config,
             user_store,
             banned_token_store,
             two_fa_code_store,
             email_client,
+            email_templates: Arc::new(EmailTemplates::default()),
+            email_outbox_store: Arc::new(RwLock::new(HashmapEmailOutboxStore::default())),
+            sms_client: Arc::new(RwLock::new(MockSmsClient)),
+            password_hasher,
+            rate_limiter: Arc::new(RwLock::new(HashmapRateLimiter::default())),
+            audit_sink: Arc::new(VecAuditSink::default()),
+            webhook_store: Arc::new(RwLock::new(HashmapWebhookStore::default())),
+            tenant_store: Arc::new(RwLock::new(HashmapTenantStore::default())),
+            api_key_store: Arc::new(RwLock::new(HashmapApiKeyStore::default())),
+            admin_api_token: None,
+            metrics: Arc::new(Metrics::default()),
+            health_checks: Arc::new(HealthChecks::default()),
+            shutdown: ShutdownHandle::new(),
         }
+        .with_admin_api_token(admin_api_token)
+    }
+
+    // Emails use the built-in templates unless others are set
+    pub fn with_email_templates(mut self, email_templates: EmailTemplates) -> Self {
+        self.email_templates = Arc::new(email_templates);
+        self
+    }
+
+    // Queued emails are only kept in memory unless a persistent store is set
+    pub fn with_email_outbox_store(mut self, email_outbox_store: EmailOutboxStoreType) -> Self {
+        self.email_outbox_store = email_outbox_store;
+        self
+    }
+
+    // Texts and calls are only printed unless a real client is set
+    pub fn with_sms_client(mut self, sms_client: SmsClientType) -> Self {
+        self.sms_client = sms_client;
+        self
+    }
+
+    // Rate limiting defaults to per-instance counters
+    pub fn with_rate_limiter(mut self, rate_limiter: RateLimiterType) -> Self {
+        self.rate_limiter = rate_limiter;
+        self
+    }
+
+    // Audit events are only kept in memory unless a persistent sink is set
+    pub fn with_audit_sink(mut self, audit_sink: AuditSinkType) -> Self {
+        self.audit_sink = audit_sink;
+        self
+    }
+
+    // Webhook subscriptions and queued deliveries are only kept in memory
+    // unless a persistent store is set
+    pub fn with_webhook_store(mut self, webhook_store: WebhookStoreType) -> Self {
+        self.webhook_store = webhook_store;
+        self
+    }
+
+    // Only the default tenant exists unless a persistent store is set
+    pub fn with_tenant_store(mut self, tenant_store: TenantStoreType) -> Self {
+        self.tenant_store = tenant_store;
+        self
+    }
+
+    // API keys are only kept in memory unless a persistent store is set
+    pub fn with_api_key_store(mut self, api_key_store: ApiKeyStoreType) -> Self {
+        self.api_key_store = api_key_store;
+        self
+    }
+
+    pub fn with_admin_api_token(mut self, admin_api_token: Option<String>) -> Self {
+        self.admin_api_token = admin_api_token.filter(|t| !t.is_empty()).map(Arc::new);
+        self
+    }
+
+    // Stores only report timings to the metrics they were wrapped with, see
+    // `services::instrumented`
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    // Readiness only depends on the databases it's told to check
+    pub fn with_health_checks(mut self, health_checks: HealthChecks) -> Self {
+        self.health_checks = Arc::new(health_checks);
+        self
+    }
+
+    // Emails from requests, with the local part lowercased if
+    // `email.lowercase_local_part` says so
+    pub fn parse_email(&self, email: String) -> Result<Email, String> {
+        Email::parse_with_options(email, self.config.email.lowercase_local_part)
     }
 }
@@ -0,0 +1,186 @@
+//! Audit log maintenance, run against the configured database:
+//!
+//!     audit verify       walk the hash chain and check every stored checkpoint
+//!     audit checkpoint   sign the current head of the chain and print the checkpoint
+//!     audit export       print all checkpoints, one JSON object per line, for off-site storage
+//!
+//! `checkpoint` needs `audit.checkpoint_signing_key` (`AUDIT_CHECKPOINT_SIGNING_KEY`).
+//! When it's set, `verify` also insists that checkpoints were signed with that key.
+
+use std::process::ExitCode;
+
+use chrono::SecondsFormat;
+
+use auth_service::domain::{AuditChainVerifier, SignedAuditCheckpoint};
+use auth_service::services::audit_checkpoint_signer::{
+    verify_checkpoint_signature, AuditCheckpointSigner,
+};
+use auth_service::services::data_stores::postgres_audit_sink::PostgresAuditSink;
+use auth_service::{get_postgres_pool, Config};
+
+const BATCH_SIZE: i64 = 1000;
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let command = std::env::args().nth(1).unwrap_or_default();
+
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let db_pool = get_postgres_pool(&config.database)
+        .await
+        .expect("Failed to create database pool");
+    let sink = PostgresAuditSink::new(db_pool);
+
+    // Already checked by `Config::load`
+    let signer = AuditCheckpointSigner::from_config(&config.audit)
+        .expect("Invalid audit checkpoint signing key");
+
+    let result = match command.as_str() {
+        "verify" => verify(&sink, signer.as_ref()).await,
+        "checkpoint" => checkpoint(&sink, signer.as_ref()).await,
+        "export" => export(&sink).await,
+        _ => Err("usage: audit <verify|checkpoint|export>".to_owned()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn verify(
+    sink: &PostgresAuditSink,
+    signer: Option<&AuditCheckpointSigner>,
+) -> Result<(), String> {
+    let first_id = sink
+        .first_chained_record_id()
+        .await
+        .map_err(|e| format!("Failed to read audit log: {:?}", e))?;
+
+    let mut verifier = AuditChainVerifier::default();
+
+    if let Some(first_id) = first_id {
+        let mut after_id = first_id - 1;
+        loop {
+            let records = sink
+                .records_after(after_id, BATCH_SIZE)
+                .await
+                .map_err(|e| format!("Failed to read audit log: {:?}", e))?;
+
+            let Some(last) = records.last() else {
+                break;
+            };
+            after_id = last.id;
+
+            for record in &records {
+                verifier
+                    .check(record)
+                    .map_err(|broken| format!("Audit chain broken: {}", broken))?;
+            }
+        }
+    }
+
+    let checkpoints = sink
+        .checkpoints()
+        .await
+        .map_err(|e| format!("Failed to read audit checkpoints: {:?}", e))?;
+
+    for signed in &checkpoints {
+        verify_checkpoint(sink, signer, signed).await?;
+    }
+
+    println!(
+        "Audit chain intact: {} records, {} checkpoints",
+        verifier.records_checked(),
+        checkpoints.len()
+    );
+    Ok(())
+}
+
+async fn verify_checkpoint(
+    sink: &PostgresAuditSink,
+    signer: Option<&AuditCheckpointSigner>,
+    signed: &SignedAuditCheckpoint,
+) -> Result<(), String> {
+    let record_id = signed.checkpoint.last_record_id;
+
+    if !verify_checkpoint_signature(signed) {
+        return Err(format!(
+            "Checkpoint at record {} has an invalid signature",
+            record_id
+        ));
+    }
+
+    if let Some(signer) = signer {
+        if signer.public_key() != signed.public_key {
+            return Err(format!(
+                "Checkpoint at record {} was signed with an unknown key {}",
+                record_id, signed.public_key
+            ));
+        }
+    }
+
+    let hash = sink
+        .record_hash(record_id)
+        .await
+        .map_err(|e| format!("Failed to read audit log: {:?}", e))?;
+
+    if hash.as_deref() != Some(signed.checkpoint.last_hash.as_str()) {
+        return Err(format!(
+            "Checkpoint at record {} doesn't match the audit log",
+            record_id
+        ));
+    }
+
+    Ok(())
+}
+
+async fn checkpoint(
+    sink: &PostgresAuditSink,
+    signer: Option<&AuditCheckpointSigner>,
+) -> Result<(), String> {
+    let signer =
+        signer.ok_or("audit.checkpoint_signing_key (AUDIT_CHECKPOINT_SIGNING_KEY) must be set")?;
+
+    match sink.create_checkpoint(signer).await {
+        Ok(Some(signed)) => println!("{}", to_json(&signed)),
+        Ok(None) => eprintln!("Nothing recorded since the last checkpoint"),
+        Err(e) => return Err(format!("Failed to create checkpoint: {:?}", e)),
+    }
+
+    Ok(())
+}
+
+async fn export(sink: &PostgresAuditSink) -> Result<(), String> {
+    let checkpoints = sink
+        .checkpoints()
+        .await
+        .map_err(|e| format!("Failed to read audit checkpoints: {:?}", e))?;
+
+    for signed in &checkpoints {
+        println!("{}", to_json(signed));
+    }
+
+    Ok(())
+}
+
+fn to_json(signed: &SignedAuditCheckpoint) -> serde_json::Value {
+    serde_json::json!({
+        "created_at": signed
+            .checkpoint
+            .created_at
+            .to_rfc3339_opts(SecondsFormat::Micros, true),
+        "last_record_id": signed.checkpoint.last_record_id,
+        "last_hash": signed.checkpoint.last_hash,
+        "public_key": signed.public_key,
+        "signature": signed.signature,
+    })
+}
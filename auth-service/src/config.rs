@@ -0,0 +1,886 @@
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::domain::RateLimitPolicy;
+use crate::services::argon2_password_hasher::Argon2Settings;
+use crate::services::audit_checkpoint_signer::AuditCheckpointSigner;
+use crate::services::http_sms_client::HttpSmsSettings;
+use crate::services::smtp_email_client::SmtpSettings;
+use crate::utils::constants::env;
+use crate::utils::email_templates::DEFAULT_PRODUCT_NAME;
+use crate::utils::rate_limit::RateLimits;
+
+/// Read when `CONFIG_FILE` isn't set, if it exists.
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Settings for the server and the stores and tokens it relies on.
+///
+/// Loaded from a TOML file, see `config.example.toml`, with environment
+/// variables taking precedence over it and `.env` read first in development.
+/// Every setting has a default except `database.url` and `auth.jwt_secret`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub redis: RedisConfig,
+    pub auth: AuthConfig,
+    pub argon2: Argon2Settings,
+    pub lockout: LockoutConfig,
+    pub rate_limits: RateLimits,
+    pub email: EmailConfig,
+    pub smtp: SmtpConfig,
+    pub sms: SmsConfig,
+    pub webhooks: WebhooksConfig,
+    pub audit: AuditConfig,
+    pub telemetry: TelemetryConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    // The address to listen on; port 0 picks a free one
+    pub address: String,
+    // How long the server keeps taking requests after readiness starts
+    // failing at shutdown, for load balancers to notice and stop sending them
+    pub pre_drain_delay_seconds: u64,
+    // How long requests in flight at shutdown get to finish
+    pub drain_timeout_seconds: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: "0.0.0.0:3000".to_owned(),
+            pre_drain_delay_seconds: 5,
+            drain_timeout_seconds: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub max_connections: u32,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            max_connections: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RedisConfig {
+    pub hostname: String,
+    pub port: u16,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            hostname: "localhost".to_owned(),
+            port: 6379,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    pub jwt_secret: String,
+    // How long auth tokens are valid, and so how long logged out ones stay banned
+    pub token_ttl_seconds: i64,
+    // Impersonation tokens can't be renewed, so keep them short
+    pub impersonation_ttl_seconds: i64,
+    pub two_fa_code_ttl_minutes: u64,
+    // Only enable when every request goes through our own reverse proxy,
+    // otherwise clients can pick their own IP for rate limiting
+    pub trust_proxy_headers: bool,
+    // Operator secret for assigning and revoking roles without an admin
+    // session, e.g. to appoint the first admin, and for scraping /metrics.
+    // Both are refused while it's unset.
+    pub admin_api_token: Option<String>,
+    pub enumeration_resistant_signup: bool,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self {
+            jwt_secret: String::new(),
+            token_ttl_seconds: 600,
+            impersonation_ttl_seconds: 300,
+            two_fa_code_ttl_minutes: 10,
+            trust_proxy_headers: false,
+            admin_api_token: None,
+            enumeration_resistant_signup: false,
+        }
+    }
+}
+
+// See `LockoutPolicy`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockoutConfig {
+    // Consecutive failed logins before each further attempt has to wait
+    pub backoff_threshold: u32,
+    // Consecutive failed logins before the account is locked
+    pub threshold: u32,
+    pub duration_seconds: i64,
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            backoff_threshold: 3,
+            threshold: 10,
+            duration_seconds: 15 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EmailConfig {
+    // The local part of an address is case-sensitive per RFC 5321, but hardly
+    // any mail server treats it that way
+    pub lowercase_local_part: bool,
+    // Named in the emails sent
+    pub product_name: String,
+    // Templates here replace the built-in ones of the same name
+    pub template_dir: Option<String>,
+    // How often the outbox is checked for emails to send; often, since users
+    // wait on 2FA codes
+    pub outbox_interval_milliseconds: u64,
+}
+
+impl Default for EmailConfig {
+    fn default() -> Self {
+        Self {
+            lowercase_local_part: true,
+            product_name: DEFAULT_PRODUCT_NAME.to_owned(),
+            template_dir: None,
+            outbox_interval_milliseconds: 500,
+        }
+    }
+}
+
+// Without a host emails are only printed, which is fine for development but
+// means nobody gets their 2FA codes
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmtpConfig {
+    pub host: Option<String>,
+    // Defaults to the usual port for `tls`
+    pub port: Option<u16>,
+    // "tls", "starttls" or "none"
+    pub tls: String,
+    // Both or neither, for servers that don't need authentication
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // The From of every email, e.g. `Example <no-reply@example.com>`
+    pub sender: Option<String>,
+    pub timeout_seconds: u64,
+    pub max_connections: u32,
+}
+
+impl Default for SmtpConfig {
+    fn default() -> Self {
+        Self {
+            host: None,
+            port: None,
+            tls: "starttls".to_owned(),
+            username: None,
+            password: None,
+            sender: None,
+            timeout_seconds: 10,
+            max_connections: 4,
+        }
+    }
+}
+
+// Without a provider texts and calls are only printed, like emails without SMTP
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SmsConfig {
+    pub provider_url: Option<String>,
+    pub provider_token: Option<String>,
+    pub sender: Option<String>,
+    pub timeout_seconds: u64,
+}
+
+impl Default for SmsConfig {
+    fn default() -> Self {
+        Self {
+            provider_url: None,
+            provider_token: None,
+            sender: None,
+            timeout_seconds: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WebhooksConfig {
+    // How often queued deliveries are checked for ones that are due
+    pub dispatch_interval_seconds: u64,
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        Self {
+            dispatch_interval_seconds: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    // Hex encoded Ed25519 seed; checkpoints are only made when it's set
+    pub checkpoint_signing_key: Option<String>,
+    pub checkpoint_interval_seconds: u64,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            checkpoint_signing_key: None,
+            checkpoint_interval_seconds: 3600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    // Base URL of an OTLP/HTTP collector, e.g. "http://localhost:4318"; spans
+    // are only exported when set
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "auth-service".to_owned(),
+        }
+    }
+}
+
+impl Config {
+    /// Read `.env`, then the file in `CONFIG_FILE` (or `config.toml`, when
+    /// there is one), then apply environment overrides and validate.
+    pub fn load() -> Result<Self, String> {
+        dotenvy::dotenv().ok();
+
+        let (path, required) = match std::env::var(env::CONFIG_FILE_ENV_VAR) {
+            Ok(path) => (path, true),
+            Err(_) => (DEFAULT_CONFIG_FILE.to_owned(), false),
+        };
+        let file = if required || Path::new(&path).exists() {
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| format!("Failed to read config file {}: {}", path, e))?;
+            Some(contents)
+        } else {
+            None
+        };
+
+        Self::from_sources(file.as_deref(), |name| std::env::var(name).ok()).map_err(
+            |e| match &file {
+                Some(_) => format!("{} (config file {})", e, path),
+                None => e,
+            },
+        )
+    }
+
+    /// Parse `file` as TOML, if any, then override it with what `lookup`
+    /// returns for each variable and validate the result.
+    pub fn from_sources(
+        file: Option<&str>,
+        lookup: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, String> {
+        let mut config: Config = match file {
+            Some(contents) => toml::from_str(contents).map_err(|e| e.to_string())?,
+            None => Config::default(),
+        };
+
+        config.apply_env(lookup)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        use env::*;
+
+        let lookup = &lookup;
+        override_string(lookup, SERVER_ADDRESS_ENV_VAR, &mut self.server.address);
+        override_string(lookup, DATABASE_URL_ENV_VAR, &mut self.database.url);
+        override_string(lookup, REDIS_HOSTNAME_ENV_VAR, &mut self.redis.hostname);
+        override_string(lookup, JWT_SECRET_ENV_VAR, &mut self.auth.jwt_secret);
+        if let Some(token) = lookup(ADMIN_API_TOKEN_ENV_VAR) {
+            self.auth.admin_api_token = Some(token);
+        }
+        // Empty when compose passes it through unset, which disables export
+        override_optional(
+            lookup,
+            OTEL_EXPORTER_OTLP_ENDPOINT_ENV_VAR,
+            &mut self.telemetry.otlp_endpoint,
+        );
+        override_string(
+            lookup,
+            OTEL_SERVICE_NAME_ENV_VAR,
+            &mut self.telemetry.service_name,
+        );
+
+        let database = &mut self.database;
+        override_number(
+            lookup,
+            DATABASE_MAX_CONNECTIONS_ENV_VAR,
+            &mut database.max_connections,
+        )?;
+        override_number(lookup, REDIS_PORT_ENV_VAR, &mut self.redis.port)?;
+        override_number(
+            lookup,
+            PRE_DRAIN_DELAY_SECONDS_ENV_VAR,
+            &mut self.server.pre_drain_delay_seconds,
+        )?;
+        override_number(
+            lookup,
+            DRAIN_TIMEOUT_SECONDS_ENV_VAR,
+            &mut self.server.drain_timeout_seconds,
+        )?;
+        let auth = &mut self.auth;
+        override_number(
+            lookup,
+            TOKEN_TTL_SECONDS_ENV_VAR,
+            &mut auth.token_ttl_seconds,
+        )?;
+        override_number(
+            lookup,
+            IMPERSONATION_TTL_SECONDS_ENV_VAR,
+            &mut auth.impersonation_ttl_seconds,
+        )?;
+        override_number(
+            lookup,
+            TWO_FA_CODE_TTL_MINUTES_ENV_VAR,
+            &mut auth.two_fa_code_ttl_minutes,
+        )?;
+
+        override_flag(
+            lookup,
+            TRUST_PROXY_HEADERS_ENV_VAR,
+            &mut auth.trust_proxy_headers,
+        )?;
+        override_flag(
+            lookup,
+            ENUMERATION_RESISTANT_SIGNUP_ENV_VAR,
+            &mut auth.enumeration_resistant_signup,
+        )?;
+        override_flag(
+            lookup,
+            EMAIL_LOWERCASE_LOCAL_PART_ENV_VAR,
+            &mut self.email.lowercase_local_part,
+        )?;
+
+        let argon2 = &mut self.argon2;
+        override_number(lookup, ARGON2_MEMORY_COST_ENV_VAR, &mut argon2.memory_cost)?;
+        override_number(lookup, ARGON2_TIME_COST_ENV_VAR, &mut argon2.time_cost)?;
+        override_number(lookup, ARGON2_PARALLELISM_ENV_VAR, &mut argon2.parallelism)?;
+        override_optional(lookup, PASSWORD_PEPPER_ENV_VAR, &mut argon2.pepper);
+
+        let lockout = &mut self.lockout;
+        override_number(
+            lookup,
+            LOCKOUT_BACKOFF_THRESHOLD_ENV_VAR,
+            &mut lockout.backoff_threshold,
+        )?;
+        override_number(lookup, LOCKOUT_THRESHOLD_ENV_VAR, &mut lockout.threshold)?;
+        override_number(
+            lookup,
+            LOCKOUT_DURATION_SECONDS_ENV_VAR,
+            &mut lockout.duration_seconds,
+        )?;
+
+        let rate_limits = &mut self.rate_limits;
+        for (name, policy) in [
+            (
+                RATE_LIMIT_LOGIN_PER_IP_ENV_VAR,
+                &mut rate_limits.login_per_ip,
+            ),
+            (
+                RATE_LIMIT_LOGIN_PER_EMAIL_ENV_VAR,
+                &mut rate_limits.login_per_email,
+            ),
+            (
+                RATE_LIMIT_SIGNUP_PER_IP_ENV_VAR,
+                &mut rate_limits.signup_per_ip,
+            ),
+            (
+                RATE_LIMIT_SIGNUP_PER_EMAIL_ENV_VAR,
+                &mut rate_limits.signup_per_email,
+            ),
+            (
+                RATE_LIMIT_VERIFY_2FA_PER_IP_ENV_VAR,
+                &mut rate_limits.verify_2fa_per_ip,
+            ),
+            (
+                RATE_LIMIT_VERIFY_2FA_PER_EMAIL_ENV_VAR,
+                &mut rate_limits.verify_2fa_per_email,
+            ),
+            (
+                RATE_LIMIT_PHONE_VERIFICATION_PER_IP_ENV_VAR,
+                &mut rate_limits.phone_verification_per_ip,
+            ),
+            (
+                RATE_LIMIT_PHONE_VERIFICATION_PER_EMAIL_ENV_VAR,
+                &mut rate_limits.phone_verification_per_email,
+            ),
+        ] {
+            if let Some(value) = lookup(name) {
+                *policy = RateLimitPolicy::parse(&value).map_err(|e| format!("{}: {}", name, e))?;
+            }
+        }
+
+        let email = &mut self.email;
+        override_string(lookup, PRODUCT_NAME_ENV_VAR, &mut email.product_name);
+        override_optional(lookup, EMAIL_TEMPLATE_DIR_ENV_VAR, &mut email.template_dir);
+        override_number(
+            lookup,
+            EMAIL_OUTBOX_INTERVAL_MILLISECONDS_ENV_VAR,
+            &mut email.outbox_interval_milliseconds,
+        )?;
+
+        let smtp = &mut self.smtp;
+        override_optional(lookup, SMTP_HOST_ENV_VAR, &mut smtp.host);
+        if let Some(port) = lookup(SMTP_PORT_ENV_VAR) {
+            let port = port.trim().parse().map_err(|_| {
+                format!(
+                    "{} must be a whole number, got {:?}",
+                    SMTP_PORT_ENV_VAR, port
+                )
+            })?;
+            smtp.port = Some(port);
+        }
+        override_string(lookup, SMTP_TLS_ENV_VAR, &mut smtp.tls);
+        override_optional(lookup, SMTP_USERNAME_ENV_VAR, &mut smtp.username);
+        override_optional(lookup, SMTP_PASSWORD_ENV_VAR, &mut smtp.password);
+        override_optional(lookup, SMTP_SENDER_ENV_VAR, &mut smtp.sender);
+        override_number(
+            lookup,
+            SMTP_TIMEOUT_SECONDS_ENV_VAR,
+            &mut smtp.timeout_seconds,
+        )?;
+        override_number(
+            lookup,
+            SMTP_MAX_CONNECTIONS_ENV_VAR,
+            &mut smtp.max_connections,
+        )?;
+
+        let sms = &mut self.sms;
+        override_optional(lookup, SMS_PROVIDER_URL_ENV_VAR, &mut sms.provider_url);
+        override_optional(lookup, SMS_PROVIDER_TOKEN_ENV_VAR, &mut sms.provider_token);
+        override_optional(lookup, SMS_SENDER_ENV_VAR, &mut sms.sender);
+        override_number(
+            lookup,
+            SMS_TIMEOUT_SECONDS_ENV_VAR,
+            &mut sms.timeout_seconds,
+        )?;
+
+        override_number(
+            lookup,
+            WEBHOOK_DISPATCH_INTERVAL_SECONDS_ENV_VAR,
+            &mut self.webhooks.dispatch_interval_seconds,
+        )?;
+        override_optional(
+            lookup,
+            AUDIT_CHECKPOINT_SIGNING_KEY_ENV_VAR,
+            &mut self.audit.checkpoint_signing_key,
+        );
+        override_number(
+            lookup,
+            AUDIT_CHECKPOINT_INTERVAL_SECONDS_ENV_VAR,
+            &mut self.audit.checkpoint_interval_seconds,
+        )?;
+
+        Ok(())
+    }
+
+    /// Check the settings make sense together, naming the offending one.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.server.address.parse::<SocketAddr>().is_err() {
+            return Err(format!(
+                "server.address (SERVER_ADDRESS) must be an IP address and port, got {:?}",
+                self.server.address
+            ));
+        }
+        if self.server.drain_timeout_seconds == 0 {
+            return Err(
+                "server.drain_timeout_seconds (DRAIN_TIMEOUT_SECONDS) must be positive".to_owned(),
+            );
+        }
+        if self.database.url.is_empty() {
+            return Err("database.url (DATABASE_URL) must be set".to_owned());
+        }
+        if self.database.max_connections == 0 {
+            return Err(
+                "database.max_connections (DATABASE_MAX_CONNECTIONS) must be at least 1".to_owned(),
+            );
+        }
+        if self.redis.hostname.is_empty() {
+            return Err("redis.hostname (REDIS_HOSTNAME) must not be empty".to_owned());
+        }
+        if self.auth.jwt_secret.is_empty() {
+            return Err("auth.jwt_secret (JWT_SECRET) must be set".to_owned());
+        }
+        if self.auth.token_ttl_seconds <= 0 {
+            return Err("auth.token_ttl_seconds (TOKEN_TTL_SECONDS) must be positive".to_owned());
+        }
+        // Logging out bans a token for `token_ttl_seconds`, which has to cover
+        // impersonation tokens too
+        if self.auth.impersonation_ttl_seconds <= 0
+            || self.auth.impersonation_ttl_seconds > self.auth.token_ttl_seconds
+        {
+            return Err(
+                "auth.impersonation_ttl_seconds (IMPERSONATION_TTL_SECONDS) must be positive \
+                 and no more than auth.token_ttl_seconds"
+                    .to_owned(),
+            );
+        }
+        if self.auth.two_fa_code_ttl_minutes == 0 {
+            return Err(
+                "auth.two_fa_code_ttl_minutes (TWO_FA_CODE_TTL_MINUTES) must be positive"
+                    .to_owned(),
+            );
+        }
+        self.argon2.validate().map_err(|e| {
+            format!(
+                "{} (argon2.memory_cost, argon2.time_cost and argon2.parallelism)",
+                e
+            )
+        })?;
+        if self.lockout.threshold == 0 || self.lockout.backoff_threshold > self.lockout.threshold {
+            return Err(
+                "lockout.threshold (LOCKOUT_THRESHOLD) must be at least 1 and not below \
+                 lockout.backoff_threshold"
+                    .to_owned(),
+            );
+        }
+        if self.lockout.duration_seconds <= 0 {
+            return Err(
+                "lockout.duration_seconds (LOCKOUT_DURATION_SECONDS) must be positive".to_owned(),
+            );
+        }
+        if self.email.product_name.trim().is_empty() {
+            return Err("email.product_name (PRODUCT_NAME) must not be empty".to_owned());
+        }
+        if let Some(dir) = &self.email.template_dir {
+            if !Path::new(dir).is_dir() {
+                return Err(format!(
+                    "email.template_dir (EMAIL_TEMPLATE_DIR) must be a directory, got {:?}",
+                    dir
+                ));
+            }
+        }
+        if self.email.outbox_interval_milliseconds == 0 {
+            return Err(
+                "email.outbox_interval_milliseconds (EMAIL_OUTBOX_INTERVAL_MILLISECONDS) must \
+                 be positive"
+                    .to_owned(),
+            );
+        }
+        SmtpSettings::from_config(&self.smtp)?;
+        HttpSmsSettings::from_config(&self.sms)?;
+        if self.webhooks.dispatch_interval_seconds == 0 {
+            return Err(
+                "webhooks.dispatch_interval_seconds (WEBHOOK_DISPATCH_INTERVAL_SECONDS) must be \
+                 positive"
+                    .to_owned(),
+            );
+        }
+        AuditCheckpointSigner::from_config(&self.audit)?;
+        if self.audit.checkpoint_interval_seconds == 0 {
+            return Err(
+                "audit.checkpoint_interval_seconds (AUDIT_CHECKPOINT_INTERVAL_SECONDS) must be \
+                 positive"
+                    .to_owned(),
+            );
+        }
+        if let Some(endpoint) = &self.telemetry.otlp_endpoint {
+            if !endpoint.starts_with("http://") && !endpoint.starts_with("https://") {
+                return Err(format!(
+                    "telemetry.otlp_endpoint (OTEL_EXPORTER_OTLP_ENDPOINT) must be an http(s) \
+                     URL, got {:?}",
+                    endpoint
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn override_string(lookup: &impl Fn(&str) -> Option<String>, name: &str, target: &mut String) {
+    if let Some(value) = lookup(name) {
+        *target = value;
+    }
+}
+
+// Empty values unset it
+fn override_optional(
+    lookup: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    target: &mut Option<String>,
+) {
+    if let Some(value) = lookup(name) {
+        *target = Some(value).filter(|value| !value.is_empty());
+    }
+}
+
+fn override_number<T: FromStr>(
+    lookup: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    target: &mut T,
+) -> Result<(), String> {
+    if let Some(value) = lookup(name) {
+        *target = value
+            .trim()
+            .parse()
+            .map_err(|_| format!("{} must be a whole number, got {:?}", name, value))?;
+    }
+    Ok(())
+}
+
+fn override_flag(
+    lookup: &impl Fn(&str) -> Option<String>,
+    name: &str,
+    target: &mut bool,
+) -> Result<(), String> {
+    if let Some(value) = lookup(name) {
+        *target = match value.trim() {
+            "true" | "1" => true,
+            "false" | "0" => false,
+            _ => return Err(format!("{} must be true or false, got {:?}", name, value)),
+        };
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn env_of(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    const REQUIRED: [(&str, &str); 2] = [
+        ("DATABASE_URL", "postgres://localhost/auth"),
+        ("JWT_SECRET", "secret"),
+    ];
+
+    #[test]
+    fn test_defaults_need_only_the_database_and_secret() {
+        let config = Config::from_sources(None, env_of(&REQUIRED)).unwrap();
+        assert_eq!(config.server.address, "0.0.0.0:3000");
+        assert_eq!(config.server.pre_drain_delay_seconds, 5);
+        assert_eq!(config.server.drain_timeout_seconds, 10);
+        assert_eq!(config.database.max_connections, 5);
+        assert_eq!(config.auth.token_ttl_seconds, 600);
+        assert_eq!(config.auth.two_fa_code_ttl_minutes, 10);
+        assert!(config.email.lowercase_local_part);
+        assert!(!config.auth.trust_proxy_headers);
+        assert_eq!(config.telemetry.otlp_endpoint, None);
+        assert_eq!(config.lockout.threshold, 10);
+        assert_eq!(config.rate_limits.login_per_ip.max_requests, 30);
+        assert_eq!(config.email.product_name, DEFAULT_PRODUCT_NAME);
+        assert_eq!(config.smtp.host, None);
+        assert_eq!(config.audit.checkpoint_signing_key, None);
+
+        let vars: Vec<_> = REQUIRED
+            .iter()
+            .copied()
+            .chain([("OTEL_EXPORTER_OTLP_ENDPOINT", "")])
+            .collect();
+        let config = Config::from_sources(None, env_of(&vars)).unwrap();
+        assert_eq!(config.telemetry.otlp_endpoint, None);
+
+        let error = Config::from_sources(None, env_of(&REQUIRED[..1])).unwrap_err();
+        assert!(error.contains("JWT_SECRET"), "{}", error);
+    }
+
+    #[test]
+    fn test_env_overrides_the_file() {
+        let file = r#"
+            [server]
+            address = "127.0.0.1:8080"
+
+            [database]
+            url = "postgres://db/auth"
+            max_connections = 20
+
+            [auth]
+            jwt_secret = "from-file"
+            token_ttl_seconds = 900
+            trust_proxy_headers = true
+        "#;
+        let env = env_of(&[("JWT_SECRET", "from-env"), ("TOKEN_TTL_SECONDS", "1200")]);
+
+        let config = Config::from_sources(Some(file), env).unwrap();
+        assert_eq!(config.server.address, "127.0.0.1:8080");
+        assert_eq!(config.database.max_connections, 20);
+        assert_eq!(config.auth.jwt_secret, "from-env");
+        assert_eq!(config.auth.token_ttl_seconds, 1200);
+        assert!(config.auth.trust_proxy_headers);
+    }
+
+    #[test]
+    fn test_sections_read_from_the_file_and_env() {
+        let file = r#"
+            [argon2]
+            time_cost = 3
+
+            [rate_limits]
+            login_per_ip = "5/60"
+
+            [smtp]
+            host = "smtp.example.com"
+            sender = "Auth Service <no-reply@example.com>"
+        "#;
+        let vars: Vec<_> = REQUIRED
+            .iter()
+            .copied()
+            .chain([
+                ("LOCKOUT_THRESHOLD", "4"),
+                ("RATE_LIMIT_SIGNUP_PER_EMAIL", "2/600"),
+                ("SMTP_PORT", "2525"),
+            ])
+            .collect();
+
+        let config = Config::from_sources(Some(file), env_of(&vars)).unwrap();
+        assert_eq!(config.argon2.time_cost, 3);
+        assert_eq!(config.lockout.threshold, 4);
+        assert_eq!(config.rate_limits.login_per_ip.max_requests, 5);
+        assert_eq!(config.rate_limits.signup_per_email.max_requests, 2);
+        assert_eq!(config.smtp.host.as_deref(), Some("smtp.example.com"));
+        assert_eq!(config.smtp.port, Some(2525));
+    }
+
+    #[test]
+    fn test_example_file_is_valid() {
+        let file = include_str!("../config.example.toml");
+        let config = Config::from_sources(Some(file), env_of(&REQUIRED[1..])).unwrap();
+        assert_eq!(config.redis.port, 6379);
+    }
+
+    #[test]
+    fn test_invalid_settings_are_named() {
+        let test_cases = [
+            (
+                None,
+                vec![("TOKEN_TTL_SECONDS", "ten")],
+                "TOKEN_TTL_SECONDS",
+            ),
+            (None, vec![("TOKEN_TTL_SECONDS", "0")], "token_ttl_seconds"),
+            (
+                None,
+                vec![("TRUST_PROXY_HEADERS", "yes")],
+                "TRUST_PROXY_HEADERS",
+            ),
+            (
+                None,
+                vec![("SERVER_ADDRESS", "localhost")],
+                "server.address",
+            ),
+            (
+                None,
+                vec![("PRE_DRAIN_DELAY_SECONDS", "soon")],
+                "PRE_DRAIN_DELAY_SECONDS",
+            ),
+            (
+                None,
+                vec![("DRAIN_TIMEOUT_SECONDS", "0")],
+                "server.drain_timeout_seconds",
+            ),
+            (
+                None,
+                vec![("IMPERSONATION_TTL_SECONDS", "3600")],
+                "impersonation_ttl_seconds",
+            ),
+            (
+                None,
+                vec![("OTEL_EXPORTER_OTLP_ENDPOINT", "localhost:4318")],
+                "telemetry.otlp_endpoint",
+            ),
+            (None, vec![("ARGON2_PARALLELISM", "0")], "parallelism"),
+            (None, vec![("LOCKOUT_THRESHOLD", "2")], "lockout.threshold"),
+            (
+                None,
+                vec![("RATE_LIMIT_LOGIN_PER_IP", "30")],
+                "RATE_LIMIT_LOGIN_PER_IP",
+            ),
+            (
+                Some("[rate_limits]\nlogin_per_ip = \"0/60\""),
+                vec![],
+                "rate limit",
+            ),
+            (None, vec![("SMTP_HOST", "smtp.example.com")], "smtp.sender"),
+            (
+                None,
+                vec![
+                    ("SMTP_HOST", "smtp.example.com"),
+                    ("SMTP_SENDER", "no-reply@example.com"),
+                    ("SMTP_TIMEOUT_SECONDS", "90"),
+                ],
+                "smtp.timeout_seconds",
+            ),
+            (
+                None,
+                vec![("SMS_PROVIDER_URL", "https://sms.example.com")],
+                "sms.provider_token",
+            ),
+            (
+                None,
+                vec![("EMAIL_TEMPLATE_DIR", "/does/not/exist")],
+                "email.template_dir",
+            ),
+            (
+                None,
+                vec![("AUDIT_CHECKPOINT_SIGNING_KEY", "abc")],
+                "audit.checkpoint_signing_key",
+            ),
+            (
+                None,
+                vec![("WEBHOOK_DISPATCH_INTERVAL_SECONDS", "0")],
+                "webhooks.dispatch_interval_seconds",
+            ),
+            (
+                Some("[database]\nmax_connection = 5"),
+                vec![],
+                "max_connection",
+            ),
+        ];
+
+        for (file, vars, expected) in test_cases {
+            let vars: Vec<_> = REQUIRED.iter().copied().chain(vars).collect();
+            let error = Config::from_sources(file, env_of(&vars)).unwrap_err();
+            assert!(error.contains(expected), "{}", error);
+        }
+    }
+}
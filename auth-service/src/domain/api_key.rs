@@ -0,0 +1,53 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::{Email, Permission, TenantId};
+
+/// A long-lived credential a user creates for scripts and CLIs, sent as
+/// `Authorization: Bearer <key>`.
+///
+/// Only a hash of the key itself is stored. A key acts for its owner in one
+/// tenant, with at most the permissions in `scopes`, and never with roles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiKey {
+    pub id: Uuid,
+    pub tenant: TenantId,
+    pub email: Email,
+    pub name: String,
+    pub scopes: Vec<Permission>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+impl ApiKey {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+}
+
+#[async_trait::async_trait]
+pub trait ApiKeyStore {
+    async fn add_key(&mut self, key: ApiKey, key_hash: &str) -> Result<(), ApiKeyStoreError>;
+    // Expired keys are still returned, so they can be listed until revoked
+    async fn get_key_by_hash(&self, key_hash: &str) -> Result<ApiKey, ApiKeyStoreError>;
+    async fn get_keys(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<Vec<ApiKey>, ApiKeyStoreError>;
+    // Only the owner's keys can be revoked; anyone else's are `KeyNotFound`
+    async fn revoke_key(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<(), ApiKeyStoreError>;
+    async fn mark_used(&mut self, id: Uuid, at: DateTime<Utc>) -> Result<(), ApiKeyStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ApiKeyStoreError {
+    KeyNotFound,
+    UnexpectedError,
+}
@@ -1,13 +1,138 @@
-use super::{Email, Password, User};
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use super::{
+    Email, LockoutState, Password, PasswordHash, PasswordHasherError, Permission, PhoneNumber,
+    Role, TenantId, TwoFAChannel, User, UserListQuery, UserPage,
+};
 use lazy_regex::regex;
 use rand::Rng;
 use uuid::Uuid;
 
+use crate::utils::telemetry::REDACTED;
+
+/// Users are identities shared by every tenant they're a member of: one
+/// password, lockout and disabled flag, but roles and visibility per tenant.
+/// Lookups through a tenant treat non-members as `UserNotFound`.
 #[async_trait::async_trait]
 pub trait UserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError>;
-    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError>;
-    async fn validate_user(&self, email: &Email, password: &Password)
+    // Also makes the new user a member of `tenant`
+    async fn add_user(&mut self, tenant: &TenantId, user: User) -> Result<(), UserStoreError>;
+    // Like `add_user`, but the user can't log in until they confirm the address
+    // with the emailed token. An unconfirmed user with the same email is
+    // replaced, so someone else signing up first can't keep the owner out.
+    async fn add_pending_user(
+        &mut self,
+        tenant: &TenantId,
+        user: User,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError>;
+    // `InvalidCredentials` unless the user is pending with `token_hash` and it hasn't expired
+    async fn confirm_signup(
+        &mut self,
+        email: &Email,
+        token_hash: &str,
+    ) -> Result<(), UserStoreError>;
+    async fn get_user(&self, tenant: &TenantId, email: &Email) -> Result<User, UserStoreError>;
+    // Also enforces the store's `LockoutPolicy`: failed attempts are recorded,
+    // and attempts during a backoff or lock are refused whatever the password.
+    // Pending users are refused as if the password were wrong.
+    async fn validate_user(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        password: &Password,
+    ) -> Result<(), UserStoreError>;
+    async fn get_lockout_state(&self, email: &Email) -> Result<LockoutState, UserStoreError>;
+    async fn unlock_user(&mut self, email: &Email) -> Result<(), UserStoreError>;
+
+    // Adding an existing member, or removing a non-member, is not an error.
+    // Removing a member also removes their roles in that tenant.
+    async fn add_member(&mut self, tenant: &TenantId, email: &Email) -> Result<(), UserStoreError>;
+    async fn remove_member(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), UserStoreError>;
+    async fn get_tenants(&self, email: &Email) -> Result<Vec<TenantId>, UserStoreError>;
+
+    // Assigning a role the user already has, or revoking one they don't, is not an error
+    async fn assign_role(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+        role: &Role,
+    ) -> Result<(), UserStoreError>;
+    async fn revoke_role(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+        role: &Role,
+    ) -> Result<(), UserStoreError>;
+    async fn get_roles(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<Vec<Role>, UserStoreError>;
+    // Everything granted by any of `roles`; unknown roles grant nothing
+    async fn get_permissions(&self, roles: &[Role]) -> Result<Vec<Permission>, UserStoreError>;
+
+    // Members of `tenant` only
+    async fn list_users(
+        &self,
+        tenant: &TenantId,
+        query: &UserListQuery,
+    ) -> Result<UserPage, UserStoreError>;
+    // Disabled users are refused at login with `AccountDisabled`, in every tenant
+    async fn set_disabled(&mut self, email: &Email, disabled: bool) -> Result<(), UserStoreError>;
+    async fn set_requires_2fa(
+        &mut self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError>;
+    // Only a hash of the emailed token is kept. Starting a reset replaces any earlier one.
+    async fn start_password_reset(
+        &mut self,
+        email: &Email,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError>;
+    // Sets the new password and ends the reset, as well as any lockout.
+    // `InvalidCredentials` unless a reset with `token_hash` is still pending.
+    async fn complete_password_reset(
+        &mut self,
+        email: &Email,
+        token_hash: &str,
+        new_password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError>;
+    // Only a hash of the texted code is kept. Starting a verification replaces
+    // any earlier one, and leaves the current phone number in place until it succeeds.
+    async fn start_phone_verification(
+        &mut self,
+        email: &Email,
+        phone: &PhoneNumber,
+        code_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError>;
+    // Makes the pending number the user's phone number and returns it.
+    // `InvalidCredentials` unless a verification with `code_hash` is still
+    // pending; a wrong code also ends the verification, so each code gets one guess.
+    async fn complete_phone_verification(
+        &mut self,
+        email: &Email,
+        code_hash: &str,
+    ) -> Result<PhoneNumber, UserStoreError>;
+    // `PhoneNotVerified` for a channel that needs a phone number the user doesn't have
+    async fn set_two_fa_channel(
+        &mut self,
+        email: &Email,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError>;
+    // Removes the user from `tenant`, and deletes them once they're no
+    // longer a member anywhere
+    async fn delete_user(&mut self, tenant: &TenantId, email: &Email)
         -> Result<(), UserStoreError>;
 }
 
@@ -15,14 +140,48 @@ pub trait UserStore {
 pub enum UserStoreError {
     UserAlreadyExists,
     UserNotFound,
+    RoleNotFound,
+    TenantNotFound,
     InvalidCredentials,
+    // `just_locked` is set only for the failed attempt that caused the lock
+    AccountLocked {
+        until: DateTime<Utc>,
+        just_locked: bool,
+    },
+    LoginThrottled {
+        until: DateTime<Utc>,
+    },
+    AccountDisabled,
+    PhoneNotVerified,
     UnexpectedError,
 }
 
+impl From<PasswordHasherError> for UserStoreError {
+    fn from(error: PasswordHasherError) -> Self {
+        match error {
+            PasswordHasherError::IncorrectPassword => UserStoreError::InvalidCredentials,
+            PasswordHasherError::UnexpectedError => UserStoreError::UnexpectedError,
+        }
+    }
+}
+
 #[async_trait::async_trait]
 pub trait BannedTokenStore {
     async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError>;
     async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError>;
+    // Ban every token `tenant` issued to `subject` at or before `issued_until`,
+    // e.g. to sign a user out everywhere. Only needs remembering for a token lifetime.
+    async fn ban_subject(
+        &mut self,
+        tenant: &TenantId,
+        subject: &str,
+        issued_until: DateTime<Utc>,
+    ) -> Result<(), BannedTokenStoreError>;
+    async fn subject_banned_until(
+        &self,
+        tenant: &TenantId,
+        subject: &str,
+    ) -> Result<Option<DateTime<Utc>>, BannedTokenStoreError>;
 }
 
 #[derive(Debug, PartialEq)]
@@ -30,10 +189,13 @@ pub enum BannedTokenStoreError {
     UnexpectedError,
 }
 
+// Codes are kept per tenant, so a login started in one tenant can't be
+// finished in another
 #[async_trait::async_trait]
 pub trait TwoFACodeStore {
     async fn add_code(
         &mut self,
+        tenant: &TenantId,
         email: Email,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
@@ -41,10 +203,15 @@ pub trait TwoFACodeStore {
 
     async fn get_code(
         &self,
+        tenant: &TenantId,
         email: &Email,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError>;
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError>;
+    async fn remove_code(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), TwoFACodeStoreError>;
     // async fn contains_code(&self, email: &Email) -> Result<bool, TwoFACodeStoreError>;
 }
 
@@ -60,7 +227,7 @@ pub struct LoginAttemptId(String);
 impl LoginAttemptId {
     pub fn parse(id: String) -> Result<Self, String> {
         // verify the id is a valid UUID
-        if !Uuid::parse_str(&id).is_ok() {
+        if Uuid::parse_str(&id).is_err() {
             return Err("Invalid id".to_string());
         }
 
@@ -80,7 +247,7 @@ impl AsRef<str> for LoginAttemptId {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, PartialEq)]
 pub struct TwoFACode(String);
 
 impl TwoFACode {
@@ -108,3 +275,12 @@ impl AsRef<str> for TwoFACode {
         &self.0
     }
 }
+
+// Never logged, see `utils::telemetry`
+impl fmt::Debug for TwoFACode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TwoFACode")
+            .field(&format_args!("{}", REDACTED))
+            .finish()
+    }
+}
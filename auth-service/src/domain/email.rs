@@ -1,19 +1,73 @@
+use std::hash::{Hash, Hasher};
+
 use serde::{Deserialize, Serialize};
 use validator::ValidateEmail;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+// Emails are canonicalized on parse, and compared case-insensitively so that
+// `Alice@Example.com` and `alice@example.com` always identify the same account,
+// even when the local part's case is preserved for delivery.
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Email(String);
 
 impl Email {
+    /// Canonicalize `email`, keeping the case of the local part. For emails
+    /// canonicalized on the way in, such as stored ones and token subjects;
+    /// emails from requests go through `AppState::parse_email`.
     pub fn parse(email: String) -> Result<Self, String> {
-        if email.validate_email() {
-            Ok(Email(email))
+        Self::parse_with_options(email, false)
+    }
+
+    /// Trim surrounding whitespace, convert the domain to lowercase ASCII
+    /// (punycode for internationalized domains) and optionally lowercase the local part.
+    pub fn parse_with_options(email: String, lowercase_local_part: bool) -> Result<Self, String> {
+        let invalid = || format!("Invalid email: {}", email);
+
+        let (local_part, domain) = email.trim().rsplit_once('@').ok_or_else(invalid)?;
+
+        let local_part = if lowercase_local_part {
+            local_part.to_lowercase()
         } else {
-            Err(format!("Invalid email: {}", email))
+            local_part.to_owned()
+        };
+
+        let domain = canonicalize_domain(domain).ok_or_else(invalid)?;
+
+        let canonical = format!("{}@{}", local_part, domain);
+        if canonical.validate_email() {
+            Ok(Email(canonical))
+        } else {
+            Err(invalid())
         }
     }
 }
 
+fn canonicalize_domain(domain: &str) -> Option<String> {
+    // Address literals such as `[127.0.0.1]` aren't domain names
+    if domain.starts_with('[') {
+        return Some(domain.to_ascii_lowercase());
+    }
+
+    // UTS #46 processing also lowercases the domain
+    match idna::domain_to_ascii(domain) {
+        Ok(ascii) if !ascii.is_empty() => Some(ascii),
+        _ => None,
+    }
+}
+
+impl PartialEq for Email {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.to_lowercase() == other.0.to_lowercase()
+    }
+}
+
+impl Eq for Email {}
+
+impl Hash for Email {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_lowercase().hash(state);
+    }
+}
+
 impl AsRef<str> for Email {
     fn as_ref(&self) -> &str {
         &self.0
@@ -41,4 +95,41 @@ mod tests {
             Err("Invalid email: ".to_string() + &email)
         );
     }
+
+    #[test]
+    fn email_is_trimmed_and_lowercased() {
+        let email = Email::parse_with_options("  Alice@Example.COM \n".to_owned(), true).unwrap();
+        assert_eq!(email.as_ref(), "alice@example.com");
+    }
+
+    #[test]
+    fn local_part_case_can_be_preserved() {
+        let email = Email::parse_with_options("Alice@Example.COM".to_owned(), false).unwrap();
+        assert_eq!(email.as_ref(), "Alice@example.com");
+        assert_eq!(
+            email,
+            Email::parse_with_options("alice@example.com".to_owned(), false).unwrap()
+        );
+    }
+
+    #[test]
+    fn internationalized_domain_is_converted_to_punycode() {
+        let email = Email::parse_with_options("user@Bücher.example".to_owned(), true).unwrap();
+        assert_eq!(email.as_ref(), "user@xn--bcher-kva.example");
+        assert_eq!(
+            email,
+            Email::parse_with_options("user@xn--bcher-kva.example".to_owned(), true).unwrap()
+        );
+    }
+
+    #[test]
+    fn equal_emails_hash_the_same() {
+        use std::collections::HashSet;
+
+        let mut emails = HashSet::new();
+        emails.insert(Email::parse_with_options("Alice@example.com".to_owned(), false).unwrap());
+
+        assert!(emails
+            .contains(&Email::parse_with_options("ALICE@EXAMPLE.COM".to_owned(), false).unwrap()));
+    }
 }
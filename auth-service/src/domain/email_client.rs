@@ -1,3 +1,5 @@
+use std::fmt;
+
 use super::Email;
 
 // This trait represents the interface that all concrete email_clients should implement.
@@ -6,7 +8,34 @@ pub trait EmailClient {
     async fn send_email(
         &self, // the concrete implementation of the trait
         email: &Email,
-        subject: &str,
-        content: &str,
-    ) -> Result<(), String>;
+        message: &EmailMessage,
+    ) -> Result<(), EmailClientError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailMessage {
+    pub subject: String,
+    pub text: String,
+    // Sent alongside the text, for mail clients that show HTML
+    pub html: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmailClientError {
+    // The message couldn't be built, e.g. the sender address is invalid
+    InvalidMessage(String),
+    // The mail server refused it for good (a 5xx reply), so retrying won't help
+    Rejected(String),
+    // The mail server couldn't be reached, timed out, or failed temporarily
+    Unavailable(String),
+}
+
+impl fmt::Display for EmailClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmailClientError::InvalidMessage(e) => write!(f, "invalid message: {}", e),
+            EmailClientError::Rejected(e) => write!(f, "rejected by the mail server: {}", e),
+            EmailClientError::Unavailable(e) => write!(f, "mail server unavailable: {}", e),
+        }
+    }
 }
@@ -0,0 +1,113 @@
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use super::{Email, EmailMessage};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboxEmailStatus {
+    Pending,
+    Sent,
+    // Rejected by the mail server, or out of attempts
+    Failed,
+}
+
+impl OutboxEmailStatus {
+    pub fn parse(status: &str) -> Result<Self, String> {
+        match status {
+            "pending" => Ok(OutboxEmailStatus::Pending),
+            "sent" => Ok(OutboxEmailStatus::Sent),
+            "failed" => Ok(OutboxEmailStatus::Failed),
+            _ => Err(format!("Unknown outbox email status: {}", status)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutboxEmailStatus::Pending => "pending",
+            OutboxEmailStatus::Sent => "sent",
+            OutboxEmailStatus::Failed => "failed",
+        }
+    }
+}
+
+/// An email waiting in the outbox, or the record of one that left it. The
+/// message is rendered when it's queued, so every attempt sends the same thing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OutboxEmail {
+    pub id: Uuid,
+    // Queuing another email with the same key does nothing, so a request that
+    // is retried doesn't send the email twice
+    pub idempotency_key: String,
+    pub recipient: Email,
+    // Emptied once the email has left the outbox, as it may hold codes and
+    // reset tokens
+    pub message: EmailMessage,
+    pub status: OutboxEmailStatus,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+impl OutboxEmail {
+    pub fn new(idempotency_key: String, recipient: &Email, message: EmailMessage) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            idempotency_key,
+            recipient: recipient.clone(),
+            message,
+            status: OutboxEmailStatus::Pending,
+            attempts: 0,
+            next_attempt_at: Utc::now(),
+            last_error: None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait EmailOutboxStore {
+    // Queuing an email whose idempotency key was queued before is not an error,
+    // and leaves the existing email as it is
+    async fn enqueue(&mut self, email: OutboxEmail) -> Result<(), EmailOutboxStoreError>;
+
+    // Pending emails due at `now`. They aren't handed out again until `lease`
+    // has passed, so several workers can share one outbox.
+    async fn claim_due(
+        &mut self,
+        now: DateTime<Utc>,
+        lease: Duration,
+        limit: usize,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError>;
+    async fn mark_sent(&mut self, id: Uuid) -> Result<(), EmailOutboxStoreError>;
+    // `next_attempt_at` of `None` marks the email as failed for good
+    async fn mark_failed(
+        &mut self,
+        id: Uuid,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), EmailOutboxStoreError>;
+    async fn get_email(&self, idempotency_key: &str) -> Result<OutboxEmail, EmailOutboxStoreError>;
+    async fn get_failed(&self) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EmailOutboxStoreError {
+    EmailNotFound,
+    UnexpectedError,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_round_trips() {
+        for status in [
+            OutboxEmailStatus::Pending,
+            OutboxEmailStatus::Sent,
+            OutboxEmailStatus::Failed,
+        ] {
+            assert_eq!(OutboxEmailStatus::parse(status.as_str()), Ok(status));
+        }
+        assert!(OutboxEmailStatus::parse("dead").is_err());
+    }
+}
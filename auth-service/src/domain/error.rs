@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 pub enum AuthAPIError {
     UserAlreadyExists,
     UserNotFound,
@@ -5,5 +7,50 @@ pub enum AuthAPIError {
     InvalidCredentials,   // Bad password, short password, etc.
     IncorrectCredentials, // Bad password, short password, etc.
     InvalidToken,
+    Forbidden, // authenticated, but lacking a required role or permission
+    RoleNotFound,
+    AccountLocked, // too many failed logins, see `LockoutPolicy`
+    AccountDisabled,
+    InvalidTenant, // bad id or settings
+    TenantNotFound,
+    TenantAlreadyExists, // the id, or one of the hosts, is taken
+    InvalidWebhook,      // bad URL or unknown event type
+    WebhookNotFound,
+    InvalidApiKey, // bad name, scope or lifetime
+    ApiKeyNotFound,
+    InvalidPhoneNumber, // unparseable, or refused by the SMS provider
+    PhoneNotVerified,   // SMS or voice 2FA without a verified phone number
+    InvalidTwoFAChannel,
+    TooManyRequests(Duration), // how long until the client may retry
     UnexpectedError,
 }
+
+impl AuthAPIError {
+    // For metrics, so only ever a fixed set of values
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuthAPIError::UserAlreadyExists => "user_already_exists",
+            AuthAPIError::UserNotFound => "user_not_found",
+            AuthAPIError::InvalidEmail => "invalid_email",
+            AuthAPIError::InvalidCredentials => "invalid_credentials",
+            AuthAPIError::IncorrectCredentials => "incorrect_credentials",
+            AuthAPIError::InvalidToken => "invalid_token",
+            AuthAPIError::Forbidden => "forbidden",
+            AuthAPIError::RoleNotFound => "role_not_found",
+            AuthAPIError::AccountLocked => "account_locked",
+            AuthAPIError::AccountDisabled => "account_disabled",
+            AuthAPIError::InvalidTenant => "invalid_tenant",
+            AuthAPIError::TenantNotFound => "tenant_not_found",
+            AuthAPIError::TenantAlreadyExists => "tenant_already_exists",
+            AuthAPIError::InvalidWebhook => "invalid_webhook",
+            AuthAPIError::WebhookNotFound => "webhook_not_found",
+            AuthAPIError::InvalidApiKey => "invalid_api_key",
+            AuthAPIError::ApiKeyNotFound => "api_key_not_found",
+            AuthAPIError::InvalidPhoneNumber => "invalid_phone_number",
+            AuthAPIError::PhoneNotVerified => "phone_not_verified",
+            AuthAPIError::InvalidTwoFAChannel => "invalid_two_fa_channel",
+            AuthAPIError::TooManyRequests(_) => "too_many_requests",
+            AuthAPIError::UnexpectedError => "unexpected_error",
+        }
+    }
+}
@@ -0,0 +1,187 @@
+use chrono::{DateTime, Duration, Utc};
+
+use super::UserStoreError;
+use crate::config::LockoutConfig;
+
+// Consecutive failed logins for one account, as recorded by the user stores.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LockoutState {
+    pub failed_attempts: u32,
+    pub last_failed_at: Option<DateTime<Utc>>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+impl LockoutState {
+    pub fn is_locked(&self, now: DateTime<Utc>) -> bool {
+        self.locked_until.is_some_and(|until| until > now)
+    }
+}
+
+/// After `backoff_threshold` consecutive failures each further attempt has to
+/// wait exponentially longer (starting at `backoff_base`), and after
+/// `lock_threshold` failures the account is locked for `lock_duration`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LockoutPolicy {
+    pub backoff_threshold: u32,
+    pub backoff_base: Duration,
+    pub lock_threshold: u32,
+    pub lock_duration: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            backoff_threshold: 3,
+            backoff_base: Duration::seconds(1),
+            lock_threshold: 10,
+            lock_duration: Duration::minutes(15),
+        }
+    }
+}
+
+impl LockoutPolicy {
+    /// The policy the `[lockout]` config section sets, with the default
+    /// backoff base.
+    pub fn from_config(config: &LockoutConfig) -> Self {
+        Self {
+            backoff_threshold: config.backoff_threshold,
+            backoff_base: Self::default().backoff_base,
+            lock_threshold: config.threshold,
+            lock_duration: Duration::seconds(config.duration_seconds),
+        }
+    }
+
+    /// Whether a login attempt may be made at all, before checking the password.
+    pub fn check(&self, state: &LockoutState, now: DateTime<Utc>) -> Result<(), UserStoreError> {
+        if let Some(until) = state.locked_until.filter(|until| *until > now) {
+            return Err(UserStoreError::AccountLocked {
+                until,
+                just_locked: false,
+            });
+        }
+
+        if let Some(until) = self.backoff_until(state).filter(|until| *until > now) {
+            return Err(UserStoreError::LoginThrottled { until });
+        }
+
+        Ok(())
+    }
+
+    /// The state after one more failed attempt at `now`.
+    pub fn register_failure(&self, state: &LockoutState, now: DateTime<Utc>) -> LockoutState {
+        let failed_attempts = state.failed_attempts.saturating_add(1);
+
+        let locked_until = if failed_attempts >= self.lock_threshold {
+            Some(now + self.lock_duration)
+        } else {
+            None
+        };
+
+        LockoutState {
+            failed_attempts,
+            last_failed_at: Some(now),
+            locked_until,
+        }
+    }
+
+    /// The error to report for a failed attempt that resulted in `state`.
+    pub fn failure_error(&self, state: &LockoutState, now: DateTime<Utc>) -> UserStoreError {
+        match state.locked_until {
+            Some(until) if until > now => UserStoreError::AccountLocked {
+                until,
+                just_locked: true,
+            },
+            _ => UserStoreError::InvalidCredentials,
+        }
+    }
+
+    fn backoff_until(&self, state: &LockoutState) -> Option<DateTime<Utc>> {
+        if state.failed_attempts < self.backoff_threshold {
+            return None;
+        }
+
+        // 1x, 2x, 4x, ... the base delay, never longer than a full lock
+        let exponent = (state.failed_attempts - self.backoff_threshold).min(30);
+        let delay = self
+            .backoff_base
+            .checked_mul(2i32.saturating_pow(exponent))
+            .unwrap_or(self.lock_duration)
+            .min(self.lock_duration);
+
+        state
+            .last_failed_at
+            .map(|last_failed_at| last_failed_at + delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_after_failures(
+        policy: &LockoutPolicy,
+        failures: u32,
+        now: DateTime<Utc>,
+    ) -> LockoutState {
+        (0..failures).fold(LockoutState::default(), |state, _| {
+            policy.register_failure(&state, now)
+        })
+    }
+
+    #[test]
+    fn test_no_backoff_below_threshold() {
+        let policy = LockoutPolicy::default();
+        let now = Utc::now();
+        let state = state_after_failures(&policy, policy.backoff_threshold - 1, now);
+
+        assert_eq!(policy.check(&state, now), Ok(()));
+    }
+
+    #[test]
+    fn test_backoff_grows_exponentially() {
+        let policy = LockoutPolicy::default();
+        let now = Utc::now();
+
+        let state = state_after_failures(&policy, policy.backoff_threshold, now);
+        assert_eq!(
+            policy.check(&state, now),
+            Err(UserStoreError::LoginThrottled {
+                until: now + Duration::seconds(1)
+            })
+        );
+        assert_eq!(policy.check(&state, now + Duration::seconds(1)), Ok(()));
+
+        let state = state_after_failures(&policy, policy.backoff_threshold + 2, now);
+        assert_eq!(
+            policy.check(&state, now),
+            Err(UserStoreError::LoginThrottled {
+                until: now + Duration::seconds(4)
+            })
+        );
+    }
+
+    #[test]
+    fn test_account_is_locked_at_threshold() {
+        let policy = LockoutPolicy::default();
+        let now = Utc::now();
+
+        let almost = state_after_failures(&policy, policy.lock_threshold - 1, now);
+        let locked = policy.register_failure(&almost, now);
+
+        assert_eq!(
+            policy.failure_error(&locked, now),
+            UserStoreError::AccountLocked {
+                until: now + policy.lock_duration,
+                just_locked: true
+            }
+        );
+        assert_eq!(
+            policy.check(&locked, now),
+            Err(UserStoreError::AccountLocked {
+                until: now + policy.lock_duration,
+                just_locked: false
+            })
+        );
+        assert_eq!(policy.check(&locked, now + policy.lock_duration), Ok(()));
+    }
+}
@@ -1,13 +1,41 @@
+pub mod api_key;
+pub mod audit;
+pub mod audit_chain;
 pub mod data_stores;
 pub mod email;
 pub mod email_client;
+pub mod email_outbox;
 pub mod error;
+pub mod lockout;
 pub mod password;
+pub mod password_hash;
+pub mod password_hasher;
+pub mod phone_number;
+pub mod rate_limiter;
+pub mod retry_policy;
+pub mod role;
+pub mod sms_client;
+pub mod tenant;
 pub mod user;
+pub mod webhook;
 
+pub use api_key::*;
+pub use audit::*;
+pub use audit_chain::*;
 pub use data_stores::*;
 pub use email::*;
 pub use email_client::*;
+pub use email_outbox::*;
 pub use error::*;
+pub use lockout::*;
 pub use password::*;
+pub use password_hash::*;
+pub use password_hasher::*;
+pub use phone_number::*;
+pub use rate_limiter::*;
+pub use retry_policy::*;
+pub use role::*;
+pub use sms_client::*;
+pub use tenant::*;
 pub use user::*;
+pub use webhook::*;
@@ -1,6 +1,10 @@
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+use crate::utils::telemetry::REDACTED;
+
+#[derive(Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Password(String);
 
 impl Password {
@@ -23,6 +27,15 @@ impl AsRef<str> for Password {
     }
 }
 
+// Never logged, see `utils::telemetry`
+impl fmt::Debug for Password {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Password")
+            .field(&format_args!("{}", REDACTED))
+            .finish()
+    }
+}
+
 #[cfg(test)]
 
 mod tests {
@@ -48,4 +61,10 @@ mod tests {
         let password = "1234567".to_owned();
         assert!(Password::parse(password).is_err());
     }
+
+    #[test]
+    fn debug_output_is_redacted() {
+        let password = Password::parse("hunter2hunter2".to_owned()).unwrap();
+        assert_eq!(format!("{:?}", password), "Password([redacted])");
+    }
 }
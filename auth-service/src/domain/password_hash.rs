@@ -0,0 +1,56 @@
+use std::fmt;
+
+use argon2::password_hash::PasswordHash as PhcString;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::telemetry::REDACTED;
+
+// A password hash in PHC string format, e.g. `$argon2id$v=19$m=19456,t=2,p=1$...`
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PasswordHash(String);
+
+impl PasswordHash {
+    pub fn parse(s: String) -> Result<Self, String> {
+        match PhcString::new(&s) {
+            Ok(_) => Ok(PasswordHash(s)),
+            Err(_) => Err("Failed to parse string to a PasswordHash type".to_owned()),
+        }
+    }
+}
+
+impl AsRef<str> for PasswordHash {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+// Never logged, see `utils::telemetry`
+impl fmt::Debug for PasswordHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("PasswordHash")
+            .field(&format_args!("{}", REDACTED))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plaintext_is_rejected() {
+        let result = PasswordHash::parse("password123".to_owned());
+
+        assert_eq!(
+            result,
+            Err("Failed to parse string to a PasswordHash type".to_owned())
+        );
+    }
+
+    #[test]
+    fn phc_string_is_accepted() {
+        let hash = "$argon2id$v=19$m=64,t=1,p=1$c29tZXNhbHQ$MDEyMzQ1Njc4OWFiY2RlZg".to_owned();
+
+        assert_eq!(PasswordHash::parse(hash.clone()).unwrap().as_ref(), hash);
+    }
+}
@@ -0,0 +1,29 @@
+use super::{Password, PasswordHash};
+
+// This trait represents the interface that all concrete password hashers should implement.
+// Stores only ever see the resulting `PasswordHash`, never the plaintext `Password`.
+#[async_trait::async_trait]
+pub trait PasswordHasher {
+    async fn hash_password(&self, password: &Password)
+        -> Result<PasswordHash, PasswordHasherError>;
+
+    async fn verify_password(
+        &self,
+        password: &Password,
+        password_hash: &PasswordHash,
+    ) -> Result<(), PasswordHasherError>;
+
+    // Do the same work as `verify_password` against a hash that never matches,
+    // so logins for unknown emails take as long as those for real users.
+    async fn verify_dummy_password(&self, password: &Password);
+
+    // Whether a hash was made with different (e.g. older, weaker) settings than
+    // the current ones, and should be replaced on the next successful login.
+    fn needs_rehash(&self, password_hash: &PasswordHash) -> bool;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum PasswordHasherError {
+    IncorrectPassword,
+    UnexpectedError,
+}
@@ -0,0 +1,106 @@
+use serde::{Deserialize, Serialize};
+
+// Phone numbers are kept in E.164 form, e.g. `+447700900123`, which is what
+// SMS providers expect. Spaces, dashes, dots and brackets are dropped on parse.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub struct PhoneNumber(String);
+
+impl PhoneNumber {
+    pub fn parse(phone: String) -> Result<Self, String> {
+        let invalid = || format!("Invalid phone number: {}", phone);
+
+        let digits: String = phone
+            .trim()
+            .strip_prefix('+')
+            .ok_or_else(invalid)?
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '.' | '(' | ')'))
+            .collect();
+
+        // Country codes never start with 0, and E.164 allows at most 15 digits
+        let valid = (8..=15).contains(&digits.len())
+            && digits.chars().all(|c| c.is_ascii_digit())
+            && !digits.starts_with('0');
+        if valid {
+            Ok(PhoneNumber(format!("+{}", digits)))
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+impl AsRef<str> for PhoneNumber {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// How a user receives their 2FA codes. Anything but email needs a verified phone number.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TwoFAChannel {
+    #[default]
+    Email,
+    Sms,
+    Voice,
+}
+
+impl TwoFAChannel {
+    pub fn parse(channel: &str) -> Result<Self, String> {
+        match channel {
+            "email" => Ok(TwoFAChannel::Email),
+            "sms" => Ok(TwoFAChannel::Sms),
+            "voice" => Ok(TwoFAChannel::Voice),
+            _ => Err(format!("Unknown 2FA channel: {}", channel)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TwoFAChannel::Email => "email",
+            TwoFAChannel::Sms => "sms",
+            TwoFAChannel::Voice => "voice",
+        }
+    }
+
+    pub fn requires_phone(&self) -> bool {
+        *self != TwoFAChannel::Email
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phone_numbers_are_normalized_to_e164() {
+        for phone in ["+447700900123", " +44 7700 900123 ", "+44 (7700) 900-123"] {
+            assert_eq!(
+                PhoneNumber::parse(phone.to_owned()).unwrap().as_ref(),
+                "+447700900123"
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid_phone_numbers_are_rejected() {
+        for phone in [
+            "",
+            "07700900123",    // no country code
+            "+0447700900123", // country codes don't start with 0
+            "+4477",
+            "+4477009001234567",
+            "+44 7700 9OO123",
+        ] {
+            assert!(PhoneNumber::parse(phone.to_owned()).is_err(), "{}", phone);
+        }
+    }
+
+    #[test]
+    fn test_channel_round_trips() {
+        for channel in [TwoFAChannel::Email, TwoFAChannel::Sms, TwoFAChannel::Voice] {
+            assert_eq!(TwoFAChannel::parse(channel.as_str()), Ok(channel));
+        }
+        assert!(TwoFAChannel::parse("pigeon").is_err());
+    }
+}
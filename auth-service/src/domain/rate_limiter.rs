@@ -0,0 +1,88 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+// At most `max_requests` requests per sliding `window`. Deserialized from the
+// same `<max requests>/<window in seconds>` form `parse` takes.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct RateLimitPolicy {
+    pub max_requests: u32,
+    pub window: Duration,
+}
+
+impl RateLimitPolicy {
+    pub fn new(max_requests: u32, window: Duration) -> Self {
+        Self {
+            max_requests,
+            window,
+        }
+    }
+
+    /// Parse a policy written as `<max requests>/<window in seconds>`, e.g. `10/60`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("Invalid rate limit {:?}, expected <requests>/<seconds>", s);
+
+        let (max_requests, window) = s.trim().split_once('/').ok_or_else(invalid)?;
+        let max_requests = max_requests.trim().parse::<u32>().map_err(|_| invalid())?;
+        let window = window.trim().parse::<u64>().map_err(|_| invalid())?;
+
+        if max_requests == 0 || window == 0 {
+            return Err(invalid());
+        }
+
+        Ok(Self::new(max_requests, Duration::from_secs(window)))
+    }
+}
+
+impl TryFrom<String> for RateLimitPolicy {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(&s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RateLimitDecision {
+    Allowed,
+    // How long until the oldest request in the window expires
+    Limited { retry_after: Duration },
+}
+
+// This trait represents the interface that all concrete rate limiters should implement.
+#[async_trait::async_trait]
+pub trait RateLimiter {
+    // Record a request against `key` and decide whether it is within `policy`.
+    // Rejected requests aren't recorded, so they don't extend the wait.
+    async fn check(
+        &mut self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateLimitDecision, RateLimiterError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RateLimiterError {
+    UnexpectedError,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_is_parsed() {
+        assert_eq!(
+            RateLimitPolicy::parse(" 10 / 60 "),
+            Ok(RateLimitPolicy::new(10, Duration::from_secs(60)))
+        );
+    }
+
+    #[test]
+    fn malformed_policies_are_rejected() {
+        for s in ["", "10", "10/", "/60", "0/60", "10/0", "ten/60"] {
+            assert!(RateLimitPolicy::parse(s).is_err(), "accepted {:?}", s);
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use chrono::{DateTime, Duration, Utc};
+
+/// Failed webhook deliveries and emails are retried after `base_delay`,
+/// doubling each time up to `max_delay`, and given up on after `max_attempts`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 10,
+            base_delay: Duration::seconds(30),
+            max_delay: Duration::hours(6),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// When to try again after the `attempts`th failed attempt, or `None` to give up.
+    pub fn next_attempt_at(&self, attempts: u32, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+
+        let exponent = attempts.saturating_sub(1).min(30);
+        let delay = self
+            .base_delay
+            .checked_mul(2i32.saturating_pow(exponent))
+            .unwrap_or(self.max_delay)
+            .min(self.max_delay);
+
+        Some(now + delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay_doubles_until_capped_then_gives_up() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::seconds(10),
+            max_delay: Duration::seconds(30),
+        };
+        let now = Utc::now();
+
+        assert_eq!(
+            policy.next_attempt_at(1, now),
+            Some(now + Duration::seconds(10))
+        );
+        assert_eq!(
+            policy.next_attempt_at(2, now),
+            Some(now + Duration::seconds(20))
+        );
+        assert_eq!(
+            policy.next_attempt_at(3, now),
+            Some(now + Duration::seconds(30))
+        );
+        assert_eq!(
+            policy.next_attempt_at(4, now),
+            Some(now + Duration::seconds(30))
+        );
+        assert_eq!(policy.next_attempt_at(5, now), None);
+    }
+}
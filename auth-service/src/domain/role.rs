@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+
+pub const ADMIN_ROLE: &str = "admin";
+
+/// The roles every deployment starts with and what they may do. The roles
+/// migration seeds the same ones, so both user stores agree.
+pub const DEFAULT_ROLES: &[(&str, &[&str])] = &[(ADMIN_ROLE, &["users:read", "users:write"])];
+
+// Names are kept to a small alphabet so they're safe to put in tokens and logs
+fn is_valid_name(name: &str, extra: &[char]) -> bool {
+    (1..=64).contains(&name.len())
+        && name.chars().all(|c| {
+            c.is_ascii_lowercase()
+                || c.is_ascii_digit()
+                || c == '_'
+                || c == '-'
+                || extra.contains(&c)
+        })
+}
+
+/// A named set of permissions, e.g. `admin`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Role(String);
+
+impl Role {
+    pub fn parse(role: String) -> Result<Self, String> {
+        if is_valid_name(&role, &[]) {
+            Ok(Role(role))
+        } else {
+            Err(format!("Invalid role: {}", role))
+        }
+    }
+}
+
+impl AsRef<str> for Role {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Something a role allows, written `<resource>:<action>`, e.g. `users:write`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Permission(String);
+
+impl Permission {
+    pub fn parse(permission: String) -> Result<Self, String> {
+        if is_valid_name(&permission, &[':', '.']) {
+            Ok(Permission(permission))
+        } else {
+            Err(format!("Invalid permission: {}", permission))
+        }
+    }
+}
+
+impl AsRef<str> for Permission {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_role() {
+        assert!(Role::parse("admin".to_owned()).is_ok());
+        assert!(Role::parse("billing-support_2".to_owned()).is_ok());
+        assert!(Role::parse("".to_owned()).is_err());
+        assert!(Role::parse("Admin".to_owned()).is_err());
+        assert!(Role::parse("users:write".to_owned()).is_err());
+        assert!(Role::parse("a".repeat(65)).is_err());
+    }
+
+    #[test]
+    fn test_parse_permission() {
+        assert!(Permission::parse("users:write".to_owned()).is_ok());
+        assert!(Permission::parse("reports.daily:read".to_owned()).is_ok());
+        assert!(Permission::parse("users write".to_owned()).is_err());
+    }
+
+    #[test]
+    fn test_default_roles_are_valid() {
+        for (role, permissions) in DEFAULT_ROLES {
+            assert!(Role::parse(role.to_string()).is_ok());
+            for permission in *permissions {
+                assert!(Permission::parse(permission.to_string()).is_ok());
+            }
+        }
+    }
+}
@@ -0,0 +1,31 @@
+use std::fmt;
+
+use super::PhoneNumber;
+
+// Like `EmailClient`, for codes sent to a phone instead
+#[async_trait::async_trait]
+pub trait SmsClient {
+    async fn send_sms(&self, recipient: &PhoneNumber, text: &str) -> Result<(), SmsClientError>;
+    // Calls the number and reads `text` out with text-to-speech
+    async fn send_voice(&self, recipient: &PhoneNumber, text: &str) -> Result<(), SmsClientError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SmsClientError {
+    // The message couldn't be built, e.g. the sender is invalid
+    InvalidMessage(String),
+    // The provider refused it for good, e.g. the number can't receive texts
+    Rejected(String),
+    // The provider couldn't be reached, timed out, or failed temporarily
+    Unavailable(String),
+}
+
+impl fmt::Display for SmsClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SmsClientError::InvalidMessage(e) => write!(f, "invalid message: {}", e),
+            SmsClientError::Rejected(e) => write!(f, "rejected by the SMS provider: {}", e),
+            SmsClientError::Unavailable(e) => write!(f, "SMS provider unavailable: {}", e),
+        }
+    }
+}
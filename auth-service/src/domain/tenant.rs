@@ -0,0 +1,160 @@
+use super::Password;
+
+/// The tenant requests belong to when neither the host nor the path names one.
+/// The tenants migration creates it and existing users are its members.
+pub const DEFAULT_TENANT_ID: &str = "default";
+
+/// An organization's slug, e.g. `acme`. Used in `/t/{tenant}/...` paths and
+/// in tokens, so it's kept to the same small alphabet as role names.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TenantId(String);
+
+impl TenantId {
+    pub fn parse(id: String) -> Result<Self, String> {
+        let valid = (1..=64).contains(&id.len())
+            && id
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+
+        if valid {
+            Ok(TenantId(id))
+        } else {
+            Err(format!("Invalid tenant id: {}", id))
+        }
+    }
+}
+
+impl Default for TenantId {
+    fn default() -> Self {
+        TenantId(DEFAULT_TENANT_ID.to_owned())
+    }
+}
+
+impl AsRef<str> for TenantId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Policies an organization can set for its own users.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantSettings {
+    // Never below the 8 characters every password needs
+    pub min_password_length: usize,
+    // Ask every member for a 2FA code, whatever their own setting
+    pub requires_2fa: bool,
+}
+
+impl Default for TenantSettings {
+    fn default() -> Self {
+        Self {
+            min_password_length: 8,
+            requires_2fa: false,
+        }
+    }
+}
+
+impl TenantSettings {
+    pub fn check_password(&self, password: &Password) -> Result<(), String> {
+        if password.as_ref().chars().count() < self.min_password_length {
+            return Err(format!(
+                "Password must be at least {} characters",
+                self.min_password_length
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tenant {
+    pub id: TenantId,
+    pub name: String,
+    // Hosts that resolve to this tenant, lowercase and without a port
+    pub hosts: Vec<String>,
+    pub settings: TenantSettings,
+}
+
+impl Tenant {
+    pub fn new(id: TenantId, name: &str) -> Self {
+        Self {
+            id,
+            name: name.to_owned(),
+            hosts: vec![],
+            settings: TenantSettings::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+pub trait TenantStore {
+    async fn add_tenant(&mut self, tenant: Tenant) -> Result<(), TenantStoreError>;
+    async fn get_tenant(&self, id: &TenantId) -> Result<Tenant, TenantStoreError>;
+    // `host` is compared ignoring case and any port
+    async fn get_tenant_by_host(&self, host: &str) -> Result<Tenant, TenantStoreError>;
+    async fn get_tenants(&self) -> Result<Vec<Tenant>, TenantStoreError>;
+    async fn update_settings(
+        &mut self,
+        id: &TenantId,
+        settings: TenantSettings,
+    ) -> Result<(), TenantStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TenantStoreError {
+    TenantAlreadyExists,
+    TenantNotFound,
+    // Another tenant already answers on one of the hosts
+    HostTaken,
+    UnexpectedError,
+}
+
+/// `host` as stored in `Tenant::hosts`: lowercase, without a port.
+pub fn normalize_host(host: &str) -> String {
+    let host = host.trim().to_lowercase();
+    // IPv6 literals keep their brackets, e.g. `[::1]:3000`
+    match host.rsplit_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) && !name.ends_with(':') => {
+            name.to_owned()
+        }
+        _ => host,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tenant_id() {
+        assert!(TenantId::parse("acme".to_owned()).is_ok());
+        assert!(TenantId::parse("acme-2".to_owned()).is_ok());
+        assert!(TenantId::parse("".to_owned()).is_err());
+        assert!(TenantId::parse("Acme".to_owned()).is_err());
+        assert!(TenantId::parse("acme/admin".to_owned()).is_err());
+        assert_eq!(TenantId::default().as_ref(), DEFAULT_TENANT_ID);
+    }
+
+    #[test]
+    fn test_check_password() {
+        let settings = TenantSettings {
+            min_password_length: 12,
+            requires_2fa: false,
+        };
+        let short = Password::parse("password123".to_owned()).unwrap();
+        let long = Password::parse("password123456".to_owned()).unwrap();
+
+        assert!(settings.check_password(&short).is_err());
+        assert!(settings.check_password(&long).is_ok());
+        assert!(TenantSettings::default().check_password(&short).is_ok());
+    }
+
+    #[test]
+    fn test_normalize_host() {
+        assert_eq!(normalize_host("Acme.Example.com"), "acme.example.com");
+        assert_eq!(normalize_host("acme.example.com:3000"), "acme.example.com");
+        assert_eq!(normalize_host("[::1]:3000"), "[::1]");
+        assert_eq!(normalize_host("[::1]"), "[::1]");
+    }
+}
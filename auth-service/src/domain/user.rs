@@ -1,19 +1,50 @@
-use super::{Email, Password};
+use super::{Email, PasswordHash, PhoneNumber, TwoFAChannel};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct User {
     pub email: Email,
-    pub password: Password,
+    pub password_hash: PasswordHash,
     pub requires_2fa: bool,
+    // Disabled users can't log in, see `UserStore::set_disabled`
+    #[serde(default)]
+    pub disabled: bool,
+    // Only ever a number the user has proven they receive codes on
+    #[serde(default)]
+    pub phone: Option<PhoneNumber>,
+    // Where 2FA codes are sent; see `UserStore::set_two_fa_channel`
+    #[serde(default)]
+    pub two_fa_channel: TwoFAChannel,
+    // Signed up but hasn't confirmed the address yet, see `UserStore::add_pending_user`
+    #[serde(default)]
+    pub pending: bool,
 }
 
 impl User {
-    pub fn new(email: Email, password: Password, requires_2fa: bool) -> Self {
+    pub fn new(email: Email, password_hash: PasswordHash, requires_2fa: bool) -> Self {
         Self {
             email,
-            password,
+            password_hash,
             requires_2fa,
+            disabled: false,
+            phone: None,
+            two_fa_channel: TwoFAChannel::Email,
+            pending: false,
         }
     }
 }
+
+/// A page of users for the admin API, optionally only those whose email
+/// contains `search` (ignoring case).
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserListQuery {
+    pub search: Option<String>,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserPage {
+    pub users: Vec<User>, // ordered by email
+    pub total: usize,     // matching users on all pages
+}
@@ -0,0 +1,193 @@
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use uuid::Uuid;
+
+use super::{Email, TenantId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WebhookEventType {
+    UserCreated,
+    TwoFAEnabled,
+    AccountLocked,
+    PasswordChanged,
+}
+
+impl WebhookEventType {
+    pub const ALL: [WebhookEventType; 4] = [
+        WebhookEventType::UserCreated,
+        WebhookEventType::TwoFAEnabled,
+        WebhookEventType::AccountLocked,
+        WebhookEventType::PasswordChanged,
+    ];
+
+    pub fn parse(event_type: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|t| t.as_str() == event_type)
+            .ok_or_else(|| format!("Unknown webhook event type: {}", event_type))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventType::UserCreated => "user.created",
+            WebhookEventType::TwoFAEnabled => "user.2fa_enabled",
+            WebhookEventType::AccountLocked => "user.locked",
+            WebhookEventType::PasswordChanged => "user.password_changed",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookEvent {
+    pub id: Uuid,
+    pub event_type: WebhookEventType,
+    pub occurred_at: DateTime<Utc>,
+    pub tenant: TenantId,
+    pub email: Email,
+}
+
+impl WebhookEvent {
+    pub fn new(tenant: &TenantId, event_type: WebhookEventType, email: &Email) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            event_type,
+            occurred_at: Utc::now(),
+            tenant: tenant.clone(),
+            email: email.clone(),
+        }
+    }
+
+    /// The JSON body sent to subscribers. Built once when the event is queued,
+    /// so every retry delivers exactly the same bytes.
+    pub fn payload(&self) -> String {
+        serde_json::json!({
+            "id": self.id.to_string(),
+            "type": self.event_type.as_str(),
+            "occurred_at": self.occurred_at.to_rfc3339_opts(SecondsFormat::Millis, true),
+            "tenant": self.tenant.as_ref(),
+            "data": {
+                "email": self.email.as_ref(),
+            },
+        })
+        .to_string()
+    }
+}
+
+/// Where to send events, and which ones. An empty `event_types` means all of them.
+/// A subscription only ever receives events from its own tenant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookSubscription {
+    pub id: Uuid,
+    pub tenant: TenantId,
+    pub url: String,
+    pub secret: String, // HMAC key shared with the receiver
+    pub event_types: Vec<WebhookEventType>,
+}
+
+impl WebhookSubscription {
+    pub fn wants(&self, event: &WebhookEvent) -> bool {
+        self.tenant == event.tenant
+            && (self.event_types.is_empty() || self.event_types.contains(&event.event_type))
+    }
+}
+
+/// One event on its way to one subscription.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    pub url: String,
+    pub secret: String,
+    pub event_type: WebhookEventType,
+    pub payload: String,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+}
+
+#[async_trait::async_trait]
+pub trait WebhookStore {
+    async fn add_subscription(
+        &mut self,
+        subscription: WebhookSubscription,
+    ) -> Result<(), WebhookStoreError>;
+    async fn get_subscriptions(&self) -> Result<Vec<WebhookSubscription>, WebhookStoreError>;
+    async fn remove_subscription(&mut self, id: Uuid) -> Result<(), WebhookStoreError>;
+
+    // Queue a delivery of `event` for every subscription that wants it
+    async fn enqueue_event(&mut self, event: &WebhookEvent) -> Result<(), WebhookStoreError>;
+
+    // Deliveries due at `now`. They aren't handed out again until `lease` has
+    // passed, so several dispatchers can share one queue.
+    async fn claim_due_deliveries(
+        &mut self,
+        now: DateTime<Utc>,
+        lease: Duration,
+        limit: usize,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError>;
+    async fn mark_delivered(&mut self, delivery_id: Uuid) -> Result<(), WebhookStoreError>;
+    // `next_attempt_at` of `None` moves the delivery to the dead-letter list
+    async fn mark_failed(
+        &mut self,
+        delivery_id: Uuid,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), WebhookStoreError>;
+    async fn get_dead_letters(&self) -> Result<Vec<WebhookDelivery>, WebhookStoreError>;
+}
+
+#[derive(Debug, PartialEq)]
+pub enum WebhookStoreError {
+    SubscriptionNotFound,
+    DeliveryNotFound,
+    UnexpectedError,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_type_round_trips() {
+        for event_type in WebhookEventType::ALL {
+            assert_eq!(WebhookEventType::parse(event_type.as_str()), Ok(event_type));
+        }
+        assert!(WebhookEventType::parse("user.deleted").is_err());
+    }
+
+    #[test]
+    fn test_subscription_without_filter_wants_everything() {
+        let tenant = TenantId::default();
+        let email = Email::parse("user@example.com".to_owned()).unwrap();
+        let locked = WebhookEvent::new(&tenant, WebhookEventType::AccountLocked, &email);
+        let created = WebhookEvent::new(&tenant, WebhookEventType::UserCreated, &email);
+
+        let mut subscription = WebhookSubscription {
+            id: Uuid::new_v4(),
+            tenant: tenant.clone(),
+            url: "http://localhost/hook".to_owned(),
+            secret: "secret".to_owned(),
+            event_types: vec![],
+        };
+        assert!(subscription.wants(&locked));
+
+        subscription.event_types = vec![WebhookEventType::UserCreated];
+        assert!(subscription.wants(&created));
+        assert!(!subscription.wants(&locked));
+    }
+
+    #[test]
+    fn test_subscription_ignores_other_tenants() {
+        let email = Email::parse("user@example.com".to_owned()).unwrap();
+        let other_tenant = TenantId::parse("acme".to_owned()).unwrap();
+        let event = WebhookEvent::new(&other_tenant, WebhookEventType::UserCreated, &email);
+
+        let subscription = WebhookSubscription {
+            id: Uuid::new_v4(),
+            tenant: TenantId::default(),
+            url: "http://localhost/hook".to_owned(),
+            secret: "secret".to_owned(),
+            event_types: vec![],
+        };
+        assert!(!subscription.wants(&event));
+    }
+}
@@ -1,23 +1,50 @@
 #![allow(unused_imports)]
 use redis::Client;
 use redis::RedisResult;
+use sqlx::migrate::Migrator;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::error::Error;
+use std::net::SocketAddr;
+use std::time::Duration;
 
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
+    middleware,
     response::{Html, IntoResponse, Response},
-    routing::{get, post},
+    routing::{delete, get, post, put},
     serve::Serve,
     Json, Router,
 };
 
-use routes::{hello, login, logout, signup, verify_2fa, verify_token};
+use config::{DatabaseConfig, RedisConfig};
+use routes::{
+    add_tenant_member, assign_role, confirm_signup, create_api_key, create_tenant, create_webhook,
+    delete_user, delete_webhook, disable_user, enable_user, get_user, hello, impersonate_user,
+    list_api_keys, list_tenants, list_users, list_webhook_dead_letters, list_webhooks, live, login,
+    logout, metrics, ready, remove_tenant_member, reset_password, reset_user_password,
+    revoke_api_key, revoke_role, revoke_user_sessions, set_two_fa_channel, set_user_2fa, signup,
+    start_phone_verification, unlock_user, update_tenant_settings, verify_2fa, verify_phone,
+    verify_token,
+};
 use serde::{Deserialize, Serialize};
-use tower_http::services::ServeDir;
+use tower::{Layer, ServiceBuilder};
+use tower_http::{
+    request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+    services::ServeDir,
+    trace::{DefaultOnResponse, TraceLayer},
+};
+use tracing::Level;
+use utils::{
+    metrics::{track_requests, ErrorName},
+    shutdown::ShutdownHandle,
+    telemetry::make_request_span,
+    tenant::resolve_tenant,
+};
 
 pub mod app_state;
+pub mod config;
+pub use config::Config;
 pub mod domain;
 pub use domain::AuthAPIError;
 pub mod routes;
@@ -26,7 +53,10 @@ pub use routes::signup::SignupResponse; // publicly expose the SignupResponse st
 pub mod services;
 pub use services::data_stores::hashmap_user_store::HashmapUserStore;
 pub mod utils;
-pub use app_state::{AppState, BannedTokenStoreType, TwoFACodeStoreType, UserStoreType};
+pub use app_state::{
+    ApiKeyStoreType, AppState, AuditSinkType, BannedTokenStoreType, PasswordHasherType,
+    TenantStoreType, TwoFACodeStoreType, UserStoreType, WebhookStoreType,
+};
 pub use utils::constants::JWT_COOKIE_NAME;
 
 // this struct encapsulates our application-related logic
@@ -34,23 +64,97 @@ pub struct Application {
     pub address: String, // public address to allow tests to access it
     router: Router,
     listener: tokio::net::TcpListener,
+    shutdown: ShutdownHandle,
+    pre_drain_delay: Duration,
+    drain_timeout: Duration,
 }
 
 impl Application {
-    pub async fn build(app_state: AppState, address: &str) -> Result<Self, Box<dyn Error>> {
+    // Listens on `server.address` from the app state's config
+    pub async fn build(app_state: AppState) -> Result<Self, Box<dyn Error>> {
+        let address = app_state.config.server.address.clone();
+        let shutdown = app_state.shutdown.clone();
+        let pre_drain_delay = Duration::from_secs(app_state.config.server.pre_drain_delay_seconds);
+        let drain_timeout = Duration::from_secs(app_state.config.server.drain_timeout_seconds);
         let router = Router::new()
             .route("/", get(root))
             .route("/signup", post(signup))
+            .route("/signup/confirm", post(confirm_signup))
             .route("/login", post(login))
             .route("/logout", post(logout))
             .route("/verify-2fa", post(verify_2fa))
             .route("/verify_2fa", post(verify_2fa)) // Keep both for compatibility
             .route("/verify_token", post(verify_token))
+            .route("/reset-password", post(reset_password))
             .route("/hello", get(hello))
+            .route("/api-keys", post(create_api_key).get(list_api_keys))
+            .route("/api-keys/{id}", delete(revoke_api_key))
+            .route("/account/phone", post(start_phone_verification))
+            .route("/account/phone/verify", post(verify_phone))
+            .route("/account/2fa-channel", put(set_two_fa_channel))
+            .route("/admin/unlock", post(unlock_user))
+            .route("/admin/roles/assign", post(assign_role))
+            .route("/admin/roles/revoke", post(revoke_role))
+            .route("/admin/users", get(list_users))
+            .route("/admin/users/{email}", get(get_user).delete(delete_user))
+            .route("/admin/users/{email}/disable", post(disable_user))
+            .route("/admin/users/{email}/enable", post(enable_user))
+            .route("/admin/users/{email}/2fa", post(set_user_2fa))
+            .route(
+                "/admin/users/{email}/password-reset",
+                post(reset_user_password),
+            )
+            .route(
+                "/admin/users/{email}/revoke-sessions",
+                post(revoke_user_sessions),
+            )
+            .route("/admin/users/{email}/impersonate", post(impersonate_user))
+            .route("/admin/tenants", post(create_tenant).get(list_tenants))
+            .route("/admin/tenants/{id}/settings", put(update_tenant_settings))
+            .route("/admin/tenants/{id}/members", post(add_tenant_member))
+            .route(
+                "/admin/tenants/{id}/members/{email}",
+                delete(remove_tenant_member),
+            )
+            .route("/admin/webhooks", post(create_webhook).get(list_webhooks))
+            .route("/admin/webhooks/{id}", delete(delete_webhook))
+            .route(
+                "/admin/webhooks/dead-letters",
+                get(list_webhook_dead_letters),
+            )
+            .route_layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                track_requests,
+            ))
             .fallback_service(ServeDir::new("assets"))
-            .with_state(app_state);
+            .with_state(app_state.clone());
+
+        // Tenant resolution rewrites `/t/{tenant}/...` paths, so it has to wrap
+        // the whole router rather than run after routing as a layer would
+        let tenant_layer = middleware::from_fn_with_state(app_state.clone(), resolve_tenant);
+        // Health checks and metrics are answered without resolving a tenant,
+        // which needs the database, so liveness doesn't depend on it
+        let router = Router::new()
+            .route("/health/live", get(live))
+            .route("/health/ready", get(ready))
+            .route("/metrics", get(metrics))
+            .with_state(app_state)
+            .fallback_service(tenant_layer.layer(router));
 
-        let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+        // Every request gets an `X-Request-Id`, unless it came with one, which
+        // is logged with everything done for it and returned in the response
+        let router = router.layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+                .layer(
+                    TraceLayer::new_for_http()
+                        .make_span_with(make_request_span)
+                        .on_response(DefaultOnResponse::new().level(Level::INFO)),
+                )
+                .layer(PropagateRequestIdLayer::x_request_id()),
+        );
+
+        let listener = tokio::net::TcpListener::bind(&address).await?;
         let address = listener.local_addr()?.to_string();
 
         // create a new Application instance and return it
@@ -58,12 +162,48 @@ impl Application {
             router,
             address,
             listener,
+            shutdown,
+            pre_drain_delay,
+            drain_timeout,
         })
     }
 
+    // Triggering it stops `run`, e.g. on SIGTERM or at the end of a test
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    /// Serve until shutdown is triggered and, with readiness failing, for the
+    /// pre-drain delay after, then stop accepting connections and give the
+    /// requests in flight up to the drain timeout to finish.
     pub async fn run(self) -> Result<(), std::io::Error> {
-        println!("listening on http://{}", self.address);
-        axum::serve(self.listener, self.router).await
+        tracing::info!("listening on http://{}", self.address);
+        let shutdown = self.shutdown.clone();
+        let pre_drain_delay = self.pre_drain_delay;
+        let drain = async move {
+            shutdown.wait().await;
+            tokio::time::sleep(pre_drain_delay).await;
+            tracing::info!("Draining requests in flight");
+        };
+        // Connection info lets handlers see the client address for rate limiting
+        let server = axum::serve(
+            self.listener,
+            self.router
+                .into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(drain);
+
+        let drain_timeout = async {
+            self.shutdown.wait().await;
+            tokio::time::sleep(self.pre_drain_delay + self.drain_timeout).await;
+        };
+        tokio::select! {
+            result = server => result,
+            _ = drain_timeout => {
+                tracing::warn!("Requests still in flight after the drain timeout, dropping them");
+                Ok(())
+            }
+        }
     }
 }
 
@@ -79,34 +219,72 @@ pub struct ErrorResponse {
 
 impl IntoResponse for AuthAPIError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"), // Conflict = 409
-            AuthAPIError::UserNotFound => (StatusCode::NOT_FOUND, "User not found"),
-            AuthAPIError::InvalidEmail => (StatusCode::BAD_REQUEST, "Invalid email"),
-            AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
-            AuthAPIError::IncorrectCredentials => {
-                (StatusCode::UNAUTHORIZED, "Incorrect credentials")
-            }
-            AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid auth token"),
-            AuthAPIError::UnexpectedError => {
-                (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error")
-            }
-        };
+        // Tagged so `track_requests` can count errors by name
+        let name = ErrorName(self.name());
+        let mut response = error_response(self);
+        response.extensions_mut().insert(name);
+        response
+    }
+}
 
-        let body = Json(ErrorResponse {
-            error: error_message.to_string(),
-        });
+fn error_response(error: AuthAPIError) -> Response {
+    let (status, error_message) = match error {
+        AuthAPIError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"), // Conflict = 409
+        AuthAPIError::UserNotFound => (StatusCode::NOT_FOUND, "User not found"),
+        AuthAPIError::InvalidEmail => (StatusCode::BAD_REQUEST, "Invalid email"),
+        AuthAPIError::InvalidCredentials => (StatusCode::BAD_REQUEST, "Invalid credentials"),
+        AuthAPIError::IncorrectCredentials => (StatusCode::UNAUTHORIZED, "Incorrect credentials"),
+        AuthAPIError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid auth token"),
+        AuthAPIError::Forbidden => (StatusCode::FORBIDDEN, "Forbidden"),
+        AuthAPIError::RoleNotFound => (StatusCode::NOT_FOUND, "Role not found"),
+        AuthAPIError::AccountLocked => (StatusCode::LOCKED, "Account locked"),
+        AuthAPIError::AccountDisabled => (StatusCode::FORBIDDEN, "Account disabled"),
+        AuthAPIError::InvalidTenant => (StatusCode::BAD_REQUEST, "Invalid tenant"),
+        AuthAPIError::TenantNotFound => (StatusCode::NOT_FOUND, "Tenant not found"),
+        AuthAPIError::TenantAlreadyExists => (StatusCode::CONFLICT, "Tenant already exists"),
+        AuthAPIError::InvalidWebhook => (StatusCode::BAD_REQUEST, "Invalid webhook"),
+        AuthAPIError::WebhookNotFound => (StatusCode::NOT_FOUND, "Webhook not found"),
+        AuthAPIError::InvalidApiKey => (StatusCode::BAD_REQUEST, "Invalid API key"),
+        AuthAPIError::ApiKeyNotFound => (StatusCode::NOT_FOUND, "API key not found"),
+        AuthAPIError::InvalidPhoneNumber => (StatusCode::BAD_REQUEST, "Invalid phone number"),
+        AuthAPIError::PhoneNotVerified => (StatusCode::BAD_REQUEST, "Phone number not verified"),
+        AuthAPIError::InvalidTwoFAChannel => (StatusCode::BAD_REQUEST, "Invalid 2FA channel"),
+        AuthAPIError::TooManyRequests(retry_after) => {
+            let body = Json(ErrorResponse {
+                error: "Too many requests".to_string(),
+            });
+            // Round up so clients never retry a moment too early
+            let retry_after = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
 
-        (status, body).into_response()
-    }
+            return (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(header::RETRY_AFTER, retry_after.to_string())],
+                body,
+            )
+                .into_response();
+        }
+        AuthAPIError::UnexpectedError => (StatusCode::INTERNAL_SERVER_ERROR, "Unexpected error"),
+    };
+
+    let body = Json(ErrorResponse {
+        error: error_message.to_string(),
+    });
+
+    (status, body).into_response()
 }
 
-pub async fn get_postgres_pool(url: &str) -> Result<PgPool, sqlx::Error> {
+// Run on startup, and checked for by /health/ready
+pub static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+pub async fn get_postgres_pool(config: &DatabaseConfig) -> Result<PgPool, sqlx::Error> {
     // Create a new PostgreSQL connection pool
-    PgPoolOptions::new().max_connections(5).connect(url).await
+    PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .connect(&config.url)
+        .await
 }
 
-pub fn get_redis_client(redis_hostname: String) -> RedisResult<Client> {
-    let redis_url = format!("redis://{}/", redis_hostname);
+pub fn get_redis_client(config: &RedisConfig) -> RedisResult<Client> {
+    let redis_url = format!("redis://{}:{}/", config.hostname, config.port);
     redis::Client::open(redis_url)
 }
@@ -1,66 +1,245 @@
 #![allow(unused_variables)]
 #![allow(unused_imports)]
 
+use auth_service::app_state::{
+    EmailClientType, EmailOutboxStoreType, SmsClientType, WebhookStoreType,
+};
 use auth_service::domain::data_stores::UserStore;
-use auth_service::{get_postgres_pool, AppState, Application, HashmapUserStore};
+use auth_service::domain::LockoutPolicy;
+use auth_service::{get_postgres_pool, AppState, Application, Config, HashmapUserStore, MIGRATOR};
 use tokio::sync::RwLock;
 
-use auth_service::services::data_stores::redis_two_fa_code_store::RedisTwoFACodeStore;
+use auth_service::services::argon2_password_hasher::Argon2PasswordHasher;
+use auth_service::services::audit_checkpoint_signer::AuditCheckpointSigner;
 use auth_service::services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore;
+use auth_service::services::data_stores::postgres_api_key_store::PostgresApiKeyStore;
+use auth_service::services::data_stores::postgres_audit_sink::PostgresAuditSink;
+use auth_service::services::data_stores::postgres_email_outbox_store::PostgresEmailOutboxStore;
+use auth_service::services::data_stores::postgres_tenant_store::PostgresTenantStore;
 use auth_service::services::data_stores::postgres_user_store::PostgresUserStore;
+use auth_service::services::data_stores::postgres_webhook_store::PostgresWebhookStore;
 use auth_service::services::data_stores::redis_banned_token_store::RedisBannedTokenStore;
+use auth_service::services::data_stores::redis_rate_limiter::RedisRateLimiter;
+use auth_service::services::data_stores::redis_two_fa_code_store::RedisTwoFACodeStore;
+use auth_service::services::email_outbox_worker::EmailOutboxWorker;
+use auth_service::services::http_sms_client::{HttpSmsClient, HttpSmsSettings};
+use auth_service::services::instrumented::Instrumented;
 use auth_service::services::mock_email_client::MockEmailClient;
+use auth_service::services::mock_sms_client::MockSmsClient;
+use auth_service::services::smtp_email_client::{SmtpEmailClient, SmtpSettings};
+use auth_service::services::webhook_dispatcher::WebhookDispatcher;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
 
+use auth_service::config::{AuditConfig, RedisConfig, SmsConfig, SmtpConfig};
 use auth_service::get_redis_client;
-use auth_service::utils::constants::REDIS_HOSTNAME;
+use auth_service::utils::email_templates::EmailTemplates;
+use auth_service::utils::health::HealthChecks;
+use auth_service::utils::metrics::Metrics;
+use auth_service::utils::shutdown::{shutdown_on_signal, ShutdownHandle};
+use auth_service::utils::telemetry::init_tracing;
 
 #[tokio::main]
 async fn main() {
-    // Load environment variables
-    dotenvy::dotenv().ok();
-
-    // Get database URL from environment
-    let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    // Load the config file and environment variables, refusing to start
+    // with invalid settings rather than failing on first use
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("Invalid configuration: {}", e);
+        std::process::exit(1);
+    });
+    let tracer_provider = init_tracing(&config.telemetry).unwrap_or_else(|e| {
+        eprintln!("Failed to set up trace export: {}", e);
+        std::process::exit(1);
+    });
 
     // Create database connection pool
-    let db_pool = get_postgres_pool(&database_url)
+    let db_pool = get_postgres_pool(&config.database)
         .await
         .expect("Failed to create database pool");
 
     // Run database migrations
-    sqlx::migrate!("./migrations")
+    MIGRATOR
         .run(&db_pool)
         .await
         .expect("Failed to run database migrations");
 
-    println!("Database migrations completed successfully!");
+    tracing::info!("Database migrations completed successfully");
+
+    // Every store, and the password hasher, is wrapped to time its calls
+    let metrics = Arc::new(Metrics::new());
 
     // let user_store = Arc::new(RwLock::new(HashmapUserStore::default()));
-    let user_store = Arc::new(RwLock::new(PostgresUserStore::new(db_pool.clone())));
-    
+    let password_hasher = Arc::new(Instrumented::new(
+        Argon2PasswordHasher::new(config.argon2.clone()),
+        metrics.clone(),
+    ));
+    let lockout_policy = LockoutPolicy::from_config(&config.lockout);
+    let user_store = Arc::new(RwLock::new(Instrumented::new(
+        PostgresUserStore::new(db_pool.clone(), password_hasher.clone())
+            .with_lockout_policy(lockout_policy),
+        metrics.clone(),
+    )));
+
     // Configure Redis connection for banned token store and 2FA code store
-    let redis_conn = configure_redis();
+    let redis_conn = configure_redis(&config.redis);
     let redis_conn = Arc::new(RwLock::new(redis_conn));
-    let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(redis_conn.clone())));
-    let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_conn.clone())));
-    let email_client = Arc::new(RwLock::new(MockEmailClient::default()));
+    let banned_token_store = Arc::new(RwLock::new(Instrumented::new(
+        RedisBannedTokenStore::new(redis_conn.clone(), config.auth.token_ttl_seconds as u64),
+        metrics.clone(),
+    )));
+    let two_fa_code_store = Arc::new(RwLock::new(Instrumented::new(
+        RedisTwoFACodeStore::new(redis_conn.clone(), config.auth.two_fa_code_ttl_minutes * 60),
+        metrics.clone(),
+    )));
+    let email_client = configure_email_client(&config.smtp);
+    let email_outbox_store = Arc::new(RwLock::new(Instrumented::new(
+        PostgresEmailOutboxStore::new(db_pool.clone()),
+        metrics.clone(),
+    )));
+    // Stopped once requests have drained, so they send what those queued
+    let workers_shutdown = ShutdownHandle::new();
+    let email_outbox_worker = start_email_outbox_worker(
+        email_outbox_store.clone(),
+        email_client.clone(),
+        Duration::from_millis(config.email.outbox_interval_milliseconds),
+        workers_shutdown.clone(),
+    );
+    let rate_limiter = Arc::new(RwLock::new(Instrumented::new(
+        RedisRateLimiter::new(redis_conn.clone()),
+        metrics.clone(),
+    )));
+    let audit_sink = Arc::new(PostgresAuditSink::new(db_pool.clone()));
+    start_audit_checkpoints(audit_sink.clone(), &config.audit);
+    let audit_sink = Arc::new(Instrumented::new(audit_sink, metrics.clone()));
+    let webhook_store = Arc::new(RwLock::new(Instrumented::new(
+        PostgresWebhookStore::new(db_pool.clone()),
+        metrics.clone(),
+    )));
+    let webhook_dispatcher = start_webhook_dispatcher(
+        webhook_store.clone(),
+        Duration::from_secs(config.webhooks.dispatch_interval_seconds),
+        workers_shutdown.clone(),
+    );
+    let tenant_store = Arc::new(RwLock::new(Instrumented::new(
+        PostgresTenantStore::new(db_pool.clone()),
+        metrics.clone(),
+    )));
+    let api_key_store = Arc::new(RwLock::new(Instrumented::new(
+        PostgresApiKeyStore::new(db_pool.clone()),
+        metrics.clone(),
+    )));
+    let email_templates =
+        EmailTemplates::from_config(&config.email).expect("Invalid email templates");
+    let sms_client = configure_sms_client(&config.sms);
     let app_state = AppState::new(
+        Arc::new(config),
         user_store,
         banned_token_store,
         two_fa_code_store,
         email_client,
+        password_hasher,
+    )
+    .with_email_templates(email_templates)
+    .with_email_outbox_store(email_outbox_store)
+    .with_sms_client(sms_client)
+    .with_rate_limiter(rate_limiter)
+    .with_audit_sink(audit_sink)
+    .with_webhook_store(webhook_store)
+    .with_tenant_store(tenant_store)
+    .with_api_key_store(api_key_store)
+    .with_metrics(metrics)
+    .with_health_checks(
+        HealthChecks::default()
+            .with_postgres(db_pool.clone())
+            .with_redis(redis_conn.clone()),
     );
 
-    let app = Application::build(app_state, "0.0.0.0:3000")
+    let drain_timeout = Duration::from_secs(app_state.config.server.drain_timeout_seconds);
+    let app = Application::build(app_state)
         .await
         .expect("Failed to build application");
+    tokio::spawn(shutdown_on_signal(app.shutdown_handle()));
+
+    let result = app.run().await;
+
+    // Requests have drained; let the workers send what's due, then close the pool
+    workers_shutdown.shutdown();
+    let workers = async { tokio::join!(email_outbox_worker, webhook_dispatcher) };
+    if tokio::time::timeout(drain_timeout, workers).await.is_err() {
+        tracing::warn!("Background workers still busy after the drain timeout, stopping them");
+    }
+    db_pool.close().await;
+
+    // Send the spans still queued for the collector
+    if let Some(tracer_provider) = tracer_provider {
+        if let Err(e) = tracer_provider.shutdown() {
+            tracing::warn!("Failed to flush traces: {}", e);
+        }
+    }
+    result.expect("Failed to run application");
+}
+
+// Checkpoints are only made when a signing key is configured
+fn start_audit_checkpoints(audit_sink: Arc<PostgresAuditSink>, config: &AuditConfig) {
+    let signer =
+        AuditCheckpointSigner::from_config(config).expect("Invalid audit checkpoint signing key");
+    let Some(signer) = signer else {
+        return;
+    };
+
+    let interval = Duration::from_secs(config.checkpoint_interval_seconds);
+    tokio::spawn(audit_sink.run_periodic_checkpoints(signer, interval));
+}
+
+fn start_webhook_dispatcher(
+    webhook_store: WebhookStoreType,
+    interval: Duration,
+    shutdown: ShutdownHandle,
+) -> JoinHandle<()> {
+    let dispatcher = WebhookDispatcher::new(webhook_store);
+    tokio::spawn(dispatcher.run(interval, shutdown))
+}
+
+fn start_email_outbox_worker(
+    email_outbox_store: EmailOutboxStoreType,
+    email_client: EmailClientType,
+    interval: Duration,
+    shutdown: ShutdownHandle,
+) -> JoinHandle<()> {
+    let worker = EmailOutboxWorker::new(email_outbox_store, email_client);
+    tokio::spawn(worker.run(interval, shutdown))
+}
+
+// Without SMTP settings emails are only printed, which is fine for development
+// but means nobody gets their 2FA codes
+fn configure_email_client(config: &SmtpConfig) -> EmailClientType {
+    match SmtpSettings::from_config(config).expect("Invalid SMTP configuration") {
+        Some(settings) => Arc::new(RwLock::new(
+            SmtpEmailClient::new(settings).expect("Invalid SMTP configuration"),
+        )),
+        None => {
+            tracing::warn!("smtp.host is not set, emails will be printed instead of sent");
+            Arc::new(RwLock::new(MockEmailClient))
+        }
+    }
+}
 
-    app.run().await.expect("Failed to run application");
+// Without a provider texts and calls are only printed, like emails without SMTP
+fn configure_sms_client(config: &SmsConfig) -> SmsClientType {
+    match HttpSmsSettings::from_config(config).expect("Invalid SMS configuration") {
+        Some(settings) => Arc::new(RwLock::new(
+            HttpSmsClient::new(settings).expect("Invalid SMS configuration"),
+        )),
+        None => {
+            tracing::warn!("sms.provider_url is not set, texts will be printed instead of sent");
+            Arc::new(RwLock::new(MockSmsClient))
+        }
+    }
 }
 
-fn configure_redis() -> redis::Connection {
-    get_redis_client(REDIS_HOSTNAME.to_owned())
+fn configure_redis(config: &RedisConfig) -> redis::Connection {
+    get_redis_client(config)
         .expect("Failed to get Redis client")
         .get_connection()
         .expect("Failed to get Redis connection")
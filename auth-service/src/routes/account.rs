@@ -0,0 +1,201 @@
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuditEvent, AuditEventType, AuthAPIError, Email, PhoneNumber, SmsClientError, TwoFAChannel,
+        UserStoreError,
+    },
+    utils::{
+        audit::record_audit_event,
+        auth::{forbid_impersonation, Authenticated, Claims},
+        client_ip::ClientIp,
+        rate_limit::{enforce_email_rate_limit, enforce_ip_rate_limit, RateLimitedRoute},
+        sms::{generate_sms_code, hash_sms_code, send_sms_code, PHONE_VERIFICATION_TTL},
+        tenant::CurrentTenant,
+        user_agent::UserAgent,
+    },
+};
+
+// Text a code to a new phone number. The number only replaces the user's
+// current one, if any, once the code comes back through `verify_phone`.
+pub async fn start_phone_verification(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    Authenticated(claims): Authenticated,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Json(request): Json<StartPhoneVerificationRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    let email = account_owner(claims)?;
+    enforce_ip_rate_limit(&state, RateLimitedRoute::PhoneVerification, &client_ip).await?;
+    enforce_email_rate_limit(&state, RateLimitedRoute::PhoneVerification, &email).await?;
+
+    let phone = PhoneNumber::parse(request.phone).map_err(|_| AuthAPIError::InvalidPhoneNumber)?;
+    let channel = match request.channel.as_deref() {
+        None => TwoFAChannel::Sms,
+        Some(channel) => TwoFAChannel::parse(channel)
+            .ok()
+            .filter(TwoFAChannel::requires_phone)
+            .ok_or(AuthAPIError::InvalidTwoFAChannel)?,
+    };
+
+    let code = generate_sms_code();
+    state
+        .user_store
+        .write()
+        .await
+        .start_phone_verification(
+            &email,
+            &phone,
+            &hash_sms_code(&code),
+            Utc::now() + PHONE_VERIFICATION_TTL,
+        )
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let sent = send_sms_code(
+        &state,
+        &phone,
+        channel,
+        &code,
+        PHONE_VERIFICATION_TTL.num_minutes(),
+    )
+    .await;
+    let event = match &sent {
+        Ok(()) => AuditEvent::success(AuditEventType::PhoneVerificationStarted),
+        Err(e) => AuditEvent::failure(AuditEventType::PhoneVerificationStarted, &e.to_string()),
+    };
+    record_account_event(
+        &state,
+        &tenant,
+        event.with_email(&email),
+        client_ip,
+        user_agent,
+    )
+    .await;
+
+    match sent {
+        Ok(()) => Ok(StatusCode::ACCEPTED),
+        Err(SmsClientError::Rejected(_)) => Err(AuthAPIError::InvalidPhoneNumber),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
+
+pub async fn verify_phone(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    Authenticated(claims): Authenticated,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Json(request): Json<VerifyPhoneRequest>,
+) -> Result<Json<PhoneResponse>, AuthAPIError> {
+    let email = account_owner(claims)?;
+    enforce_ip_rate_limit(&state, RateLimitedRoute::PhoneVerification, &client_ip).await?;
+    enforce_email_rate_limit(&state, RateLimitedRoute::PhoneVerification, &email).await?;
+
+    let result = state
+        .user_store
+        .write()
+        .await
+        .complete_phone_verification(&email, &hash_sms_code(request.code.trim()))
+        .await;
+
+    let phone = match result {
+        Ok(phone) => phone,
+        Err(UserStoreError::InvalidCredentials) => {
+            let event = AuditEvent::failure(AuditEventType::PhoneVerified, "incorrect code")
+                .with_email(&email);
+            record_account_event(&state, &tenant, event, client_ip, user_agent).await;
+            return Err(AuthAPIError::IncorrectCredentials);
+        }
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    };
+
+    let event = AuditEvent::success(AuditEventType::PhoneVerified).with_email(&email);
+    record_account_event(&state, &tenant, event, client_ip, user_agent).await;
+
+    Ok(Json(PhoneResponse {
+        phone: phone.as_ref().to_owned(),
+    }))
+}
+
+// Where the user's 2FA codes go. SMS and voice need a verified phone number.
+pub async fn set_two_fa_channel(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    Authenticated(claims): Authenticated,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Json(request): Json<SetTwoFAChannelRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    let email = account_owner(claims)?;
+    let channel =
+        TwoFAChannel::parse(&request.channel).map_err(|_| AuthAPIError::InvalidTwoFAChannel)?;
+
+    let result = state
+        .user_store
+        .write()
+        .await
+        .set_two_fa_channel(&email, channel)
+        .await;
+    match result {
+        Ok(()) => {}
+        Err(UserStoreError::PhoneNotVerified) => return Err(AuthAPIError::PhoneNotVerified),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    }
+
+    let event = AuditEvent::success(AuditEventType::TwoFAChanged)
+        .with_email(&email)
+        .with_detail(&format!("channel {}", channel.as_str()));
+    record_account_event(&state, &tenant, event, client_ip, user_agent).await;
+
+    Ok(StatusCode::OK)
+}
+
+// Where 2FA codes go is only up to the user themselves: not an admin
+// impersonating them, nor a scoped credential such as an API key
+fn account_owner(claims: Claims) -> Result<Email, AuthAPIError> {
+    forbid_impersonation(&claims)?;
+    if claims.scope.is_some() {
+        return Err(AuthAPIError::Forbidden);
+    }
+    Email::parse(claims.sub).map_err(|_| AuthAPIError::InvalidToken)
+}
+
+async fn record_account_event(
+    state: &AppState,
+    tenant: &CurrentTenant,
+    event: AuditEvent,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+) {
+    let event = event
+        .with_tenant(tenant.id().as_ref())
+        .with_client(client_ip.0, user_agent.as_deref());
+    record_audit_event(state, event).await;
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartPhoneVerificationRequest {
+    pub phone: String,
+    // `sms` (the default) or `voice`, for numbers that can't receive texts
+    pub channel: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyPhoneRequest {
+    pub code: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PhoneResponse {
+    pub phone: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetTwoFAChannelRequest {
+    pub channel: String,
+}
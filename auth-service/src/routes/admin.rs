@@ -0,0 +1,399 @@
+use axum::{
+    extract::{FromRequestParts, Path, State},
+    http::{request::Parts, HeaderMap, StatusCode},
+    Json,
+};
+use rand::{rng, Rng};
+use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuditEvent, AuditEventType, AuthAPIError, Email, Role, UserStoreError, WebhookDelivery,
+        WebhookEventType, WebhookStoreError, WebhookSubscription,
+    },
+    routes::admin_users::{record_admin_event, require_member, Admin},
+    utils::{
+        audit::record_audit_event, auth::forbid_impersonation, client_ip::ClientIp,
+        tenant::CurrentTenant, user_agent::UserAgent,
+    },
+};
+
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+// What the audit log names as the actor of changes made with the admin API token
+const ADMIN_API_TOKEN_ACTOR: &str = "admin-api-token";
+
+/// Who is changing roles: an admin of the request's tenant or, to appoint
+/// the first admins (or remove one), the operator with the admin API token.
+/// Other than here, the token is only accepted for scraping `/metrics`.
+pub enum RoleManager {
+    Admin(Admin),
+    Operator,
+}
+
+impl RoleManager {
+    fn actor(&self) -> &str {
+        match self {
+            RoleManager::Admin(admin) => admin.claims.actor(),
+            RoleManager::Operator => ADMIN_API_TOKEN_ACTOR,
+        }
+    }
+}
+
+impl FromRequestParts<AppState> for RoleManager {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        if parts.headers.contains_key(ADMIN_TOKEN_HEADER) {
+            require_admin(state, &parts.headers)?;
+            return Ok(RoleManager::Operator);
+        }
+        let admin = Admin::from_request_parts(parts, state).await?;
+        forbid_impersonation(&admin.claims)?;
+        Ok(RoleManager::Admin(admin))
+    }
+}
+
+// Clear the failed login counter and any lock on an account, e.g. after the
+// owner has confirmed their identity with support. Locks apply in every
+// tenant, but admins can only unlock their own members.
+pub async fn unlock_user(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Json(request): Json<UnlockUserRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    forbid_impersonation(&admin.claims)?;
+    let email = state
+        .parse_email(request.email)
+        .map_err(|_| AuthAPIError::InvalidEmail)?;
+    require_member(&state, &tenant, &email).await?;
+
+    let result = state.user_store.write().await.unlock_user(&email).await;
+    match result {
+        Ok(()) => {}
+        Err(UserStoreError::UserNotFound) => return Err(AuthAPIError::UserNotFound),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    }
+
+    let event = AuditEvent::success(AuditEventType::AccountUnlocked).with_email(&email);
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok(StatusCode::OK)
+}
+
+// Roles are embedded in tokens, so changes apply from the user's next login.
+// They're per tenant, so the change is made in the tenant the request is for.
+pub async fn assign_role(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    manager: RoleManager,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Json(request): Json<RoleRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    let (email, role) = request.parse(&state)?;
+    let result = state
+        .user_store
+        .write()
+        .await
+        .assign_role(tenant.id(), &email, &role)
+        .await;
+    map_role_change_error(result)?;
+
+    let event = AuditEvent::success(AuditEventType::RoleAssigned)
+        .with_email(&email)
+        .with_detail(role.as_ref());
+    record_audit_event(
+        &state,
+        event
+            .with_actor(manager.actor())
+            .with_tenant(tenant.id().as_ref())
+            .with_client(client_ip.0, user_agent.as_deref()),
+    )
+    .await;
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn revoke_role(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    manager: RoleManager,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Json(request): Json<RoleRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    let (email, role) = request.parse(&state)?;
+    let result = state
+        .user_store
+        .write()
+        .await
+        .revoke_role(tenant.id(), &email, &role)
+        .await;
+    map_role_change_error(result)?;
+
+    let event = AuditEvent::success(AuditEventType::RoleRevoked)
+        .with_email(&email)
+        .with_detail(role.as_ref());
+    record_audit_event(
+        &state,
+        event
+            .with_actor(manager.actor())
+            .with_tenant(tenant.id().as_ref())
+            .with_client(client_ip.0, user_agent.as_deref()),
+    )
+    .await;
+
+    Ok(StatusCode::OK)
+}
+
+fn map_role_change_error(result: Result<(), UserStoreError>) -> Result<(), AuthAPIError> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(UserStoreError::UserNotFound) => Err(AuthAPIError::UserNotFound),
+        Err(UserStoreError::RoleNotFound) => Err(AuthAPIError::RoleNotFound),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
+
+// Subscribe a URL to the webhook events of the tenant the request is for. The
+// signing secret is generated here and only ever returned in this response.
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Json(request): Json<CreateWebhookRequest>,
+) -> Result<(StatusCode, Json<CreateWebhookResponse>), AuthAPIError> {
+    forbid_impersonation(&admin.claims)?;
+    let url = reqwest::Url::parse(&request.url).map_err(|_| AuthAPIError::InvalidWebhook)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AuthAPIError::InvalidWebhook);
+    }
+
+    let event_types = request
+        .event_types
+        .iter()
+        .map(|event_type| WebhookEventType::parse(event_type))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| AuthAPIError::InvalidWebhook)?;
+
+    let subscription = WebhookSubscription {
+        id: Uuid::new_v4(),
+        tenant: tenant.id().clone(),
+        url: url.to_string(),
+        secret: hex::encode(rng().random::<[u8; 32]>()),
+        event_types,
+    };
+
+    let response = CreateWebhookResponse {
+        id: subscription.id,
+        secret: subscription.secret.clone(),
+    };
+
+    let event = AuditEvent::success(AuditEventType::WebhookCreated).with_detail(&subscription.url);
+    state
+        .webhook_store
+        .write()
+        .await
+        .add_subscription(subscription)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+pub async fn list_webhooks(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    _admin: Admin,
+) -> Result<Json<Vec<WebhookResponse>>, AuthAPIError> {
+    let webhook_store = state.webhook_store.read().await;
+    let subscriptions = webhook_store
+        .get_subscriptions()
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(Json(
+        subscriptions
+            .iter()
+            .filter(|subscription| &subscription.tenant == tenant.id())
+            .map(WebhookResponse::from)
+            .collect(),
+    ))
+}
+
+// Only the tenant's own subscriptions; others are treated as missing
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AuthAPIError> {
+    forbid_impersonation(&admin.claims)?;
+    let mut webhook_store = state.webhook_store.write().await;
+    let subscriptions = webhook_store
+        .get_subscriptions()
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    let subscription = subscriptions
+        .iter()
+        .find(|subscription| subscription.id == id && &subscription.tenant == tenant.id())
+        .ok_or(AuthAPIError::WebhookNotFound)?;
+
+    match webhook_store.remove_subscription(id).await {
+        Ok(()) => {}
+        Err(WebhookStoreError::SubscriptionNotFound) => return Err(AuthAPIError::WebhookNotFound),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    }
+    drop(webhook_store);
+
+    let event = AuditEvent::success(AuditEventType::WebhookDeleted).with_detail(&subscription.url);
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Deliveries to the tenant's subscriptions that ran out of retries, so they
+// can be investigated or replayed by hand
+pub async fn list_webhook_dead_letters(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    _admin: Admin,
+) -> Result<Json<Vec<DeadLetterResponse>>, AuthAPIError> {
+    let webhook_store = state.webhook_store.read().await;
+    let subscriptions = webhook_store
+        .get_subscriptions()
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    let dead_letters = webhook_store
+        .get_dead_letters()
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let tenant_subscription = |id: Uuid| {
+        subscriptions
+            .iter()
+            .any(|subscription| subscription.id == id && &subscription.tenant == tenant.id())
+    };
+    Ok(Json(
+        dead_letters
+            .iter()
+            .filter(|delivery| tenant_subscription(delivery.subscription_id))
+            .map(DeadLetterResponse::from)
+            .collect(),
+    ))
+}
+
+pub(crate) fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<(), AuthAPIError> {
+    let expected = state
+        .admin_api_token
+        .as_ref()
+        .ok_or(AuthAPIError::InvalidToken)?;
+
+    let provided = headers
+        .get(ADMIN_TOKEN_HEADER)
+        .map(|value| value.as_bytes())
+        .ok_or(AuthAPIError::InvalidToken)?;
+
+    if bool::from(provided.ct_eq(expected.as_bytes())) {
+        Ok(())
+    } else {
+        Err(AuthAPIError::InvalidToken)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UnlockUserRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RoleRequest {
+    pub email: String,
+    pub role: String,
+}
+
+impl RoleRequest {
+    fn parse(self, state: &AppState) -> Result<(Email, Role), AuthAPIError> {
+        let email = state
+            .parse_email(self.email)
+            .map_err(|_| AuthAPIError::InvalidEmail)?;
+        let role = Role::parse(self.role).map_err(|_| AuthAPIError::RoleNotFound)?;
+        Ok((email, role))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    // Empty for every event type
+    #[serde(default)]
+    pub event_types: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateWebhookResponse {
+    pub id: Uuid,
+    pub secret: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookResponse {
+    pub id: Uuid,
+    pub url: String,
+    pub event_types: Vec<String>,
+}
+
+impl From<&WebhookSubscription> for WebhookResponse {
+    fn from(subscription: &WebhookSubscription) -> Self {
+        Self {
+            id: subscription.id,
+            url: subscription.url.clone(),
+            event_types: subscription
+                .event_types
+                .iter()
+                .map(|event_type| event_type.as_str().to_owned())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeadLetterResponse {
+    pub id: Uuid,
+    pub subscription_id: Uuid,
+    pub url: String,
+    pub event_type: String,
+    pub payload: String,
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+impl From<&WebhookDelivery> for DeadLetterResponse {
+    fn from(delivery: &WebhookDelivery) -> Self {
+        Self {
+            id: delivery.id,
+            subscription_id: delivery.subscription_id,
+            url: delivery.url.clone(),
+            event_type: delivery.event_type.as_str().to_owned(),
+            payload: delivery.payload.clone(),
+            attempts: delivery.attempts,
+            last_error: delivery.last_error.clone(),
+        }
+    }
+}
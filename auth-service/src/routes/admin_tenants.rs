@@ -0,0 +1,296 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        normalize_host, AuditEvent, AuditEventType, AuthAPIError, Email, Tenant, TenantId,
+        TenantSettings, TenantStoreError, UserStoreError,
+    },
+    routes::admin_users::{record_admin_event, Admin},
+    utils::{
+        auth::forbid_impersonation, client_ip::ClientIp, tenant::CurrentTenant,
+        user_agent::UserAgent,
+    },
+};
+
+// Far beyond anything a person would type, but keeps the value storable
+const MAX_MIN_PASSWORD_LENGTH: usize = 128;
+
+// Tenants are managed by the operator, i.e. the admins of the default
+// tenant, rather than by any other tenant's own admins.
+fn require_operator(tenant: &CurrentTenant) -> Result<(), AuthAPIError> {
+    if tenant.id() == &TenantId::default() {
+        Ok(())
+    } else {
+        Err(AuthAPIError::Forbidden)
+    }
+}
+
+pub async fn create_tenant(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Json(request): Json<CreateTenantRequest>,
+) -> Result<(StatusCode, Json<TenantResponse>), AuthAPIError> {
+    require_operator(&tenant)?;
+    forbid_impersonation(&admin.claims)?;
+
+    let id = parse_tenant_id(request.id)?;
+    let name = request.name.trim();
+    if name.is_empty() {
+        return Err(AuthAPIError::InvalidTenant);
+    }
+
+    let mut new_tenant = Tenant::new(id, name);
+    new_tenant.hosts = request
+        .hosts
+        .iter()
+        .map(|host| normalize_host(host))
+        .collect();
+    if new_tenant.hosts.iter().any(|host| host.is_empty()) {
+        return Err(AuthAPIError::InvalidTenant);
+    }
+    new_tenant.settings = request.settings.into_settings()?;
+
+    state
+        .tenant_store
+        .write()
+        .await
+        .add_tenant(new_tenant.clone())
+        .await
+        .map_err(map_tenant_error)?;
+
+    let event =
+        AuditEvent::success(AuditEventType::TenantCreated).with_detail(new_tenant.id.as_ref());
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok((StatusCode::CREATED, Json(TenantResponse::from(&new_tenant))))
+}
+
+pub async fn list_tenants(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    _admin: Admin,
+) -> Result<Json<Vec<TenantResponse>>, AuthAPIError> {
+    require_operator(&tenant)?;
+
+    let tenants = state
+        .tenant_store
+        .read()
+        .await
+        .get_tenants()
+        .await
+        .map_err(map_tenant_error)?;
+
+    Ok(Json(tenants.iter().map(TenantResponse::from).collect()))
+}
+
+pub async fn update_tenant_settings(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Path(id): Path<String>,
+    Json(request): Json<TenantSettingsRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    require_operator(&tenant)?;
+    forbid_impersonation(&admin.claims)?;
+
+    let id = parse_tenant_id(id)?;
+    let settings = request.into_settings()?;
+
+    state
+        .tenant_store
+        .write()
+        .await
+        .update_settings(&id, settings)
+        .await
+        .map_err(map_tenant_error)?;
+
+    let event = AuditEvent::success(AuditEventType::TenantSettingsChanged).with_detail(id.as_ref());
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok(StatusCode::OK)
+}
+
+// Give an existing user access to another tenant, with no roles there yet
+pub async fn add_tenant_member(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Path(id): Path<String>,
+    Json(request): Json<TenantMemberRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    require_operator(&tenant)?;
+    forbid_impersonation(&admin.claims)?;
+
+    let id = existing_tenant_id(&state, id).await?;
+    let email = state
+        .parse_email(request.email)
+        .map_err(|_| AuthAPIError::InvalidEmail)?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .add_member(&id, &email)
+        .await
+        .map_err(map_member_error)?;
+
+    let event = AuditEvent::success(AuditEventType::TenantMemberAdded)
+        .with_email(&email)
+        .with_detail(id.as_ref());
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok(StatusCode::OK)
+}
+
+// Also ends the user's sessions in that tenant
+pub async fn remove_tenant_member(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Path((id, email)): Path<(String, String)>,
+) -> Result<StatusCode, AuthAPIError> {
+    require_operator(&tenant)?;
+    forbid_impersonation(&admin.claims)?;
+
+    let id = existing_tenant_id(&state, id).await?;
+    let email = state
+        .parse_email(email)
+        .map_err(|_| AuthAPIError::InvalidEmail)?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .remove_member(&id, &email)
+        .await
+        .map_err(map_member_error)?;
+    state
+        .banned_token_store
+        .write()
+        .await
+        .ban_subject(&id, email.as_ref(), Utc::now())
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let event = AuditEvent::success(AuditEventType::TenantMemberRemoved)
+        .with_email(&email)
+        .with_detail(id.as_ref());
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn parse_tenant_id(id: String) -> Result<TenantId, AuthAPIError> {
+    TenantId::parse(id).map_err(|_| AuthAPIError::InvalidTenant)
+}
+
+async fn existing_tenant_id(state: &AppState, id: String) -> Result<TenantId, AuthAPIError> {
+    let id = TenantId::parse(id).map_err(|_| AuthAPIError::TenantNotFound)?;
+    state
+        .tenant_store
+        .read()
+        .await
+        .get_tenant(&id)
+        .await
+        .map_err(map_tenant_error)?;
+    Ok(id)
+}
+
+fn map_tenant_error(error: TenantStoreError) -> AuthAPIError {
+    match error {
+        TenantStoreError::TenantAlreadyExists | TenantStoreError::HostTaken => {
+            AuthAPIError::TenantAlreadyExists
+        }
+        TenantStoreError::TenantNotFound => AuthAPIError::TenantNotFound,
+        TenantStoreError::UnexpectedError => AuthAPIError::UnexpectedError,
+    }
+}
+
+fn map_member_error(error: UserStoreError) -> AuthAPIError {
+    match error {
+        UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+        UserStoreError::TenantNotFound => AuthAPIError::TenantNotFound,
+        _ => AuthAPIError::UnexpectedError,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTenantRequest {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    #[serde(flatten)]
+    pub settings: TenantSettingsRequest,
+}
+
+// Unset fields get the defaults
+#[derive(Debug, Deserialize)]
+pub struct TenantSettingsRequest {
+    pub min_password_length: Option<usize>,
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: Option<bool>,
+}
+
+impl TenantSettingsRequest {
+    fn into_settings(self) -> Result<TenantSettings, AuthAPIError> {
+        let defaults = TenantSettings::default();
+        let settings = TenantSettings {
+            min_password_length: self
+                .min_password_length
+                .unwrap_or(defaults.min_password_length),
+            requires_2fa: self.requires_2fa.unwrap_or(defaults.requires_2fa),
+        };
+
+        // A tenant can only be stricter than the global policy
+        if !(defaults.min_password_length..=MAX_MIN_PASSWORD_LENGTH)
+            .contains(&settings.min_password_length)
+        {
+            return Err(AuthAPIError::InvalidTenant);
+        }
+        Ok(settings)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TenantMemberRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TenantResponse {
+    pub id: String,
+    pub name: String,
+    pub hosts: Vec<String>,
+    pub min_password_length: usize,
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: bool,
+}
+
+impl From<&Tenant> for TenantResponse {
+    fn from(tenant: &Tenant) -> Self {
+        Self {
+            id: tenant.id.as_ref().to_owned(),
+            name: tenant.name.clone(),
+            hosts: tenant.hosts.clone(),
+            min_password_length: tenant.settings.min_password_length,
+            requires_2fa: tenant.settings.requires_2fa,
+        }
+    }
+}
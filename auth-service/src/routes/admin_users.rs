@@ -0,0 +1,480 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        AuditEvent, AuditEventType, AuthAPIError, Email, TenantId, User, UserListQuery,
+        UserStoreError, WebhookEventType,
+    },
+    utils::{
+        audit::record_audit_event,
+        auth::{forbid_impersonation, generate_impersonation_token, AdminRole, RequireRole},
+        client_ip::ClientIp,
+        email_outbox::queue_email,
+        email_templates::EmailTemplate,
+        password_reset::{
+            generate_password_reset_token, hash_password_reset_token, PASSWORD_RESET_TTL,
+        },
+        tenant::CurrentTenant,
+        user_agent::UserAgent,
+        webhooks::publish_webhook_event,
+    },
+};
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 100;
+
+// Admins manage the members of their own tenant. Some changes, like disabling
+// an account, apply to the user everywhere, so they're refused for users who
+// are members of other tenants too.
+pub(super) type Admin = RequireRole<AdminRole>;
+
+// Users ordered by email, optionally filtered by a case-insensitive substring
+pub async fn list_users(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    _admin: Admin,
+    Query(params): Query<ListUsersParams>,
+) -> Result<Json<UserListResponse>, AuthAPIError> {
+    let query = UserListQuery {
+        search: params.search.filter(|search| !search.is_empty()),
+        offset: params.offset.unwrap_or(0),
+        limit: params
+            .limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE),
+    };
+
+    let page = state
+        .user_store
+        .read()
+        .await
+        .list_users(tenant.id(), &query)
+        .await
+        .map_err(map_user_error)?;
+
+    Ok(Json(UserListResponse {
+        users: page.users.iter().map(UserSummary::from).collect(),
+        total: page.total,
+        offset: query.offset,
+        limit: query.limit,
+    }))
+}
+
+pub async fn get_user(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    _admin: Admin,
+    Path(email): Path<String>,
+) -> Result<Json<UserDetailResponse>, AuthAPIError> {
+    let email = parse_email(&state, email)?;
+
+    let user_store = state.user_store.read().await;
+    let user = user_store
+        .get_user(tenant.id(), &email)
+        .await
+        .map_err(map_user_error)?;
+    let roles = user_store
+        .get_roles(tenant.id(), &email)
+        .await
+        .map_err(map_user_error)?;
+    let lockout_state = user_store
+        .get_lockout_state(&email)
+        .await
+        .map_err(map_user_error)?;
+
+    Ok(Json(UserDetailResponse {
+        user: UserSummary::from(&user),
+        roles: roles.iter().map(|role| role.as_ref().to_owned()).collect(),
+        failed_login_attempts: lockout_state.failed_attempts,
+        locked_until: lockout_state
+            .locked_until
+            .filter(|until| *until > Utc::now())
+            .map(|until| until.to_rfc3339()),
+    }))
+}
+
+// Disabling also ends the user's current sessions, not just future logins
+pub async fn disable_user(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Path(email): Path<String>,
+) -> Result<StatusCode, AuthAPIError> {
+    forbid_impersonation(&admin.claims)?;
+    let email = parse_email(&state, email)?;
+    require_sole_member(&state, &tenant, &email).await?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_disabled(&email, true)
+        .await
+        .map_err(map_user_error)?;
+    end_user_sessions_everywhere(&state, &email).await?;
+
+    let event = AuditEvent::success(AuditEventType::UserDisabled).with_email(&email);
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn enable_user(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Path(email): Path<String>,
+) -> Result<StatusCode, AuthAPIError> {
+    forbid_impersonation(&admin.claims)?;
+    let email = parse_email(&state, email)?;
+    require_sole_member(&state, &tenant, &email).await?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_disabled(&email, false)
+        .await
+        .map_err(map_user_error)?;
+
+    let event = AuditEvent::success(AuditEventType::UserEnabled).with_email(&email);
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok(StatusCode::OK)
+}
+
+// Takes effect from the user's next login
+pub async fn set_user_2fa(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Path(email): Path<String>,
+    Json(request): Json<SetTwoFARequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    forbid_impersonation(&admin.claims)?;
+    let email = parse_email(&state, email)?;
+    require_sole_member(&state, &tenant, &email).await?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .set_requires_2fa(&email, request.requires_2fa)
+        .await
+        .map_err(map_user_error)?;
+
+    let detail = if request.requires_2fa { "on" } else { "off" };
+    let event = AuditEvent::success(AuditEventType::TwoFAChanged)
+        .with_email(&email)
+        .with_detail(detail);
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    if request.requires_2fa {
+        publish_webhook_event(&state, tenant.id(), WebhookEventType::TwoFAEnabled, &email).await;
+    }
+
+    Ok(StatusCode::OK)
+}
+
+// Email the user a single-use token for `/reset-password`. Their current
+// password keeps working until the reset is completed.
+pub async fn reset_user_password(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Path(email): Path<String>,
+) -> Result<StatusCode, AuthAPIError> {
+    forbid_impersonation(&admin.claims)?;
+    let email = parse_email(&state, email)?;
+    require_member(&state, &tenant, &email).await?;
+
+    let token = generate_password_reset_token();
+    let token_hash = hash_password_reset_token(&token);
+    let expires_at = Utc::now() + PASSWORD_RESET_TTL;
+    state
+        .user_store
+        .write()
+        .await
+        .start_password_reset(&email, &token_hash, expires_at)
+        .await
+        .map_err(map_user_error)?;
+
+    // Unlike a notification, the reset is useless if the email can't be queued
+    let template = EmailTemplate::PasswordReset {
+        code: token,
+        expires_at,
+    };
+    let idempotency_key = format!("password_reset:{}", token_hash);
+    queue_email(&state, &email, template, idempotency_key)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let event = AuditEvent::success(AuditEventType::PasswordResetRequested).with_email(&email);
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+pub async fn revoke_user_sessions(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Path(email): Path<String>,
+) -> Result<StatusCode, AuthAPIError> {
+    forbid_impersonation(&admin.claims)?;
+    let email = parse_email(&state, email)?;
+
+    // Make sure the user exists, so a typo doesn't look like it worked
+    require_member(&state, &tenant, &email).await?;
+    end_user_sessions(&state, tenant.id(), &email).await?;
+
+    let event = AuditEvent::success(AuditEventType::SessionsRevoked).with_email(&email);
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok(StatusCode::OK)
+}
+
+pub async fn delete_user(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Path(email): Path<String>,
+) -> Result<StatusCode, AuthAPIError> {
+    forbid_impersonation(&admin.claims)?;
+    let email = parse_email(&state, email)?;
+
+    state
+        .user_store
+        .write()
+        .await
+        .delete_user(tenant.id(), &email)
+        .await
+        .map_err(map_user_error)?;
+    // Otherwise tokens would stay valid, and work again if the email is reused
+    end_user_sessions(&state, tenant.id(), &email).await?;
+
+    let event = AuditEvent::success(AuditEventType::UserDeleted).with_email(&email);
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// A short-lived token for support staff to see what the user sees. It names
+// the admin in its `act` claim, and sensitive routes refuse it.
+pub async fn impersonate_user(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    admin: Admin,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Path(email): Path<String>,
+) -> Result<Json<ImpersonationResponse>, AuthAPIError> {
+    forbid_impersonation(&admin.claims)?;
+    let email = parse_email(&state, email)?;
+    let actor = Email::parse(admin.claims.sub.clone()).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let (user, roles) = {
+        let user_store = state.user_store.read().await;
+        let user = user_store
+            .get_user(tenant.id(), &email)
+            .await
+            .map_err(map_user_error)?;
+        let roles = user_store
+            .get_roles(tenant.id(), &email)
+            .await
+            .map_err(map_user_error)?;
+        (user, roles)
+    };
+    if user.disabled {
+        return Err(AuthAPIError::AccountDisabled);
+    }
+
+    let config = &state.config.auth;
+    let token = generate_impersonation_token(config, tenant.id(), &email, &roles, &actor)
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let event = AuditEvent::success(AuditEventType::Impersonation).with_email(&email);
+    record_admin_event(&state, &tenant, &admin, event, client_ip, user_agent).await;
+
+    Ok(Json(ImpersonationResponse {
+        token,
+        expires_in: config.impersonation_ttl_seconds,
+    }))
+}
+
+/// Invalidate every token `tenant` has issued to `email` so far.
+async fn end_user_sessions(
+    state: &AppState,
+    tenant: &TenantId,
+    email: &Email,
+) -> Result<(), AuthAPIError> {
+    state
+        .banned_token_store
+        .write()
+        .await
+        .ban_subject(tenant, email.as_ref(), Utc::now())
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+/// [`end_user_sessions`] in every tenant `email` is a member of, for changes
+/// to the account itself.
+pub(crate) async fn end_user_sessions_everywhere(
+    state: &AppState,
+    email: &Email,
+) -> Result<(), AuthAPIError> {
+    let tenants = state
+        .user_store
+        .read()
+        .await
+        .get_tenants(email)
+        .await
+        .map_err(map_user_error)?;
+
+    for tenant in &tenants {
+        end_user_sessions(state, tenant, email).await?;
+    }
+    Ok(())
+}
+
+pub(super) async fn require_member(
+    state: &AppState,
+    tenant: &CurrentTenant,
+    email: &Email,
+) -> Result<(), AuthAPIError> {
+    state
+        .user_store
+        .read()
+        .await
+        .get_user(tenant.id(), email)
+        .await
+        .map(|_| ())
+        .map_err(map_user_error)
+}
+
+// For changes to the account itself, which would reach into other tenants
+async fn require_sole_member(
+    state: &AppState,
+    tenant: &CurrentTenant,
+    email: &Email,
+) -> Result<(), AuthAPIError> {
+    require_member(state, tenant, email).await?;
+
+    let tenants = state
+        .user_store
+        .read()
+        .await
+        .get_tenants(email)
+        .await
+        .map_err(map_user_error)?;
+    if tenants.iter().any(|other| other != tenant.id()) {
+        return Err(AuthAPIError::Forbidden);
+    }
+    Ok(())
+}
+
+pub(super) async fn record_admin_event(
+    state: &AppState,
+    tenant: &CurrentTenant,
+    admin: &Admin,
+    event: AuditEvent,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+) {
+    let event = event
+        .with_actor(admin.claims.actor())
+        .with_tenant(tenant.id().as_ref())
+        .with_client(client_ip.0, user_agent.as_deref());
+    record_audit_event(state, event).await;
+}
+
+fn parse_email(state: &AppState, email: String) -> Result<Email, AuthAPIError> {
+    state
+        .parse_email(email)
+        .map_err(|_| AuthAPIError::InvalidEmail)
+}
+
+fn map_user_error(error: UserStoreError) -> AuthAPIError {
+    match error {
+        UserStoreError::UserNotFound => AuthAPIError::UserNotFound,
+        _ => AuthAPIError::UnexpectedError,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListUsersParams {
+    pub search: Option<String>,
+    pub offset: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SetTwoFARequest {
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserSummary {
+    pub email: String,
+    #[serde(rename = "requires2FA")]
+    pub requires_2fa: bool,
+    pub disabled: bool,
+    #[serde(rename = "twoFAChannel")]
+    pub two_fa_channel: String,
+}
+
+impl From<&User> for UserSummary {
+    fn from(user: &User) -> Self {
+        Self {
+            email: user.email.as_ref().to_owned(),
+            requires_2fa: user.requires_2fa,
+            disabled: user.disabled,
+            two_fa_channel: user.two_fa_channel.as_str().to_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserListResponse {
+    pub users: Vec<UserSummary>,
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImpersonationResponse {
+    pub token: String,
+    // Seconds, as in an OAuth token response
+    pub expires_in: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserDetailResponse {
+    #[serde(flatten)]
+    pub user: UserSummary,
+    pub roles: Vec<String>,
+    pub failed_login_attempts: u32,
+    // RFC 3339, only set while the account is locked
+    pub locked_until: Option<String>,
+}
@@ -0,0 +1,218 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    app_state::AppState,
+    domain::{
+        ApiKey, ApiKeyStoreError, AuditEvent, AuditEventType, AuthAPIError, Email, Permission, Role,
+    },
+    utils::{
+        api_key::{generate_api_key, hash_api_key, DEFAULT_API_KEY_TTL, MAX_API_KEY_TTL},
+        audit::record_audit_event,
+        auth::{forbid_impersonation, Authenticated},
+        client_ip::ClientIp,
+        tenant::CurrentTenant,
+        user_agent::UserAgent,
+    },
+};
+
+const MAX_NAME_LENGTH: usize = 100;
+
+// The key itself is only ever in this response; afterwards just its hash is
+// kept. Keys can't be created with a scoped credential, API keys included,
+// or while impersonating, and can only be scoped to permissions the user has
+// right now.
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    Authenticated(claims): Authenticated,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Json(request): Json<CreateApiKeyRequest>,
+) -> Result<(StatusCode, Json<CreateApiKeyResponse>), AuthAPIError> {
+    forbid_impersonation(&claims)?;
+    if claims.scope.is_some() {
+        return Err(AuthAPIError::Forbidden);
+    }
+    let email = Email::parse(claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let name = request.name.trim();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        return Err(AuthAPIError::InvalidApiKey);
+    }
+
+    let ttl = match request.expires_in_days {
+        Some(days) => Duration::try_days(days)
+            .filter(|ttl| *ttl > Duration::zero() && *ttl <= MAX_API_KEY_TTL)
+            .ok_or(AuthAPIError::InvalidApiKey)?,
+        None => DEFAULT_API_KEY_TTL,
+    };
+
+    let mut scopes: Vec<Permission> = request
+        .scopes
+        .into_iter()
+        .map(Permission::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| AuthAPIError::InvalidApiKey)?;
+    scopes.sort();
+    scopes.dedup();
+
+    let permissions = {
+        let user_store = state.user_store.read().await;
+        let roles: Vec<Role> = user_store
+            .get_roles(tenant.id(), &email)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?;
+        user_store
+            .get_permissions(&roles)
+            .await
+            .map_err(|_| AuthAPIError::UnexpectedError)?
+    };
+    if !scopes.iter().all(|scope| permissions.contains(scope)) {
+        return Err(AuthAPIError::Forbidden);
+    }
+
+    let key = generate_api_key();
+    let created_at = Utc::now();
+    let api_key = ApiKey {
+        id: Uuid::new_v4(),
+        tenant: tenant.id().clone(),
+        email: email.clone(),
+        name: name.to_owned(),
+        scopes,
+        created_at,
+        expires_at: created_at + ttl,
+        last_used_at: None,
+    };
+    let response = CreateApiKeyResponse {
+        key,
+        api_key: ApiKeyResponse::from(&api_key),
+    };
+
+    state
+        .api_key_store
+        .write()
+        .await
+        .add_key(api_key, &hash_api_key(&response.key))
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let event = AuditEvent::success(AuditEventType::ApiKeyCreated)
+        .with_email(&email)
+        .with_detail(&response.api_key.id.to_string());
+    record_key_event(&state, &tenant, event, client_ip, user_agent).await;
+
+    Ok((StatusCode::CREATED, Json(response)))
+}
+
+// The caller's own keys in this tenant, expired ones included
+pub async fn list_api_keys(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    Authenticated(claims): Authenticated,
+) -> Result<Json<Vec<ApiKeyResponse>>, AuthAPIError> {
+    let email = Email::parse(claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let keys = state
+        .api_key_store
+        .read()
+        .await
+        .get_keys(tenant.id(), &email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(Json(keys.iter().map(ApiKeyResponse::from).collect()))
+}
+
+pub async fn revoke_api_key(
+    State(state): State<AppState>,
+    tenant: CurrentTenant,
+    Authenticated(claims): Authenticated,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, AuthAPIError> {
+    forbid_impersonation(&claims)?;
+    let email = Email::parse(claims.sub).map_err(|_| AuthAPIError::InvalidToken)?;
+
+    let revoked = state
+        .api_key_store
+        .write()
+        .await
+        .revoke_key(tenant.id(), &email, id)
+        .await;
+    match revoked {
+        Ok(()) => {}
+        Err(ApiKeyStoreError::KeyNotFound) => return Err(AuthAPIError::ApiKeyNotFound),
+        Err(_) => return Err(AuthAPIError::UnexpectedError),
+    }
+
+    let event = AuditEvent::success(AuditEventType::ApiKeyRevoked)
+        .with_email(&email)
+        .with_detail(&id.to_string());
+    record_key_event(&state, &tenant, event, client_ip, user_agent).await;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn record_key_event(
+    state: &AppState,
+    tenant: &CurrentTenant,
+    event: AuditEvent,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+) {
+    let event = event
+        .with_tenant(tenant.id().as_ref())
+        .with_client(client_ip.0, user_agent.as_deref());
+    record_audit_event(state, event).await;
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    #[serde(default)]
+    pub scopes: Vec<String>,
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CreateApiKeyResponse {
+    pub key: String,
+    #[serde(flatten)]
+    pub api_key: ApiKeyResponse,
+}
+
+// Everything about a key except the key itself
+#[derive(Debug, Serialize)]
+pub struct ApiKeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub created_at: String,
+    pub expires_at: String,
+    pub last_used_at: Option<String>,
+}
+
+impl From<&ApiKey> for ApiKeyResponse {
+    fn from(key: &ApiKey) -> Self {
+        Self {
+            id: key.id,
+            name: key.name.clone(),
+            scopes: key
+                .scopes
+                .iter()
+                .map(|scope| scope.as_ref().to_owned())
+                .collect(),
+            created_at: key.created_at.to_rfc3339(),
+            expires_at: key.expires_at.to_rfc3339(),
+            last_used_at: key.last_used_at.map(|at| at.to_rfc3339()),
+        }
+    }
+}
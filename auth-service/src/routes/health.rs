@@ -0,0 +1,27 @@
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use serde_json::json;
+
+use crate::{app_state::AppState, utils::health::Status};
+
+// The process is up and serving requests, whatever state its dependencies are in
+pub async fn live() -> impl IntoResponse {
+    Json(json!({ "status": "alive" }))
+}
+
+// Whether requests can be served, so load balancers know to send them here
+pub async fn ready(State(state): State<AppState>) -> impl IntoResponse {
+    // Fails first on shutdown, so no more requests are sent while these drain
+    if state.shutdown.is_shutting_down() {
+        let body = json!({ "status": "shutting_down", "checks": {} });
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(body));
+    }
+
+    let checks = state.health_checks.check().await;
+    let ready = checks.values().all(|check| check.status == Status::Up);
+
+    let (status, body) = match ready {
+        true => (StatusCode::OK, "ready"),
+        false => (StatusCode::SERVICE_UNAVAILABLE, "not_ready"),
+    };
+    (status, Json(json!({ "status": body, "checks": checks })))
+}
@@ -2,69 +2,234 @@
 
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
+use chrono::{DateTime, Utc};
 use rand::{rng, Rng};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
     app_state::AppState,
-    domain::{Email, LoginAttemptId, Password, TwoFACode},
-    utils::auth::generate_auth_cookie,
+    domain::{
+        AuditEvent, AuditEventType, Email, LoginAttemptId, Password, Permission, Role, TenantId,
+        TwoFAChannel, TwoFACode, User, UserStoreError, WebhookEventType,
+    },
+    utils::{
+        audit::record_audit_event,
+        auth::{generate_auth_cookie, grant_scope},
+        client_ip::ClientIp,
+        email_outbox::queue_email,
+        email_templates::EmailTemplate,
+        rate_limit::{enforce_email_rate_limit, enforce_ip_rate_limit, RateLimitedRoute},
+        sms::send_sms_code,
+        tenant::CurrentTenant,
+        user_agent::UserAgent,
+        webhooks::publish_webhook_event,
+    },
     AuthAPIError,
 };
 
 pub async fn login(
     State(app_state): State<AppState>,
+    tenant: CurrentTenant,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
     jar: CookieJar,
     Json(request): Json<LoginRequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
-    println!("Login endpoint called!");
+    let (jar, result) =
+        attempt_login(&app_state, tenant, client_ip, user_agent, jar, request).await;
+    app_state.metrics.record_login(login_outcome(&result));
+    (jar, result)
+}
+
+async fn attempt_login(
+    app_state: &AppState,
+    tenant: CurrentTenant,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    jar: CookieJar,
+    request: LoginRequest,
+) -> (
+    CookieJar,
+    Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
+) {
+    let audit_event = |event: AuditEvent| {
+        event
+            .with_tenant(tenant.id().as_ref())
+            .with_client(client_ip.0, user_agent.as_deref())
+    };
+
+    if let Err(e) = enforce_ip_rate_limit(app_state, RateLimitedRoute::Login, &client_ip).await {
+        let event =
+            AuditEvent::failure(AuditEventType::Login, "rate limited").with_subject(&request.email);
+        record_audit_event(app_state, audit_event(event)).await;
+        return (jar, Err(e));
+    }
 
     let password = match Password::parse(request.password.clone()) {
         Ok(password) => password,
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
 
-    let email: Email = match Email::parse(request.email.clone()) {
+    let email: Email = match app_state.parse_email(request.email.clone()) {
         Ok(email) => email,
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
 
+    if let Err(e) = enforce_email_rate_limit(app_state, RateLimitedRoute::Login, &email).await {
+        let event = AuditEvent::failure(AuditEventType::Login, "rate limited").with_email(&email);
+        record_audit_event(app_state, audit_event(event)).await;
+        return (jar, Err(e));
+    }
+
     // Perform login validation in a single transaction
-    let user = {
+    let (user, roles) = {
         let user_store = app_state.user_store.read().await;
 
-        // First, check if user exists
-        let user = match user_store.get_user(&email).await {
-            Ok(user) => {
-                // User exists, now validate password
-                if let Err(_) = user_store.validate_user(&email, &password).await {
-                    return (jar, Err(AuthAPIError::IncorrectCredentials));
-                }
-                // Both user exists and password is valid - success!
-                user
-            }
-            Err(crate::domain::data_stores::UserStoreError::UserNotFound) => {
-                return (jar, Err(AuthAPIError::IncorrectCredentials));
-            }
-            Err(_) => {
-                return (jar, Err(AuthAPIError::UnexpectedError));
-            }
+        // Validate first: unknown emails go through the same (slow) password
+        // check as known ones, so timing doesn't reveal which are registered
+        if let Err(e) = user_store
+            .validate_user(tenant.id(), &email, &password)
+            .await
+        {
+            let event = AuditEvent::failure(AuditEventType::Login, login_failure_reason(&e))
+                .with_email(&email);
+            record_audit_event(app_state, audit_event(event)).await;
+            let error = handle_failed_login(e, tenant.id(), &email, client_ip, app_state).await;
+            return (jar, Err(error));
+        }
+
+        let user = match user_store.get_user(tenant.id(), &email).await {
+            Ok(user) => user,
+            Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
         };
-        user
+
+        match user_store.get_roles(tenant.id(), &email).await {
+            Ok(roles) => (user, roles),
+            Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+        }
     }; // Lock is released here
 
-    return match user.requires_2fa {
-        true => handle_2fa(&email, &app_state, jar).await,
+    // A tenant can require 2FA of all its members
+    let requires_2fa = user.requires_2fa || tenant.0.settings.requires_2fa;
+
+    let event = match requires_2fa {
+        true => AuditEvent::success(AuditEventType::Login).with_detail("2FA required"),
+        false => AuditEvent::success(AuditEventType::Login),
+    };
+    record_audit_event(app_state, audit_event(event.with_email(&email))).await;
+
+    match requires_2fa {
+        true => handle_2fa(tenant.id(), &user, client_ip, app_state, audit_event, jar).await,
         // If the user does not require 2FA, add the auth cookie to the cookie jar
-        false => handle_no_2fa(&user.email, add_auth_cookie(jar, &email).await).await,
+        false => {
+            let scope = match grant_scope(app_state, &roles, request.scope.as_deref()).await {
+                Ok(scope) => scope,
+                Err(e) => return (jar, Err(e)),
+            };
+            let jar = add_auth_cookie(
+                app_state,
+                jar,
+                tenant.id(),
+                &email,
+                &roles,
+                scope.as_deref(),
+            )
+            .await;
+            handle_no_2fa(&user.email, jar).await
+        }
+    }
+}
+
+fn login_outcome(result: &Result<(StatusCode, Json<LoginResponse>), AuthAPIError>) -> &'static str {
+    match result {
+        Ok((StatusCode::PARTIAL_CONTENT, _)) => "two_fa_required",
+        Ok(_) => "success",
+        Err(AuthAPIError::IncorrectCredentials) => "incorrect_credentials",
+        Err(AuthAPIError::AccountLocked) => "locked",
+        Err(AuthAPIError::AccountDisabled) => "disabled",
+        Err(AuthAPIError::TooManyRequests(_)) => "rate_limited",
+        Err(AuthAPIError::InvalidCredentials) => "invalid_request",
+        Err(_) => "error",
+    }
+}
+
+fn login_failure_reason(error: &UserStoreError) -> &'static str {
+    match error {
+        UserStoreError::UserNotFound => "unknown user",
+        UserStoreError::InvalidCredentials => "incorrect password",
+        UserStoreError::AccountLocked { .. } => "account locked",
+        UserStoreError::LoginThrottled { .. } => "throttled",
+        UserStoreError::AccountDisabled => "account disabled",
+        _ => "unexpected error",
+    }
+}
+
+async fn handle_failed_login(
+    error: UserStoreError,
+    tenant: &TenantId,
+    email: &Email,
+    client_ip: ClientIp,
+    app_state: &AppState,
+) -> AuthAPIError {
+    // Only registered emails get throttled and locked, so when signups don't
+    // reveal which emails are registered, neither do logins: the owner hears
+    // of a lock by email instead
+    let hide_account = app_state.config.auth.enumeration_resistant_signup;
+
+    match error {
+        UserStoreError::AccountLocked { until, just_locked } => {
+            if just_locked {
+                send_account_locked_email(email, until, client_ip, app_state).await;
+                publish_webhook_event(app_state, tenant, WebhookEventType::AccountLocked, email)
+                    .await;
+            }
+            match hide_account {
+                true => AuthAPIError::IncorrectCredentials,
+                false => AuthAPIError::AccountLocked,
+            }
+        }
+        UserStoreError::LoginThrottled { .. } if hide_account => AuthAPIError::IncorrectCredentials,
+        UserStoreError::LoginThrottled { until } => {
+            AuthAPIError::TooManyRequests((until - Utc::now()).to_std().unwrap_or_default())
+        }
+        UserStoreError::AccountDisabled => AuthAPIError::AccountDisabled,
+        UserStoreError::UnexpectedError => AuthAPIError::UnexpectedError,
+        _ => AuthAPIError::IncorrectCredentials,
+    }
+}
+
+/// Let the owner know someone has been guessing their password.
+/// The lock is already in place, so a failure here is only logged.
+async fn send_account_locked_email(
+    email: &Email,
+    until: DateTime<Utc>,
+    client_ip: ClientIp,
+    app_state: &AppState,
+) {
+    let template = EmailTemplate::AccountLocked {
+        until,
+        ip: client_ip.0,
     };
+    // One email per lock, however many requests see it
+    let idempotency_key = format!("account_locked:{}:{}", email.as_ref(), until.timestamp());
+    if let Err(e) = queue_email(app_state, email, template, idempotency_key).await {
+        tracing::error!(error = %e, "Failed to queue account locked email");
+    }
 }
 
 /// Add the auth cookie to the cookie jar
 /// If the function call fails return the original cookie jar
-async fn add_auth_cookie(jar: CookieJar, email: &Email) -> CookieJar {
-    let auth_cookie = match generate_auth_cookie(&email) {
+async fn add_auth_cookie(
+    app_state: &AppState,
+    jar: CookieJar,
+    tenant: &TenantId,
+    email: &Email,
+    roles: &[Role],
+    scope: Option<&[Permission]>,
+) -> CookieJar {
+    let config = &app_state.config.auth;
+    let auth_cookie = match generate_auth_cookie(config, tenant, email, roles, scope) {
         Ok(cookie) => cookie,
         Err(_) => return jar,
     };
@@ -72,13 +237,18 @@ async fn add_auth_cookie(jar: CookieJar, email: &Email) -> CookieJar {
 }
 
 async fn handle_2fa(
-    email: &Email,
+    tenant: &TenantId,
+    user: &User,
+    client_ip: ClientIp,
     app_state: &AppState,
+    audit_event: impl Fn(AuditEvent) -> AuditEvent,
     jar: CookieJar,
 ) -> (
     CookieJar,
     Result<(StatusCode, Json<LoginResponse>), AuthAPIError>,
 ) {
+    let email = &user.email;
+
     // Generate login attempt ID and 2FA code
     let login_attempt_id = match LoginAttemptId::parse(Uuid::new_v4().to_string()) {
         Ok(id) => id,
@@ -91,24 +261,62 @@ async fn handle_2fa(
         Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
     };
 
-    // Store the 2FA code
-    let mut two_fa_code_store = app_state.two_fa_code_store.write().await;
-    if let Err(_) = two_fa_code_store
-        .add_code(email.clone(), login_attempt_id.clone(), two_fa_code.clone())
+    // Store the 2FA code. The guard is dropped straight away, so other logins
+    // aren't held up while the code is sent
+    let added = app_state
+        .two_fa_code_store
+        .write()
         .await
-    {
+        .add_code(
+            tenant,
+            email.clone(),
+            login_attempt_id.clone(),
+            two_fa_code.clone(),
+        )
+        .await;
+    if added.is_err() {
         return (jar, Err(AuthAPIError::UnexpectedError));
     }
 
-    // Send 2FA code via the email client
-    let email_client = app_state.email_client.read().await;
-    if let Err(_) = email_client
-        .send_email(&email, "2FA code", &generated_two_fa_code)
+    // How long the 2FA code store keeps the code, as the email or text tells the user
+    let ttl_minutes = app_state.config.auth.two_fa_code_ttl_minutes;
+    let sent = match (user.two_fa_channel, &user.phone) {
+        // Texts and calls go out now rather than through an outbox, as the
+        // code is only useful for the next few minutes
+        (channel, Some(phone)) if channel.requires_phone() => send_sms_code(
+            app_state,
+            phone,
+            channel,
+            &generated_two_fa_code,
+            ttl_minutes as i64,
+        )
         .await
-    {
+        .map_err(|e| format!("{} not sent: {}", channel.as_str(), e)),
+        // Queue the 2FA code email; the outbox worker sends it
+        _ => {
+            let template = EmailTemplate::TwoFACode {
+                code: generated_two_fa_code,
+                expires_in_minutes: ttl_minutes,
+                ip: client_ip.0,
+            };
+            let idempotency_key = format!("two_fa_code:{}", login_attempt_id.as_ref());
+            queue_email(app_state, email, template, idempotency_key)
+                .await
+                .map_err(|_| "email not queued".to_owned())
+        }
+    };
+    if let Err(reason) = sent {
+        let event = AuditEvent::failure(AuditEventType::TwoFASent, &reason).with_email(email);
+        record_audit_event(app_state, audit_event(event)).await;
         return (jar, Err(AuthAPIError::UnexpectedError));
     }
 
+    let event = AuditEvent::success(AuditEventType::TwoFASent)
+        .with_email(email)
+        .with_detail(user.two_fa_channel.as_str());
+    record_audit_event(app_state, audit_event(event)).await;
+    app_state.metrics.record_two_fa_code("sent");
+
     (
         jar,
         Ok((
@@ -135,6 +343,9 @@ async fn handle_no_2fa(
 pub struct LoginRequest {
     pub email: String,
     pub password: String,
+    // The permissions to limit the token to, space-separated as in OAuth.
+    // With 2FA the token comes from /verify_2fa, so it's asked for there.
+    pub scope: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -1,5 +1,87 @@
-use axum::{http::StatusCode, response::IntoResponse};
+use axum::{extract::State, http::StatusCode};
+use axum_extra::extract::{cookie::Cookie, CookieJar};
 
-pub async fn logout() -> impl IntoResponse {
-    StatusCode::OK.into_response()
+use crate::{
+    app_state::AppState,
+    domain::{AuditEvent, AuditEventType, AuthAPIError},
+    utils::{
+        audit::record_audit_event, auth::validate_token, client_ip::ClientIp,
+        constants::JWT_COOKIE_NAME, tenant::CurrentTenant, user_agent::UserAgent,
+    },
+};
+
+// Logging out is idempotent: without a valid session there's nothing to end,
+// and the response is the same.
+pub async fn logout(
+    State(app_state): State<AppState>,
+    tenant: CurrentTenant,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    jar: CookieJar,
+) -> (CookieJar, Result<StatusCode, AuthAPIError>) {
+    let audit_event = |event: AuditEvent| {
+        event
+            .with_tenant(tenant.id().as_ref())
+            .with_client(client_ip.0, user_agent.as_deref())
+    };
+
+    let token = match jar.get(JWT_COOKIE_NAME) {
+        Some(cookie) => cookie.value().to_owned(),
+        None => {
+            let event = AuditEvent::failure(AuditEventType::Logout, "no session");
+            record_audit_event(&app_state, audit_event(event)).await;
+            return (jar, Ok(StatusCode::OK));
+        }
+    };
+
+    let jar = jar.remove(Cookie::build(JWT_COOKIE_NAME).path("/"));
+
+    let banned_token_store = app_state.banned_token_store.clone();
+    let claims = match validate_token(
+        &app_state.config.auth,
+        &token,
+        tenant.id(),
+        banned_token_store,
+    )
+    .await
+    {
+        Ok(claims) => claims,
+        Err(_) => {
+            let event = AuditEvent::failure(AuditEventType::Logout, "invalid token");
+            record_audit_event(&app_state, audit_event(event)).await;
+            return (jar, Ok(StatusCode::OK));
+        }
+    };
+
+    // An admin ending an impersonation session is named as the actor
+    let session_event = |event: AuditEvent| {
+        let event = event.with_subject(&claims.sub);
+        if claims.is_impersonated() {
+            audit_event(event.with_actor(claims.actor()))
+        } else {
+            audit_event(event)
+        }
+    };
+
+    // The token stays valid until it expires unless we ban it
+    let banned = app_state
+        .banned_token_store
+        .write()
+        .await
+        .add_token(token)
+        .await;
+
+    if banned.is_err() {
+        let event = AuditEvent::failure(AuditEventType::TokenBanned, "banned token store error");
+        record_audit_event(&app_state, session_event(event)).await;
+        return (jar, Err(AuthAPIError::UnexpectedError));
+    }
+
+    let event = AuditEvent::success(AuditEventType::TokenBanned);
+    record_audit_event(&app_state, session_event(event)).await;
+
+    let event = AuditEvent::success(AuditEventType::Logout);
+    record_audit_event(&app_state, session_event(event)).await;
+
+    (jar, Ok(StatusCode::OK))
 }
@@ -0,0 +1,24 @@
+use axum::{
+    extract::State,
+    http::{header, HeaderMap},
+    response::IntoResponse,
+};
+use prometheus::TEXT_FORMAT;
+
+use crate::{app_state::AppState, domain::AuthAPIError, routes::admin::require_admin};
+
+// For Prometheus to scrape with the admin API token, as the counts say a lot
+// about who is using the service
+pub async fn metrics(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AuthAPIError> {
+    require_admin(&state, &headers)?;
+
+    let body = state
+        .metrics
+        .render()
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(([(header::CONTENT_TYPE, TEXT_FORMAT)], body))
+}
@@ -1,13 +1,37 @@
+mod account;
+mod admin;
+mod admin_tenants;
+mod admin_users;
+mod api_keys;
+mod health;
 mod hello;
 pub mod login;
 mod logout;
+mod metrics;
+mod reset_password;
 pub mod signup;
 mod verify_2fa;
 mod verify_token;
 
+pub use account::{set_two_fa_channel, start_phone_verification, verify_phone};
+pub use admin::{
+    assign_role, create_webhook, delete_webhook, list_webhook_dead_letters, list_webhooks,
+    revoke_role, unlock_user,
+};
+pub use admin_tenants::{
+    add_tenant_member, create_tenant, list_tenants, remove_tenant_member, update_tenant_settings,
+};
+pub use admin_users::{
+    delete_user, disable_user, enable_user, get_user, impersonate_user, list_users,
+    reset_user_password, revoke_user_sessions, set_user_2fa,
+};
+pub use api_keys::{create_api_key, list_api_keys, revoke_api_key};
+pub use health::{live, ready};
 pub use hello::hello;
 pub use login::login;
 pub use logout::logout;
-pub use signup::signup;
+pub use metrics::metrics;
+pub use reset_password::reset_password;
+pub use signup::{confirm_signup, signup};
 pub use verify_2fa::verify_2fa;
 pub use verify_token::verify_token;
@@ -0,0 +1,86 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuditEvent, AuditEventType, AuthAPIError, Email, Password, WebhookEventType},
+    routes::admin_users::end_user_sessions_everywhere,
+    utils::{
+        audit::record_audit_event, client_ip::ClientIp, password_reset::hash_password_reset_token,
+        tenant::CurrentTenant, user_agent::UserAgent, webhooks::publish_webhook_event,
+    },
+};
+
+// Complete a reset started by an admin, using the token they were emailed.
+// Every existing session, in every tenant, is ended, since the old password
+// may have been compromised.
+pub async fn reset_password(
+    State(app_state): State<AppState>,
+    tenant: CurrentTenant,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Json(request): Json<ResetPasswordRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    let audit_event = |event: AuditEvent| {
+        event
+            .with_tenant(tenant.id().as_ref())
+            .with_client(client_ip.0, user_agent.as_deref())
+    };
+
+    let email = app_state
+        .parse_email(request.email)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let password =
+        Password::parse(request.password).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    tenant
+        .0
+        .settings
+        .check_password(&password)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let password_hash = app_state
+        .password_hasher
+        .hash_password(&password)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let result = app_state
+        .user_store
+        .write()
+        .await
+        .complete_password_reset(
+            &email,
+            &hash_password_reset_token(&request.token),
+            password_hash,
+        )
+        .await;
+
+    // Unknown users, wrong tokens and expired ones all look the same
+    if result.is_err() {
+        let event = AuditEvent::failure(AuditEventType::PasswordReset, "invalid reset token")
+            .with_email(&email);
+        record_audit_event(&app_state, audit_event(event)).await;
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    end_user_sessions_everywhere(&app_state, &email).await?;
+
+    let event = AuditEvent::success(AuditEventType::PasswordReset).with_email(&email);
+    record_audit_event(&app_state, audit_event(event)).await;
+    publish_webhook_event(
+        &app_state,
+        tenant.id(),
+        WebhookEventType::PasswordChanged,
+        &email,
+    )
+    .await;
+
+    Ok(StatusCode::OK)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetPasswordRequest {
+    pub email: String,
+    pub token: String,
+    pub password: String,
+}
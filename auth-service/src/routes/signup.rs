@@ -1,35 +1,101 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, Password, User},
+    domain::{
+        AuditEvent, AuditEventType, AuthAPIError, Email, Password, TenantId, User, UserStoreError,
+        WebhookEventType,
+    },
+    utils::{
+        audit::record_audit_event,
+        client_ip::ClientIp,
+        email_outbox::queue_email,
+        email_templates::EmailTemplate,
+        password_reset::{generate_password_reset_token, hash_password_reset_token},
+        rate_limit::{enforce_email_rate_limit, enforce_ip_rate_limit, RateLimitedRoute},
+        tenant::CurrentTenant,
+        user_agent::UserAgent,
+        webhooks::publish_webhook_event,
+    },
 };
 
+/// How long the code in the welcome email confirms the signup for.
+pub const SIGNUP_CONFIRMATION_TTL: Duration = Duration::days(1);
+
 // TODO: Use Axum's state extractor to pass in AppState
 pub async fn signup(
     State(app_state): State<AppState>,
+    tenant: CurrentTenant,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
     Json(request): Json<SignupRequest>,
 ) -> Result<impl IntoResponse, AuthAPIError> {
-    println!("Signup endpoint called!"); // Add this
+    let audit_event = |event: AuditEvent| {
+        event
+            .with_tenant(tenant.id().as_ref())
+            .with_client(client_ip.0, user_agent.as_deref())
+    };
+
+    if let Err(e) = enforce_ip_rate_limit(&app_state, RateLimitedRoute::Signup, &client_ip).await {
+        let event = AuditEvent::failure(AuditEventType::Signup, "rate limited")
+            .with_subject(&request.email);
+        record_audit_event(&app_state, audit_event(event)).await;
+        return Err(e);
+    }
 
-    let email =
-        Email::parse(request.email.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    let email = app_state
+        .parse_email(request.email.clone())
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    if let Err(e) = enforce_email_rate_limit(&app_state, RateLimitedRoute::Signup, &email).await {
+        let event = AuditEvent::failure(AuditEventType::Signup, "rate limited").with_email(&email);
+        record_audit_event(&app_state, audit_event(event)).await;
+        return Err(e);
+    }
     let password =
         Password::parse(request.password.clone()).map_err(|_| AuthAPIError::InvalidCredentials)?;
+    // Tenants can ask for more than the global minimum
+    tenant
+        .0
+        .settings
+        .check_password(&password)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
 
-    let user = User::new(email, password, request.requires_2fa);
+    let password_hash = app_state
+        .password_hasher
+        .hash_password(&password)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
 
-    // Use the async trait method to add the user
-    let mut user_store = app_state.user_store.write().await;
+    let user = User::new(email.clone(), password_hash, request.requires_2fa);
 
-    if user_store.get_user(&user.email).await.is_ok() {
-        return Err(AuthAPIError::UserAlreadyExists);
+    // With enumeration-resistant signup the account stays pending until the
+    // owner of the address confirms it, or logging in would show it was new.
+    // The token is the same kind as a password reset's.
+    let confirmation = app_state.config.auth.enumeration_resistant_signup.then(|| {
+        (
+            generate_password_reset_token(),
+            Utc::now() + SIGNUP_CONFIRMATION_TTL,
+        )
+    });
+
+    let created = add_user(&app_state, tenant.id(), user, confirmation.as_ref()).await?;
+    let outcome = if created { "created" } else { "already_exists" };
+    app_state.metrics.record_signup(outcome);
+    record_audit_event(&app_state, audit_event(signup_event(created, &email))).await;
+
+    if let Some(confirmation) = confirmation {
+        let confirmation = created.then_some(confirmation);
+        return send_signup_outcome(&app_state, &email, confirmation, client_ip).await;
     }
 
-    if user_store.add_user(user).await.is_err() {
-        return Err(AuthAPIError::UnexpectedError);
+    if !created {
+        return Err(AuthAPIError::UserAlreadyExists);
     }
+    publish_user_created(&app_state, tenant.id(), &email, request.requires_2fa).await;
 
     let response = Json(SignupResponse {
         message: "User created successfully!".to_string(),
@@ -38,6 +104,132 @@ pub async fn signup(
     Ok((StatusCode::CREATED, response))
 }
 
+// Whether the user was added, `false` if the email is already registered.
+// That includes registrations in other tenants: joining one is up to its admins.
+async fn add_user(
+    app_state: &AppState,
+    tenant: &TenantId,
+    user: User,
+    confirmation: Option<&(String, DateTime<Utc>)>,
+) -> Result<bool, AuthAPIError> {
+    let mut user_store = app_state.user_store.write().await;
+    let result = match confirmation {
+        Some((token, expires_at)) => {
+            let token_hash = hash_password_reset_token(token);
+            user_store
+                .add_pending_user(tenant, user, &token_hash, *expires_at)
+                .await
+        }
+        None => user_store.add_user(tenant, user).await,
+    };
+    match result {
+        Ok(()) => Ok(true),
+        Err(UserStoreError::UserAlreadyExists) => Ok(false),
+        Err(_) => Err(AuthAPIError::UnexpectedError),
+    }
+}
+
+fn signup_event(created: bool, email: &Email) -> AuditEvent {
+    let event = if created {
+        AuditEvent::success(AuditEventType::Signup)
+    } else {
+        AuditEvent::failure(AuditEventType::Signup, "user already exists")
+    };
+    event.with_email(email)
+}
+
+async fn publish_user_created(
+    app_state: &AppState,
+    tenant: &TenantId,
+    email: &Email,
+    requires_2fa: bool,
+) {
+    publish_webhook_event(app_state, tenant, WebhookEventType::UserCreated, email).await;
+    if requires_2fa {
+        publish_webhook_event(app_state, tenant, WebhookEventType::TwoFAEnabled, email).await;
+    }
+}
+
+// The response is the same whether or not the email was already registered;
+// only the owner of the address learns which it was. `confirmation` is the
+// token and expiry of the account that was made, if one was.
+async fn send_signup_outcome(
+    app_state: &AppState,
+    email: &Email,
+    confirmation: Option<(String, DateTime<Utc>)>,
+    client_ip: ClientIp,
+) -> Result<(StatusCode, Json<SignupResponse>), AuthAPIError> {
+    let template = match confirmation {
+        Some((code, expires_at)) => EmailTemplate::Welcome { code, expires_at },
+        None => EmailTemplate::SignupAttempt { ip: client_ip.0 },
+    };
+
+    // Every signup request is worth its own email, so the key is never reused
+    let idempotency_key = format!("signup:{}", Uuid::new_v4());
+    if queue_email(app_state, email, template, idempotency_key)
+        .await
+        .is_err()
+    {
+        return Err(AuthAPIError::UnexpectedError);
+    }
+
+    let response = Json(SignupResponse {
+        message: "Check your email to finish signing up".to_string(),
+    });
+
+    Ok((StatusCode::ACCEPTED, response))
+}
+
+// Finish an enumeration-resistant signup with the code from the welcome email
+pub async fn confirm_signup(
+    State(app_state): State<AppState>,
+    tenant: CurrentTenant,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
+    Json(request): Json<ConfirmSignupRequest>,
+) -> Result<StatusCode, AuthAPIError> {
+    let audit_event = |event: AuditEvent| {
+        event
+            .with_tenant(tenant.id().as_ref())
+            .with_client(client_ip.0, user_agent.as_deref())
+    };
+
+    let email = app_state
+        .parse_email(request.email)
+        .map_err(|_| AuthAPIError::InvalidCredentials)?;
+
+    let result = app_state
+        .user_store
+        .write()
+        .await
+        .confirm_signup(&email, &hash_password_reset_token(&request.token))
+        .await;
+
+    // Unknown users, wrong tokens and expired ones all look the same
+    if result.is_err() {
+        let event = AuditEvent::failure(AuditEventType::SignupConfirmed, "invalid signup token")
+            .with_email(&email);
+        record_audit_event(&app_state, audit_event(event)).await;
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    let event = AuditEvent::success(AuditEventType::SignupConfirmed).with_email(&email);
+    record_audit_event(&app_state, audit_event(event)).await;
+
+    // Webhooks go to the tenant the user signed up in
+    let user = app_state
+        .user_store
+        .read()
+        .await
+        .get_user(tenant.id(), &email)
+        .await;
+    if let Ok(user) = user {
+        publish_user_created(&app_state, tenant.id(), &email, user.requires_2fa).await;
+    }
+
+    Ok(StatusCode::OK)
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug)]
 pub struct SignupResponse {
     pub message: String,
@@ -50,3 +242,9 @@ pub struct SignupRequest {
     #[serde(rename = "requires2FA")]
     pub requires_2fa: bool,
 }
+
+#[derive(Deserialize)]
+pub struct ConfirmSignupRequest {
+    pub email: String,
+    pub token: String,
+}
@@ -1,24 +1,56 @@
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use axum_extra::extract::CookieJar;
 use serde::Deserialize;
+use subtle::ConstantTimeEq;
 
 use crate::{
     app_state::AppState,
-    domain::{AuthAPIError, Email, LoginAttemptId, TwoFACode},
-    utils::auth::generate_auth_cookie,
+    domain::{AuditEvent, AuditEventType, AuthAPIError, Email, LoginAttemptId, TwoFACode},
+    utils::{
+        audit::record_audit_event,
+        auth::{generate_auth_cookie, grant_scope},
+        client_ip::ClientIp,
+        rate_limit::{enforce_email_rate_limit, enforce_ip_rate_limit, RateLimitedRoute},
+        tenant::CurrentTenant,
+        user_agent::UserAgent,
+    },
 };
 
 pub async fn verify_2fa(
     State(app_state): State<AppState>,
+    tenant: CurrentTenant,
+    client_ip: ClientIp,
+    user_agent: UserAgent,
     jar: CookieJar,
     Json(request): Json<Verify2FARequest>,
 ) -> (CookieJar, Result<impl IntoResponse, AuthAPIError>) {
+    let audit_event = |event: AuditEvent| {
+        event
+            .with_tenant(tenant.id().as_ref())
+            .with_client(client_ip.0, user_agent.as_deref())
+    };
+
+    if let Err(e) = enforce_ip_rate_limit(&app_state, RateLimitedRoute::Verify2FA, &client_ip).await
+    {
+        let event = AuditEvent::failure(AuditEventType::TwoFAVerification, "rate limited")
+            .with_subject(&request.email);
+        record_audit_event(&app_state, audit_event(event)).await;
+        return (jar, Err(e));
+    }
 
-    let email = match Email::parse(request.email.clone()) {
+    let email = match app_state.parse_email(request.email.clone()) {
         Ok(email) => email,
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
     };
 
+    if let Err(e) = enforce_email_rate_limit(&app_state, RateLimitedRoute::Verify2FA, &email).await
+    {
+        let event = AuditEvent::failure(AuditEventType::TwoFAVerification, "rate limited")
+            .with_email(&email);
+        record_audit_event(&app_state, audit_event(event)).await;
+        return (jar, Err(e));
+    }
+
     let login_attempt_id = match LoginAttemptId::parse(request.login_attempt_id.clone()) {
         Ok(login_attempt_id) => login_attempt_id,
         Err(_) => return (jar, Err(AuthAPIError::InvalidCredentials)),
@@ -31,26 +63,73 @@ pub async fn verify_2fa(
 
     let mut two_fa_code_store = app_state.two_fa_code_store.write().await;
 
-    let code_tuple = match two_fa_code_store.get_code(&email).await {
+    let code_tuple = match two_fa_code_store.get_code(tenant.id(), &email).await {
         Ok(code_tuple) => code_tuple,
-        Err(_) => return (jar, Err(AuthAPIError::IncorrectCredentials)),
+        Err(_) => {
+            let event = AuditEvent::failure(AuditEventType::TwoFAVerification, "no pending code")
+                .with_email(&email);
+            record_audit_event(&app_state, audit_event(event)).await;
+            app_state.metrics.record_two_fa_code("failed");
+            return (jar, Err(AuthAPIError::IncorrectCredentials));
+        }
     };
 
-    if !code_tuple.0.eq(&login_attempt_id) || !code_tuple.1.eq(&two_fa_code) {
+    // Compare in constant time, and both values, so a mismatch can't be
+    // narrowed down from how quickly it was rejected
+    let (stored_login_attempt_id, stored_two_fa_code) = code_tuple;
+    let login_attempt_id_matches = stored_login_attempt_id
+        .as_ref()
+        .as_bytes()
+        .ct_eq(login_attempt_id.as_ref().as_bytes());
+    let two_fa_code_matches = stored_two_fa_code
+        .as_ref()
+        .as_bytes()
+        .ct_eq(two_fa_code.as_ref().as_bytes());
+
+    if !bool::from(login_attempt_id_matches & two_fa_code_matches) {
+        let event = AuditEvent::failure(AuditEventType::TwoFAVerification, "incorrect code")
+            .with_email(&email);
+        record_audit_event(&app_state, audit_event(event)).await;
+        app_state.metrics.record_two_fa_code("failed");
         return (jar, Err(AuthAPIError::IncorrectCredentials));
     }
 
-    if two_fa_code_store.remove_code(&email).await.is_err() {
+    if two_fa_code_store
+        .remove_code(tenant.id(), &email)
+        .await
+        .is_err()
+    {
         return (jar, Err(AuthAPIError::UnexpectedError));
     }
 
-    let cookie = match generate_auth_cookie(&email) {
+    let roles = match app_state
+        .user_store
+        .read()
+        .await
+        .get_roles(tenant.id(), &email)
+        .await
+    {
+        Ok(roles) => roles,
+        Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
+    };
+
+    let scope = match grant_scope(&app_state, &roles, request.scope.as_deref()).await {
+        Ok(scope) => scope,
+        Err(e) => return (jar, Err(e)),
+    };
+
+    let config = &app_state.config.auth;
+    let cookie = match generate_auth_cookie(config, tenant.id(), &email, &roles, scope.as_deref()) {
         Ok(cookie) => cookie,
         Err(_) => return (jar, Err(AuthAPIError::UnexpectedError)),
     };
 
     let updated_jar = jar.add(cookie);
 
+    let event = AuditEvent::success(AuditEventType::TwoFAVerification).with_email(&email);
+    record_audit_event(&app_state, audit_event(event)).await;
+    app_state.metrics.record_two_fa_code("verified");
+
     (updated_jar, Ok(StatusCode::OK.into_response()))
 }
 
@@ -61,4 +140,6 @@ pub struct Verify2FARequest {
     pub login_attempt_id: String,
     #[serde(rename = "2FACode")]
     pub code: String,
+    // Like `scope` at /login, for the token issued here
+    pub scope: Option<String>,
 }
@@ -1,21 +1,72 @@
 #![allow(unused_variables)]
 
-use crate::utils::auth::validate_token;
+use crate::domain::Permission;
+use crate::utils::{
+    auth::{authenticate_credential, bearer_credential, require_scopes, Actor},
+    tenant::CurrentTenant,
+};
 use crate::{app_state::AppState, domain::AuthAPIError};
-use axum::{extract::State, http::StatusCode, Json};
-use serde::Deserialize;
+use axum::{extract::State, http::HeaderMap, Json};
+use serde::{Deserialize, Serialize};
 
+// The credential is a token or API key, from the body or an
+// `Authorization: Bearer` header; the body wins if both are sent. With a
+// bearer credential the body is optional. The response says whose it is, and
+// which admin is acting for them if it's an impersonation token.
 pub async fn verify_token(
     State(state): State<AppState>,
-    Json(request): Json<VerifyTokenRequest>,
-) -> Result<StatusCode, AuthAPIError> {
-    match validate_token(&request.token, state.banned_token_store.clone()).await {
-        Ok(user) => Ok(StatusCode::OK),
-        Err(e) => Err(AuthAPIError::InvalidToken),
+    tenant: CurrentTenant,
+    headers: HeaderMap,
+    request: Option<Json<VerifyTokenRequest>>,
+) -> Result<Json<VerifyTokenResponse>, AuthAPIError> {
+    let request = request.map(|Json(request)| request).unwrap_or_default();
+    let credential = request
+        .token
+        .as_deref()
+        .or_else(|| bearer_credential(&headers))
+        .ok_or(AuthAPIError::InvalidToken)?;
+    let claims = authenticate_credential(&state, tenant.id(), credential).await?;
+
+    if let Some(required_role) = request.required_role {
+        if !claims.has_role(&required_role) {
+            return Err(AuthAPIError::Forbidden);
+        }
     }
+
+    // Resolved from the scope of the credential, or the roles in it when it
+    // has none, like `required_role`. Nobody holds a scope that can't even
+    // be parsed.
+    let required_scopes: Vec<Permission> = request
+        .required_permission
+        .into_iter()
+        .chain(request.required_scopes)
+        .map(Permission::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| AuthAPIError::Forbidden)?;
+    require_scopes(&state, &claims, &required_scopes).await?;
+
+    Ok(Json(VerifyTokenResponse {
+        sub: claims.sub,
+        impersonated: claims.act.is_some(),
+        act: claims.act,
+    }))
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 pub struct VerifyTokenRequest {
-    pub token: String,
+    pub token: Option<String>,
+    // Also refuse valid tokens that lack this role, permission or any of
+    // these scopes (403)
+    pub required_role: Option<String>,
+    pub required_permission: Option<String>,
+    #[serde(default)]
+    pub required_scopes: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyTokenResponse {
+    pub sub: String,
+    pub impersonated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
 }
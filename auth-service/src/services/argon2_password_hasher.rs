@@ -0,0 +1,364 @@
+use argon2::{
+    password_hash::{
+        rand_core::OsRng, PasswordHash as PhcString, PasswordHasher as _, PasswordVerifier as _,
+        SaltString,
+    },
+    Algorithm, Argon2, KeyId, Params, ParamsBuilder, Version,
+};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tokio::sync::OnceCell;
+
+use crate::domain::{Password, PasswordHash, PasswordHasher, PasswordHasherError};
+
+/// Cost parameters and optional pepper used when hashing passwords with
+/// Argon2id, the `[argon2]` config section.
+///
+/// Stored hashes carry the parameters they were created with, so these can be
+/// raised over time: `validate_user` re-hashes any password whose stored hash
+/// doesn't match the current settings. Peppered hashes also carry an id of the
+/// pepper as their `keyid`, so hashes made before a pepper was set (or with
+/// another one) are re-hashed in the same way.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Argon2Settings {
+    pub memory_cost: u32, // in KiB
+    pub time_cost: u32,
+    pub parallelism: u32,
+    pub pepper: Option<String>,
+}
+
+impl Default for Argon2Settings {
+    fn default() -> Self {
+        Self {
+            memory_cost: Params::DEFAULT_M_COST,
+            time_cost: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+            pepper: None,
+        }
+    }
+}
+
+impl Argon2Settings {
+    /// Whether Argon2 accepts the cost parameters, so that bad settings fail
+    /// at startup rather than on the first signup.
+    pub fn validate(&self) -> Result<(), String> {
+        self.params().map(|_| ())
+    }
+
+    fn params(&self) -> Result<Params, String> {
+        let mut builder = ParamsBuilder::new();
+        builder
+            .m_cost(self.memory_cost)
+            .t_cost(self.time_cost)
+            .p_cost(self.parallelism);
+        if let Some(pepper_id) = self.pepper_id() {
+            builder.keyid(pepper_id);
+        }
+        builder
+            .build()
+            .map_err(|e| format!("Invalid Argon2 parameters: {}", e))
+    }
+
+    // Tells peppers apart without giving away anything useful about them
+    fn pepper_id(&self) -> Option<KeyId> {
+        let pepper = self.pepper.as_ref()?;
+        let digest = Sha256::digest(pepper.as_bytes());
+        KeyId::new(&digest[..4]).ok()
+    }
+
+    fn argon2(&self, params: Params) -> Result<Argon2<'_>, PasswordHasherError> {
+        // Only hashes with a key id were made with a pepper
+        let pepper = self.pepper.as_ref().filter(|_| !params.keyid().is_empty());
+
+        match pepper {
+            Some(pepper) => Argon2::new_with_secret(
+                pepper.as_bytes(),
+                Algorithm::Argon2id,
+                Version::V0x13,
+                params,
+            )
+            .map_err(|_| PasswordHasherError::UnexpectedError),
+            None => Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params)),
+        }
+    }
+
+    fn hash(&self, password: &Password) -> Result<PasswordHash, PasswordHasherError> {
+        let salt = SaltString::generate(&mut OsRng);
+        let params = self
+            .params()
+            .map_err(|_| PasswordHasherError::UnexpectedError)?;
+        let hash = self
+            .argon2(params)?
+            .hash_password(password.as_ref().as_bytes(), &salt)
+            .map_err(|_| PasswordHasherError::UnexpectedError)?;
+
+        PasswordHash::parse(hash.to_string()).map_err(|_| PasswordHasherError::UnexpectedError)
+    }
+
+    fn verify(
+        &self,
+        password: &Password,
+        password_hash: &PasswordHash,
+    ) -> Result<(), PasswordHasherError> {
+        let parsed_hash = PhcString::new(password_hash.as_ref())
+            .map_err(|_| PasswordHasherError::UnexpectedError)?;
+
+        let params =
+            Params::try_from(&parsed_hash).map_err(|_| PasswordHasherError::UnexpectedError)?;
+
+        // The algorithm, version and cost parameters are taken from the stored
+        // hash, only the pepper comes from our settings. Hashes made before the
+        // pepper was set are verified without it, until they're re-hashed.
+        self.argon2(params)?
+            .verify_password(password.as_ref().as_bytes(), &parsed_hash)
+            .map_err(|e| match e {
+                argon2::password_hash::Error::Password => PasswordHasherError::IncorrectPassword,
+                _ => PasswordHasherError::UnexpectedError,
+            })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Argon2PasswordHasher {
+    settings: Argon2Settings,
+    // Made with the current settings on first use, so verifying against it
+    // costs the same as verifying a real user's (up to date) hash.
+    dummy_hash: Arc<OnceCell<PasswordHash>>,
+}
+
+impl Argon2PasswordHasher {
+    pub fn new(settings: Argon2Settings) -> Self {
+        Self {
+            settings,
+            dummy_hash: Arc::default(),
+        }
+    }
+
+    async fn dummy_hash(&self) -> Result<&PasswordHash, PasswordHasherError> {
+        self.dummy_hash
+            .get_or_try_init(|| async {
+                let password = Password::parse(uuid::Uuid::new_v4().to_string())
+                    .map_err(|_| PasswordHasherError::UnexpectedError)?;
+                self.hash_password(&password).await
+            })
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl PasswordHasher for Argon2PasswordHasher {
+    async fn hash_password(
+        &self,
+        password: &Password,
+    ) -> Result<PasswordHash, PasswordHasherError> {
+        // Argon2 is deliberately slow, keep it off the async worker threads
+        let settings = self.settings.clone();
+        let password = password.clone();
+
+        tokio::task::spawn_blocking(move || settings.hash(&password))
+            .await
+            .map_err(|_| PasswordHasherError::UnexpectedError)?
+    }
+
+    async fn verify_password(
+        &self,
+        password: &Password,
+        password_hash: &PasswordHash,
+    ) -> Result<(), PasswordHasherError> {
+        let settings = self.settings.clone();
+        let password = password.clone();
+        let password_hash = password_hash.clone();
+
+        tokio::task::spawn_blocking(move || settings.verify(&password, &password_hash))
+            .await
+            .map_err(|_| PasswordHasherError::UnexpectedError)?
+    }
+
+    async fn verify_dummy_password(&self, password: &Password) {
+        if let Ok(dummy_hash) = self.dummy_hash().await {
+            let _ = self.verify_password(password, dummy_hash).await;
+        }
+    }
+
+    fn needs_rehash(&self, password_hash: &PasswordHash) -> bool {
+        let parsed_hash = match PhcString::new(password_hash.as_ref()) {
+            Ok(hash) => hash,
+            Err(_) => return true,
+        };
+
+        if parsed_hash.algorithm != Algorithm::Argon2id.ident() {
+            return true;
+        }
+
+        if parsed_hash.version != Some(Version::V0x13.into()) {
+            return true;
+        }
+
+        match Params::try_from(&parsed_hash) {
+            Ok(params) => {
+                params.m_cost() != self.settings.memory_cost
+                    || params.t_cost() != self.settings.time_cost
+                    || params.p_cost() != self.settings.parallelism
+                    || params.keyid() != self.settings.pepper_id().unwrap_or_default().as_bytes()
+            }
+            Err(_) => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_with(settings: &Argon2Settings, algorithm: Algorithm) -> PasswordHash {
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Argon2::new(algorithm, Version::V0x13, settings.params().unwrap())
+            .hash_password(b"password123", &salt)
+            .unwrap()
+            .to_string();
+        PasswordHash::parse(hash).unwrap()
+    }
+
+    fn cheap_settings() -> Argon2Settings {
+        Argon2Settings {
+            memory_cost: 64,
+            time_cost: 1,
+            parallelism: 1,
+            pepper: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hash_and_verify_password() {
+        let hasher = Argon2PasswordHasher::new(cheap_settings());
+        let password = Password::parse("password123".to_owned()).unwrap();
+
+        let hash = hasher.hash_password(&password).await.unwrap();
+
+        assert_ne!(hash.as_ref(), password.as_ref());
+        assert_eq!(hasher.verify_password(&password, &hash).await, Ok(()));
+
+        let wrong_password = Password::parse("wrongpassword".to_owned()).unwrap();
+        assert_eq!(
+            hasher.verify_password(&wrong_password, &hash).await,
+            Err(PasswordHasherError::IncorrectPassword)
+        );
+    }
+
+    #[tokio::test]
+    async fn dummy_hash_is_made_once_with_current_settings() {
+        let hasher = Argon2PasswordHasher::new(cheap_settings());
+        let password = Password::parse("password123".to_owned()).unwrap();
+
+        hasher.verify_dummy_password(&password).await;
+        let dummy_hash = hasher.dummy_hash().await.unwrap().clone();
+
+        hasher.verify_dummy_password(&password).await;
+        assert_eq!(hasher.dummy_hash().await.unwrap(), &dummy_hash);
+        assert!(!hasher.needs_rehash(&dummy_hash));
+        assert_eq!(
+            hasher.verify_password(&password, &dummy_hash).await,
+            Err(PasswordHasherError::IncorrectPassword)
+        );
+    }
+
+    #[test]
+    fn hash_with_current_settings_does_not_need_rehash() {
+        let settings = cheap_settings();
+        let hash = hash_with(&settings, Algorithm::Argon2id);
+
+        assert!(!Argon2PasswordHasher::new(settings).needs_rehash(&hash));
+    }
+
+    #[test]
+    fn hash_with_weaker_parameters_needs_rehash() {
+        let old_settings = cheap_settings();
+        let hash = hash_with(&old_settings, Algorithm::Argon2id);
+
+        let new_settings = Argon2Settings {
+            memory_cost: 128,
+            time_cost: 2,
+            ..old_settings
+        };
+
+        assert!(Argon2PasswordHasher::new(new_settings).needs_rehash(&hash));
+    }
+
+    #[test]
+    fn hash_with_other_algorithm_needs_rehash() {
+        let settings = cheap_settings();
+        let hash = hash_with(&settings, Algorithm::Argon2i);
+
+        assert!(Argon2PasswordHasher::new(settings).needs_rehash(&hash));
+    }
+
+    #[tokio::test]
+    async fn peppered_hash_only_verifies_with_the_pepper() {
+        let peppered = Argon2PasswordHasher::new(Argon2Settings {
+            pepper: Some("pepper".to_owned()),
+            ..cheap_settings()
+        });
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let hash = peppered.hash_password(&password).await.unwrap();
+
+        assert_eq!(peppered.verify_password(&password, &hash).await, Ok(()));
+        assert_eq!(
+            Argon2PasswordHasher::new(cheap_settings())
+                .verify_password(&password, &hash)
+                .await,
+            Err(PasswordHasherError::IncorrectPassword)
+        );
+    }
+
+    #[tokio::test]
+    async fn unpeppered_hash_verifies_and_needs_rehash_once_a_pepper_is_set() {
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let old_hash = Argon2PasswordHasher::new(cheap_settings())
+            .hash_password(&password)
+            .await
+            .unwrap();
+
+        let peppered = Argon2PasswordHasher::new(Argon2Settings {
+            pepper: Some("pepper".to_owned()),
+            ..cheap_settings()
+        });
+        assert_eq!(peppered.verify_password(&password, &old_hash).await, Ok(()));
+        assert!(peppered.needs_rehash(&old_hash));
+
+        let new_hash = peppered.hash_password(&password).await.unwrap();
+        assert_eq!(peppered.verify_password(&password, &new_hash).await, Ok(()));
+        assert!(!peppered.needs_rehash(&new_hash));
+    }
+
+    #[tokio::test]
+    async fn hash_with_another_pepper_needs_rehash() {
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let hash = Argon2PasswordHasher::new(Argon2Settings {
+            pepper: Some("old pepper".to_owned()),
+            ..cheap_settings()
+        })
+        .hash_password(&password)
+        .await
+        .unwrap();
+
+        let rotated = Argon2PasswordHasher::new(Argon2Settings {
+            pepper: Some("new pepper".to_owned()),
+            ..cheap_settings()
+        });
+        assert!(rotated.needs_rehash(&hash));
+        assert!(Argon2PasswordHasher::new(cheap_settings()).needs_rehash(&hash));
+    }
+
+    #[test]
+    fn invalid_parameters_are_rejected() {
+        let settings = Argon2Settings {
+            memory_cost: 1,
+            ..cheap_settings()
+        };
+
+        assert!(settings.params().is_err());
+    }
+}
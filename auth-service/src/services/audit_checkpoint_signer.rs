@@ -0,0 +1,102 @@
+use ring::signature::{self, Ed25519KeyPair, KeyPair};
+
+use crate::config::AuditConfig;
+use crate::domain::{AuditCheckpoint, SignedAuditCheckpoint};
+
+/// Signs audit checkpoints with Ed25519, so they can be checked off-site with
+/// just the public key.
+pub struct AuditCheckpointSigner {
+    key_pair: Ed25519KeyPair,
+}
+
+impl AuditCheckpointSigner {
+    /// `seed` is the 32 byte Ed25519 private key.
+    pub fn from_seed(seed: &[u8]) -> Result<Self, String> {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(seed)
+            .map_err(|_| "Audit checkpoint signing key must be 32 bytes".to_owned())?;
+        Ok(Self { key_pair })
+    }
+
+    /// The signer for the hex encoded seed in `audit.checkpoint_signing_key`.
+    /// Checkpoints are disabled (`Ok(None)`) when it isn't set.
+    pub fn from_config(config: &AuditConfig) -> Result<Option<Self>, String> {
+        let Some(seed) = &config.checkpoint_signing_key else {
+            return Ok(None);
+        };
+
+        let invalid = || {
+            "audit.checkpoint_signing_key (AUDIT_CHECKPOINT_SIGNING_KEY) must be 32 hex \
+             encoded bytes"
+                .to_owned()
+        };
+        let seed = hex::decode(seed.trim()).map_err(|_| invalid())?;
+
+        Self::from_seed(&seed).map(Some).map_err(|_| invalid())
+    }
+
+    pub fn public_key(&self) -> String {
+        hex::encode(self.key_pair.public_key().as_ref())
+    }
+
+    pub fn sign(&self, checkpoint: AuditCheckpoint) -> SignedAuditCheckpoint {
+        let signature = self.key_pair.sign(&checkpoint.signing_payload());
+
+        SignedAuditCheckpoint {
+            public_key: self.public_key(),
+            signature: hex::encode(signature.as_ref()),
+            checkpoint,
+        }
+    }
+}
+
+/// Whether the checkpoint was signed by the private key for its `public_key`.
+/// Callers should also check that `public_key` is one they trust.
+pub fn verify_checkpoint_signature(signed: &SignedAuditCheckpoint) -> bool {
+    let (Ok(public_key), Ok(signature)) = (
+        hex::decode(&signed.public_key),
+        hex::decode(&signed.signature),
+    ) else {
+        return false;
+    };
+
+    signature::UnparsedPublicKey::new(&signature::ED25519, public_key)
+        .verify(&signed.checkpoint.signing_payload(), &signature)
+        .is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn checkpoint() -> AuditCheckpoint {
+        AuditCheckpoint {
+            created_at: Utc::now(),
+            last_record_id: 42,
+            last_hash: "ab".repeat(32),
+        }
+    }
+
+    #[test]
+    fn test_signed_checkpoint_verifies() {
+        let signer = AuditCheckpointSigner::from_seed(&[7; 32]).unwrap();
+        let signed = signer.sign(checkpoint());
+
+        assert_eq!(signed.public_key, signer.public_key());
+        assert!(verify_checkpoint_signature(&signed));
+    }
+
+    #[test]
+    fn test_altered_checkpoint_does_not_verify() {
+        let signer = AuditCheckpointSigner::from_seed(&[7; 32]).unwrap();
+        let mut signed = signer.sign(checkpoint());
+        signed.checkpoint.last_record_id += 1;
+
+        assert!(!verify_checkpoint_signature(&signed));
+    }
+
+    #[test]
+    fn test_seed_must_be_32_bytes() {
+        assert!(AuditCheckpointSigner::from_seed(&[7; 16]).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use crate::domain::{ApiKey, ApiKeyStore, ApiKeyStoreError, Email, TenantId};
+
+// In-memory API keys, each with the hash it's looked up by
+#[derive(Default)]
+pub struct HashmapApiKeyStore {
+    keys: HashMap<Uuid, (ApiKey, String)>,
+}
+
+#[async_trait::async_trait]
+impl ApiKeyStore for HashmapApiKeyStore {
+    async fn add_key(&mut self, key: ApiKey, key_hash: &str) -> Result<(), ApiKeyStoreError> {
+        // Ids and hashes are random, so a clash means something is badly wrong
+        let clash =
+            self.keys.contains_key(&key.id) || self.keys.values().any(|(_, hash)| hash == key_hash);
+        if clash {
+            return Err(ApiKeyStoreError::UnexpectedError);
+        }
+
+        self.keys.insert(key.id, (key, key_hash.to_owned()));
+        Ok(())
+    }
+
+    async fn get_key_by_hash(&self, key_hash: &str) -> Result<ApiKey, ApiKeyStoreError> {
+        self.keys
+            .values()
+            .find(|(_, hash)| hash == key_hash)
+            .map(|(key, _)| key.clone())
+            .ok_or(ApiKeyStoreError::KeyNotFound)
+    }
+
+    async fn get_keys(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<Vec<ApiKey>, ApiKeyStoreError> {
+        let mut keys: Vec<ApiKey> = self
+            .keys
+            .values()
+            .map(|(key, _)| key)
+            .filter(|key| &key.tenant == tenant && &key.email == email)
+            .cloned()
+            .collect();
+        keys.sort_by_key(|key| key.created_at);
+        Ok(keys)
+    }
+
+    async fn revoke_key(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<(), ApiKeyStoreError> {
+        match self.keys.get(&id) {
+            Some((key, _)) if &key.tenant == tenant && &key.email == email => {
+                self.keys.remove(&id);
+                Ok(())
+            }
+            _ => Err(ApiKeyStoreError::KeyNotFound),
+        }
+    }
+
+    async fn mark_used(&mut self, id: Uuid, at: DateTime<Utc>) -> Result<(), ApiKeyStoreError> {
+        let (key, _) = self
+            .keys
+            .get_mut(&id)
+            .ok_or(ApiKeyStoreError::KeyNotFound)?;
+        key.last_used_at = Some(at);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::domain::Permission;
+
+    fn api_key(email: &Email) -> ApiKey {
+        let now = Utc::now();
+        ApiKey {
+            id: Uuid::new_v4(),
+            tenant: TenantId::default(),
+            email: email.clone(),
+            name: "ci".to_owned(),
+            scopes: vec![Permission::parse("users:read".to_owned()).unwrap()],
+            created_at: now,
+            expires_at: now + Duration::days(30),
+            last_used_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_add_find_and_mark_used() {
+        let mut store = HashmapApiKeyStore::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let key = api_key(&email);
+        store.add_key(key.clone(), "hash").await.unwrap();
+
+        assert_eq!(store.get_key_by_hash("hash").await, Ok(key.clone()));
+        assert_eq!(
+            store.get_key_by_hash("other").await,
+            Err(ApiKeyStoreError::KeyNotFound)
+        );
+
+        let used_at = Utc::now();
+        store.mark_used(key.id, used_at).await.unwrap();
+        let keys = store.get_keys(&TenantId::default(), &email).await.unwrap();
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].last_used_at, Some(used_at));
+    }
+
+    #[tokio::test]
+    async fn test_only_the_owner_can_revoke() {
+        let mut store = HashmapApiKeyStore::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let other = Email::parse("other@example.com".to_owned()).unwrap();
+        let key = api_key(&email);
+        store.add_key(key.clone(), "hash").await.unwrap();
+
+        assert_eq!(
+            store.revoke_key(&TenantId::default(), &other, key.id).await,
+            Err(ApiKeyStoreError::KeyNotFound)
+        );
+        store
+            .revoke_key(&TenantId::default(), &email, key.id)
+            .await
+            .unwrap();
+        assert_eq!(
+            store.get_key_by_hash("hash").await,
+            Err(ApiKeyStoreError::KeyNotFound)
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::domain::{
+    EmailMessage, EmailOutboxStore, EmailOutboxStoreError, OutboxEmail, OutboxEmailStatus,
+};
+
+// In-memory outbox. Queued emails are lost on restart, so this is for tests
+// and local development.
+#[derive(Default)]
+pub struct HashmapEmailOutboxStore {
+    emails: HashMap<Uuid, OutboxEmail>,
+}
+
+impl HashmapEmailOutboxStore {
+    fn get_email_mut(&mut self, id: Uuid) -> Result<&mut OutboxEmail, EmailOutboxStoreError> {
+        self.emails
+            .get_mut(&id)
+            .ok_or(EmailOutboxStoreError::EmailNotFound)
+    }
+}
+
+// Only the subject is kept once the email has left the outbox
+fn clear_body(message: &mut EmailMessage) {
+    message.text.clear();
+    message.html = None;
+}
+
+#[async_trait::async_trait]
+impl EmailOutboxStore for HashmapEmailOutboxStore {
+    async fn enqueue(&mut self, email: OutboxEmail) -> Result<(), EmailOutboxStoreError> {
+        let queued = self
+            .emails
+            .values()
+            .any(|queued| queued.idempotency_key == email.idempotency_key);
+        if !queued {
+            self.emails.insert(email.id, email);
+        }
+        Ok(())
+    }
+
+    async fn claim_due(
+        &mut self,
+        now: DateTime<Utc>,
+        lease: Duration,
+        limit: usize,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+        let mut due: Vec<&mut OutboxEmail> = self
+            .emails
+            .values_mut()
+            .filter(|email| {
+                email.status == OutboxEmailStatus::Pending && email.next_attempt_at <= now
+            })
+            .collect();
+
+        due.sort_by_key(|email| email.next_attempt_at);
+        due.truncate(limit);
+
+        Ok(due
+            .into_iter()
+            .map(|email| {
+                email.next_attempt_at = now + lease;
+                email.clone()
+            })
+            .collect())
+    }
+
+    async fn mark_sent(&mut self, id: Uuid) -> Result<(), EmailOutboxStoreError> {
+        let email = self.get_email_mut(id)?;
+        email.attempts += 1;
+        email.status = OutboxEmailStatus::Sent;
+        clear_body(&mut email.message);
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &mut self,
+        id: Uuid,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), EmailOutboxStoreError> {
+        let email = self.get_email_mut(id)?;
+        email.attempts += 1;
+        email.last_error = Some(error.to_owned());
+
+        match next_attempt_at {
+            Some(next_attempt_at) => email.next_attempt_at = next_attempt_at,
+            None => {
+                email.status = OutboxEmailStatus::Failed;
+                clear_body(&mut email.message);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn get_email(&self, idempotency_key: &str) -> Result<OutboxEmail, EmailOutboxStoreError> {
+        self.emails
+            .values()
+            .find(|email| email.idempotency_key == idempotency_key)
+            .cloned()
+            .ok_or(EmailOutboxStoreError::EmailNotFound)
+    }
+
+    async fn get_failed(&self) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+        Ok(self
+            .emails
+            .values()
+            .filter(|email| email.status == OutboxEmailStatus::Failed)
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::Email;
+
+    fn email(idempotency_key: &str, text: &str) -> OutboxEmail {
+        let recipient = Email::parse("test@example.com".to_owned()).unwrap();
+        let message = EmailMessage {
+            subject: "2FA code".to_owned(),
+            text: text.to_owned(),
+            html: None,
+        };
+        OutboxEmail::new(idempotency_key.to_owned(), &recipient, message)
+    }
+
+    #[tokio::test]
+    async fn test_emails_with_the_same_idempotency_key_are_queued_once() {
+        let mut store = HashmapEmailOutboxStore::default();
+        store.enqueue(email("login:1", "123456")).await.unwrap();
+        store.enqueue(email("login:1", "654321")).await.unwrap();
+        store.enqueue(email("login:2", "111111")).await.unwrap();
+
+        let due = store
+            .claim_due(Utc::now(), Duration::minutes(1), 10)
+            .await
+            .unwrap();
+        assert_eq!(due.len(), 2);
+        let queued = store.get_email("login:1").await.unwrap();
+        assert_eq!(queued.message.text, "123456");
+    }
+
+    #[tokio::test]
+    async fn test_claimed_emails_are_not_handed_out_again_during_lease() {
+        let mut store = HashmapEmailOutboxStore::default();
+        store.enqueue(email("login:1", "123456")).await.unwrap();
+
+        let now = Utc::now();
+        let lease = Duration::minutes(1);
+        assert_eq!(store.claim_due(now, lease, 10).await.unwrap().len(), 1);
+        assert!(store.claim_due(now, lease, 10).await.unwrap().is_empty());
+        assert_eq!(
+            store.claim_due(now + lease, lease, 10).await.unwrap().len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_body_is_cleared_once_the_email_leaves_the_outbox() {
+        let mut store = HashmapEmailOutboxStore::default();
+        store.enqueue(email("login:1", "123456")).await.unwrap();
+        store.enqueue(email("login:2", "654321")).await.unwrap();
+        let now = Utc::now();
+
+        let sent = store.get_email("login:1").await.unwrap();
+        store.mark_sent(sent.id).await.unwrap();
+        let sent = store.get_email("login:1").await.unwrap();
+        assert_eq!(sent.status, OutboxEmailStatus::Sent);
+        assert_eq!(sent.attempts, 1);
+        assert_eq!(sent.message.subject, "2FA code");
+        assert!(sent.message.text.is_empty());
+
+        let failed = store.get_email("login:2").await.unwrap();
+        store
+            .mark_failed(failed.id, "unavailable", Some(now))
+            .await
+            .unwrap();
+        assert!(store.get_failed().await.unwrap().is_empty());
+        store
+            .mark_failed(failed.id, "rejected", None)
+            .await
+            .unwrap();
+
+        let failed = store.get_failed().await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].attempts, 2);
+        assert_eq!(failed[0].last_error.as_deref(), Some("rejected"));
+        assert!(failed[0].message.text.is_empty());
+        assert!(store
+            .claim_due(now, Duration::zero(), 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
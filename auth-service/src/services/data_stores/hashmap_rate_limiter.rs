@@ -0,0 +1,114 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+use crate::domain::{RateLimitDecision, RateLimitPolicy, RateLimiter, RateLimiterError};
+
+// In-process sliding window log. Limits are per instance, so this is meant for
+// tests, single-instance deployments, and as a fallback when Redis is down.
+#[derive(Default)]
+pub struct HashmapRateLimiter {
+    requests: HashMap<String, VecDeque<Instant>>,
+}
+
+impl HashmapRateLimiter {
+    pub fn check_at(
+        &mut self,
+        key: &str,
+        policy: &RateLimitPolicy,
+        now: Instant,
+    ) -> RateLimitDecision {
+        let requests = self.requests.entry(key.to_owned()).or_default();
+
+        // Forget requests that have slid out of the window
+        while let Some(oldest) = requests.front() {
+            if now.duration_since(*oldest) >= policy.window {
+                requests.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        if requests.len() >= policy.max_requests as usize {
+            let oldest = requests.front().copied().unwrap_or(now);
+            return RateLimitDecision::Limited {
+                retry_after: policy.window.saturating_sub(now.duration_since(oldest)),
+            };
+        }
+
+        requests.push_back(now);
+        RateLimitDecision::Allowed
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimiter for HashmapRateLimiter {
+    async fn check(
+        &mut self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateLimitDecision, RateLimiterError> {
+        Ok(self.check_at(key, policy, Instant::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_requests_within_limit_are_allowed() {
+        let mut limiter = HashmapRateLimiter::default();
+        let policy = RateLimitPolicy::new(3, Duration::from_secs(60));
+
+        for _ in 0..3 {
+            assert_eq!(
+                limiter.check("key", &policy).await,
+                Ok(RateLimitDecision::Allowed)
+            );
+        }
+    }
+
+    #[test]
+    fn test_requests_over_limit_are_limited_until_window_slides() {
+        let mut limiter = HashmapRateLimiter::default();
+        let policy = RateLimitPolicy::new(2, Duration::from_secs(60));
+        let start = Instant::now();
+
+        limiter.check_at("key", &policy, start);
+        limiter.check_at("key", &policy, start + Duration::from_secs(10));
+
+        assert_eq!(
+            limiter.check_at("key", &policy, start + Duration::from_secs(20)),
+            RateLimitDecision::Limited {
+                retry_after: Duration::from_secs(40)
+            }
+        );
+
+        // The first request has left the window
+        assert_eq!(
+            limiter.check_at("key", &policy, start + Duration::from_secs(60)),
+            RateLimitDecision::Allowed
+        );
+    }
+
+    #[test]
+    fn test_keys_are_limited_independently() {
+        let mut limiter = HashmapRateLimiter::default();
+        let policy = RateLimitPolicy::new(1, Duration::from_secs(60));
+        let now = Instant::now();
+
+        assert_eq!(
+            limiter.check_at("a", &policy, now),
+            RateLimitDecision::Allowed
+        );
+        assert_eq!(
+            limiter.check_at("b", &policy, now),
+            RateLimitDecision::Allowed
+        );
+        assert!(matches!(
+            limiter.check_at("a", &policy, now),
+            RateLimitDecision::Limited { .. }
+        ));
+    }
+}
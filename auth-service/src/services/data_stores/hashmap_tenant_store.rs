@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use crate::domain::{
+    normalize_host, Tenant, TenantId, TenantSettings, TenantStore, TenantStoreError,
+};
+
+// In-memory tenants, starting with just the default one
+pub struct HashmapTenantStore {
+    tenants: BTreeMap<TenantId, Tenant>,
+}
+
+impl Default for HashmapTenantStore {
+    fn default() -> Self {
+        let default_tenant = Tenant::new(TenantId::default(), "Default");
+        Self {
+            tenants: BTreeMap::from([(default_tenant.id.clone(), default_tenant)]),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TenantStore for HashmapTenantStore {
+    async fn add_tenant(&mut self, mut tenant: Tenant) -> Result<(), TenantStoreError> {
+        if self.tenants.contains_key(&tenant.id) {
+            return Err(TenantStoreError::TenantAlreadyExists);
+        }
+
+        tenant.hosts = tenant
+            .hosts
+            .iter()
+            .map(|host| normalize_host(host))
+            .collect();
+        let host_taken = self
+            .tenants
+            .values()
+            .any(|other| other.hosts.iter().any(|host| tenant.hosts.contains(host)));
+        if host_taken {
+            return Err(TenantStoreError::HostTaken);
+        }
+
+        self.tenants.insert(tenant.id.clone(), tenant);
+        Ok(())
+    }
+
+    async fn get_tenant(&self, id: &TenantId) -> Result<Tenant, TenantStoreError> {
+        self.tenants
+            .get(id)
+            .cloned()
+            .ok_or(TenantStoreError::TenantNotFound)
+    }
+
+    async fn get_tenant_by_host(&self, host: &str) -> Result<Tenant, TenantStoreError> {
+        let host = normalize_host(host);
+        self.tenants
+            .values()
+            .find(|tenant| tenant.hosts.contains(&host))
+            .cloned()
+            .ok_or(TenantStoreError::TenantNotFound)
+    }
+
+    async fn get_tenants(&self) -> Result<Vec<Tenant>, TenantStoreError> {
+        Ok(self.tenants.values().cloned().collect())
+    }
+
+    async fn update_settings(
+        &mut self,
+        id: &TenantId,
+        settings: TenantSettings,
+    ) -> Result<(), TenantStoreError> {
+        self.tenants
+            .get_mut(id)
+            .ok_or(TenantStoreError::TenantNotFound)?
+            .settings = settings;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_add_and_resolve_tenants() {
+        let mut store = HashmapTenantStore::default();
+        let acme = TenantId::parse("acme".to_owned()).unwrap();
+        let mut tenant = Tenant::new(acme.clone(), "Acme");
+        tenant.hosts = vec!["Auth.Acme.example:8443".to_owned()];
+
+        store.add_tenant(tenant.clone()).await.unwrap();
+        assert_eq!(
+            store.add_tenant(tenant).await,
+            Err(TenantStoreError::TenantAlreadyExists)
+        );
+
+        let resolved = store.get_tenant_by_host("auth.acme.example").await.unwrap();
+        assert_eq!(resolved.id, acme);
+        assert_eq!(
+            store.get_tenant_by_host("other.example").await,
+            Err(TenantStoreError::TenantNotFound)
+        );
+
+        let mut other = Tenant::new(TenantId::parse("other".to_owned()).unwrap(), "Other");
+        other.hosts = vec!["auth.acme.example".to_owned()];
+        assert_eq!(
+            store.add_tenant(other).await,
+            Err(TenantStoreError::HostTaken)
+        );
+
+        assert_eq!(store.get_tenants().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_update_settings() {
+        let mut store = HashmapTenantStore::default();
+        let settings = TenantSettings {
+            min_password_length: 12,
+            requires_2fa: true,
+        };
+
+        store
+            .update_settings(&TenantId::default(), settings.clone())
+            .await
+            .unwrap();
+        let tenant = store.get_tenant(&TenantId::default()).await.unwrap();
+        assert_eq!(tenant.settings, settings);
+
+        let missing = TenantId::parse("missing".to_owned()).unwrap();
+        assert_eq!(
+            store.update_settings(&missing, settings).await,
+            Err(TenantStoreError::TenantNotFound)
+        );
+    }
+}
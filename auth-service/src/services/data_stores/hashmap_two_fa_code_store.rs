@@ -2,10 +2,11 @@ use std::collections::HashMap;
 
 use crate::domain::data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError};
 use crate::domain::email::Email;
+use crate::domain::TenantId;
 
 #[derive(Default)]
 pub struct HashmapTwoFACodeStore {
-    codes: HashMap<Email, (LoginAttemptId, TwoFACode)>,
+    codes: HashMap<(TenantId, Email), (LoginAttemptId, TwoFACode)>,
 }
 
 impl HashmapTwoFACodeStore {
@@ -20,16 +21,22 @@ impl HashmapTwoFACodeStore {
 impl TwoFACodeStore for HashmapTwoFACodeStore {
     async fn add_code(
         &mut self,
+        tenant: &TenantId,
         email: Email,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
-        self.codes.insert(email, (login_attempt_id, code));
+        self.codes
+            .insert((tenant.clone(), email), (login_attempt_id, code));
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
-        match self.codes.remove(email) {
+    async fn remove_code(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), TwoFACodeStoreError> {
+        match self.codes.remove(&(tenant.clone(), email.clone())) {
             Some(_) => Ok(()),
             None => Err(TwoFACodeStoreError::LoginAttemptIdNotFound),
         }
@@ -37,11 +44,12 @@ impl TwoFACodeStore for HashmapTwoFACodeStore {
 
     async fn get_code(
         &self,
+        tenant: &TenantId,
         email: &Email,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
         let code = self
             .codes
-            .get(email)
+            .get(&(tenant.clone(), email.clone()))
             .ok_or(TwoFACodeStoreError::LoginAttemptIdNotFound)?;
         Ok((code.0.clone(), code.1.clone()))
     }
@@ -53,46 +61,59 @@ mod tests {
     #[tokio::test]
     async fn test_add_code() {
         let mut store = HashmapTwoFACodeStore::default();
+        let tenant = TenantId::default();
         let email = Email::parse("test@example.com".to_string()).unwrap();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
         let result = store
-            .add_code(email.clone(), login_attempt_id.clone(), code.clone())
+            .add_code(
+                &tenant,
+                email.clone(),
+                login_attempt_id.clone(),
+                code.clone(),
+            )
             .await;
 
         assert!(result.is_ok());
-        assert_eq!(store.codes.get(&email), Some(&(login_attempt_id, code)));
+        assert_eq!(
+            store.codes.get(&(tenant, email)),
+            Some(&(login_attempt_id, code))
+        );
     }
 
     #[tokio::test]
     async fn test_remove_code() {
         let mut store = HashmapTwoFACodeStore::default();
+        let tenant = TenantId::default();
         let email = Email::parse("test@example.com".to_string()).unwrap();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
 
-        store
-            .codes
-            .insert(email.clone(), (login_attempt_id.clone(), code.clone()));
+        store.codes.insert(
+            (tenant.clone(), email.clone()),
+            (login_attempt_id.clone(), code.clone()),
+        );
 
-        let result = store.remove_code(&email).await;
+        let result = store.remove_code(&tenant, &email).await;
 
         assert!(result.is_ok());
-        assert_eq!(store.codes.get(&email), None);
+        assert_eq!(store.codes.get(&(tenant, email)), None);
     }
 
     #[tokio::test]
     async fn test_get_code() {
         let mut store = HashmapTwoFACodeStore::default();
+        let tenant = TenantId::default();
         let email = Email::parse("test@example.com".to_string()).unwrap();
         let login_attempt_id = LoginAttemptId::default();
         let code = TwoFACode::default();
-        store
-            .codes
-            .insert(email.clone(), (login_attempt_id.clone(), code.clone()));
+        store.codes.insert(
+            (tenant.clone(), email.clone()),
+            (login_attempt_id.clone(), code.clone()),
+        );
 
-        let result = store.get_code(&email).await;
+        let result = store.get_code(&tenant, &email).await;
 
         assert!(result.is_ok());
         assert_eq!(result.unwrap(), (login_attempt_id, code));
@@ -101,9 +122,10 @@ mod tests {
     #[tokio::test]
     async fn test_get_code_not_found() {
         let store = HashmapTwoFACodeStore::default();
+        let tenant = TenantId::default();
         let email = Email::parse("test@example.com".to_string()).unwrap();
 
-        let result = store.get_code(&email).await;
+        let result = store.get_code(&tenant, &email).await;
 
         assert!(result.is_err());
         assert_eq!(
@@ -111,4 +133,27 @@ mod tests {
             TwoFACodeStoreError::LoginAttemptIdNotFound
         );
     }
+
+    #[tokio::test]
+    async fn test_codes_are_kept_per_tenant() {
+        let mut store = HashmapTwoFACodeStore::default();
+        let tenant = TenantId::default();
+        let other_tenant = TenantId::parse("acme".to_owned()).unwrap();
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        store
+            .add_code(
+                &tenant,
+                email.clone(),
+                LoginAttemptId::default(),
+                TwoFACode::default(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.get_code(&other_tenant, &email).await,
+            Err(TwoFACodeStoreError::LoginAttemptIdNotFound)
+        );
+        assert!(store.get_code(&tenant, &email).await.is_ok());
+    }
 }
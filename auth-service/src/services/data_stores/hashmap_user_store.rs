@@ -1,34 +1,199 @@
 #![allow(unused_variables)]
 
 use async_trait::async_trait;
-use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::sync::RwLock;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
 
-use crate::domain::{Email, Password, User, UserStore, UserStoreError};
+use crate::app_state::PasswordHasherType;
+
+use crate::domain::{
+    Email, LockoutPolicy, LockoutState, Password, PasswordHash, Permission, PhoneNumber, Role,
+    TenantId, TwoFAChannel, User, UserListQuery, UserPage, UserStore, UserStoreError,
+    DEFAULT_ROLES,
+};
+use crate::services::argon2_password_hasher::Argon2PasswordHasher;
 
-#[derive(Debug, Default)]
 pub struct HashmapUserStore {
-    users: HashMap<Email, User>, // key: Email tuple as key, value: User object, email is unique
+    // key: Email tuple as key, value: User object, email is unique.
+    // Behind a lock so `validate_user` can upgrade outdated hashes through `&self`,
+    // the same way the Postgres store does.
+    users: RwLock<HashMap<Email, User>>,
+    lockouts: RwLock<HashMap<Email, LockoutState>>,
+    memberships: HashMap<Email, BTreeSet<TenantId>>,
+    user_roles: HashMap<(TenantId, Email), BTreeSet<Role>>,
+    // token hash and expiry of pending password resets
+    password_resets: HashMap<Email, (String, DateTime<Utc>)>,
+    // token hash and expiry of signups that haven't been confirmed
+    pending_signups: HashMap<Email, (String, DateTime<Utc>)>,
+    // number, code hash and expiry of pending phone verifications
+    phone_verifications: HashMap<Email, (PhoneNumber, String, DateTime<Utc>)>,
+    // The roles that exist and what they grant, `DEFAULT_ROLES` unless replaced
+    role_permissions: HashMap<Role, Vec<Permission>>,
+    password_hasher: PasswordHasherType,
+    lockout_policy: LockoutPolicy,
+}
+
+impl HashmapUserStore {
+    pub fn new(password_hasher: PasswordHasherType) -> Self {
+        Self {
+            users: RwLock::new(HashMap::new()),
+            lockouts: RwLock::new(HashMap::new()),
+            memberships: HashMap::new(),
+            user_roles: HashMap::new(),
+            password_resets: HashMap::new(),
+            pending_signups: HashMap::new(),
+            phone_verifications: HashMap::new(),
+            role_permissions: default_role_permissions(),
+            password_hasher,
+            lockout_policy: LockoutPolicy::default(),
+        }
+    }
+
+    pub fn with_lockout_policy(mut self, lockout_policy: LockoutPolicy) -> Self {
+        self.lockout_policy = lockout_policy;
+        self
+    }
+
+    pub fn with_role_permissions(
+        mut self,
+        role_permissions: HashMap<Role, Vec<Permission>>,
+    ) -> Self {
+        self.role_permissions = role_permissions;
+        self
+    }
+
+    fn is_member(&self, tenant: &TenantId, email: &Email) -> bool {
+        self.memberships
+            .get(email)
+            .is_some_and(|tenants| tenants.contains(tenant))
+    }
+
+    fn get_user_mut(&mut self, email: &Email) -> Result<&mut User, UserStoreError> {
+        self.users
+            .get_mut()
+            .map_err(|_| UserStoreError::UnexpectedError)?
+            .get_mut(email)
+            .ok_or(UserStoreError::UserNotFound)
+    }
+
+    // Everything kept about the user, in every tenant
+    fn remove_user(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        self.users
+            .get_mut()
+            .map_err(|_| UserStoreError::UnexpectedError)?
+            .remove(email)
+            .ok_or(UserStoreError::UserNotFound)?;
+        self.lockouts
+            .get_mut()
+            .map_err(|_| UserStoreError::UnexpectedError)?
+            .remove(email);
+        self.memberships.remove(email);
+        self.user_roles.retain(|(_, member), _| member != email);
+        self.password_resets.remove(email);
+        self.pending_signups.remove(email);
+        self.phone_verifications.remove(email);
+        Ok(())
+    }
+
+    fn record_failed_login(&self, email: &Email) -> Result<UserStoreError, UserStoreError> {
+        let now = Utc::now();
+        let mut lockouts = self
+            .lockouts
+            .write()
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let state = lockouts.entry(email.clone()).or_default();
+        *state = self.lockout_policy.register_failure(state, now);
+
+        Ok(self.lockout_policy.failure_error(state, now))
+    }
+}
+
+fn default_role_permissions() -> HashMap<Role, Vec<Permission>> {
+    DEFAULT_ROLES
+        .iter()
+        .map(|(role, permissions)| {
+            let role = Role::parse(role.to_string()).expect("default roles are valid");
+            let permissions = permissions
+                .iter()
+                .map(|p| Permission::parse(p.to_string()).expect("default permissions are valid"))
+                .collect();
+            (role, permissions)
+        })
+        .collect()
+}
+
+impl Default for HashmapUserStore {
+    fn default() -> Self {
+        Self::new(Arc::new(Argon2PasswordHasher::default()))
+    }
 }
 
 #[async_trait::async_trait]
 impl UserStore for HashmapUserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
+    async fn add_user(&mut self, tenant: &TenantId, user: User) -> Result<(), UserStoreError> {
         // Return `UserStoreError::UserAlreadyExists` if the user already exists,
         // otherwise insert the user into the hashmap and return `Ok(())`.
-        if self.users.contains_key(&user.email) {
+        let users = self
+            .users
+            .get_mut()
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+        if users.contains_key(&user.email) {
             return Err(UserStoreError::UserAlreadyExists);
         }
-        self.users.insert(user.email.clone(), user); // user is consumed by this function
+        self.memberships
+            .entry(user.email.clone())
+            .or_default()
+            .insert(tenant.clone());
+        users.insert(user.email.clone(), user); // user is consumed by this function
+        Ok(())
+    }
+
+    async fn add_pending_user(
+        &mut self,
+        tenant: &TenantId,
+        mut user: User,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        if self.pending_signups.contains_key(&user.email) {
+            self.remove_user(&user.email)?;
+        }
+        user.pending = true;
+        let email = user.email.clone();
+        self.add_user(tenant, user).await?;
+        self.pending_signups
+            .insert(email, (token_hash.to_owned(), expires_at));
+        Ok(())
+    }
+
+    async fn confirm_signup(
+        &mut self,
+        email: &Email,
+        token_hash: &str,
+    ) -> Result<(), UserStoreError> {
+        match self.pending_signups.get(email) {
+            Some((pending, expires_at)) if pending == token_hash && *expires_at > Utc::now() => {}
+            _ => return Err(UserStoreError::InvalidCredentials),
+        }
+        self.pending_signups.remove(email);
+        self.get_user_mut(email)?.pending = false;
         Ok(())
     }
 
     // return a `Result` type containing either a
     // `User` object or a `UserStoreError`.
     // Return `UserStoreError::UserNotFound` if the user can not be found.
-    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
-        match self.users.get(email) {
+    async fn get_user(&self, tenant: &TenantId, email: &Email) -> Result<User, UserStoreError> {
+        if !self.is_member(tenant, email) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        let users = self
+            .users
+            .read()
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+        match users.get(email) {
             Some(user) => Ok(user.clone()),
             None => Err(UserStoreError::UserNotFound),
         }
@@ -38,98 +203,421 @@ impl UserStore for HashmapUserStore {
     // Return `UserStoreError::InvalidCredentials` if the password is incorrect.
     async fn validate_user(
         &self,
+        tenant: &TenantId,
         email: &Email,
         password: &Password,
     ) -> Result<(), UserStoreError> {
-        match self.users.get(email) {
-            Some(user) => {
-                if user.password.eq(password) {
-                    Ok(())
-                } else {
-                    Err(UserStoreError::InvalidCredentials)
+        let user = match self.get_user(tenant, email).await {
+            Ok(user) => user,
+            Err(UserStoreError::UserNotFound) => {
+                // Don't let the response time give away that the email isn't registered
+                self.password_hasher.verify_dummy_password(password).await;
+                return Err(UserStoreError::UserNotFound);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let lockout_state = self.get_lockout_state(email).await?;
+
+        // Verified before the lock is checked, so a locked account takes as
+        // long to answer as any other
+        let verified = self
+            .password_hasher
+            .verify_password(password, &user.password_hash)
+            .await
+            .map_err(UserStoreError::from);
+
+        self.lockout_policy.check(&lockout_state, Utc::now())?;
+
+        match verified {
+            // Logging in to an account that was just signed up for would
+            // otherwise show the address wasn't registered before
+            Ok(()) if !user.pending => {}
+            Ok(()) | Err(UserStoreError::InvalidCredentials) => {
+                return Err(self.record_failed_login(email)?)
+            }
+            Err(e) => return Err(e),
+        }
+
+        // Only reported with the right password, so it doesn't reveal anything
+        // to someone guessing
+        if user.disabled {
+            return Err(UserStoreError::AccountDisabled);
+        }
+
+        // A successful login ends the run of consecutive failures
+        self.lockouts
+            .write()
+            .map_err(|_| UserStoreError::UnexpectedError)?
+            .remove(email);
+
+        if self.password_hasher.needs_rehash(&user.password_hash) {
+            if let Ok(new_hash) = self.password_hasher.hash_password(password).await {
+                let mut users = self
+                    .users
+                    .write()
+                    .map_err(|_| UserStoreError::UnexpectedError)?;
+                if let Some(stored) = users.get_mut(email) {
+                    // Only replace the hash we verified against, in case it changed concurrently
+                    if stored.password_hash == user.password_hash {
+                        stored.password_hash = new_hash;
+                    }
                 }
             }
-            None => Err(UserStoreError::UserNotFound),
         }
+
+        Ok(())
+    }
+
+    async fn get_lockout_state(&self, email: &Email) -> Result<LockoutState, UserStoreError> {
+        let lockouts = self
+            .lockouts
+            .read()
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+        Ok(lockouts.get(email).cloned().unwrap_or_default())
+    }
+
+    async fn unlock_user(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        self.get_user_mut(email)?;
+        self.lockouts
+            .get_mut()
+            .map_err(|_| UserStoreError::UnexpectedError)?
+            .remove(email);
+        Ok(())
+    }
+
+    async fn add_member(&mut self, tenant: &TenantId, email: &Email) -> Result<(), UserStoreError> {
+        self.get_user_mut(email)?;
+        self.memberships
+            .entry(email.clone())
+            .or_default()
+            .insert(tenant.clone());
+        Ok(())
+    }
+
+    async fn remove_member(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), UserStoreError> {
+        self.get_user_mut(email)?;
+        if let Some(tenants) = self.memberships.get_mut(email) {
+            tenants.remove(tenant);
+        }
+        self.user_roles.remove(&(tenant.clone(), email.clone()));
+        Ok(())
+    }
+
+    async fn get_tenants(&self, email: &Email) -> Result<Vec<TenantId>, UserStoreError> {
+        Ok(self
+            .memberships
+            .get(email)
+            .map(|tenants| tenants.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn assign_role(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+        role: &Role,
+    ) -> Result<(), UserStoreError> {
+        self.get_user(tenant, email).await?;
+        if !self.role_permissions.contains_key(role) {
+            return Err(UserStoreError::RoleNotFound);
+        }
+        self.user_roles
+            .entry((tenant.clone(), email.clone()))
+            .or_default()
+            .insert(role.clone());
+        Ok(())
+    }
+
+    async fn revoke_role(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+        role: &Role,
+    ) -> Result<(), UserStoreError> {
+        self.get_user(tenant, email).await?;
+        if let Some(roles) = self.user_roles.get_mut(&(tenant.clone(), email.clone())) {
+            roles.remove(role);
+        }
+        Ok(())
+    }
+
+    async fn get_roles(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<Vec<Role>, UserStoreError> {
+        self.get_user(tenant, email).await?;
+        Ok(self
+            .user_roles
+            .get(&(tenant.clone(), email.clone()))
+            .map(|roles| roles.iter().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    async fn get_permissions(&self, roles: &[Role]) -> Result<Vec<Permission>, UserStoreError> {
+        let permissions: BTreeSet<Permission> = roles
+            .iter()
+            .filter_map(|role| self.role_permissions.get(role))
+            .flatten()
+            .cloned()
+            .collect();
+        Ok(permissions.into_iter().collect())
+    }
+
+    async fn list_users(
+        &self,
+        tenant: &TenantId,
+        query: &UserListQuery,
+    ) -> Result<UserPage, UserStoreError> {
+        let users = self
+            .users
+            .read()
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+        let search = query.search.as_deref().map(str::to_lowercase);
+
+        let mut matching: Vec<&User> = users
+            .values()
+            .filter(|user| self.is_member(tenant, &user.email))
+            .filter(|user| match &search {
+                Some(search) => user.email.as_ref().to_lowercase().contains(search),
+                None => true,
+            })
+            .collect();
+        matching.sort_by_key(|user| user.email.as_ref().to_lowercase());
+
+        Ok(UserPage {
+            total: matching.len(),
+            users: matching
+                .into_iter()
+                .skip(query.offset)
+                .take(query.limit)
+                .cloned()
+                .collect(),
+        })
+    }
+
+    async fn set_disabled(&mut self, email: &Email, disabled: bool) -> Result<(), UserStoreError> {
+        self.get_user_mut(email)?.disabled = disabled;
+        Ok(())
+    }
+
+    async fn set_requires_2fa(
+        &mut self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        self.get_user_mut(email)?.requires_2fa = requires_2fa;
+        Ok(())
+    }
+
+    async fn start_password_reset(
+        &mut self,
+        email: &Email,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        self.get_user_mut(email)?;
+        self.password_resets
+            .insert(email.clone(), (token_hash.to_owned(), expires_at));
+        Ok(())
+    }
+
+    async fn complete_password_reset(
+        &mut self,
+        email: &Email,
+        token_hash: &str,
+        new_password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError> {
+        match self.password_resets.get(email) {
+            Some((pending, expires_at)) if pending == token_hash && *expires_at > Utc::now() => {}
+            _ => return Err(UserStoreError::InvalidCredentials),
+        }
+
+        self.get_user_mut(email)?.password_hash = new_password_hash;
+        self.password_resets.remove(email);
+        self.lockouts
+            .get_mut()
+            .map_err(|_| UserStoreError::UnexpectedError)?
+            .remove(email);
+        Ok(())
+    }
+
+    async fn start_phone_verification(
+        &mut self,
+        email: &Email,
+        phone: &PhoneNumber,
+        code_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        self.get_user_mut(email)?;
+        self.phone_verifications.insert(
+            email.clone(),
+            (phone.clone(), code_hash.to_owned(), expires_at),
+        );
+        Ok(())
+    }
+
+    async fn complete_phone_verification(
+        &mut self,
+        email: &Email,
+        code_hash: &str,
+    ) -> Result<PhoneNumber, UserStoreError> {
+        let phone = match self.phone_verifications.remove(email) {
+            Some((phone, pending, expires_at))
+                if pending == code_hash && expires_at > Utc::now() =>
+            {
+                phone
+            }
+            _ => return Err(UserStoreError::InvalidCredentials),
+        };
+
+        self.get_user_mut(email)?.phone = Some(phone.clone());
+        Ok(phone)
+    }
+
+    async fn set_two_fa_channel(
+        &mut self,
+        email: &Email,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError> {
+        let user = self.get_user_mut(email)?;
+        if channel.requires_phone() && user.phone.is_none() {
+            return Err(UserStoreError::PhoneNotVerified);
+        }
+        user.two_fa_channel = channel;
+        Ok(())
+    }
+
+    async fn delete_user(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), UserStoreError> {
+        if !self.is_member(tenant, email) {
+            return Err(UserStoreError::UserNotFound);
+        }
+        self.remove_member(tenant, email).await?;
+        if self
+            .memberships
+            .get(email)
+            .is_some_and(|tenants| !tenants.is_empty())
+        {
+            return Ok(());
+        }
+
+        self.remove_user(email)
     }
 }
 
-// TODO: Add unit tests for your `HashmapUserStore` implementation
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::domain::PasswordHasher;
+    use crate::services::argon2_password_hasher::Argon2Settings;
+
+    fn test_password_hasher() -> Argon2PasswordHasher {
+        Argon2PasswordHasher::new(Argon2Settings {
+            memory_cost: 64,
+            time_cost: 1,
+            parallelism: 1,
+            pepper: None,
+        })
+    }
+
+    fn test_tenant() -> TenantId {
+        TenantId::default()
+    }
 
     fn test_create_hashmap_user_store() -> HashmapUserStore {
-        let user_store = HashmapUserStore::default();
-        user_store
+        HashmapUserStore::new(Arc::new(test_password_hasher()))
+    }
+
+    async fn test_user(email: &Email, password: &Password, requires_2fa: bool) -> User {
+        let password_hash = test_password_hasher()
+            .hash_password(password)
+            .await
+            .unwrap();
+        User::new(email.clone(), password_hash, requires_2fa)
     }
 
     #[tokio::test]
     async fn test_add_user() {
         let mut user_store = test_create_hashmap_user_store();
-        let user = User {
-            email: Email::parse("test@example.com".to_string()).unwrap(),
-            password: Password::parse("password123".to_string()).unwrap(),
-            requires_2fa: false,
-        };
+        let email = Email::parse("test@example.com".to_string()).unwrap();
+        let password = Password::parse("password123".to_string()).unwrap();
+        let user = test_user(&email, &password, false).await;
 
         // Test successful user addition
-        let result = user_store.add_user(user.clone()).await;
+        let result = user_store.add_user(&test_tenant(), user.clone()).await;
         assert!(result.is_ok());
-        assert_eq!(user_store.users.len(), 1);
+        assert_eq!(user_store.users.read().unwrap().len(), 1);
 
         // Test adding existing user
-        let result = user_store.add_user(user).await;
+        let result = user_store.add_user(&test_tenant(), user).await;
         assert_eq!(result, Err(UserStoreError::UserAlreadyExists));
     }
 
     #[tokio::test]
     async fn test_get_user() {
-        let mut user_store = HashmapUserStore::default();
+        let mut user_store = test_create_hashmap_user_store();
 
         let requires_2fa = true;
         let email = Email::parse("test@example.com".to_string()).unwrap();
         let password = Password::parse("12345678".to_string()).unwrap();
-        let user = User::new(email.clone(), password.clone(), requires_2fa);
+        let user = test_user(&email, &password, requires_2fa).await;
 
         // Add user and test getting existing user
-        user_store.users.insert(email.clone(), user.clone());
-        let result = user_store.get_user(&email.clone()).await;
+        user_store
+            .add_user(&test_tenant(), user.clone())
+            .await
+            .unwrap();
+        let result = user_store.get_user(&test_tenant(), &email.clone()).await;
         assert_eq!(result, Ok(user));
 
         // Test getting non-existent user
-        let non_existent_user = User::new(
-            Email::parse("nonexistent@example.com".to_string()).unwrap(),
-            password.clone(),
-            requires_2fa,
-        );
+        let result = user_store
+            .get_user(
+                &test_tenant(),
+                &Email::parse("nonexistent@example.com".to_string()).unwrap(),
+            )
+            .await;
+        assert_eq!(result, Err(UserStoreError::UserNotFound));
     }
 
     #[tokio::test]
     async fn test_validate_user() {
-        let mut user_store = HashmapUserStore::default();
+        let mut user_store = test_create_hashmap_user_store();
         let email = Email::parse("test@example.com".to_owned()).unwrap();
         let password = Password::parse("password".to_owned()).unwrap();
 
-        let user = User {
-            email: email.clone(),
-            password: password.clone(),
-            requires_2fa: false,
-        };
+        let user = test_user(&email, &password, false).await;
 
         // Test validating a user that exists with correct password
-        user_store.users.insert(email.clone(), user.clone());
-        let result = user_store.validate_user(&email, &password).await;
+        user_store
+            .add_user(&test_tenant(), user.clone())
+            .await
+            .unwrap();
+        let result = user_store
+            .validate_user(&test_tenant(), &email, &password)
+            .await;
         assert_eq!(result, Ok(()));
 
         // Test validating a user that exists with incorrect password
         let wrong_password = Password::parse("wrongpassword".to_owned()).unwrap();
-        let result = user_store.validate_user(&email, &wrong_password).await;
+        let result = user_store
+            .validate_user(&test_tenant(), &email, &wrong_password)
+            .await;
         assert_eq!(result, Err(UserStoreError::InvalidCredentials));
 
         // Test validating a user that doesn't exist
         let result = user_store
             .validate_user(
+                &test_tenant(),
                 &Email::parse("nonexistent@example.com".to_string()).unwrap(),
                 &password,
             )
@@ -137,4 +625,452 @@ mod tests {
 
         assert_eq!(result, Err(UserStoreError::UserNotFound));
     }
+
+    #[tokio::test]
+    async fn test_validate_user_locks_account_after_repeated_failures() {
+        let policy = LockoutPolicy {
+            backoff_threshold: 3,
+            backoff_base: chrono::Duration::zero(),
+            lock_threshold: 3,
+            lock_duration: chrono::Duration::minutes(15),
+        };
+        let mut user_store = test_create_hashmap_user_store().with_lockout_policy(policy);
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let wrong_password = Password::parse("wrongpassword".to_owned()).unwrap();
+        user_store
+            .add_user(&test_tenant(), test_user(&email, &password, false).await)
+            .await
+            .unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(
+                user_store
+                    .validate_user(&test_tenant(), &email, &wrong_password)
+                    .await,
+                Err(UserStoreError::InvalidCredentials)
+            );
+        }
+        assert!(matches!(
+            user_store
+                .validate_user(&test_tenant(), &email, &wrong_password)
+                .await,
+            Err(UserStoreError::AccountLocked {
+                just_locked: true,
+                ..
+            })
+        ));
+
+        // Whatever the password, it's refused while locked
+        for password in [&password, &wrong_password] {
+            assert!(matches!(
+                user_store
+                    .validate_user(&test_tenant(), &email, password)
+                    .await,
+                Err(UserStoreError::AccountLocked {
+                    just_locked: false,
+                    ..
+                })
+            ));
+        }
+        assert_eq!(
+            user_store
+                .get_lockout_state(&email)
+                .await
+                .unwrap()
+                .failed_attempts,
+            3
+        );
+
+        user_store.unlock_user(&email).await.unwrap();
+        assert_eq!(
+            user_store
+                .validate_user(&test_tenant(), &email, &password)
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            user_store.get_lockout_state(&email).await,
+            Ok(LockoutState::default())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_assign_and_revoke_roles() {
+        let mut user_store = test_create_hashmap_user_store();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let admin = Role::parse("admin".to_owned()).unwrap();
+        user_store
+            .add_user(&test_tenant(), test_user(&email, &password, false).await)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            user_store.get_roles(&test_tenant(), &email).await,
+            Ok(vec![])
+        );
+
+        user_store
+            .assign_role(&test_tenant(), &email, &admin)
+            .await
+            .unwrap();
+        user_store
+            .assign_role(&test_tenant(), &email, &admin)
+            .await
+            .unwrap();
+        assert_eq!(
+            user_store.get_roles(&test_tenant(), &email).await,
+            Ok(vec![admin.clone()])
+        );
+
+        let permissions = user_store
+            .get_permissions(std::slice::from_ref(&admin))
+            .await
+            .unwrap();
+        assert!(permissions.contains(&Permission::parse("users:write".to_owned()).unwrap()));
+
+        user_store
+            .revoke_role(&test_tenant(), &email, &admin)
+            .await
+            .unwrap();
+        assert_eq!(
+            user_store.get_roles(&test_tenant(), &email).await,
+            Ok(vec![])
+        );
+
+        let unknown = Role::parse("superuser".to_owned()).unwrap();
+        assert_eq!(
+            user_store
+                .assign_role(&test_tenant(), &email, &unknown)
+                .await,
+            Err(UserStoreError::RoleNotFound)
+        );
+        assert_eq!(user_store.get_permissions(&[unknown]).await, Ok(vec![]));
+
+        let nobody = Email::parse("nobody@example.com".to_owned()).unwrap();
+        assert_eq!(
+            user_store
+                .assign_role(&test_tenant(), &nobody, &admin)
+                .await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_list_users_searches_and_paginates() {
+        let mut user_store = test_create_hashmap_user_store();
+        let password = Password::parse("password123".to_owned()).unwrap();
+        for email in ["carol@example.com", "alice@example.com", "bob@other.com"] {
+            let email = Email::parse(email.to_owned()).unwrap();
+            user_store
+                .add_user(&test_tenant(), test_user(&email, &password, false).await)
+                .await
+                .unwrap();
+        }
+
+        let page = user_store
+            .list_users(
+                &test_tenant(),
+                &UserListQuery {
+                    search: None,
+                    offset: 1,
+                    limit: 1,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.total, 3);
+        assert_eq!(page.users.len(), 1);
+        assert_eq!(page.users[0].email.as_ref(), "bob@other.com");
+
+        let page = user_store
+            .list_users(
+                &test_tenant(),
+                &UserListQuery {
+                    search: Some("EXAMPLE".to_owned()),
+                    offset: 0,
+                    limit: 10,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.users[0].email.as_ref(), "alice@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_disabled_user_cannot_log_in() {
+        let mut user_store = test_create_hashmap_user_store();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let wrong_password = Password::parse("wrongpassword".to_owned()).unwrap();
+        user_store
+            .add_user(&test_tenant(), test_user(&email, &password, false).await)
+            .await
+            .unwrap();
+
+        user_store.set_disabled(&email, true).await.unwrap();
+        assert_eq!(
+            user_store
+                .validate_user(&test_tenant(), &email, &password)
+                .await,
+            Err(UserStoreError::AccountDisabled)
+        );
+        assert_eq!(
+            user_store
+                .validate_user(&test_tenant(), &email, &wrong_password)
+                .await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+
+        user_store.set_disabled(&email, false).await.unwrap();
+        assert_eq!(
+            user_store
+                .validate_user(&test_tenant(), &email, &password)
+                .await,
+            Ok(())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_password_reset() {
+        let mut user_store = test_create_hashmap_user_store();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let new_password = Password::parse("new-password".to_owned()).unwrap();
+        let new_hash = test_password_hasher()
+            .hash_password(&new_password)
+            .await
+            .unwrap();
+        user_store
+            .add_user(&test_tenant(), test_user(&email, &password, false).await)
+            .await
+            .unwrap();
+
+        let expires_at = Utc::now() + chrono::Duration::hours(1);
+        user_store
+            .start_password_reset(&email, "token-hash", expires_at)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            user_store
+                .complete_password_reset(&email, "other-hash", new_hash.clone())
+                .await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+        user_store
+            .complete_password_reset(&email, "token-hash", new_hash.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            user_store
+                .validate_user(&test_tenant(), &email, &new_password)
+                .await,
+            Ok(())
+        );
+
+        // A reset can only be used once
+        assert_eq!(
+            user_store
+                .complete_password_reset(&email, "token-hash", new_hash)
+                .await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pending_signup() {
+        let mut user_store = test_create_hashmap_user_store();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let other_password = Password::parse("other-password".to_owned()).unwrap();
+        let expires_at = Utc::now() + chrono::Duration::hours(1);
+
+        user_store
+            .add_pending_user(
+                &test_tenant(),
+                test_user(&email, &other_password, false).await,
+                "first-hash",
+                expires_at,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            user_store
+                .validate_user(&test_tenant(), &email, &other_password)
+                .await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+
+        // Signing up again replaces the pending user, and its token
+        user_store
+            .add_pending_user(
+                &test_tenant(),
+                test_user(&email, &password, false).await,
+                "token-hash",
+                expires_at,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            user_store.confirm_signup(&email, "first-hash").await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+        user_store
+            .confirm_signup(&email, "token-hash")
+            .await
+            .unwrap();
+
+        assert!(
+            !user_store
+                .get_user(&test_tenant(), &email)
+                .await
+                .unwrap()
+                .pending
+        );
+        assert_eq!(
+            user_store
+                .validate_user(&test_tenant(), &email, &password)
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            user_store
+                .validate_user(&test_tenant(), &email, &other_password)
+                .await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+
+        // Confirmed users aren't replaced
+        assert_eq!(
+            user_store
+                .add_pending_user(
+                    &test_tenant(),
+                    test_user(&email, &other_password, false).await,
+                    "other-hash",
+                    expires_at,
+                )
+                .await,
+            Err(UserStoreError::UserAlreadyExists)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_phone_verification() {
+        let mut user_store = test_create_hashmap_user_store();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let phone = PhoneNumber::parse("+447700900123".to_owned()).unwrap();
+        user_store
+            .add_user(&test_tenant(), test_user(&email, &password, true).await)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            user_store
+                .set_two_fa_channel(&email, TwoFAChannel::Sms)
+                .await,
+            Err(UserStoreError::PhoneNotVerified)
+        );
+
+        let expires_at = Utc::now() + chrono::Duration::minutes(10);
+        user_store
+            .start_phone_verification(&email, &phone, "code-hash", expires_at)
+            .await
+            .unwrap();
+        assert_eq!(
+            user_store
+                .complete_phone_verification(&email, "code-hash")
+                .await,
+            Ok(phone.clone())
+        );
+        user_store
+            .set_two_fa_channel(&email, TwoFAChannel::Sms)
+            .await
+            .unwrap();
+
+        let user = user_store.get_user(&test_tenant(), &email).await.unwrap();
+        assert_eq!(user.phone, Some(phone.clone()));
+        assert_eq!(user.two_fa_channel, TwoFAChannel::Sms);
+
+        // A wrong guess uses up the code
+        user_store
+            .start_phone_verification(&email, &phone, "code-hash", expires_at)
+            .await
+            .unwrap();
+        assert_eq!(
+            user_store
+                .complete_phone_verification(&email, "other-hash")
+                .await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+        assert_eq!(
+            user_store
+                .complete_phone_verification(&email, "code-hash")
+                .await,
+            Err(UserStoreError::InvalidCredentials)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delete_user() {
+        let mut user_store = test_create_hashmap_user_store();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = Password::parse("password123".to_owned()).unwrap();
+        user_store
+            .add_user(&test_tenant(), test_user(&email, &password, false).await)
+            .await
+            .unwrap();
+
+        user_store
+            .delete_user(&test_tenant(), &email)
+            .await
+            .unwrap();
+        assert_eq!(
+            user_store.get_user(&test_tenant(), &email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+        assert_eq!(
+            user_store.delete_user(&test_tenant(), &email).await,
+            Err(UserStoreError::UserNotFound)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_user_rehashes_outdated_hash() {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let password = Password::parse("password123".to_owned()).unwrap();
+        let user = test_user(&email, &password, false).await;
+
+        let stronger_hasher = Argon2PasswordHasher::new(Argon2Settings {
+            memory_cost: 128,
+            time_cost: 2,
+            parallelism: 1,
+            pepper: None,
+        });
+        let mut user_store = HashmapUserStore::new(Arc::new(stronger_hasher.clone()));
+        user_store
+            .add_user(&test_tenant(), user.clone())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            user_store
+                .validate_user(&test_tenant(), &email, &password)
+                .await,
+            Ok(())
+        );
+
+        let upgraded = user_store.get_user(&test_tenant(), &email).await.unwrap();
+        assert_ne!(upgraded.password_hash, user.password_hash);
+        assert!(!stronger_hasher.needs_rehash(&upgraded.password_hash));
+        assert_eq!(
+            user_store
+                .validate_user(&test_tenant(), &email, &password)
+                .await,
+            Ok(())
+        );
+    }
 }
@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::domain::{
+    WebhookDelivery, WebhookEvent, WebhookStore, WebhookStoreError, WebhookSubscription,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DeliveryStatus {
+    Pending,
+    Delivered,
+    Dead,
+}
+
+// In-memory subscriptions and delivery queue. Queued deliveries are lost on
+// restart, so this is for tests and local development.
+#[derive(Default)]
+pub struct HashmapWebhookStore {
+    subscriptions: HashMap<Uuid, WebhookSubscription>,
+    deliveries: HashMap<Uuid, (WebhookDelivery, DeliveryStatus)>,
+}
+
+impl HashmapWebhookStore {
+    fn get_delivery_mut(
+        &mut self,
+        delivery_id: Uuid,
+    ) -> Result<&mut (WebhookDelivery, DeliveryStatus), WebhookStoreError> {
+        self.deliveries
+            .get_mut(&delivery_id)
+            .ok_or(WebhookStoreError::DeliveryNotFound)
+    }
+}
+
+#[async_trait::async_trait]
+impl WebhookStore for HashmapWebhookStore {
+    async fn add_subscription(
+        &mut self,
+        subscription: WebhookSubscription,
+    ) -> Result<(), WebhookStoreError> {
+        self.subscriptions.insert(subscription.id, subscription);
+        Ok(())
+    }
+
+    async fn get_subscriptions(&self) -> Result<Vec<WebhookSubscription>, WebhookStoreError> {
+        Ok(self.subscriptions.values().cloned().collect())
+    }
+
+    async fn remove_subscription(&mut self, id: Uuid) -> Result<(), WebhookStoreError> {
+        self.subscriptions
+            .remove(&id)
+            .ok_or(WebhookStoreError::SubscriptionNotFound)?;
+        // Nobody is listening for the queued deliveries any more
+        self.deliveries
+            .retain(|_, (delivery, _)| delivery.subscription_id != id);
+        Ok(())
+    }
+
+    async fn enqueue_event(&mut self, event: &WebhookEvent) -> Result<(), WebhookStoreError> {
+        let payload = event.payload();
+
+        for subscription in self.subscriptions.values() {
+            if !subscription.wants(event) {
+                continue;
+            }
+
+            let delivery = WebhookDelivery {
+                id: Uuid::new_v4(),
+                subscription_id: subscription.id,
+                url: subscription.url.clone(),
+                secret: subscription.secret.clone(),
+                event_type: event.event_type,
+                payload: payload.clone(),
+                attempts: 0,
+                next_attempt_at: event.occurred_at,
+                last_error: None,
+            };
+            self.deliveries
+                .insert(delivery.id, (delivery, DeliveryStatus::Pending));
+        }
+
+        Ok(())
+    }
+
+    async fn claim_due_deliveries(
+        &mut self,
+        now: DateTime<Utc>,
+        lease: Duration,
+        limit: usize,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        let mut due: Vec<&mut WebhookDelivery> = self
+            .deliveries
+            .values_mut()
+            .filter(|(delivery, status)| {
+                *status == DeliveryStatus::Pending && delivery.next_attempt_at <= now
+            })
+            .map(|(delivery, _)| delivery)
+            .collect();
+
+        due.sort_by_key(|delivery| delivery.next_attempt_at);
+        due.truncate(limit);
+
+        Ok(due
+            .into_iter()
+            .map(|delivery| {
+                delivery.next_attempt_at = now + lease;
+                delivery.clone()
+            })
+            .collect())
+    }
+
+    async fn mark_delivered(&mut self, delivery_id: Uuid) -> Result<(), WebhookStoreError> {
+        let (delivery, status) = self.get_delivery_mut(delivery_id)?;
+        delivery.attempts += 1;
+        *status = DeliveryStatus::Delivered;
+        Ok(())
+    }
+
+    async fn mark_failed(
+        &mut self,
+        delivery_id: Uuid,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), WebhookStoreError> {
+        let (delivery, status) = self.get_delivery_mut(delivery_id)?;
+        delivery.attempts += 1;
+        delivery.last_error = Some(error.to_owned());
+
+        match next_attempt_at {
+            Some(next_attempt_at) => delivery.next_attempt_at = next_attempt_at,
+            None => *status = DeliveryStatus::Dead,
+        }
+
+        Ok(())
+    }
+
+    async fn get_dead_letters(&self) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        Ok(self
+            .deliveries
+            .values()
+            .filter(|(_, status)| *status == DeliveryStatus::Dead)
+            .map(|(delivery, _)| delivery.clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{Email, TenantId, WebhookEventType};
+
+    fn subscription(event_types: Vec<WebhookEventType>) -> WebhookSubscription {
+        WebhookSubscription {
+            id: Uuid::new_v4(),
+            tenant: TenantId::default(),
+            url: "http://localhost/hook".to_owned(),
+            secret: "secret".to_owned(),
+            event_types,
+        }
+    }
+
+    fn event(event_type: WebhookEventType) -> WebhookEvent {
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        WebhookEvent::new(&TenantId::default(), event_type, &email)
+    }
+
+    #[tokio::test]
+    async fn test_events_are_queued_for_matching_subscriptions() {
+        let mut store = HashmapWebhookStore::default();
+        let everything = subscription(vec![]);
+        let locks_only = subscription(vec![WebhookEventType::AccountLocked]);
+        store.add_subscription(everything.clone()).await.unwrap();
+        store.add_subscription(locks_only).await.unwrap();
+
+        store
+            .enqueue_event(&event(WebhookEventType::UserCreated))
+            .await
+            .unwrap();
+
+        let due = store
+            .claim_due_deliveries(Utc::now(), Duration::minutes(1), 10)
+            .await
+            .unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].subscription_id, everything.id);
+        assert_eq!(due[0].event_type, WebhookEventType::UserCreated);
+    }
+
+    #[tokio::test]
+    async fn test_claimed_deliveries_are_not_handed_out_again_during_lease() {
+        let mut store = HashmapWebhookStore::default();
+        store.add_subscription(subscription(vec![])).await.unwrap();
+        store
+            .enqueue_event(&event(WebhookEventType::UserCreated))
+            .await
+            .unwrap();
+
+        let now = Utc::now();
+        let lease = Duration::minutes(1);
+        assert_eq!(
+            store
+                .claim_due_deliveries(now, lease, 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(store
+            .claim_due_deliveries(now, lease, 10)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store
+                .claim_due_deliveries(now + lease, lease, 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_without_retry_is_dead_lettered() {
+        let mut store = HashmapWebhookStore::default();
+        store.add_subscription(subscription(vec![])).await.unwrap();
+        store
+            .enqueue_event(&event(WebhookEventType::AccountLocked))
+            .await
+            .unwrap();
+
+        let now = Utc::now();
+        let delivery = store
+            .claim_due_deliveries(now, Duration::zero(), 10)
+            .await
+            .unwrap()
+            .remove(0);
+
+        store
+            .mark_failed(delivery.id, "HTTP 500", Some(now))
+            .await
+            .unwrap();
+        assert!(store.get_dead_letters().await.unwrap().is_empty());
+
+        store
+            .mark_failed(delivery.id, "HTTP 500", None)
+            .await
+            .unwrap();
+        let dead_letters = store.get_dead_letters().await.unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].attempts, 2);
+        assert_eq!(dead_letters[0].last_error.as_deref(), Some("HTTP 500"));
+        assert!(store
+            .claim_due_deliveries(now, Duration::zero(), 10)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
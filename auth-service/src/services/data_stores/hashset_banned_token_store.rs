@@ -1,10 +1,15 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
 
 use crate::domain::data_stores::{BannedTokenStore, BannedTokenStoreError};
+use crate::domain::TenantId;
 
 #[derive(Default)]
 pub struct HashsetBannedTokenStore {
     tokens: HashSet<String>,
+    // Subjects are compared ignoring case, like emails
+    subjects: HashMap<(TenantId, String), DateTime<Utc>>,
 }
 
 #[async_trait::async_trait]
@@ -17,6 +22,31 @@ impl BannedTokenStore for HashsetBannedTokenStore {
     async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
         Ok(self.tokens.contains(token))
     }
+
+    async fn ban_subject(
+        &mut self,
+        tenant: &TenantId,
+        subject: &str,
+        issued_until: DateTime<Utc>,
+    ) -> Result<(), BannedTokenStoreError> {
+        let banned_until = self
+            .subjects
+            .entry((tenant.clone(), subject.to_lowercase()))
+            .or_default();
+        *banned_until = issued_until.max(*banned_until);
+        Ok(())
+    }
+
+    async fn subject_banned_until(
+        &self,
+        tenant: &TenantId,
+        subject: &str,
+    ) -> Result<Option<DateTime<Utc>>, BannedTokenStoreError> {
+        Ok(self
+            .subjects
+            .get(&(tenant.clone(), subject.to_lowercase()))
+            .copied())
+    }
 }
 
 #[cfg(test)]
@@ -43,4 +73,38 @@ mod tests {
 
         assert!(result.unwrap());
     }
+
+    #[tokio::test]
+    async fn test_ban_subject() {
+        let mut store = HashsetBannedTokenStore::default();
+        let tenant = TenantId::default();
+        let other_tenant = TenantId::parse("acme".to_owned()).unwrap();
+        let now = Utc::now();
+
+        assert_eq!(
+            store.subject_banned_until(&tenant, "a@example.com").await,
+            Ok(None)
+        );
+
+        store
+            .ban_subject(&tenant, "A@example.com", now)
+            .await
+            .unwrap();
+        // An older ban never shortens a newer one
+        store
+            .ban_subject(&tenant, "a@example.com", now - chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.subject_banned_until(&tenant, "a@example.com").await,
+            Ok(Some(now))
+        );
+        assert_eq!(
+            store
+                .subject_banned_until(&other_tenant, "a@example.com")
+                .await,
+            Ok(None)
+        );
+    }
 }
@@ -1,6 +1,18 @@
+pub mod hashmap_api_key_store;
+pub mod hashmap_email_outbox_store;
+pub mod hashmap_rate_limiter;
+pub mod hashmap_tenant_store;
 pub mod hashmap_two_fa_code_store;
 pub mod hashmap_user_store;
+pub mod hashmap_webhook_store;
 pub mod hashset_banned_token_store;
+pub mod postgres_api_key_store;
+pub mod postgres_audit_sink;
+pub mod postgres_email_outbox_store;
+pub mod postgres_tenant_store;
 pub mod postgres_user_store;
+pub mod postgres_webhook_store;
 pub mod redis_banned_token_store;
+pub mod redis_rate_limiter;
 pub mod redis_two_fa_code_store;
+pub mod vec_audit_sink;
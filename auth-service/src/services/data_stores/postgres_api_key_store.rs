@@ -0,0 +1,167 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{ApiKey, ApiKeyStore, ApiKeyStoreError, Email, Permission, TenantId};
+
+pub struct PostgresApiKeyStore {
+    pool: PgPool,
+}
+
+impl PostgresApiKeyStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+struct ApiKeyRow {
+    id: Uuid,
+    tenant_id: String,
+    email: String,
+    name: String,
+    scopes: Vec<String>,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+impl TryFrom<ApiKeyRow> for ApiKey {
+    type Error = ApiKeyStoreError;
+
+    fn try_from(row: ApiKeyRow) -> Result<Self, Self::Error> {
+        Ok(ApiKey {
+            id: row.id,
+            tenant: TenantId::parse(row.tenant_id)
+                .map_err(|_| ApiKeyStoreError::UnexpectedError)?,
+            email: Email::parse(row.email).map_err(|_| ApiKeyStoreError::UnexpectedError)?,
+            name: row.name,
+            scopes: row
+                .scopes
+                .into_iter()
+                .map(|scope| {
+                    Permission::parse(scope).map_err(|_| ApiKeyStoreError::UnexpectedError)
+                })
+                .collect::<Result<_, _>>()?,
+            created_at: row.created_at,
+            expires_at: row.expires_at,
+            last_used_at: row.last_used_at,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl ApiKeyStore for PostgresApiKeyStore {
+    #[tracing::instrument(skip_all)]
+    async fn add_key(&mut self, key: ApiKey, key_hash: &str) -> Result<(), ApiKeyStoreError> {
+        let scopes: Vec<String> = key
+            .scopes
+            .iter()
+            .map(|scope| scope.as_ref().to_owned())
+            .collect();
+
+        sqlx::query!(
+            r#"
+            INSERT INTO api_keys (id, tenant_id, email, name, key_hash, scopes, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+            key.id,
+            key.tenant.as_ref(),
+            key.email.as_ref(),
+            key.name,
+            key_hash,
+            &scopes,
+            key.created_at,
+            key.expires_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| ApiKeyStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_key_by_hash(&self, key_hash: &str) -> Result<ApiKey, ApiKeyStoreError> {
+        let row = sqlx::query_as!(
+            ApiKeyRow,
+            r#"
+            SELECT id, tenant_id, email, name, scopes, created_at, expires_at, last_used_at
+            FROM api_keys
+            WHERE key_hash = $1
+            "#,
+            key_hash,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| ApiKeyStoreError::UnexpectedError)?
+        .ok_or(ApiKeyStoreError::KeyNotFound)?;
+
+        ApiKey::try_from(row)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_keys(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<Vec<ApiKey>, ApiKeyStoreError> {
+        let rows = sqlx::query_as!(
+            ApiKeyRow,
+            r#"
+            SELECT id, tenant_id, email, name, scopes, created_at, expires_at, last_used_at
+            FROM api_keys
+            WHERE tenant_id = $1 AND email = $2
+            ORDER BY created_at
+            "#,
+            tenant.as_ref(),
+            email.as_ref(),
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| ApiKeyStoreError::UnexpectedError)?;
+
+        rows.into_iter().map(ApiKey::try_from).collect()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn revoke_key(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<(), ApiKeyStoreError> {
+        let result = sqlx::query!(
+            "DELETE FROM api_keys WHERE id = $1 AND tenant_id = $2 AND email = $3",
+            id,
+            tenant.as_ref(),
+            email.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| ApiKeyStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(ApiKeyStoreError::KeyNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn mark_used(&mut self, id: Uuid, at: DateTime<Utc>) -> Result<(), ApiKeyStoreError> {
+        let result = sqlx::query!(
+            "UPDATE api_keys SET last_used_at = $2 WHERE id = $1",
+            id,
+            at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| ApiKeyStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(ApiKeyStoreError::KeyNotFound);
+        }
+
+        Ok(())
+    }
+}
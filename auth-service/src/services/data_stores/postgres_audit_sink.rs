@@ -0,0 +1,235 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{SubsecRound, Utc};
+use sqlx::PgPool;
+
+use crate::domain::{
+    chain_hash, AuditCheckpoint, AuditEvent, AuditEventType, AuditOutcome, AuditRecord, AuditSink,
+    AuditSinkError, SignedAuditCheckpoint, GENESIS_HASH,
+};
+use crate::services::audit_checkpoint_signer::AuditCheckpointSigner;
+
+// Writes to the `audit_log` table, which refuses updates and deletes.
+pub struct PostgresAuditSink {
+    pool: PgPool,
+}
+
+impl PostgresAuditSink {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Id of the first hash-chained record. Records before it were written
+    /// before the chain was introduced.
+    pub async fn first_chained_record_id(&self) -> Result<Option<i64>, AuditSinkError> {
+        sqlx::query_scalar!("SELECT min(id) FROM audit_log WHERE hash IS NOT NULL")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(|_| AuditSinkError::UnexpectedError)
+    }
+
+    /// Up to `limit` records with ids above `after_id`, in chain order.
+    pub async fn records_after(
+        &self,
+        after_id: i64,
+        limit: i64,
+    ) -> Result<Vec<AuditRecord>, AuditSinkError> {
+        // Unchained rows come back with empty hashes, which the verifier reports
+        let rows = sqlx::query!(
+            r#"
+            SELECT id, occurred_at, event_type, outcome, subject, host(ip) AS ip, user_agent, detail, actor, tenant,
+                   COALESCE(prev_hash, '') AS "prev_hash!", COALESCE(hash, '') AS "hash!"
+            FROM audit_log
+            WHERE id > $1
+            ORDER BY id
+            LIMIT $2
+            "#,
+            after_id,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AuditSinkError::UnexpectedError)?;
+
+        rows.into_iter()
+            .map(|row| {
+                let event = AuditEvent {
+                    occurred_at: row.occurred_at,
+                    event_type: AuditEventType::parse(&row.event_type)
+                        .map_err(|_| AuditSinkError::UnexpectedError)?,
+                    outcome: AuditOutcome::parse(&row.outcome)
+                        .map_err(|_| AuditSinkError::UnexpectedError)?,
+                    subject: row.subject,
+                    ip: row.ip.and_then(|ip| ip.parse().ok()),
+                    user_agent: row.user_agent,
+                    detail: row.detail,
+                    actor: row.actor,
+                    tenant: row.tenant,
+                };
+
+                Ok(AuditRecord {
+                    id: row.id,
+                    event,
+                    prev_hash: row.prev_hash,
+                    hash: row.hash,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn record_hash(&self, id: i64) -> Result<Option<String>, AuditSinkError> {
+        sqlx::query_scalar!("SELECT hash FROM audit_log WHERE id = $1", id)
+            .fetch_optional(&self.pool)
+            .await
+            .map(Option::flatten)
+            .map_err(|_| AuditSinkError::UnexpectedError)
+    }
+
+    /// Sign the current head of the chain and store the checkpoint.
+    /// Returns `None` if nothing was recorded since the last checkpoint.
+    pub async fn create_checkpoint(
+        &self,
+        signer: &AuditCheckpointSigner,
+    ) -> Result<Option<SignedAuditCheckpoint>, AuditSinkError> {
+        let head = sqlx::query!(
+            r#"SELECT id, hash AS "hash!" FROM audit_log WHERE hash IS NOT NULL ORDER BY id DESC LIMIT 1"#
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| AuditSinkError::UnexpectedError)?;
+
+        let Some(head) = head else {
+            return Ok(None);
+        };
+
+        let last_checkpointed_id =
+            sqlx::query_scalar!("SELECT max(last_record_id) FROM audit_checkpoints")
+                .fetch_one(&self.pool)
+                .await
+                .map_err(|_| AuditSinkError::UnexpectedError)?;
+
+        if last_checkpointed_id == Some(head.id) {
+            return Ok(None);
+        }
+
+        let signed = signer.sign(AuditCheckpoint {
+            // Microseconds, like Postgres, so the stored checkpoint still verifies
+            created_at: Utc::now().trunc_subsecs(6),
+            last_record_id: head.id,
+            last_hash: head.hash,
+        });
+
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_checkpoints (created_at, last_record_id, last_hash, public_key, signature)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+            signed.checkpoint.created_at,
+            signed.checkpoint.last_record_id,
+            signed.checkpoint.last_hash,
+            signed.public_key,
+            signed.signature,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| AuditSinkError::UnexpectedError)?;
+
+        Ok(Some(signed))
+    }
+
+    pub async fn checkpoints(&self) -> Result<Vec<SignedAuditCheckpoint>, AuditSinkError> {
+        let rows = sqlx::query!(
+            "SELECT created_at, last_record_id, last_hash, public_key, signature FROM audit_checkpoints ORDER BY id"
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| AuditSinkError::UnexpectedError)?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| SignedAuditCheckpoint {
+                checkpoint: AuditCheckpoint {
+                    created_at: row.created_at,
+                    last_record_id: row.last_record_id,
+                    last_hash: row.last_hash,
+                },
+                public_key: row.public_key,
+                signature: row.signature,
+            })
+            .collect())
+    }
+
+    /// Create a checkpoint every `interval`, for as long as the service runs.
+    pub async fn run_periodic_checkpoints(
+        self: Arc<Self>,
+        signer: AuditCheckpointSigner,
+        interval: Duration,
+    ) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = self.create_checkpoint(&signer).await {
+                tracing::error!(error = ?e, "Failed to create audit checkpoint");
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditSink for PostgresAuditSink {
+    #[tracing::instrument(skip_all)]
+    async fn record(&self, event: AuditEvent) -> Result<(), AuditSinkError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| AuditSinkError::UnexpectedError)?;
+
+        // One writer at a time, so every record links to the one before it.
+        // Reads aren't blocked.
+        sqlx::query!("LOCK TABLE audit_log IN SHARE ROW EXCLUSIVE MODE")
+            .execute(&mut *transaction)
+            .await
+            .map_err(|_| AuditSinkError::UnexpectedError)?;
+
+        let prev_hash = sqlx::query_scalar!(
+            "SELECT hash FROM audit_log WHERE hash IS NOT NULL ORDER BY id DESC LIMIT 1"
+        )
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(|_| AuditSinkError::UnexpectedError)?
+        .flatten()
+        .unwrap_or_else(|| GENESIS_HASH.to_owned());
+
+        let hash = chain_hash(&prev_hash, &event);
+
+        sqlx::query!(
+            r#"
+            INSERT INTO audit_log (occurred_at, event_type, outcome, subject, ip, user_agent, detail, actor, tenant, prev_hash, hash)
+            VALUES ($1, $2, $3, $4, $5::text::inet, $6, $7, $8, $9, $10, $11)
+            "#,
+            event.occurred_at,
+            event.event_type.as_str(),
+            event.outcome.as_str(),
+            event.subject,
+            event.ip.map(|ip| ip.to_string()),
+            event.user_agent,
+            event.detail,
+            event.actor,
+            event.tenant,
+            prev_hash,
+            hash,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| AuditSinkError::UnexpectedError)?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|_| AuditSinkError::UnexpectedError)?;
+
+        Ok(())
+    }
+}
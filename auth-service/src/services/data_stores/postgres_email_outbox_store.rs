@@ -0,0 +1,210 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{
+    Email, EmailMessage, EmailOutboxStore, EmailOutboxStoreError, OutboxEmail, OutboxEmailStatus,
+};
+
+pub struct PostgresEmailOutboxStore {
+    pool: PgPool,
+}
+
+impl PostgresEmailOutboxStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+struct OutboxEmailRow {
+    id: Uuid,
+    idempotency_key: String,
+    recipient: String,
+    subject: String,
+    text_body: String,
+    html_body: Option<String>,
+    status: String,
+    attempts: i32,
+    next_attempt_at: DateTime<Utc>,
+    last_error: Option<String>,
+}
+
+impl TryFrom<OutboxEmailRow> for OutboxEmail {
+    type Error = EmailOutboxStoreError;
+
+    fn try_from(row: OutboxEmailRow) -> Result<Self, Self::Error> {
+        Ok(OutboxEmail {
+            id: row.id,
+            idempotency_key: row.idempotency_key,
+            recipient: Email::parse(row.recipient)
+                .map_err(|_| EmailOutboxStoreError::UnexpectedError)?,
+            message: EmailMessage {
+                subject: row.subject,
+                text: row.text_body,
+                html: row.html_body,
+            },
+            status: OutboxEmailStatus::parse(&row.status)
+                .map_err(|_| EmailOutboxStoreError::UnexpectedError)?,
+            attempts: row.attempts.max(0) as u32,
+            next_attempt_at: row.next_attempt_at,
+            last_error: row.last_error,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailOutboxStore for PostgresEmailOutboxStore {
+    #[tracing::instrument(skip_all)]
+    async fn enqueue(&mut self, email: OutboxEmail) -> Result<(), EmailOutboxStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO email_outbox
+                (id, idempotency_key, recipient, subject, text_body, html_body, next_attempt_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (idempotency_key) DO NOTHING
+            "#,
+            email.id,
+            email.idempotency_key,
+            email.recipient.as_ref(),
+            email.message.subject,
+            email.message.text,
+            email.message.html,
+            email.next_attempt_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| EmailOutboxStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn claim_due(
+        &mut self,
+        now: DateTime<Utc>,
+        lease: Duration,
+        limit: usize,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+        // SKIP LOCKED lets workers on other instances claim other rows
+        // instead of waiting for these
+        let rows = sqlx::query_as!(
+            OutboxEmailRow,
+            r#"
+            UPDATE email_outbox SET next_attempt_at = $2
+            WHERE id IN (
+                SELECT id FROM email_outbox
+                WHERE status = 'pending' AND next_attempt_at <= $1
+                ORDER BY next_attempt_at
+                LIMIT $3
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, idempotency_key, recipient, subject, text_body, html_body, status,
+                      attempts, next_attempt_at, last_error
+            "#,
+            now,
+            now + lease,
+            limit as i64,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| EmailOutboxStoreError::UnexpectedError)?;
+
+        rows.into_iter().map(OutboxEmail::try_from).collect()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn mark_sent(&mut self, id: Uuid) -> Result<(), EmailOutboxStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE email_outbox SET
+                status = 'sent',
+                attempts = attempts + 1,
+                text_body = '',
+                html_body = NULL
+            WHERE id = $1
+            "#,
+            id,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| EmailOutboxStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(EmailOutboxStoreError::EmailNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn mark_failed(
+        &mut self,
+        id: Uuid,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), EmailOutboxStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE email_outbox SET
+                attempts = attempts + 1,
+                last_error = $2,
+                status = CASE WHEN $3::timestamptz IS NULL THEN 'failed' ELSE status END,
+                text_body = CASE WHEN $3::timestamptz IS NULL THEN '' ELSE text_body END,
+                html_body = CASE WHEN $3::timestamptz IS NULL THEN NULL ELSE html_body END,
+                next_attempt_at = COALESCE($3, next_attempt_at)
+            WHERE id = $1
+            "#,
+            id,
+            error,
+            next_attempt_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| EmailOutboxStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(EmailOutboxStoreError::EmailNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_email(&self, idempotency_key: &str) -> Result<OutboxEmail, EmailOutboxStoreError> {
+        let row = sqlx::query_as!(
+            OutboxEmailRow,
+            r#"
+            SELECT id, idempotency_key, recipient, subject, text_body, html_body, status,
+                   attempts, next_attempt_at, last_error
+            FROM email_outbox
+            WHERE idempotency_key = $1
+            "#,
+            idempotency_key,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| EmailOutboxStoreError::UnexpectedError)?
+        .ok_or(EmailOutboxStoreError::EmailNotFound)?;
+
+        OutboxEmail::try_from(row)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_failed(&self) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+        let rows = sqlx::query_as!(
+            OutboxEmailRow,
+            r#"
+            SELECT id, idempotency_key, recipient, subject, text_body, html_body, status,
+                   attempts, next_attempt_at, last_error
+            FROM email_outbox
+            WHERE status = 'failed'
+            ORDER BY next_attempt_at
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| EmailOutboxStoreError::UnexpectedError)?;
+
+        rows.into_iter().map(OutboxEmail::try_from).collect()
+    }
+}
@@ -0,0 +1,174 @@
+use sqlx::PgPool;
+
+use crate::domain::{
+    normalize_host, Tenant, TenantId, TenantSettings, TenantStore, TenantStoreError,
+};
+
+pub struct PostgresTenantStore {
+    pool: PgPool,
+}
+
+impl PostgresTenantStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    async fn get_hosts(&self, id: &str) -> Result<Vec<String>, TenantStoreError> {
+        sqlx::query_scalar!(
+            "SELECT host FROM tenant_hosts WHERE tenant_id = $1 ORDER BY host",
+            id,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| TenantStoreError::UnexpectedError)
+    }
+
+    async fn to_tenant(
+        &self,
+        id: String,
+        name: String,
+        min_password_length: i32,
+        requires_2fa: bool,
+    ) -> Result<Tenant, TenantStoreError> {
+        let hosts = self.get_hosts(&id).await?;
+
+        Ok(Tenant {
+            id: TenantId::parse(id).map_err(|_| TenantStoreError::UnexpectedError)?,
+            name,
+            hosts,
+            settings: TenantSettings {
+                min_password_length: usize::try_from(min_password_length)
+                    .map_err(|_| TenantStoreError::UnexpectedError)?,
+                requires_2fa,
+            },
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl TenantStore for PostgresTenantStore {
+    #[tracing::instrument(skip_all)]
+    async fn add_tenant(&mut self, tenant: Tenant) -> Result<(), TenantStoreError> {
+        let min_password_length = i32::try_from(tenant.settings.min_password_length)
+            .map_err(|_| TenantStoreError::UnexpectedError)?;
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| TenantStoreError::UnexpectedError)?;
+
+        sqlx::query!(
+            "INSERT INTO tenants (id, name, min_password_length, requires_2fa) VALUES ($1, $2, $3, $4)",
+            tenant.id.as_ref(),
+            tenant.name,
+            min_password_length,
+            tenant.settings.requires_2fa,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                TenantStoreError::TenantAlreadyExists
+            }
+            _ => TenantStoreError::UnexpectedError,
+        })?;
+
+        for host in &tenant.hosts {
+            sqlx::query!(
+                "INSERT INTO tenant_hosts (host, tenant_id) VALUES ($1, $2)",
+                normalize_host(host),
+                tenant.id.as_ref(),
+            )
+            .execute(&mut *transaction)
+            .await
+            .map_err(|e| match e {
+                sqlx::Error::Database(e) if e.is_unique_violation() => TenantStoreError::HostTaken,
+                _ => TenantStoreError::UnexpectedError,
+            })?;
+        }
+
+        transaction
+            .commit()
+            .await
+            .map_err(|_| TenantStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_tenant(&self, id: &TenantId) -> Result<Tenant, TenantStoreError> {
+        let row = sqlx::query!(
+            "SELECT id, name, min_password_length, requires_2fa FROM tenants WHERE id = $1",
+            id.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| TenantStoreError::UnexpectedError)?
+        .ok_or(TenantStoreError::TenantNotFound)?;
+
+        self.to_tenant(row.id, row.name, row.min_password_length, row.requires_2fa)
+            .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_tenant_by_host(&self, host: &str) -> Result<Tenant, TenantStoreError> {
+        let row = sqlx::query!(
+            r#"
+            SELECT t.id, t.name, t.min_password_length, t.requires_2fa FROM tenants t
+            JOIN tenant_hosts h ON h.tenant_id = t.id
+            WHERE h.host = $1
+            "#,
+            normalize_host(host),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| TenantStoreError::UnexpectedError)?
+        .ok_or(TenantStoreError::TenantNotFound)?;
+
+        self.to_tenant(row.id, row.name, row.min_password_length, row.requires_2fa)
+            .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_tenants(&self) -> Result<Vec<Tenant>, TenantStoreError> {
+        let rows = sqlx::query!(
+            "SELECT id, name, min_password_length, requires_2fa FROM tenants ORDER BY id"
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| TenantStoreError::UnexpectedError)?;
+
+        let mut tenants = Vec::with_capacity(rows.len());
+        for row in rows {
+            tenants.push(
+                self.to_tenant(row.id, row.name, row.min_password_length, row.requires_2fa)
+                    .await?,
+            );
+        }
+        Ok(tenants)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn update_settings(
+        &mut self,
+        id: &TenantId,
+        settings: TenantSettings,
+    ) -> Result<(), TenantStoreError> {
+        let min_password_length = i32::try_from(settings.min_password_length)
+            .map_err(|_| TenantStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            "UPDATE tenants SET min_password_length = $2, requires_2fa = $3 WHERE id = $1",
+            id.as_ref(),
+            min_password_length,
+            settings.requires_2fa,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| TenantStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(TenantStoreError::TenantNotFound);
+        }
+
+        Ok(())
+    }
+}
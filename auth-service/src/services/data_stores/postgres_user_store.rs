@@ -1,14 +1,13 @@
 #![allow(unused_variables)]
 
-use argon2::{
-    password_hash::{PasswordHasher, PasswordVerifier, SaltString},
-    Algorithm, Argon2, Version,
-};
-use rand::rng;
-
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 
-use crate::domain::{data_stores::UserStore, Email, Password, User, UserStoreError};
+use crate::app_state::PasswordHasherType;
+use crate::domain::{
+    data_stores::UserStore, Email, LockoutPolicy, LockoutState, Password, PasswordHash, Permission,
+    PhoneNumber, Role, TenantId, TwoFAChannel, User, UserListQuery, UserPage, UserStoreError,
+};
 
 // use async_trait::async_trait;
 // use std::collections::HashMap;
@@ -22,49 +21,288 @@ use crate::domain::{data_stores::UserStore, Email, Password, User, UserStoreErro
 //     users: HashMap<Email, User>, // key: Email tuple as key, value: User object, email is unique
 // }
 
-#[derive(Debug)]
 pub struct PostgresUserStore {
     pool: PgPool,
+    password_hasher: PasswordHasherType,
+    lockout_policy: LockoutPolicy,
 }
 
 impl PostgresUserStore {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, password_hasher: PasswordHasherType) -> Self {
+        Self {
+            pool,
+            password_hasher,
+            lockout_policy: LockoutPolicy::default(),
+        }
+    }
+
+    pub fn with_lockout_policy(mut self, lockout_policy: LockoutPolicy) -> Self {
+        self.lockout_policy = lockout_policy;
+        self
+    }
+
+    // Incremented in the database rather than from the state we read, so
+    // concurrent attempts from several instances are all counted.
+    async fn record_failed_login(&self, email: &Email) -> Result<UserStoreError, UserStoreError> {
+        let now = Utc::now();
+        let lock_threshold = i32::try_from(self.lockout_policy.lock_threshold).unwrap_or(i32::MAX);
+
+        let result = sqlx::query!(
+            r#"
+            UPDATE users SET
+                failed_login_attempts = failed_login_attempts + 1,
+                last_failed_login_at = $2,
+                locked_until = CASE WHEN failed_login_attempts + 1 >= $3 THEN $4::timestamptz ELSE NULL END
+            WHERE lower(email) = lower($1)
+            RETURNING failed_login_attempts, last_failed_login_at, locked_until
+            "#,
+            email.as_ref(),
+            now,
+            lock_threshold,
+            now + self.lockout_policy.lock_duration,
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let state = LockoutState {
+            failed_attempts: result.failed_login_attempts.max(0) as u32,
+            last_failed_at: result.last_failed_login_at,
+            locked_until: result.locked_until,
+        };
+
+        Ok(self.lockout_policy.failure_error(&state, now))
+    }
+
+    async fn reset_failed_logins(&self, email: &Email) -> Result<(), UserStoreError> {
+        sqlx::query!(
+            r#"
+            UPDATE users SET failed_login_attempts = 0, last_failed_login_at = NULL, locked_until = NULL
+            WHERE lower(email) = lower($1) AND (failed_login_attempts > 0 OR locked_until IS NOT NULL)
+            "#,
+            email.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    // The email as stored, which `tenant_members` and `user_roles` reference
+    async fn stored_email(&self, email: &Email) -> Result<String, UserStoreError> {
+        sqlx::query_scalar!(
+            "SELECT email FROM users WHERE lower(email) = lower($1)",
+            email.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)
+    }
+
+    // Like `stored_email`, but only for members of `tenant`
+    async fn stored_member_email(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<String, UserStoreError> {
+        sqlx::query_scalar!(
+            "SELECT email FROM tenant_members WHERE tenant_id = $1 AND lower(email) = lower($2)",
+            tenant.as_ref(),
+            email.as_ref(),
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?
+        .ok_or(UserStoreError::UserNotFound)
+    }
+
+    // Run a statement against one user, treating no matching row as a missing user
+    async fn execute_for_user(
+        &self,
+        query: sqlx::query::Query<'_, sqlx::Postgres, sqlx::postgres::PgArguments>,
+    ) -> Result<(), UserStoreError> {
+        let result = query
+            .execute(&self.pool)
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
     }
-}
 
-use argon2::password_hash::rand_core::OsRng;
+    // Best effort: a failed upgrade must not turn a successful login into an error,
+    // the old hash still verifies and we'll try again on the next login.
+    async fn rehash_password(&self, email: &Email, password: &Password, old_hash: &PasswordHash) {
+        let new_hash = match self.password_hasher.hash_password(password).await {
+            Ok(hash) => hash,
+            Err(_) => return,
+        };
+
+        // Only replace the hash we verified against, in case it changed concurrently
+        let result = sqlx::query!(
+            "UPDATE users SET password_hash = $1 WHERE lower(email) = lower($2) AND password_hash = $3",
+            new_hash.as_ref(),
+            email.as_ref(),
+            old_hash.as_ref(),
+        )
+        .execute(&self.pool)
+        .await;
+
+        if let Err(e) = result {
+            tracing::warn!(error = %e, "Failed to upgrade password hash");
+        }
+    }
+}
 
 #[async_trait::async_trait]
 impl UserStore for PostgresUserStore {
-    async fn add_user(&mut self, user: User) -> Result<(), UserStoreError> {
-        let salt = SaltString::generate(&mut OsRng);
-        let password_hash = Argon2::default()
-            .hash_password(user.password.as_ref().as_bytes(), &salt)
+    #[tracing::instrument(skip_all)]
+    async fn add_user(&mut self, tenant: &TenantId, user: User) -> Result<(), UserStoreError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
             .map_err(|_| UserStoreError::UnexpectedError)?;
 
         let result = sqlx::query!(
             "INSERT INTO users (email, password_hash, requires_2fa) VALUES ($1, $2, $3) RETURNING email",
             user.email.as_ref(),
-            password_hash.to_string(),
+            user.password_hash.as_ref(),
             user.requires_2fa,
         )
-        .fetch_one(&self.pool)
+        .fetch_one(&mut *transaction)
         .await
-        .map_err(|_| UserStoreError::UnexpectedError)?;
+        .map_err(|e| match e {
+            // Also raised by the case-insensitive index on email
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                UserStoreError::UserAlreadyExists
+            }
+            _ => UserStoreError::UnexpectedError,
+        })?;
 
         let user_id = result.email;
 
+        sqlx::query!(
+            "INSERT INTO tenant_members (tenant_id, email) VALUES ($1, $2)",
+            tenant.as_ref(),
+            user_id,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(map_membership_error)?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn add_pending_user(
+        &mut self,
+        tenant: &TenantId,
+        user: User,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        // Memberships and roles of the replaced user go with it
+        sqlx::query!(
+            "DELETE FROM users WHERE lower(email) = lower($1) AND signup_token_hash IS NOT NULL",
+            user.email.as_ref(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let result = sqlx::query!(
+            r#"
+            INSERT INTO users (email, password_hash, requires_2fa, signup_token_hash, signup_expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING email
+            "#,
+            user.email.as_ref(),
+            user.password_hash.as_ref(),
+            user.requires_2fa,
+            token_hash,
+            expires_at,
+        )
+        .fetch_one(&mut *transaction)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_unique_violation() => {
+                UserStoreError::UserAlreadyExists
+            }
+            _ => UserStoreError::UnexpectedError,
+        })?;
+
+        sqlx::query!(
+            "INSERT INTO tenant_members (tenant_id, email) VALUES ($1, $2)",
+            tenant.as_ref(),
+            result.email,
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(map_membership_error)?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn confirm_signup(
+        &mut self,
+        email: &Email,
+        token_hash: &str,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE users SET signup_token_hash = NULL, signup_expires_at = NULL
+            WHERE lower(email) = lower($1)
+                AND signup_token_hash = $2
+                AND signup_expires_at > now()
+            "#,
+            email.as_ref(),
+            token_hash,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::InvalidCredentials);
+        }
+
         Ok(())
     }
 
     // return a `Result` type containing either a
     // `User` object or a `UserStoreError`.
     // Return `UserStoreError::UserNotFound` if the user can not be found.
-    async fn get_user(&self, email: &Email) -> Result<User, UserStoreError> {
+    #[tracing::instrument(skip_all)]
+    async fn get_user(&self, tenant: &TenantId, email: &Email) -> Result<User, UserStoreError> {
         let result = sqlx::query!(
-            "SELECT email, password_hash, requires_2fa FROM users WHERE email = $1",
+            r#"
+            SELECT u.email, u.password_hash, u.requires_2fa, u.disabled, u.phone, u.two_fa_channel,
+                u.signup_token_hash IS NOT NULL AS "pending!"
+            FROM users u
+            JOIN tenant_members m ON m.email = u.email AND m.tenant_id = $2
+            WHERE lower(u.email) = lower($1)
+            "#,
             email.as_ref(),
+            tenant.as_ref(),
         )
         .fetch_one(&self.pool)
         .await
@@ -77,9 +315,15 @@ impl UserStore for PostgresUserStore {
         })?;
 
         let user = User {
-            email: Email::parse(result.email).unwrap(),
-            password: Password::parse(result.password_hash).unwrap(),
+            email: Email::parse(result.email).map_err(|_| UserStoreError::UnexpectedError)?,
+            password_hash: PasswordHash::parse(result.password_hash)
+                .map_err(|_| UserStoreError::UnexpectedError)?,
             requires_2fa: result.requires_2fa,
+            disabled: result.disabled,
+            phone: parse_phone(result.phone)?,
+            two_fa_channel: TwoFAChannel::parse(&result.two_fa_channel)
+                .map_err(|_| UserStoreError::UnexpectedError)?,
+            pending: result.pending,
         };
 
         Ok(user)
@@ -87,32 +331,539 @@ impl UserStore for PostgresUserStore {
 
     // Return `UserStoreError::UserNotFound` if the user can not be found.
     // Return `UserStoreError::InvalidCredentials` if the password is incorrect.
+    #[tracing::instrument(skip_all)]
     async fn validate_user(
         &self,
+        tenant: &TenantId,
         email: &Email,
         password: &Password,
     ) -> Result<(), UserStoreError> {
+        let user = match self.get_user(tenant, email).await {
+            Ok(user) => user,
+            Err(UserStoreError::UserNotFound) => {
+                // Don't let the response time give away that the email isn't registered
+                self.password_hasher.verify_dummy_password(password).await;
+                return Err(UserStoreError::UserNotFound);
+            }
+            Err(e) => return Err(e),
+        };
+
+        let lockout_state = self.get_lockout_state(email).await?;
+
+        // Verified before the lock is checked, so a locked account takes as
+        // long to answer as any other
+        let verified = self
+            .password_hasher
+            .verify_password(password, &user.password_hash)
+            .await
+            .map_err(UserStoreError::from);
+
+        self.lockout_policy.check(&lockout_state, Utc::now())?;
+
+        match verified {
+            // Logging in to an account that was just signed up for would
+            // otherwise show the address wasn't registered before
+            Ok(()) if !user.pending => {}
+            Ok(()) | Err(UserStoreError::InvalidCredentials) => {
+                return Err(self.record_failed_login(email).await?)
+            }
+            Err(e) => return Err(e),
+        }
+
+        // Only reported with the right password, so it doesn't reveal anything
+        // to someone guessing
+        if user.disabled {
+            return Err(UserStoreError::AccountDisabled);
+        }
+
+        // A successful login ends the run of consecutive failures
+        if lockout_state != LockoutState::default() {
+            self.reset_failed_logins(email).await?;
+        }
+
+        if self.password_hasher.needs_rehash(&user.password_hash) {
+            self.rehash_password(email, password, &user.password_hash)
+                .await;
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_lockout_state(&self, email: &Email) -> Result<LockoutState, UserStoreError> {
+        let result = sqlx::query!(
+            "SELECT failed_login_attempts, last_failed_login_at, locked_until FROM users WHERE lower(email) = lower($1)",
+            email.as_ref(),
+        )
+        .fetch_one(&self.pool)
+        .await
+        .map_err(|e| {
+            if let sqlx::Error::RowNotFound = e {
+                UserStoreError::UserNotFound
+            } else {
+                UserStoreError::UnexpectedError
+            }
+        })?;
+
+        Ok(LockoutState {
+            failed_attempts: result.failed_login_attempts.max(0) as u32,
+            last_failed_at: result.last_failed_login_at,
+            locked_until: result.locked_until,
+        })
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn unlock_user(&mut self, email: &Email) -> Result<(), UserStoreError> {
         let result = sqlx::query!(
-            "SELECT email, password_hash, requires_2fa FROM users WHERE email = $1",
+            "UPDATE users SET failed_login_attempts = 0, last_failed_login_at = NULL, locked_until = NULL WHERE lower(email) = lower($1)",
+            email.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn add_member(&mut self, tenant: &TenantId, email: &Email) -> Result<(), UserStoreError> {
+        let stored_email = self.stored_email(email).await?;
+
+        sqlx::query!(
+            "INSERT INTO tenant_members (tenant_id, email) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+            tenant.as_ref(),
+            stored_email,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(map_membership_error)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn remove_member(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), UserStoreError> {
+        let stored_email = self.stored_email(email).await?;
+
+        // Roles in the tenant go with the membership through the cascading foreign key
+        sqlx::query!(
+            "DELETE FROM tenant_members WHERE tenant_id = $1 AND email = $2",
+            tenant.as_ref(),
+            stored_email,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_tenants(&self, email: &Email) -> Result<Vec<TenantId>, UserStoreError> {
+        let tenants = sqlx::query_scalar!(
+            "SELECT tenant_id FROM tenant_members WHERE lower(email) = lower($1) ORDER BY tenant_id",
             email.as_ref(),
         )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        tenants
+            .into_iter()
+            .map(|tenant| TenantId::parse(tenant).map_err(|_| UserStoreError::UnexpectedError))
+            .collect()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn assign_role(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+        role: &Role,
+    ) -> Result<(), UserStoreError> {
+        let stored_email = self.stored_member_email(tenant, email).await?;
+
+        sqlx::query!(
+            "INSERT INTO user_roles (tenant_id, email, role) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+            tenant.as_ref(),
+            stored_email,
+            role.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::Database(e) if e.is_foreign_key_violation() => {
+                UserStoreError::RoleNotFound
+            }
+            _ => UserStoreError::UnexpectedError,
+        })?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn revoke_role(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+        role: &Role,
+    ) -> Result<(), UserStoreError> {
+        let stored_email = self.stored_member_email(tenant, email).await?;
+
+        sqlx::query!(
+            "DELETE FROM user_roles WHERE tenant_id = $1 AND email = $2 AND role = $3",
+            tenant.as_ref(),
+            stored_email,
+            role.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_roles(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<Vec<Role>, UserStoreError> {
+        let stored_email = self.stored_member_email(tenant, email).await?;
+
+        let roles = sqlx::query_scalar!(
+            "SELECT role FROM user_roles WHERE tenant_id = $1 AND email = $2 ORDER BY role",
+            tenant.as_ref(),
+            stored_email,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        roles
+            .into_iter()
+            .map(|role| Role::parse(role).map_err(|_| UserStoreError::UnexpectedError))
+            .collect()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_permissions(&self, roles: &[Role]) -> Result<Vec<Permission>, UserStoreError> {
+        let roles: Vec<String> = roles.iter().map(|role| role.as_ref().to_owned()).collect();
+
+        let permissions = sqlx::query_scalar!(
+            "SELECT DISTINCT permission FROM role_permissions WHERE role = ANY($1) ORDER BY permission",
+            &roles,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        permissions
+            .into_iter()
+            .map(|permission| {
+                Permission::parse(permission).map_err(|_| UserStoreError::UnexpectedError)
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn list_users(
+        &self,
+        tenant: &TenantId,
+        query: &UserListQuery,
+    ) -> Result<UserPage, UserStoreError> {
+        // Match the search literally, not as a LIKE pattern
+        let pattern = query.search.as_ref().map(|search| {
+            let escaped = search
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{}%", escaped)
+        });
+        let offset = i64::try_from(query.offset).unwrap_or(i64::MAX);
+        let limit = i64::try_from(query.limit).unwrap_or(i64::MAX);
+
+        let total = sqlx::query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!" FROM tenant_members
+            WHERE tenant_id = $1 AND ($2::text IS NULL OR email ILIKE $2)
+            "#,
+            tenant.as_ref(),
+            pattern,
+        )
         .fetch_one(&self.pool)
         .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let rows = sqlx::query!(
+            r#"
+            SELECT u.email, u.password_hash, u.requires_2fa, u.disabled, u.phone, u.two_fa_channel,
+                u.signup_token_hash IS NOT NULL AS "pending!"
+            FROM users u
+            JOIN tenant_members m ON m.email = u.email AND m.tenant_id = $1
+            WHERE ($2::text IS NULL OR u.email ILIKE $2)
+            ORDER BY lower(u.email)
+            OFFSET $3 LIMIT $4
+            "#,
+            tenant.as_ref(),
+            pattern,
+            offset,
+            limit,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        let users = rows
+            .into_iter()
+            .map(|row| {
+                Ok(User {
+                    email: Email::parse(row.email).map_err(|_| UserStoreError::UnexpectedError)?,
+                    password_hash: PasswordHash::parse(row.password_hash)
+                        .map_err(|_| UserStoreError::UnexpectedError)?,
+                    requires_2fa: row.requires_2fa,
+                    disabled: row.disabled,
+                    phone: parse_phone(row.phone)?,
+                    two_fa_channel: TwoFAChannel::parse(&row.two_fa_channel)
+                        .map_err(|_| UserStoreError::UnexpectedError)?,
+                    pending: row.pending,
+                })
+            })
+            .collect::<Result<Vec<_>, UserStoreError>>()?;
+
+        Ok(UserPage {
+            users,
+            total: usize::try_from(total).unwrap_or(0),
+        })
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn set_disabled(&mut self, email: &Email, disabled: bool) -> Result<(), UserStoreError> {
+        self.execute_for_user(sqlx::query!(
+            "UPDATE users SET disabled = $2 WHERE lower(email) = lower($1)",
+            email.as_ref(),
+            disabled,
+        ))
+        .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn set_requires_2fa(
+        &mut self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        self.execute_for_user(sqlx::query!(
+            "UPDATE users SET requires_2fa = $2 WHERE lower(email) = lower($1)",
+            email.as_ref(),
+            requires_2fa,
+        ))
+        .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn start_password_reset(
+        &mut self,
+        email: &Email,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        self.execute_for_user(sqlx::query!(
+            r#"
+            UPDATE users SET password_reset_token_hash = $2, password_reset_expires_at = $3
+            WHERE lower(email) = lower($1)
+            "#,
+            email.as_ref(),
+            token_hash,
+            expires_at,
+        ))
+        .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn complete_password_reset(
+        &mut self,
+        email: &Email,
+        token_hash: &str,
+        new_password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError> {
+        // Clearing the token in the same statement makes each reset single use
+        let result = sqlx::query!(
+            r#"
+            UPDATE users SET
+                password_hash = $3,
+                password_reset_token_hash = NULL,
+                password_reset_expires_at = NULL,
+                failed_login_attempts = 0,
+                last_failed_login_at = NULL,
+                locked_until = NULL
+            WHERE lower(email) = lower($1)
+                AND password_reset_token_hash = $2
+                AND password_reset_expires_at > now()
+            "#,
+            email.as_ref(),
+            token_hash,
+            new_password_hash.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::InvalidCredentials);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn start_phone_verification(
+        &mut self,
+        email: &Email,
+        phone: &PhoneNumber,
+        code_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        self.execute_for_user(sqlx::query!(
+            r#"
+            UPDATE users SET pending_phone = $2, phone_code_hash = $3, phone_code_expires_at = $4
+            WHERE lower(email) = lower($1)
+            "#,
+            email.as_ref(),
+            phone.as_ref(),
+            code_hash,
+            expires_at,
+        ))
+        .await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn complete_phone_verification(
+        &mut self,
+        email: &Email,
+        code_hash: &str,
+    ) -> Result<PhoneNumber, UserStoreError> {
+        // Whether or not the code matches, the verification ends here
+        let row = sqlx::query!(
+            r#"
+            WITH pending AS (
+                SELECT email, pending_phone,
+                    COALESCE(phone_code_hash = $2 AND phone_code_expires_at > now(), FALSE) AS valid
+                FROM users
+                WHERE lower(email) = lower($1)
+                FOR UPDATE
+            )
+            UPDATE users u SET
+                phone = CASE WHEN pending.valid THEN pending.pending_phone ELSE u.phone END,
+                pending_phone = NULL,
+                phone_code_hash = NULL,
+                phone_code_expires_at = NULL
+            FROM pending
+            WHERE u.email = pending.email
+            RETURNING pending.valid AS "valid!", pending.pending_phone
+            "#,
+            email.as_ref(),
+            code_hash,
+        )
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        match row {
+            Some(row) if row.valid => PhoneNumber::parse(row.pending_phone.unwrap_or_default())
+                .map_err(|_| UserStoreError::UnexpectedError),
+            _ => Err(UserStoreError::InvalidCredentials),
+        }
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn set_two_fa_channel(
+        &mut self,
+        email: &Email,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError> {
+        let result = sqlx::query!(
+            "UPDATE users SET two_fa_channel = $2 WHERE lower(email) = lower($1)",
+            email.as_ref(),
+            channel.as_str(),
+        )
+        .execute(&self.pool)
+        .await
         .map_err(|e| match e {
-            sqlx::Error::RowNotFound => UserStoreError::UserNotFound,
+            // `users_two_fa_channel_phone`: there's no verified phone number
+            sqlx::Error::Database(e) if e.is_check_violation() => UserStoreError::PhoneNotVerified,
             _ => UserStoreError::UnexpectedError,
         })?;
 
-        // Verify the password using argon2
-        use argon2::{PasswordHash, PasswordVerifier};
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        Ok(())
+    }
 
-        let parsed_hash = PasswordHash::new(&result.password_hash)
+    #[tracing::instrument(skip_all)]
+    async fn delete_user(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), UserStoreError> {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
             .map_err(|_| UserStoreError::UnexpectedError)?;
 
-        Argon2::default()
-            .verify_password(password.as_ref().as_bytes(), &parsed_hash)
-            .map_err(|_| UserStoreError::InvalidCredentials)?;
+        // Roles go with the membership through the cascading foreign key
+        let result = sqlx::query!(
+            "DELETE FROM tenant_members WHERE tenant_id = $1 AND lower(email) = lower($2)",
+            tenant.as_ref(),
+            email.as_ref(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
 
-        Ok(())
+        if result.rows_affected() == 0 {
+            return Err(UserStoreError::UserNotFound);
+        }
+
+        sqlx::query!(
+            r#"
+            DELETE FROM users u WHERE lower(u.email) = lower($1)
+                AND NOT EXISTS (SELECT 1 FROM tenant_members m WHERE m.email = u.email)
+            "#,
+            email.as_ref(),
+        )
+        .execute(&mut *transaction)
+        .await
+        .map_err(|_| UserStoreError::UnexpectedError)?;
+
+        transaction
+            .commit()
+            .await
+            .map_err(|_| UserStoreError::UnexpectedError)
+    }
+}
+
+fn map_membership_error(error: sqlx::Error) -> UserStoreError {
+    match error {
+        sqlx::Error::Database(e) if e.is_foreign_key_violation() => UserStoreError::TenantNotFound,
+        _ => UserStoreError::UnexpectedError,
     }
 }
+
+fn parse_phone(phone: Option<String>) -> Result<Option<PhoneNumber>, UserStoreError> {
+    phone
+        .map(PhoneNumber::parse)
+        .transpose()
+        .map_err(|_| UserStoreError::UnexpectedError)
+}
@@ -0,0 +1,246 @@
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::{
+    TenantId, WebhookDelivery, WebhookEvent, WebhookEventType, WebhookStore, WebhookStoreError,
+    WebhookSubscription,
+};
+
+pub struct PostgresWebhookStore {
+    pool: PgPool,
+}
+
+impl PostgresWebhookStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+fn parse_event_types(event_types: Vec<String>) -> Result<Vec<WebhookEventType>, WebhookStoreError> {
+    event_types
+        .iter()
+        .map(|event_type| {
+            WebhookEventType::parse(event_type).map_err(|_| WebhookStoreError::UnexpectedError)
+        })
+        .collect()
+}
+
+#[async_trait::async_trait]
+impl WebhookStore for PostgresWebhookStore {
+    #[tracing::instrument(skip_all)]
+    async fn add_subscription(
+        &mut self,
+        subscription: WebhookSubscription,
+    ) -> Result<(), WebhookStoreError> {
+        let event_types: Vec<String> = subscription
+            .event_types
+            .iter()
+            .map(|event_type| event_type.as_str().to_owned())
+            .collect();
+
+        sqlx::query!(
+            "INSERT INTO webhook_subscriptions (id, tenant_id, url, secret, event_types) VALUES ($1, $2, $3, $4, $5)",
+            subscription.id,
+            subscription.tenant.as_ref(),
+            subscription.url,
+            subscription.secret,
+            &event_types,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| WebhookStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_subscriptions(&self) -> Result<Vec<WebhookSubscription>, WebhookStoreError> {
+        let rows = sqlx::query!(
+            "SELECT id, tenant_id, url, secret, event_types FROM webhook_subscriptions ORDER BY created_at"
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| WebhookStoreError::UnexpectedError)?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(WebhookSubscription {
+                    id: row.id,
+                    tenant: TenantId::parse(row.tenant_id)
+                        .map_err(|_| WebhookStoreError::UnexpectedError)?,
+                    url: row.url,
+                    secret: row.secret,
+                    event_types: parse_event_types(row.event_types)?,
+                })
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn remove_subscription(&mut self, id: Uuid) -> Result<(), WebhookStoreError> {
+        // Queued deliveries go with it (ON DELETE CASCADE)
+        let result = sqlx::query!("DELETE FROM webhook_subscriptions WHERE id = $1", id)
+            .execute(&self.pool)
+            .await
+            .map_err(|_| WebhookStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(WebhookStoreError::SubscriptionNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn enqueue_event(&mut self, event: &WebhookEvent) -> Result<(), WebhookStoreError> {
+        sqlx::query!(
+            r#"
+            INSERT INTO webhook_deliveries (id, subscription_id, event_type, payload, next_attempt_at)
+            SELECT gen_random_uuid(), id, $1, $2, $3
+            FROM webhook_subscriptions
+            WHERE tenant_id = $4 AND (cardinality(event_types) = 0 OR $1 = ANY(event_types))
+            "#,
+            event.event_type.as_str(),
+            event.payload(),
+            event.occurred_at,
+            event.tenant.as_ref(),
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| WebhookStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn claim_due_deliveries(
+        &mut self,
+        now: DateTime<Utc>,
+        lease: Duration,
+        limit: usize,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        // SKIP LOCKED lets dispatchers on other instances claim other rows
+        // instead of waiting for these
+        let rows = sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries d SET next_attempt_at = $2
+            FROM webhook_subscriptions s
+            WHERE d.subscription_id = s.id AND d.id IN (
+                SELECT id FROM webhook_deliveries
+                WHERE status = 'pending' AND next_attempt_at <= $1
+                ORDER BY next_attempt_at
+                LIMIT $3
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING d.id, d.subscription_id, s.url, s.secret, d.event_type, d.payload,
+                      d.attempts, d.next_attempt_at, d.last_error
+            "#,
+            now,
+            now + lease,
+            limit as i64,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| WebhookStoreError::UnexpectedError)?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(WebhookDelivery {
+                    id: row.id,
+                    subscription_id: row.subscription_id,
+                    url: row.url,
+                    secret: row.secret,
+                    event_type: WebhookEventType::parse(&row.event_type)
+                        .map_err(|_| WebhookStoreError::UnexpectedError)?,
+                    payload: row.payload,
+                    attempts: row.attempts.max(0) as u32,
+                    next_attempt_at: row.next_attempt_at,
+                    last_error: row.last_error,
+                })
+            })
+            .collect()
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn mark_delivered(&mut self, delivery_id: Uuid) -> Result<(), WebhookStoreError> {
+        let result = sqlx::query!(
+            "UPDATE webhook_deliveries SET status = 'delivered', attempts = attempts + 1 WHERE id = $1",
+            delivery_id,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| WebhookStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(WebhookStoreError::DeliveryNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn mark_failed(
+        &mut self,
+        delivery_id: Uuid,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), WebhookStoreError> {
+        let result = sqlx::query!(
+            r#"
+            UPDATE webhook_deliveries SET
+                attempts = attempts + 1,
+                last_error = $2,
+                status = CASE WHEN $3::timestamptz IS NULL THEN 'dead' ELSE status END,
+                next_attempt_at = COALESCE($3, next_attempt_at)
+            WHERE id = $1
+            "#,
+            delivery_id,
+            error,
+            next_attempt_at,
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(|_| WebhookStoreError::UnexpectedError)?;
+
+        if result.rows_affected() == 0 {
+            return Err(WebhookStoreError::DeliveryNotFound);
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn get_dead_letters(&self) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        let rows = sqlx::query!(
+            r#"
+            SELECT d.id, d.subscription_id, s.url, s.secret, d.event_type, d.payload,
+                   d.attempts, d.next_attempt_at, d.last_error
+            FROM webhook_deliveries d
+            JOIN webhook_subscriptions s ON s.id = d.subscription_id
+            WHERE d.status = 'dead'
+            ORDER BY d.next_attempt_at
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(|_| WebhookStoreError::UnexpectedError)?;
+
+        rows.into_iter()
+            .map(|row| {
+                Ok(WebhookDelivery {
+                    id: row.id,
+                    subscription_id: row.subscription_id,
+                    url: row.url,
+                    secret: row.secret,
+                    event_type: WebhookEventType::parse(&row.event_type)
+                        .map_err(|_| WebhookStoreError::UnexpectedError)?,
+                    payload: row.payload,
+                    attempts: row.attempts.max(0) as u32,
+                    next_attempt_at: row.next_attempt_at,
+                    last_error: row.last_error,
+                })
+            })
+            .collect()
+    }
+}
@@ -1,50 +1,56 @@
 use std::sync::Arc;
 
+use chrono::{DateTime, Utc};
 use redis::{Commands, Connection};
 use tokio::sync::RwLock;
 
 use crate::{
-    domain::data_stores::{BannedTokenStore, BannedTokenStoreError},
-    utils::auth::TOKEN_TTL_SECONDS,
+    domain::{
+        data_stores::{BannedTokenStore, BannedTokenStoreError},
+        TenantId,
+    },
 };
 
 pub struct RedisBannedTokenStore {
     conn: Arc<RwLock<Connection>>,
+    // How long tokens are valid for; a ban is pointless once they've expired
+    token_ttl_seconds: u64,
 }
 
 impl RedisBannedTokenStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
-        Self { conn }
+    pub fn new(conn: Arc<RwLock<Connection>>, token_ttl_seconds: u64) -> Self {
+        Self {
+            conn,
+            token_ttl_seconds,
+        }
     }
 }
 
 #[async_trait::async_trait]
 impl BannedTokenStore for RedisBannedTokenStore {
+    #[tracing::instrument(skip_all)]
     async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError> {
         // TODO:
         // 1. Create a new key using the get_key helper function.
         // 2. Call the set_ex command on the Redis connection to set a new key/value pair with an expiration time (TTL).
         // The value should simply be a `true` (boolean value).
-        // The expiration time should be set to TOKEN_TTL_SECONDS.
-        // NOTE: The TTL is expected to be a u64 so you will have to cast TOKEN_TTL_SECONDS to a u64.
-        // Return BannedTokenStoreError::UnexpectedError if casting fails or the call to set_ex fails.
+        // The expiration time should be set to the token TTL.
+        // Return BannedTokenStoreError::UnexpectedError if the call to set_ex fails.
 
         let key = get_key(&token);
-        let ttl = TOKEN_TTL_SECONDS as u64;
+        let ttl = self.token_ttl_seconds;
 
-        let result: () = self
+        let _: () = self
             .conn
             .write()
             .await
-            .set_ex(key.clone(), true, ttl)
+            .set_ex(key, true, ttl)
             .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
 
-        println!("Added token to Redis: {}", key);
-        println!("TTL: {}", ttl);
-        println!("Result: {:?}", result);
         Ok(())
     }
 
+    #[tracing::instrument(skip_all)]
     async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
         // Check if the token exists by calling the exists method on the Redis connection
         let key = get_key(token);
@@ -53,6 +59,47 @@ impl BannedTokenStore for RedisBannedTokenStore {
             .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
         Ok(exists)
     }
+
+    #[tracing::instrument(skip_all)]
+    async fn ban_subject(
+        &mut self,
+        tenant: &TenantId,
+        subject: &str,
+        issued_until: DateTime<Utc>,
+    ) -> Result<(), BannedTokenStoreError> {
+        // Tokens issued before the ban have all expired once the key does
+        let key = get_subject_key(tenant, subject);
+        let ttl = self.token_ttl_seconds;
+
+        let current = self.subject_banned_until(tenant, subject).await?;
+        let issued_until = current.map_or(issued_until, |current| current.max(issued_until));
+
+        let _: () = self
+            .conn
+            .write()
+            .await
+            .set_ex(key, issued_until.timestamp_micros(), ttl)
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn subject_banned_until(
+        &self,
+        tenant: &TenantId,
+        subject: &str,
+    ) -> Result<Option<DateTime<Utc>>, BannedTokenStoreError> {
+        let key = get_subject_key(tenant, subject);
+        let micros: Option<i64> = self
+            .conn
+            .write()
+            .await
+            .get(&key)
+            .map_err(|_| BannedTokenStoreError::UnexpectedError)?;
+
+        Ok(micros.and_then(DateTime::from_timestamp_micros))
+    }
 }
 
 // We are using a key prefix to prevent collisions and organize data!
@@ -61,3 +108,15 @@ const BANNED_TOKEN_KEY_PREFIX: &str = "banned_token:";
 fn get_key(token: &str) -> String {
     format!("{}{}", BANNED_TOKEN_KEY_PREFIX, token)
 }
+
+const BANNED_SUBJECT_KEY_PREFIX: &str = "banned_subject:";
+
+// Subjects are emails, which are compared ignoring case
+fn get_subject_key(tenant: &TenantId, subject: &str) -> String {
+    format!(
+        "{}{}:{}",
+        BANNED_SUBJECT_KEY_PREFIX,
+        tenant.as_ref(),
+        subject.to_lowercase()
+    )
+}
@@ -0,0 +1,98 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use redis::{Commands, Connection};
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::domain::{RateLimitDecision, RateLimitPolicy, RateLimiter, RateLimiterError};
+
+use super::hashmap_rate_limiter::HashmapRateLimiter;
+
+// Sliding window log kept in a Redis sorted set per key, scored by request time,
+// so limits are shared between all instances of the service.
+pub struct RedisRateLimiter {
+    conn: Arc<RwLock<Connection>>,
+    // Used while Redis is unreachable, so an outage degrades to per-instance
+    // limits instead of no limits (or no logins) at all.
+    fallback: HashmapRateLimiter,
+}
+
+impl RedisRateLimiter {
+    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
+        Self {
+            conn,
+            fallback: HashmapRateLimiter::default(),
+        }
+    }
+
+    async fn check_in_redis(
+        &mut self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> redis::RedisResult<RateLimitDecision> {
+        let key = get_key(key);
+        let now_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let window_ms = policy.window.as_millis() as u64;
+        let member = format!("{}:{}", now_ms, Uuid::new_v4());
+
+        let mut conn = self.conn.write().await;
+
+        let (count, oldest): (usize, Vec<(String, f64)>) = redis::pipe()
+            .atomic()
+            .zrembyscore(&key, 0, now_ms.saturating_sub(window_ms))
+            .ignore()
+            .zadd(&key, &member, now_ms)
+            .ignore()
+            .zcard(&key)
+            .zrange_withscores(&key, 0, 0)
+            .pexpire(&key, window_ms as i64)
+            .ignore()
+            .query(&mut *conn)?;
+
+        if count <= policy.max_requests as usize {
+            return Ok(RateLimitDecision::Allowed);
+        }
+
+        // Don't count the rejected request against the caller
+        let _: usize = conn.zrem(&key, &member)?;
+
+        let oldest_ms = oldest
+            .first()
+            .map(|(_, score)| *score as u64)
+            .unwrap_or(now_ms);
+        let retry_after_ms = (oldest_ms + window_ms).saturating_sub(now_ms);
+
+        Ok(RateLimitDecision::Limited {
+            retry_after: Duration::from_millis(retry_after_ms),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl RateLimiter for RedisRateLimiter {
+    #[tracing::instrument(skip_all)]
+    async fn check(
+        &mut self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateLimitDecision, RateLimiterError> {
+        match self.check_in_redis(key, policy).await {
+            Ok(decision) => Ok(decision),
+            Err(e) => {
+                tracing::warn!(error = %e, "Rate limiting falling back to in-memory counters");
+                self.fallback.check(key, policy).await
+            }
+        }
+    }
+}
+
+// We are using a key prefix to prevent collisions and organize data!
+const RATE_LIMIT_KEY_PREFIX: &str = "rate_limit:";
+
+fn get_key(key: &str) -> String {
+    format!("{}{}", RATE_LIMIT_KEY_PREFIX, key)
+}
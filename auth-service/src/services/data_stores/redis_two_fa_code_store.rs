@@ -6,29 +6,33 @@ use tokio::sync::RwLock;
 
 use crate::domain::{
     data_stores::{LoginAttemptId, TwoFACode, TwoFACodeStore, TwoFACodeStoreError},
-    Email,
+    Email, TenantId,
 };
 
 pub struct RedisTwoFACodeStore {
     conn: Arc<RwLock<Connection>>,
+    // Codes expire this long after they're added
+    ttl_seconds: u64,
 }
 
 impl RedisTwoFACodeStore {
-    pub fn new(conn: Arc<RwLock<Connection>>) -> Self {
-        Self { conn }
+    pub fn new(conn: Arc<RwLock<Connection>>, ttl_seconds: u64) -> Self {
+        Self { conn, ttl_seconds }
     }
 }
 
 #[async_trait::async_trait]
 impl TwoFACodeStore for RedisTwoFACodeStore {
+    #[tracing::instrument(skip_all)]
     async fn add_code(
         &mut self,
+        tenant: &TenantId,
         email: Email,
         login_attempt_id: LoginAttemptId,
         code: TwoFACode,
     ) -> Result<(), TwoFACodeStoreError> {
         // 1. Create a new key using the get_key helper function.
-        let key = get_key(&email);
+        let key = get_key(tenant, &email);
         
         // 2. Create a TwoFATuple instance.
         let two_fa_tuple = TwoFATuple(login_attempt_id.as_ref().to_string(), code.as_ref().to_string());
@@ -39,15 +43,20 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
         
         // 4. Call the set_ex command on the Redis connection to set a new key/value pair with an expiration time (TTL).
         let _result: () = self.conn.write().await
-            .set_ex(key, serialized_tuple, TEN_MINUTES_IN_SECONDS)
+            .set_ex(key, serialized_tuple, self.ttl_seconds)
             .map_err(|_| TwoFACodeStoreError::UnexpectedError)?;
             
         Ok(())
     }
 
-    async fn remove_code(&mut self, email: &Email) -> Result<(), TwoFACodeStoreError> {
+    #[tracing::instrument(skip_all)]
+    async fn remove_code(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), TwoFACodeStoreError> {
         // 1. Create a new key using the get_key helper function.
-        let key = get_key(email);
+        let key = get_key(tenant, email);
         
         // 2. Call the del command on the Redis connection to delete the 2FA code entry.
         let _result: () = self.conn.write().await
@@ -57,12 +66,14 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
         Ok(())
     }
 
+    #[tracing::instrument(skip_all)]
     async fn get_code(
         &self,
+        tenant: &TenantId,
         email: &Email,
     ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
         // 1. Create a new key using the get_key helper function.
-        let key = get_key(email);
+        let key = get_key(tenant, email);
         
         // 2. Call the get command on the Redis connection to get the value stored for the key.
         let serialized_tuple: String = self.conn.write().await
@@ -86,9 +97,14 @@ impl TwoFACodeStore for RedisTwoFACodeStore {
 #[derive(Serialize, Deserialize)]
 struct TwoFATuple(pub String, pub String);
 
-const TEN_MINUTES_IN_SECONDS: u64 = 600;
 const TWO_FA_CODE_PREFIX: &str = "two_fa_code:";
 
-fn get_key(email: &Email) -> String {
-    format!("{}{}", TWO_FA_CODE_PREFIX, email.as_ref())
+fn get_key(tenant: &TenantId, email: &Email) -> String {
+    // Emails compare case-insensitively, so must their keys
+    format!(
+        "{}{}:{}",
+        TWO_FA_CODE_PREFIX,
+        tenant.as_ref(),
+        email.as_ref().to_lowercase()
+    )
 }
\ No newline at end of file
@@ -0,0 +1,89 @@
+use std::sync::Mutex;
+
+use crate::domain::{AuditEvent, AuditRecord, AuditSink, AuditSinkError, GENESIS_HASH};
+
+// Keeps events in memory, for tests and local development. Records are
+// hash-chained the same way as in Postgres.
+#[derive(Default)]
+pub struct VecAuditSink {
+    records: Mutex<Vec<AuditRecord>>,
+}
+
+impl VecAuditSink {
+    // A snapshot of everything recorded so far, oldest first
+    pub fn records(&self) -> Vec<AuditRecord> {
+        self.records
+            .lock()
+            .map(|records| records.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn events(&self) -> Vec<AuditEvent> {
+        self.records()
+            .into_iter()
+            .map(|record| record.event)
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl AuditSink for VecAuditSink {
+    async fn record(&self, event: AuditEvent) -> Result<(), AuditSinkError> {
+        let mut records = self
+            .records
+            .lock()
+            .map_err(|_| AuditSinkError::UnexpectedError)?;
+
+        let prev_hash = records
+            .last()
+            .map_or(GENESIS_HASH, |record| record.hash.as_str());
+        let record = AuditRecord::new(records.len() as i64 + 1, event, prev_hash);
+        records.push(record);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{AuditChainVerifier, AuditEventType, AuditOutcome};
+
+    #[tokio::test]
+    async fn test_events_are_kept_in_order() {
+        let sink = VecAuditSink::default();
+
+        sink.record(AuditEvent::success(AuditEventType::Signup).with_subject("a@example.com"))
+            .await
+            .unwrap();
+        sink.record(AuditEvent::failure(
+            AuditEventType::Login,
+            "incorrect password",
+        ))
+        .await
+        .unwrap();
+
+        let events = sink.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event_type, AuditEventType::Signup);
+        assert_eq!(events[0].subject.as_deref(), Some("a@example.com"));
+        assert_eq!(events[1].outcome, AuditOutcome::Failure);
+        assert_eq!(events[1].detail.as_deref(), Some("incorrect password"));
+    }
+
+    #[tokio::test]
+    async fn test_records_form_a_chain() {
+        let sink = VecAuditSink::default();
+        for _ in 0..3 {
+            sink.record(AuditEvent::success(AuditEventType::Logout))
+                .await
+                .unwrap();
+        }
+
+        let mut verifier = AuditChainVerifier::default();
+        for record in sink.records() {
+            verifier.check(&record).unwrap();
+        }
+        assert_eq!(verifier.records_checked(), 3);
+    }
+}
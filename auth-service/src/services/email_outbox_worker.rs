@@ -0,0 +1,336 @@
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::app_state::{EmailClientType, EmailOutboxStoreType};
+use crate::domain::{EmailClientError, EmailOutboxStoreError, RetryPolicy};
+use crate::utils::shutdown::ShutdownHandle;
+
+// How long a claimed email is left to the worker that claimed it. Emails are
+// claimed one at a time, so this only has to outlast a single send, which the
+// SMTP settings are held to; otherwise another worker could send it again.
+pub const OUTBOX_LEASE: Duration = Duration::from_secs(120);
+// Most emails sent in one run, so a long queue doesn't hold up shutdown
+const BATCH_SIZE: usize = 50;
+
+/// Sends the emails queued in the outbox.
+///
+/// An unavailable mail server is retried according to the retry policy. A
+/// rejected or invalid email fails at once, since sending it again won't help.
+pub struct EmailOutboxWorker {
+    outbox_store: EmailOutboxStoreType,
+    email_client: EmailClientType,
+    retry_policy: RetryPolicy,
+    lease: Duration,
+}
+
+impl EmailOutboxWorker {
+    pub fn new(outbox_store: EmailOutboxStoreType, email_client: EmailClientType) -> Self {
+        Self {
+            outbox_store,
+            email_client,
+            // Most emails carry a code that expires within the hour, so
+            // retrying for longer than that is pointless
+            retry_policy: RetryPolicy {
+                max_attempts: 8,
+                base_delay: chrono::Duration::seconds(5),
+                max_delay: chrono::Duration::minutes(10),
+            },
+            lease: OUTBOX_LEASE,
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    pub fn with_lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// Attempt every email that is due now, returning how many were attempted.
+    #[tracing::instrument(skip_all)]
+    pub async fn send_due(&self) -> Result<usize, EmailOutboxStoreError> {
+        let lease = chrono::Duration::from_std(self.lease)
+            .map_err(|_| EmailOutboxStoreError::UnexpectedError)?;
+
+        let started = Utc::now();
+
+        let mut attempted = 0;
+        while attempted < BATCH_SIZE {
+            // Claimed just before it's sent, so its lease can't run out while
+            // the emails ahead of it are still going out. Only emails due when
+            // the run started are claimed, so one that fails waits for its
+            // retry rather than being tried again in the same run.
+            let elapsed = Utc::now() - started;
+            let claimed = self
+                .outbox_store
+                .write()
+                .await
+                .claim_due(started, lease + elapsed, 1)
+                .await?;
+            let Some(email) = claimed.first() else {
+                break;
+            };
+            attempted += 1;
+
+            let result = self
+                .email_client
+                .read()
+                .await
+                .send_email(&email.recipient, &email.message)
+                .await;
+
+            // The lock isn't held while sending, so a slow mail server doesn't
+            // hold up requests that queue new emails
+            let mut outbox_store = self.outbox_store.write().await;
+            match result {
+                Ok(()) => outbox_store.mark_sent(email.id).await?,
+                Err(error) => {
+                    let next_attempt_at = match error {
+                        EmailClientError::Unavailable(_) => self
+                            .retry_policy
+                            .next_attempt_at(email.attempts + 1, Utc::now()),
+                        EmailClientError::InvalidMessage(_) | EmailClientError::Rejected(_) => None,
+                    };
+                    outbox_store
+                        .mark_failed(email.id, &error.to_string(), next_attempt_at)
+                        .await?
+                }
+            }
+        }
+
+        Ok(attempted)
+    }
+
+    /// Poll the outbox every `interval` until `shutdown` is triggered, then
+    /// send whatever is still due once more, so it isn't left for the next
+    /// instance to start.
+    pub async fn run(self, interval: Duration, shutdown: ShutdownHandle) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            let stopping = tokio::select! {
+                _ = ticker.tick() => false,
+                _ = shutdown.wait() => true,
+            };
+            if let Err(e) = self.send_due().await {
+                tracing::error!(error = ?e, "Failed to send queued emails");
+            }
+            if stopping {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use tokio::sync::RwLock;
+
+    use super::*;
+    use crate::domain::{
+        Email, EmailClient, EmailMessage, EmailOutboxStore, OutboxEmail, OutboxEmailStatus,
+    };
+    use crate::services::data_stores::hashmap_email_outbox_store::HashmapEmailOutboxStore;
+
+    // Fails with each of `failures` in turn, then sends, taking `delay` each time
+    #[derive(Default)]
+    struct StubEmailClient {
+        failures: Mutex<Vec<EmailClientError>>,
+        sent: Mutex<Vec<(Email, EmailMessage)>>,
+        delay: Duration,
+    }
+
+    #[async_trait::async_trait]
+    impl EmailClient for StubEmailClient {
+        async fn send_email(
+            &self,
+            recipient: &Email,
+            message: &EmailMessage,
+        ) -> Result<(), EmailClientError> {
+            tokio::time::sleep(self.delay).await;
+            let mut failures = self.failures.lock().unwrap();
+            if !failures.is_empty() {
+                return Err(failures.remove(0));
+            }
+            self.sent
+                .lock()
+                .unwrap()
+                .push((recipient.clone(), message.clone()));
+            Ok(())
+        }
+    }
+
+    async fn worker_with_email(
+        failures: Vec<EmailClientError>,
+    ) -> (
+        EmailOutboxWorker,
+        EmailOutboxStoreType,
+        Arc<RwLock<StubEmailClient>>,
+    ) {
+        let mut store = HashmapEmailOutboxStore::default();
+        let recipient = Email::parse("test@example.com".to_owned()).unwrap();
+        let message = EmailMessage {
+            subject: "2FA code".to_owned(),
+            text: "123456".to_owned(),
+            html: None,
+        };
+        store
+            .enqueue(OutboxEmail::new("login:1".to_owned(), &recipient, message))
+            .await
+            .unwrap();
+
+        let store: EmailOutboxStoreType = Arc::new(RwLock::new(store));
+        let client = Arc::new(RwLock::new(StubEmailClient {
+            failures: Mutex::new(failures),
+            ..Default::default()
+        }));
+        let worker =
+            EmailOutboxWorker::new(store.clone(), client.clone()).with_retry_policy(RetryPolicy {
+                max_attempts: 3,
+                base_delay: chrono::Duration::zero(),
+                max_delay: chrono::Duration::zero(),
+            });
+
+        (worker, store, client)
+    }
+
+    #[tokio::test]
+    async fn test_sends_queued_email_once() {
+        let (worker, store, client) = worker_with_email(vec![]).await;
+
+        assert_eq!(worker.send_due().await, Ok(1));
+        assert_eq!(worker.send_due().await, Ok(0));
+
+        let sent = client.read().await.sent.lock().unwrap().clone();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].1.text, "123456");
+        let email = store.read().await.get_email("login:1").await.unwrap();
+        assert_eq!(email.status, OutboxEmailStatus::Sent);
+    }
+
+    #[tokio::test]
+    async fn test_sends_what_is_due_before_stopping() {
+        let (worker, store, client) = worker_with_email(vec![]).await;
+
+        // However long the interval, queued emails go out as it stops
+        let shutdown = ShutdownHandle::new();
+        shutdown.shutdown();
+        tokio::time::timeout(
+            Duration::from_secs(1),
+            worker.run(Duration::from_secs(3600), shutdown),
+        )
+        .await
+        .expect("Worker didn't stop");
+
+        assert_eq!(client.read().await.sent.lock().unwrap().len(), 1);
+        let email = store.read().await.get_email("login:1").await.unwrap();
+        assert_eq!(email.status, OutboxEmailStatus::Sent);
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_server_is_retried_until_it_recovers() {
+        let unavailable = EmailClientError::Unavailable("timed out".to_owned());
+        let (worker, store, client) =
+            worker_with_email(vec![unavailable.clone(), unavailable]).await;
+
+        for _ in 0..3 {
+            assert_eq!(worker.send_due().await, Ok(1));
+        }
+        assert_eq!(worker.send_due().await, Ok(0));
+
+        assert_eq!(client.read().await.sent.lock().unwrap().len(), 1);
+        let email = store.read().await.get_email("login:1").await.unwrap();
+        assert_eq!(email.status, OutboxEmailStatus::Sent);
+        assert_eq!(email.attempts, 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_when_out_of_attempts() {
+        let unavailable = EmailClientError::Unavailable("timed out".to_owned());
+        let (worker, store, _) = worker_with_email(vec![unavailable; 3]).await;
+
+        for _ in 0..3 {
+            assert_eq!(worker.send_due().await, Ok(1));
+        }
+        assert_eq!(worker.send_due().await, Ok(0));
+
+        let failed = store.read().await.get_failed().await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].attempts, 3);
+        assert_eq!(
+            failed[0].last_error.as_deref(),
+            Some("mail server unavailable: timed out")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_email_fails_without_retrying() {
+        let rejected = EmailClientError::Rejected("550 No such user".to_owned());
+        let (worker, store, client) = worker_with_email(vec![rejected]).await;
+
+        assert_eq!(worker.send_due().await, Ok(1));
+        assert_eq!(worker.send_due().await, Ok(0));
+
+        assert!(client.read().await.sent.lock().unwrap().is_empty());
+        let failed = store.read().await.get_failed().await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].attempts, 1);
+    }
+
+    #[tokio::test]
+    async fn test_lease_running_out_mid_batch_does_not_resend() {
+        let mut store = HashmapEmailOutboxStore::default();
+        let recipient = Email::parse("test@example.com".to_owned()).unwrap();
+        for i in 0..3 {
+            let message = EmailMessage {
+                subject: "2FA code".to_owned(),
+                text: i.to_string(),
+                html: None,
+            };
+            store
+                .enqueue(OutboxEmail::new(
+                    format!("login:{}", i),
+                    &recipient,
+                    message,
+                ))
+                .await
+                .unwrap();
+        }
+        let store: EmailOutboxStoreType = Arc::new(RwLock::new(store));
+        let client = Arc::new(RwLock::new(StubEmailClient {
+            delay: Duration::from_millis(100),
+            ..Default::default()
+        }));
+
+        // Each send fits in the lease, but the three together don't, so the
+        // second worker starts while the first is still working through them
+        let worker = |lease| {
+            EmailOutboxWorker::new(store.clone(), client.clone())
+                .with_lease(Duration::from_millis(lease))
+        };
+        let first = worker(150);
+        let second = worker(150);
+        let (first, second) = tokio::join!(first.send_due(), async {
+            tokio::time::sleep(Duration::from_millis(160)).await;
+            second.send_due().await
+        });
+        assert_eq!(first.unwrap() + second.unwrap(), 3);
+
+        let mut sent: Vec<_> = client
+            .read()
+            .await
+            .sent
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(_, message)| message.text.clone())
+            .collect();
+        sent.sort();
+        assert_eq!(sent, ["0", "1", "2"]);
+    }
+}
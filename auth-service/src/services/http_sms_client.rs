@@ -0,0 +1,250 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use serde::Serialize;
+
+use crate::{
+    config::SmsConfig,
+    domain::{PhoneNumber, SmsClient, SmsClientError},
+};
+
+#[derive(Debug, Clone)]
+pub struct HttpSmsSettings {
+    // The provider's endpoint for sending messages
+    pub url: String,
+    // Sent as a bearer token
+    pub token: String,
+    // The number or alphanumeric sender id messages come from
+    pub sender: String,
+    pub timeout: Duration,
+}
+
+impl HttpSmsSettings {
+    /// The settings from the `[sms]` config section, or `None` when
+    /// `sms.provider_url` isn't set and texts can't be delivered.
+    pub fn from_config(config: &SmsConfig) -> Result<Option<Self>, String> {
+        let Some(url) = config.provider_url.clone() else {
+            return Ok(None);
+        };
+
+        let token = config.provider_token.clone().ok_or_else(|| {
+            "sms.provider_token (SMS_PROVIDER_TOKEN) must be set when sms.provider_url is"
+                .to_owned()
+        })?;
+        let sender = config.sender.clone().ok_or_else(|| {
+            "sms.sender (SMS_SENDER) must be set when sms.provider_url is".to_owned()
+        })?;
+        if config.timeout_seconds == 0 {
+            return Err("sms.timeout_seconds (SMS_TIMEOUT_SECONDS) must be positive".to_owned());
+        }
+
+        Ok(Some(Self {
+            url,
+            token,
+            sender,
+            timeout: Duration::from_secs(config.timeout_seconds),
+        }))
+    }
+}
+
+/// Sends texts and voice calls through an HTTP provider, as a JSON POST of
+/// `{"from", "to", "body", "channel"}` where `channel` is `sms` or `voice`.
+///
+/// Any 2xx response counts as sent. Other 4xx responses mean the provider
+/// refused the message; 408, 429, 5xx and transport errors are temporary.
+pub struct HttpSmsClient {
+    http_client: reqwest::Client,
+    settings: HttpSmsSettings,
+}
+
+#[derive(Serialize)]
+struct SendRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    body: &'a str,
+    channel: &'a str,
+}
+
+impl HttpSmsClient {
+    pub fn new(settings: HttpSmsSettings) -> Result<Self, String> {
+        let http_client = reqwest::Client::builder()
+            .timeout(settings.timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            http_client,
+            settings,
+        })
+    }
+
+    async fn send(
+        &self,
+        recipient: &PhoneNumber,
+        text: &str,
+        channel: &str,
+    ) -> Result<(), SmsClientError> {
+        let request = SendRequest {
+            from: &self.settings.sender,
+            to: recipient.as_ref(),
+            body: text,
+            channel,
+        };
+
+        let response = self
+            .http_client
+            .post(&self.settings.url)
+            .bearer_auth(&self.settings.token)
+            .json(&request)
+            .send()
+            .await
+            .map_err(|e| SmsClientError::Unavailable(e.to_string()))?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+
+        let error = format!("HTTP {}", status.as_u16());
+        match status {
+            StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS => {
+                Err(SmsClientError::Unavailable(error))
+            }
+            status if status.is_client_error() => Err(SmsClientError::Rejected(error)),
+            _ => Err(SmsClientError::Unavailable(error)),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl SmsClient for HttpSmsClient {
+    #[tracing::instrument(skip_all)]
+    async fn send_sms(&self, recipient: &PhoneNumber, text: &str) -> Result<(), SmsClientError> {
+        self.send(recipient, text, "sms").await
+    }
+
+    #[tracing::instrument(skip_all)]
+    async fn send_voice(&self, recipient: &PhoneNumber, text: &str) -> Result<(), SmsClientError> {
+        self.send(recipient, text, "voice").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc, Mutex,
+    };
+
+    use axum::{extract::State, http::HeaderMap, routing::post, Json, Router};
+    use serde_json::Value;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct StubProvider {
+        status: Arc<AtomicU16>,
+        received: Arc<Mutex<Vec<(HeaderMap, Value)>>>,
+    }
+
+    async fn receive(
+        State(provider): State<StubProvider>,
+        headers: HeaderMap,
+        Json(body): Json<Value>,
+    ) -> axum::http::StatusCode {
+        provider.received.lock().unwrap().push((headers, body));
+        axum::http::StatusCode::from_u16(provider.status.load(Ordering::SeqCst)).unwrap()
+    }
+
+    // Serve the provider on a random local port, returning a client for it
+    async fn start_provider(status: u16) -> (StubProvider, HttpSmsClient) {
+        let provider = StubProvider::default();
+        provider.status.store(status, Ordering::SeqCst);
+
+        let router = Router::new()
+            .route("/messages", post(receive))
+            .with_state(provider.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/messages", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let client = HttpSmsClient::new(HttpSmsSettings {
+            url,
+            token: "secret-token".to_owned(),
+            sender: "AuthService".to_owned(),
+            timeout: Duration::from_secs(5),
+        })
+        .unwrap();
+
+        (provider, client)
+    }
+
+    fn phone() -> PhoneNumber {
+        PhoneNumber::parse("+447700900123".to_owned()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_sends_sms_and_voice_to_provider() {
+        let (provider, client) = start_provider(202).await;
+
+        client
+            .send_sms(&phone(), "Your code is 123456")
+            .await
+            .unwrap();
+        client.send_voice(&phone(), "1 2 3 4 5 6").await.unwrap();
+
+        let received = provider.received.lock().unwrap().clone();
+        assert_eq!(received.len(), 2);
+        let (headers, body) = &received[0];
+        assert_eq!(headers["authorization"], "Bearer secret-token");
+        assert_eq!(
+            body,
+            &serde_json::json!({
+                "from": "AuthService",
+                "to": "+447700900123",
+                "body": "Your code is 123456",
+                "channel": "sms",
+            })
+        );
+        assert_eq!(received[1].1["channel"], "voice");
+    }
+
+    #[tokio::test]
+    async fn test_provider_errors_are_classified() {
+        let (provider, client) = start_provider(400).await;
+        assert_eq!(
+            client.send_sms(&phone(), "code").await,
+            Err(SmsClientError::Rejected("HTTP 400".to_owned()))
+        );
+
+        for status in [429, 503] {
+            provider.status.store(status, Ordering::SeqCst);
+            assert_eq!(
+                client.send_sms(&phone(), "code").await,
+                Err(SmsClientError::Unavailable(format!("HTTP {}", status)))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_provider_is_unavailable() {
+        // Bind and drop a listener to get a port nothing is listening on
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/messages", listener.local_addr().unwrap());
+        drop(listener);
+
+        let client = HttpSmsClient::new(HttpSmsSettings {
+            url,
+            token: "secret-token".to_owned(),
+            sender: "AuthService".to_owned(),
+            timeout: Duration::from_secs(5),
+        })
+        .unwrap();
+
+        assert!(matches!(
+            client.send_sms(&phone(), "code").await,
+            Err(SmsClientError::Unavailable(_))
+        ));
+    }
+}
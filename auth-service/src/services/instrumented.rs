@@ -0,0 +1,671 @@
+use std::any::type_name;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+use crate::domain::{
+    ApiKey, ApiKeyStore, ApiKeyStoreError, AuditEvent, AuditSink, AuditSinkError, BannedTokenStore,
+    BannedTokenStoreError, Email, EmailOutboxStore, EmailOutboxStoreError, LockoutState,
+    LoginAttemptId, OutboxEmail, Password, PasswordHash, PasswordHasher, PasswordHasherError,
+    Permission, PhoneNumber, RateLimitDecision, RateLimitPolicy, RateLimiter, RateLimiterError,
+    Role, Tenant, TenantId, TenantSettings, TenantStore, TenantStoreError, TwoFAChannel, TwoFACode,
+    TwoFACodeStore, TwoFACodeStoreError, User, UserListQuery, UserPage, UserStore, UserStoreError,
+    WebhookDelivery, WebhookEvent, WebhookStore, WebhookStoreError, WebhookSubscription,
+};
+use crate::utils::metrics::Metrics;
+
+/// Wraps a store, or password hasher, to time every call to it in `metrics`.
+/// Calls are labelled with the wrapped type's name, e.g. `PostgresUserStore`.
+pub struct Instrumented<S> {
+    inner: S,
+    name: &'static str,
+    metrics: Arc<Metrics>,
+}
+
+impl<S> Instrumented<S> {
+    pub fn new(inner: S, metrics: Arc<Metrics>) -> Self {
+        Self {
+            inner,
+            name: short_type_name::<S>(),
+            metrics,
+        }
+    }
+}
+
+// The type's own name, without its path or that of a wrapper like `Arc`
+fn short_type_name<S>() -> &'static str {
+    let name = type_name::<S>();
+    let name = name.rsplit("::").next().unwrap_or(name);
+    name.trim_end_matches('>')
+}
+
+#[async_trait::async_trait]
+impl<S: UserStore + Send + Sync> UserStore for Instrumented<S> {
+    async fn add_user(&mut self, tenant: &TenantId, user: User) -> Result<(), UserStoreError> {
+        let call = self.inner.add_user(tenant, user);
+        self.metrics
+            .time_store_call(self.name, "add_user", call)
+            .await
+    }
+
+    async fn add_pending_user(
+        &mut self,
+        tenant: &TenantId,
+        user: User,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        let call = self
+            .inner
+            .add_pending_user(tenant, user, token_hash, expires_at);
+        self.metrics
+            .time_store_call(self.name, "add_pending_user", call)
+            .await
+    }
+
+    async fn confirm_signup(
+        &mut self,
+        email: &Email,
+        token_hash: &str,
+    ) -> Result<(), UserStoreError> {
+        let call = self.inner.confirm_signup(email, token_hash);
+        self.metrics
+            .time_store_call(self.name, "confirm_signup", call)
+            .await
+    }
+
+    async fn get_user(&self, tenant: &TenantId, email: &Email) -> Result<User, UserStoreError> {
+        let call = self.inner.get_user(tenant, email);
+        self.metrics
+            .time_store_call(self.name, "get_user", call)
+            .await
+    }
+
+    async fn validate_user(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+        password: &Password,
+    ) -> Result<(), UserStoreError> {
+        let call = self.inner.validate_user(tenant, email, password);
+        self.metrics
+            .time_store_call(self.name, "validate_user", call)
+            .await
+    }
+
+    async fn get_lockout_state(&self, email: &Email) -> Result<LockoutState, UserStoreError> {
+        let call = self.inner.get_lockout_state(email);
+        self.metrics
+            .time_store_call(self.name, "get_lockout_state", call)
+            .await
+    }
+
+    async fn unlock_user(&mut self, email: &Email) -> Result<(), UserStoreError> {
+        let call = self.inner.unlock_user(email);
+        self.metrics
+            .time_store_call(self.name, "unlock_user", call)
+            .await
+    }
+
+    async fn add_member(&mut self, tenant: &TenantId, email: &Email) -> Result<(), UserStoreError> {
+        let call = self.inner.add_member(tenant, email);
+        self.metrics
+            .time_store_call(self.name, "add_member", call)
+            .await
+    }
+
+    async fn remove_member(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), UserStoreError> {
+        let call = self.inner.remove_member(tenant, email);
+        self.metrics
+            .time_store_call(self.name, "remove_member", call)
+            .await
+    }
+
+    async fn get_tenants(&self, email: &Email) -> Result<Vec<TenantId>, UserStoreError> {
+        let call = self.inner.get_tenants(email);
+        self.metrics
+            .time_store_call(self.name, "get_tenants", call)
+            .await
+    }
+
+    async fn assign_role(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+        role: &Role,
+    ) -> Result<(), UserStoreError> {
+        let call = self.inner.assign_role(tenant, email, role);
+        self.metrics
+            .time_store_call(self.name, "assign_role", call)
+            .await
+    }
+
+    async fn revoke_role(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+        role: &Role,
+    ) -> Result<(), UserStoreError> {
+        let call = self.inner.revoke_role(tenant, email, role);
+        self.metrics
+            .time_store_call(self.name, "revoke_role", call)
+            .await
+    }
+
+    async fn get_roles(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<Vec<Role>, UserStoreError> {
+        let call = self.inner.get_roles(tenant, email);
+        self.metrics
+            .time_store_call(self.name, "get_roles", call)
+            .await
+    }
+
+    async fn get_permissions(&self, roles: &[Role]) -> Result<Vec<Permission>, UserStoreError> {
+        let call = self.inner.get_permissions(roles);
+        self.metrics
+            .time_store_call(self.name, "get_permissions", call)
+            .await
+    }
+
+    async fn list_users(
+        &self,
+        tenant: &TenantId,
+        query: &UserListQuery,
+    ) -> Result<UserPage, UserStoreError> {
+        let call = self.inner.list_users(tenant, query);
+        self.metrics
+            .time_store_call(self.name, "list_users", call)
+            .await
+    }
+
+    async fn set_disabled(&mut self, email: &Email, disabled: bool) -> Result<(), UserStoreError> {
+        let call = self.inner.set_disabled(email, disabled);
+        self.metrics
+            .time_store_call(self.name, "set_disabled", call)
+            .await
+    }
+
+    async fn set_requires_2fa(
+        &mut self,
+        email: &Email,
+        requires_2fa: bool,
+    ) -> Result<(), UserStoreError> {
+        let call = self.inner.set_requires_2fa(email, requires_2fa);
+        self.metrics
+            .time_store_call(self.name, "set_requires_2fa", call)
+            .await
+    }
+
+    async fn start_password_reset(
+        &mut self,
+        email: &Email,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        let call = self
+            .inner
+            .start_password_reset(email, token_hash, expires_at);
+        self.metrics
+            .time_store_call(self.name, "start_password_reset", call)
+            .await
+    }
+
+    async fn complete_password_reset(
+        &mut self,
+        email: &Email,
+        token_hash: &str,
+        new_password_hash: PasswordHash,
+    ) -> Result<(), UserStoreError> {
+        let call = self
+            .inner
+            .complete_password_reset(email, token_hash, new_password_hash);
+        self.metrics
+            .time_store_call(self.name, "complete_password_reset", call)
+            .await
+    }
+
+    async fn start_phone_verification(
+        &mut self,
+        email: &Email,
+        phone: &PhoneNumber,
+        code_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), UserStoreError> {
+        let call = self
+            .inner
+            .start_phone_verification(email, phone, code_hash, expires_at);
+        self.metrics
+            .time_store_call(self.name, "start_phone_verification", call)
+            .await
+    }
+
+    async fn complete_phone_verification(
+        &mut self,
+        email: &Email,
+        code_hash: &str,
+    ) -> Result<PhoneNumber, UserStoreError> {
+        let call = self.inner.complete_phone_verification(email, code_hash);
+        self.metrics
+            .time_store_call(self.name, "complete_phone_verification", call)
+            .await
+    }
+
+    async fn set_two_fa_channel(
+        &mut self,
+        email: &Email,
+        channel: TwoFAChannel,
+    ) -> Result<(), UserStoreError> {
+        let call = self.inner.set_two_fa_channel(email, channel);
+        self.metrics
+            .time_store_call(self.name, "set_two_fa_channel", call)
+            .await
+    }
+
+    async fn delete_user(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), UserStoreError> {
+        let call = self.inner.delete_user(tenant, email);
+        self.metrics
+            .time_store_call(self.name, "delete_user", call)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: BannedTokenStore + Send + Sync> BannedTokenStore for Instrumented<S> {
+    async fn add_token(&mut self, token: String) -> Result<(), BannedTokenStoreError> {
+        let call = self.inner.add_token(token);
+        self.metrics
+            .time_store_call(self.name, "add_token", call)
+            .await
+    }
+
+    // Also counts the banned tokens found
+    async fn contains_token(&self, token: &str) -> Result<bool, BannedTokenStoreError> {
+        let call = self.inner.contains_token(token);
+        let result = self
+            .metrics
+            .time_store_call(self.name, "contains_token", call)
+            .await;
+        if let Ok(true) = result {
+            self.metrics.record_banned_token_hit();
+        }
+        result
+    }
+
+    async fn ban_subject(
+        &mut self,
+        tenant: &TenantId,
+        subject: &str,
+        issued_until: DateTime<Utc>,
+    ) -> Result<(), BannedTokenStoreError> {
+        let call = self.inner.ban_subject(tenant, subject, issued_until);
+        self.metrics
+            .time_store_call(self.name, "ban_subject", call)
+            .await
+    }
+
+    async fn subject_banned_until(
+        &self,
+        tenant: &TenantId,
+        subject: &str,
+    ) -> Result<Option<DateTime<Utc>>, BannedTokenStoreError> {
+        let call = self.inner.subject_banned_until(tenant, subject);
+        self.metrics
+            .time_store_call(self.name, "subject_banned_until", call)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: TwoFACodeStore + Send + Sync> TwoFACodeStore for Instrumented<S> {
+    async fn add_code(
+        &mut self,
+        tenant: &TenantId,
+        email: Email,
+        login_attempt_id: LoginAttemptId,
+        code: TwoFACode,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let call = self.inner.add_code(tenant, email, login_attempt_id, code);
+        self.metrics
+            .time_store_call(self.name, "add_code", call)
+            .await
+    }
+
+    async fn get_code(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(LoginAttemptId, TwoFACode), TwoFACodeStoreError> {
+        let call = self.inner.get_code(tenant, email);
+        self.metrics
+            .time_store_call(self.name, "get_code", call)
+            .await
+    }
+
+    async fn remove_code(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<(), TwoFACodeStoreError> {
+        let call = self.inner.remove_code(tenant, email);
+        self.metrics
+            .time_store_call(self.name, "remove_code", call)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: ApiKeyStore + Send + Sync> ApiKeyStore for Instrumented<S> {
+    async fn add_key(&mut self, key: ApiKey, key_hash: &str) -> Result<(), ApiKeyStoreError> {
+        let call = self.inner.add_key(key, key_hash);
+        self.metrics
+            .time_store_call(self.name, "add_key", call)
+            .await
+    }
+
+    async fn get_key_by_hash(&self, key_hash: &str) -> Result<ApiKey, ApiKeyStoreError> {
+        let call = self.inner.get_key_by_hash(key_hash);
+        self.metrics
+            .time_store_call(self.name, "get_key_by_hash", call)
+            .await
+    }
+
+    async fn get_keys(
+        &self,
+        tenant: &TenantId,
+        email: &Email,
+    ) -> Result<Vec<ApiKey>, ApiKeyStoreError> {
+        let call = self.inner.get_keys(tenant, email);
+        self.metrics
+            .time_store_call(self.name, "get_keys", call)
+            .await
+    }
+
+    async fn revoke_key(
+        &mut self,
+        tenant: &TenantId,
+        email: &Email,
+        id: Uuid,
+    ) -> Result<(), ApiKeyStoreError> {
+        let call = self.inner.revoke_key(tenant, email, id);
+        self.metrics
+            .time_store_call(self.name, "revoke_key", call)
+            .await
+    }
+
+    async fn mark_used(&mut self, id: Uuid, at: DateTime<Utc>) -> Result<(), ApiKeyStoreError> {
+        let call = self.inner.mark_used(id, at);
+        self.metrics
+            .time_store_call(self.name, "mark_used", call)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: EmailOutboxStore + Send + Sync> EmailOutboxStore for Instrumented<S> {
+    async fn enqueue(&mut self, email: OutboxEmail) -> Result<(), EmailOutboxStoreError> {
+        let call = self.inner.enqueue(email);
+        self.metrics
+            .time_store_call(self.name, "enqueue", call)
+            .await
+    }
+
+    async fn claim_due(
+        &mut self,
+        now: DateTime<Utc>,
+        lease: Duration,
+        limit: usize,
+    ) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+        let call = self.inner.claim_due(now, lease, limit);
+        self.metrics
+            .time_store_call(self.name, "claim_due", call)
+            .await
+    }
+
+    async fn mark_sent(&mut self, id: Uuid) -> Result<(), EmailOutboxStoreError> {
+        let call = self.inner.mark_sent(id);
+        self.metrics
+            .time_store_call(self.name, "mark_sent", call)
+            .await
+    }
+
+    async fn mark_failed(
+        &mut self,
+        id: Uuid,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), EmailOutboxStoreError> {
+        let call = self.inner.mark_failed(id, error, next_attempt_at);
+        self.metrics
+            .time_store_call(self.name, "mark_failed", call)
+            .await
+    }
+
+    async fn get_email(&self, idempotency_key: &str) -> Result<OutboxEmail, EmailOutboxStoreError> {
+        let call = self.inner.get_email(idempotency_key);
+        self.metrics
+            .time_store_call(self.name, "get_email", call)
+            .await
+    }
+
+    async fn get_failed(&self) -> Result<Vec<OutboxEmail>, EmailOutboxStoreError> {
+        let call = self.inner.get_failed();
+        self.metrics
+            .time_store_call(self.name, "get_failed", call)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: TenantStore + Send + Sync> TenantStore for Instrumented<S> {
+    async fn add_tenant(&mut self, tenant: Tenant) -> Result<(), TenantStoreError> {
+        let call = self.inner.add_tenant(tenant);
+        self.metrics
+            .time_store_call(self.name, "add_tenant", call)
+            .await
+    }
+
+    async fn get_tenant(&self, id: &TenantId) -> Result<Tenant, TenantStoreError> {
+        let call = self.inner.get_tenant(id);
+        self.metrics
+            .time_store_call(self.name, "get_tenant", call)
+            .await
+    }
+
+    async fn get_tenant_by_host(&self, host: &str) -> Result<Tenant, TenantStoreError> {
+        let call = self.inner.get_tenant_by_host(host);
+        self.metrics
+            .time_store_call(self.name, "get_tenant_by_host", call)
+            .await
+    }
+
+    async fn get_tenants(&self) -> Result<Vec<Tenant>, TenantStoreError> {
+        let call = self.inner.get_tenants();
+        self.metrics
+            .time_store_call(self.name, "get_tenants", call)
+            .await
+    }
+
+    async fn update_settings(
+        &mut self,
+        id: &TenantId,
+        settings: TenantSettings,
+    ) -> Result<(), TenantStoreError> {
+        let call = self.inner.update_settings(id, settings);
+        self.metrics
+            .time_store_call(self.name, "update_settings", call)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: WebhookStore + Send + Sync> WebhookStore for Instrumented<S> {
+    async fn add_subscription(
+        &mut self,
+        subscription: WebhookSubscription,
+    ) -> Result<(), WebhookStoreError> {
+        let call = self.inner.add_subscription(subscription);
+        self.metrics
+            .time_store_call(self.name, "add_subscription", call)
+            .await
+    }
+
+    async fn get_subscriptions(&self) -> Result<Vec<WebhookSubscription>, WebhookStoreError> {
+        let call = self.inner.get_subscriptions();
+        self.metrics
+            .time_store_call(self.name, "get_subscriptions", call)
+            .await
+    }
+
+    async fn remove_subscription(&mut self, id: Uuid) -> Result<(), WebhookStoreError> {
+        let call = self.inner.remove_subscription(id);
+        self.metrics
+            .time_store_call(self.name, "remove_subscription", call)
+            .await
+    }
+
+    async fn enqueue_event(&mut self, event: &WebhookEvent) -> Result<(), WebhookStoreError> {
+        let call = self.inner.enqueue_event(event);
+        self.metrics
+            .time_store_call(self.name, "enqueue_event", call)
+            .await
+    }
+
+    async fn claim_due_deliveries(
+        &mut self,
+        now: DateTime<Utc>,
+        lease: Duration,
+        limit: usize,
+    ) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        let call = self.inner.claim_due_deliveries(now, lease, limit);
+        self.metrics
+            .time_store_call(self.name, "claim_due_deliveries", call)
+            .await
+    }
+
+    async fn mark_delivered(&mut self, delivery_id: Uuid) -> Result<(), WebhookStoreError> {
+        let call = self.inner.mark_delivered(delivery_id);
+        self.metrics
+            .time_store_call(self.name, "mark_delivered", call)
+            .await
+    }
+
+    async fn mark_failed(
+        &mut self,
+        delivery_id: Uuid,
+        error: &str,
+        next_attempt_at: Option<DateTime<Utc>>,
+    ) -> Result<(), WebhookStoreError> {
+        let call = self.inner.mark_failed(delivery_id, error, next_attempt_at);
+        self.metrics
+            .time_store_call(self.name, "mark_failed", call)
+            .await
+    }
+
+    async fn get_dead_letters(&self) -> Result<Vec<WebhookDelivery>, WebhookStoreError> {
+        let call = self.inner.get_dead_letters();
+        self.metrics
+            .time_store_call(self.name, "get_dead_letters", call)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: RateLimiter + Send + Sync> RateLimiter for Instrumented<S> {
+    async fn check(
+        &mut self,
+        key: &str,
+        policy: &RateLimitPolicy,
+    ) -> Result<RateLimitDecision, RateLimiterError> {
+        let call = self.inner.check(key, policy);
+        self.metrics.time_store_call(self.name, "check", call).await
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: AuditSink + Send + Sync> AuditSink for Instrumented<S> {
+    async fn record(&self, event: AuditEvent) -> Result<(), AuditSinkError> {
+        let call = self.inner.record(event);
+        self.metrics
+            .time_store_call(self.name, "record", call)
+            .await
+    }
+}
+
+// Timed apart from the stores, as the work is deliberately slow
+#[async_trait::async_trait]
+impl<S: PasswordHasher + Send + Sync> PasswordHasher for Instrumented<S> {
+    async fn hash_password(
+        &self,
+        password: &Password,
+    ) -> Result<PasswordHash, PasswordHasherError> {
+        let call = self.inner.hash_password(password);
+        self.metrics.time_password_hash("hash", call).await
+    }
+
+    async fn verify_password(
+        &self,
+        password: &Password,
+        password_hash: &PasswordHash,
+    ) -> Result<(), PasswordHasherError> {
+        let call = self.inner.verify_password(password, password_hash);
+        self.metrics.time_password_hash("verify", call).await
+    }
+
+    async fn verify_dummy_password(&self, password: &Password) {
+        let call = self.inner.verify_dummy_password(password);
+        self.metrics.time_password_hash("verify", call).await
+    }
+
+    fn needs_rehash(&self, password_hash: &PasswordHash) -> bool {
+        self.inner.needs_rehash(password_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::data_stores::hashmap_user_store::HashmapUserStore;
+    use crate::services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore;
+
+    #[test]
+    fn test_short_type_name() {
+        assert_eq!(short_type_name::<HashmapUserStore>(), "HashmapUserStore");
+        assert_eq!(
+            short_type_name::<Arc<HashsetBannedTokenStore>>(),
+            "HashsetBannedTokenStore"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_calls_are_timed_and_banned_tokens_counted() {
+        let metrics = Arc::new(Metrics::new());
+        let mut store = Instrumented::new(HashsetBannedTokenStore::default(), metrics.clone());
+
+        store.add_token("banned".to_owned()).await.unwrap();
+        assert!(store.contains_token("banned").await.unwrap());
+        assert!(!store.contains_token("fine").await.unwrap());
+
+        let output = metrics.render().unwrap();
+        assert!(output.contains(
+            "auth_store_call_duration_seconds_count\
+             {operation=\"contains_token\",store=\"HashsetBannedTokenStore\"} 2"
+        ));
+        assert!(output.contains(
+            "auth_store_call_duration_seconds_count\
+             {operation=\"add_token\",store=\"HashsetBannedTokenStore\"} 1"
+        ));
+        assert!(output.contains("auth_banned_token_hits_total 1"));
+    }
+}
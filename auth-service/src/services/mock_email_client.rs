@@ -1,4 +1,4 @@
-use crate::domain::{Email, EmailClient};
+use crate::domain::{Email, EmailClient, EmailClientError, EmailMessage};
 
 #[derive(Default)]
 pub struct MockEmailClient;
@@ -8,15 +8,15 @@ impl EmailClient for MockEmailClient {
     async fn send_email(
         &self,
         recipient: &Email,
-        subject: &str,
-        content: &str,
-    ) -> Result<(), String> {
-        // Our mock email client will simply log the recipient, subject, and content to standard output
+        message: &EmailMessage,
+    ) -> Result<(), EmailClientError> {
+        // Our mock email client will simply log the recipient, subject, and text content to standard output.
+        // Printed rather than traced, as this is how developers read the codes it would have sent.
         println!(
             "Sending email to {} with subject: {} and content: {}",
             recipient.as_ref(),
-            subject,
-            content
+            message.subject,
+            message.text
         );
 
         Ok(())
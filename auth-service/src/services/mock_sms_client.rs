@@ -0,0 +1,24 @@
+use crate::domain::{PhoneNumber, SmsClient, SmsClientError};
+
+#[derive(Default)]
+pub struct MockSmsClient;
+
+#[async_trait::async_trait]
+impl SmsClient for MockSmsClient {
+    async fn send_sms(&self, recipient: &PhoneNumber, text: &str) -> Result<(), SmsClientError> {
+        // Printed rather than traced, like the mock email client's emails
+        println!(
+            "Sending SMS to {} with content: {}",
+            recipient.as_ref(),
+            text
+        );
+
+        Ok(())
+    }
+
+    async fn send_voice(&self, recipient: &PhoneNumber, text: &str) -> Result<(), SmsClientError> {
+        println!("Calling {} to say: {}", recipient.as_ref(), text);
+
+        Ok(())
+    }
+}
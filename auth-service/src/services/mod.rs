@@ -1,3 +1,11 @@
+pub mod argon2_password_hasher;
+pub mod audit_checkpoint_signer;
 
-pub mod mock_email_client;
 pub mod data_stores;
+pub mod email_outbox_worker;
+pub mod instrumented;
+pub mod http_sms_client;
+pub mod mock_email_client;
+pub mod mock_sms_client;
+pub mod smtp_email_client;
+pub mod webhook_dispatcher;
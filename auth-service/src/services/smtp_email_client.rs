@@ -0,0 +1,388 @@
+use std::time::Duration;
+
+use lettre::{
+    message::{Mailbox, MultiPart, SinglePart},
+    transport::smtp::{authentication::Credentials, PoolConfig},
+    AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use crate::{
+    config::SmtpConfig,
+    domain::{Email, EmailClient, EmailClientError, EmailMessage},
+    services::email_outbox_worker::OUTBOX_LEASE,
+};
+
+/// How the connection to the mail server is secured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    // Implicit TLS from the start, usually on port 465
+    Tls,
+    // Plain connection upgraded with STARTTLS, which is required
+    StartTls,
+    // No encryption at all, only for a relay on the same host or network
+    None,
+}
+
+impl SmtpTls {
+    pub fn parse(tls: &str) -> Result<Self, String> {
+        match tls.to_lowercase().as_str() {
+            "tls" => Ok(SmtpTls::Tls),
+            "starttls" => Ok(SmtpTls::StartTls),
+            "none" => Ok(SmtpTls::None),
+            _ => Err(format!("Unknown SMTP TLS mode: {}", tls)),
+        }
+    }
+
+    fn default_port(&self) -> u16 {
+        match self {
+            SmtpTls::Tls => 465,
+            SmtpTls::StartTls => 587,
+            SmtpTls::None => 25,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub tls: SmtpTls,
+    // Both or neither, for servers that don't need authentication
+    pub credentials: Option<(String, String)>,
+    // The From of every email, e.g. `Example <no-reply@example.com>`
+    pub sender: String,
+    // For connecting and for each reply from the server
+    pub timeout: Duration,
+    // Connections are kept open and reused, up to this many at once
+    pub max_connections: u32,
+}
+
+impl SmtpSettings {
+    /// The settings from the `[smtp]` config section, or `None` when
+    /// `smtp.host` isn't set and emails can't be delivered.
+    pub fn from_config(config: &SmtpConfig) -> Result<Option<Self>, String> {
+        let Some(host) = config.host.clone() else {
+            return Ok(None);
+        };
+
+        let tls = SmtpTls::parse(&config.tls).map_err(|e| format!("smtp.tls (SMTP_TLS): {}", e))?;
+
+        let credentials = match (&config.username, &config.password) {
+            (Some(username), Some(password)) => Some((username.clone(), password.clone())),
+            (None, None) => None,
+            _ => {
+                return Err(
+                    "smtp.username (SMTP_USERNAME) and smtp.password (SMTP_PASSWORD) must be \
+                     set together"
+                        .to_owned(),
+                )
+            }
+        };
+
+        let sender = config
+            .sender
+            .clone()
+            .ok_or_else(|| "smtp.sender (SMTP_SENDER) must be set when smtp.host is".to_owned())?;
+        if config.timeout_seconds == 0 {
+            return Err("smtp.timeout_seconds (SMTP_TIMEOUT_SECONDS) must be positive".to_owned());
+        }
+        // A send can take twice the timeout, see `send_email`, and has to end
+        // before its outbox lease does
+        if config.timeout_seconds >= OUTBOX_LEASE.as_secs() / 2 {
+            return Err(format!(
+                "smtp.timeout_seconds (SMTP_TIMEOUT_SECONDS) must be under {} seconds, within \
+                 the email outbox lease",
+                OUTBOX_LEASE.as_secs() / 2
+            ));
+        }
+        if config.max_connections == 0 {
+            return Err(
+                "smtp.max_connections (SMTP_MAX_CONNECTIONS) must be at least 1".to_owned(),
+            );
+        }
+
+        Ok(Some(Self {
+            host,
+            port: config.port.unwrap_or_else(|| tls.default_port()),
+            tls,
+            credentials,
+            sender,
+            timeout: Duration::from_secs(config.timeout_seconds),
+            max_connections: config.max_connections,
+        }))
+    }
+}
+
+pub struct SmtpEmailClient {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    sender: Mailbox,
+    timeout: Duration,
+}
+
+impl SmtpEmailClient {
+    pub fn new(settings: SmtpSettings) -> Result<Self, String> {
+        let sender = settings
+            .sender
+            .parse::<Mailbox>()
+            .map_err(|e| format!("Invalid SMTP sender: {}", e))?;
+
+        let builder = match settings.tls {
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host),
+            SmtpTls::StartTls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+            }
+            SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &settings.host,
+            )),
+        }
+        .map_err(|e| format!("Invalid SMTP host: {}", e))?;
+
+        let mut builder = builder
+            .port(settings.port)
+            .timeout(Some(settings.timeout))
+            .pool_config(PoolConfig::new().max_size(settings.max_connections));
+        if let Some((username, password)) = settings.credentials {
+            builder = builder.credentials(Credentials::new(username, password));
+        }
+
+        Ok(Self {
+            transport: builder.build(),
+            sender,
+            timeout: settings.timeout,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailClient for SmtpEmailClient {
+    #[tracing::instrument(skip_all)]
+    async fn send_email(
+        &self,
+        recipient: &Email,
+        message: &EmailMessage,
+    ) -> Result<(), EmailClientError> {
+        let to = recipient
+            .as_ref()
+            .parse::<Mailbox>()
+            .map_err(|e| EmailClientError::InvalidMessage(e.to_string()))?;
+        let builder = Message::builder()
+            .from(self.sender.clone())
+            .to(to)
+            .subject(&message.subject);
+        let message = match &message.html {
+            Some(html) => builder.multipart(MultiPart::alternative_plain_html(
+                message.text.clone(),
+                html.clone(),
+            )),
+            None => builder.singlepart(SinglePart::plain(message.text.clone())),
+        }
+        .map_err(|e| EmailClientError::InvalidMessage(e.to_string()))?;
+
+        // lettre's timeout doesn't cover waiting for the greeting, so the
+        // whole exchange is bounded here as well
+        let sent = tokio::time::timeout(self.timeout * 2, self.transport.send(message))
+            .await
+            .map_err(|_| EmailClientError::Unavailable("timed out".to_owned()))?;
+
+        sent.map(|_| ()).map_err(|e| {
+            if e.is_permanent() {
+                EmailClientError::Rejected(e.to_string())
+            } else {
+                EmailClientError::Unavailable(e.to_string())
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    };
+
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::{TcpListener, TcpStream},
+    };
+
+    use super::*;
+
+    // Just enough of an SMTP server to accept (or refuse) mail from lettre
+    #[derive(Default)]
+    struct StandIn {
+        messages: Mutex<Vec<String>>,
+        auth: Mutex<Vec<String>>,
+        connections: AtomicUsize,
+    }
+
+    async fn start_stand_in(rcpt_reply: &'static str) -> (SocketAddr, Arc<StandIn>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let stand_in = Arc::new(StandIn::default());
+
+        let state = stand_in.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                state.connections.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(serve(stream, state.clone(), rcpt_reply));
+            }
+        });
+
+        (address, stand_in)
+    }
+
+    async fn serve(stream: TcpStream, state: Arc<StandIn>, rcpt_reply: &'static str) {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        writer.write_all(b"220 stand-in ESMTP\r\n").await.unwrap();
+
+        while let Ok(Some(line)) = lines.next_line().await {
+            let command = line.to_uppercase();
+            let reply = if command.starts_with("EHLO") {
+                "250-stand-in\r\n250-AUTH PLAIN LOGIN\r\n250 8BITMIME"
+            } else if command.starts_with("AUTH") {
+                state.auth.lock().unwrap().push(line.clone());
+                "235 2.7.0 Authenticated"
+            } else if command.starts_with("RCPT") {
+                rcpt_reply
+            } else if command.starts_with("DATA") {
+                writer.write_all(b"354 Go ahead\r\n").await.unwrap();
+                let mut data = String::new();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    if line == "." {
+                        break;
+                    }
+                    data.push_str(&line);
+                    data.push('\n');
+                }
+                state.messages.lock().unwrap().push(data);
+                "250 2.0.0 Queued"
+            } else if command.starts_with("QUIT") {
+                let _ = writer.write_all(b"221 Bye\r\n").await;
+                return;
+            } else {
+                // MAIL, RSET, NOOP
+                "250 OK"
+            };
+            writer
+                .write_all(format!("{}\r\n", reply).as_bytes())
+                .await
+                .unwrap();
+            // A used connection goes back to the pool in the background
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+
+    fn settings(address: SocketAddr) -> SmtpSettings {
+        SmtpSettings {
+            host: address.ip().to_string(),
+            port: address.port(),
+            tls: SmtpTls::None,
+            credentials: Some(("user".to_owned(), "secret".to_owned())),
+            sender: "Auth Service <no-reply@example.com>".to_owned(),
+            timeout: Duration::from_secs(2),
+            max_connections: 1,
+        }
+    }
+
+    fn recipient() -> Email {
+        Email::parse("test@example.com".to_owned()).unwrap()
+    }
+
+    fn message(code: &str, html: bool) -> EmailMessage {
+        EmailMessage {
+            subject: "2FA code".to_owned(),
+            text: format!("Your code is {}", code),
+            html: html.then(|| format!("<p>Your code is <b>{}</b></p>", code)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sends_authenticated_email_over_one_connection() {
+        let (address, stand_in) = start_stand_in("250 OK").await;
+        let client = SmtpEmailClient::new(settings(address)).unwrap();
+
+        for (code, html) in [("123456", false), ("654321", true)] {
+            client
+                .send_email(&recipient(), &message(code, html))
+                .await
+                .unwrap();
+            // A used connection goes back to the pool in the background
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        let messages = stand_in.messages.lock().unwrap().clone();
+        assert_eq!(messages.len(), 2);
+        assert!(messages[0].contains("From: \"Auth Service\" <no-reply@example.com>"));
+        assert!(messages[0].contains("To: test@example.com"));
+        assert!(messages[0].contains("Subject: 2FA code"));
+        assert!(messages[0].contains("Content-Type: text/plain"));
+        assert!(messages[0].contains("Your code is 123456"));
+        // With HTML the text is sent alongside it as an alternative
+        assert!(messages[1].contains("Content-Type: multipart/alternative"));
+        assert!(messages[1].contains("Your code is 654321"));
+        assert!(messages[1].contains("Content-Type: text/html"));
+        assert!(messages[1].contains("<b>654321</b>"));
+
+        // The connection is reused, so it's authenticated just once
+        assert_eq!(stand_in.connections.load(Ordering::SeqCst), 1);
+        assert_eq!(stand_in.auth.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_permanent_and_temporary_failures_are_told_apart() {
+        let (address, _) = start_stand_in("550 5.1.1 No such user").await;
+        let client = SmtpEmailClient::new(settings(address)).unwrap();
+        let result = client
+            .send_email(&recipient(), &message("123456", false))
+            .await;
+        assert!(matches!(result, Err(EmailClientError::Rejected(_))));
+
+        let (address, _) = start_stand_in("451 4.3.0 Try again later").await;
+        let client = SmtpEmailClient::new(settings(address)).unwrap();
+        let result = client
+            .send_email(&recipient(), &message("123456", false))
+            .await;
+        assert!(matches!(result, Err(EmailClientError::Unavailable(_))));
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_server_times_out() {
+        // Accepts connections but never greets
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut streams = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                streams.push(stream);
+            }
+        });
+
+        let mut settings = settings(address);
+        settings.timeout = Duration::from_millis(200);
+        let client = SmtpEmailClient::new(settings).unwrap();
+        let result = client
+            .send_email(&recipient(), &message("123456", false))
+            .await;
+        assert!(matches!(result, Err(EmailClientError::Unavailable(_))));
+    }
+
+    #[test]
+    fn test_invalid_sender_is_refused() {
+        let mut settings = settings("127.0.0.1:25".parse().unwrap());
+        settings.sender = "not an address".to_owned();
+        assert!(SmtpEmailClient::new(settings).is_err());
+    }
+
+    #[test]
+    fn test_parse_tls() {
+        assert_eq!(SmtpTls::parse("STARTTLS"), Ok(SmtpTls::StartTls));
+        assert_eq!(SmtpTls::parse("tls"), Ok(SmtpTls::Tls));
+        assert_eq!(SmtpTls::parse("none"), Ok(SmtpTls::None));
+        assert!(SmtpTls::parse("ssl").is_err());
+    }
+}
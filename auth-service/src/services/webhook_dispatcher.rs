@@ -0,0 +1,281 @@
+use std::time::Duration;
+
+use chrono::Utc;
+
+use crate::app_state::WebhookStoreType;
+use crate::domain::{RetryPolicy, WebhookDelivery, WebhookStoreError};
+use crate::utils::shutdown::ShutdownHandle;
+use crate::utils::webhook_signature::{sign_webhook_payload, WEBHOOK_SIGNATURE_HEADER};
+
+pub const WEBHOOK_ID_HEADER: &str = "x-webhook-id";
+pub const WEBHOOK_EVENT_HEADER: &str = "x-webhook-event";
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const BATCH_SIZE: usize = 50;
+
+/// Sends queued webhook deliveries to their subscribers.
+///
+/// Any 2xx response counts as delivered. Anything else, including a timeout,
+/// is retried according to the retry policy and dead-lettered once it runs out.
+pub struct WebhookDispatcher {
+    webhook_store: WebhookStoreType,
+    http_client: reqwest::Client,
+    retry_policy: RetryPolicy,
+}
+
+impl WebhookDispatcher {
+    pub fn new(webhook_store: WebhookStoreType) -> Self {
+        let http_client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()
+            .expect("Failed to build webhook HTTP client");
+
+        Self {
+            webhook_store,
+            http_client,
+            retry_policy: RetryPolicy::default(),
+        }
+    }
+
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Attempt every delivery that is due now, returning how many were attempted.
+    pub async fn dispatch_due(&self) -> Result<usize, WebhookStoreError> {
+        // Claimed deliveries stay invisible to other dispatchers until every
+        // request in the batch could have timed out
+        let lease = chrono::Duration::from_std(REQUEST_TIMEOUT * 2)
+            .map_err(|_| WebhookStoreError::UnexpectedError)?;
+        let deliveries = self
+            .webhook_store
+            .write()
+            .await
+            .claim_due_deliveries(Utc::now(), lease, BATCH_SIZE)
+            .await?;
+
+        for delivery in &deliveries {
+            let result = self.send(delivery).await;
+
+            // The lock isn't held while sending, so a slow receiver doesn't
+            // hold up requests that queue new events
+            let mut webhook_store = self.webhook_store.write().await;
+            match result {
+                Ok(()) => webhook_store.mark_delivered(delivery.id).await?,
+                Err(error) => {
+                    let next_attempt_at = self
+                        .retry_policy
+                        .next_attempt_at(delivery.attempts + 1, Utc::now());
+                    webhook_store
+                        .mark_failed(delivery.id, &error, next_attempt_at)
+                        .await?
+                }
+            }
+        }
+
+        Ok(deliveries.len())
+    }
+
+    /// Poll the queue every `interval` until `shutdown` is triggered, then
+    /// dispatch whatever is still due once more.
+    pub async fn run(self, interval: Duration, shutdown: ShutdownHandle) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            let stopping = tokio::select! {
+                _ = ticker.tick() => false,
+                _ = shutdown.wait() => true,
+            };
+            if let Err(e) = self.dispatch_due().await {
+                tracing::error!(error = ?e, "Failed to dispatch webhooks");
+            }
+            if stopping {
+                return;
+            }
+        }
+    }
+
+    async fn send(&self, delivery: &WebhookDelivery) -> Result<(), String> {
+        let signature =
+            sign_webhook_payload(&delivery.secret, Utc::now().timestamp(), &delivery.payload);
+
+        let response = self
+            .http_client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(WEBHOOK_ID_HEADER, delivery.id.to_string())
+            .header(WEBHOOK_EVENT_HEADER, delivery.event_type.as_str())
+            .header(WEBHOOK_SIGNATURE_HEADER, signature)
+            .body(delivery.payload.clone())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("HTTP {}", response.status().as_u16()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicU16, Ordering},
+        Arc, Mutex,
+    };
+
+    use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+    use tokio::sync::RwLock;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::domain::{
+        Email, TenantId, WebhookEvent, WebhookEventType, WebhookStore, WebhookSubscription,
+    };
+    use crate::services::data_stores::hashmap_webhook_store::HashmapWebhookStore;
+    use crate::utils::webhook_signature::verify_webhook_signature;
+
+    #[derive(Clone, Default)]
+    struct StubReceiver {
+        status: Arc<AtomicU16>,
+        received: Arc<Mutex<Vec<(HeaderMap, String)>>>,
+    }
+
+    async fn receive(
+        State(receiver): State<StubReceiver>,
+        headers: HeaderMap,
+        body: String,
+    ) -> StatusCode {
+        receiver.received.lock().unwrap().push((headers, body));
+        StatusCode::from_u16(receiver.status.load(Ordering::SeqCst)).unwrap()
+    }
+
+    // Serve the receiver on a random local port, returning its URL
+    async fn start_receiver(status: StatusCode) -> (StubReceiver, String) {
+        let receiver = StubReceiver::default();
+        receiver.status.store(status.as_u16(), Ordering::SeqCst);
+
+        let router = Router::new()
+            .route("/hook", post(receive))
+            .with_state(receiver.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        (receiver, url)
+    }
+
+    async fn store_with_event(url: &str) -> (WebhookStoreType, WebhookEvent) {
+        let mut store = HashmapWebhookStore::default();
+        store
+            .add_subscription(WebhookSubscription {
+                id: Uuid::new_v4(),
+                tenant: TenantId::default(),
+                url: url.to_owned(),
+                secret: "secret".to_owned(),
+                event_types: vec![],
+            })
+            .await
+            .unwrap();
+
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let event = WebhookEvent::new(
+            &TenantId::default(),
+            WebhookEventType::AccountLocked,
+            &email,
+        );
+        store.enqueue_event(&event).await.unwrap();
+
+        (Arc::new(RwLock::new(store)), event)
+    }
+
+    #[tokio::test]
+    async fn test_delivers_signed_payload() {
+        let (receiver, url) = start_receiver(StatusCode::OK).await;
+        let (store, event) = store_with_event(&url).await;
+        let dispatcher = WebhookDispatcher::new(store.clone());
+
+        assert_eq!(dispatcher.dispatch_due().await, Ok(1));
+
+        let received = receiver.received.lock().unwrap().clone();
+        assert_eq!(received.len(), 1);
+        let (headers, body) = &received[0];
+        assert_eq!(body, &event.payload());
+        assert_eq!(headers[WEBHOOK_EVENT_HEADER], "user.locked");
+        let signature = headers[WEBHOOK_SIGNATURE_HEADER].to_str().unwrap();
+        assert!(verify_webhook_signature(
+            "secret",
+            signature,
+            body,
+            Utc::now().timestamp(),
+            300
+        ));
+
+        // Nothing left to send
+        assert_eq!(dispatcher.dispatch_due().await, Ok(0));
+        assert!(store
+            .read()
+            .await
+            .get_dead_letters()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_deliveries_are_retried_then_dead_lettered() {
+        let (receiver, url) = start_receiver(StatusCode::INTERNAL_SERVER_ERROR).await;
+        let (store, _) = store_with_event(&url).await;
+        let dispatcher = WebhookDispatcher::new(store.clone()).with_retry_policy(RetryPolicy {
+            max_attempts: 3,
+            base_delay: chrono::Duration::zero(),
+            max_delay: chrono::Duration::zero(),
+        });
+
+        for _ in 0..3 {
+            assert_eq!(dispatcher.dispatch_due().await, Ok(1));
+        }
+        assert_eq!(dispatcher.dispatch_due().await, Ok(0));
+        assert_eq!(receiver.received.lock().unwrap().len(), 3);
+
+        let dead_letters = store.read().await.get_dead_letters().await.unwrap();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].attempts, 3);
+        assert_eq!(dead_letters[0].last_error.as_deref(), Some("HTTP 500"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_is_delivered_once_receiver_recovers() {
+        let (receiver, url) = start_receiver(StatusCode::SERVICE_UNAVAILABLE).await;
+        let (store, _) = store_with_event(&url).await;
+        let dispatcher = WebhookDispatcher::new(store.clone()).with_retry_policy(RetryPolicy {
+            max_attempts: 3,
+            base_delay: chrono::Duration::zero(),
+            max_delay: chrono::Duration::zero(),
+        });
+
+        assert_eq!(dispatcher.dispatch_due().await, Ok(1));
+        receiver
+            .status
+            .store(StatusCode::NO_CONTENT.as_u16(), Ordering::SeqCst);
+        assert_eq!(dispatcher.dispatch_due().await, Ok(1));
+        assert_eq!(dispatcher.dispatch_due().await, Ok(0));
+
+        // Both attempts carried the same delivery id, so receivers can dedupe
+        let received = receiver.received.lock().unwrap().clone();
+        assert_eq!(
+            received[0].0[WEBHOOK_ID_HEADER],
+            received[1].0[WEBHOOK_ID_HEADER]
+        );
+        assert!(store
+            .read()
+            .await
+            .get_dead_letters()
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
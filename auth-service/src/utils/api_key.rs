@@ -0,0 +1,213 @@
+use chrono::{Duration, Utc};
+use rand::{rng, Rng};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    app_state::AppState,
+    domain::{ApiKey, AuthAPIError, Permission, Role, TenantId},
+};
+
+use super::auth::{format_scope, Claims};
+
+/// Every API key starts with this, so a bearer credential can be told apart
+/// from a JWT without a lookup, and leaked keys are easy to search for.
+pub const API_KEY_PREFIX: &str = "ak_";
+
+/// How long a key lasts when its owner doesn't say, and the longest allowed.
+pub const DEFAULT_API_KEY_TTL: Duration = Duration::days(90);
+pub const MAX_API_KEY_TTL: Duration = Duration::days(365);
+
+/// A new random key, shown to its owner once and never stored as is.
+pub fn generate_api_key() -> String {
+    format!(
+        "{}{}",
+        API_KEY_PREFIX,
+        hex::encode(rng().random::<[u8; 32]>())
+    )
+}
+
+/// What the API key store keeps and looks keys up by. The keys are random,
+/// so a fast hash is enough.
+pub fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+pub fn is_api_key(credential: &str) -> bool {
+    credential.starts_with(API_KEY_PREFIX)
+}
+
+/// The claims `key` acts with in `tenant`: its owner, no roles, and the
+/// key's scopes that the owner still has permission for.
+///
+/// Refused like an invalid token when the key is unknown, expired, for
+/// another tenant, or its owner has since been disabled, removed from the
+/// tenant or signed out everywhere.
+pub async fn authenticate_api_key(
+    state: &AppState,
+    tenant: &TenantId,
+    key: &str,
+) -> Result<Claims, AuthAPIError> {
+    let now = Utc::now();
+    let api_key = state
+        .api_key_store
+        .read()
+        .await
+        .get_key_by_hash(&hash_api_key(key))
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)?;
+
+    if &api_key.tenant != tenant || api_key.is_expired(now) {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    {
+        let user_store = state.user_store.read().await;
+        let user = user_store
+            .get_user(tenant, &api_key.email)
+            .await
+            .map_err(|_| AuthAPIError::InvalidToken)?;
+        if user.disabled {
+            return Err(AuthAPIError::InvalidToken);
+        }
+    }
+
+    let banned_until = state
+        .banned_token_store
+        .read()
+        .await
+        .subject_banned_until(tenant, api_key.email.as_ref())
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    if banned_until.is_some_and(|until| api_key.created_at <= until) {
+        return Err(AuthAPIError::InvalidToken);
+    }
+
+    let scopes = allowed_scopes(state, &api_key).await?;
+
+    // Only informational, so a failure doesn't refuse the request
+    if let Err(e) = state
+        .api_key_store
+        .write()
+        .await
+        .mark_used(api_key.id, now)
+        .await
+    {
+        tracing::warn!(error = ?e, "Failed to record API key use");
+    }
+
+    Ok(Claims {
+        sub: api_key.email.as_ref().to_owned(),
+        exp: timestamp(api_key.expires_at.timestamp())?,
+        iat: timestamp(api_key.created_at.timestamp())?,
+        tenant: tenant.as_ref().to_owned(),
+        roles: vec![],
+        scope: Some(format_scope(&scopes)),
+        act: None,
+    })
+}
+
+// Scopes are checked against the owner's permissions on every use, so a key
+// never outlives a role the owner has lost
+async fn allowed_scopes(
+    state: &AppState,
+    api_key: &ApiKey,
+) -> Result<Vec<Permission>, AuthAPIError> {
+    let user_store = state.user_store.read().await;
+    let roles: Vec<Role> = user_store
+        .get_roles(&api_key.tenant, &api_key.email)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+    let permissions = user_store
+        .get_permissions(&roles)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    Ok(api_key
+        .scopes
+        .iter()
+        .filter(|scope| permissions.contains(scope))
+        .cloned()
+        .collect())
+}
+
+fn timestamp(seconds: i64) -> Result<usize, AuthAPIError> {
+    usize::try_from(seconds).map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use tokio::sync::RwLock;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        config::Config,
+        domain::Email,
+        services::{
+            argon2_password_hasher::Argon2PasswordHasher,
+            data_stores::{
+                hashmap_api_key_store::HashmapApiKeyStore,
+                hashmap_two_fa_code_store::HashmapTwoFACodeStore,
+                hashmap_user_store::HashmapUserStore,
+                hashset_banned_token_store::HashsetBannedTokenStore,
+            },
+            mock_email_client::MockEmailClient,
+        },
+    };
+
+    #[test]
+    fn test_keys_are_prefixed_unique_and_hashed() {
+        let key = generate_api_key();
+        assert!(is_api_key(&key));
+        assert_eq!(key.len(), API_KEY_PREFIX.len() + 64);
+        assert_ne!(key, generate_api_key());
+
+        let hash = hash_api_key(&key);
+        assert_ne!(hash, key);
+        assert_eq!(hash, hash_api_key(&key));
+        assert!(!is_api_key("eyJhbGciOiJIUzI1NiJ9.e30.sig"));
+    }
+
+    #[tokio::test]
+    async fn test_refuses_expired_keys_and_other_tenants() {
+        let state = AppState::new(
+            Arc::new(Config::default()),
+            Arc::new(RwLock::new(HashmapUserStore::default())),
+            Arc::new(RwLock::new(HashsetBannedTokenStore::default())),
+            Arc::new(RwLock::new(HashmapTwoFACodeStore::default())),
+            Arc::new(RwLock::new(MockEmailClient)),
+            Arc::new(Argon2PasswordHasher::default()),
+        )
+        .with_api_key_store(Arc::new(RwLock::new(HashmapApiKeyStore::default())));
+
+        let now = Utc::now();
+        let expired = generate_api_key();
+        let current = generate_api_key();
+        for (key, expires_at) in [(&expired, now), (&current, now + DEFAULT_API_KEY_TTL)] {
+            let api_key = ApiKey {
+                id: Uuid::new_v4(),
+                tenant: TenantId::default(),
+                email: Email::parse("test@example.com".to_owned()).unwrap(),
+                name: "ci".to_owned(),
+                scopes: vec![],
+                created_at: now - DEFAULT_API_KEY_TTL,
+                expires_at,
+                last_used_at: None,
+            };
+            let mut api_key_store = state.api_key_store.write().await;
+            api_key_store
+                .add_key(api_key, &hash_api_key(key))
+                .await
+                .unwrap();
+        }
+
+        let other_tenant = TenantId::parse("acme".to_owned()).unwrap();
+        for (tenant, key) in [(TenantId::default(), &expired), (other_tenant, &current)] {
+            assert!(matches!(
+                authenticate_api_key(&state, &tenant, key).await,
+                Err(AuthAPIError::InvalidToken)
+            ));
+        }
+    }
+}
@@ -0,0 +1,16 @@
+use crate::{app_state::AppState, domain::AuditEvent};
+
+/// Record `event` in the audit log.
+///
+/// A sink failure is logged but doesn't fail the request: refusing logins
+/// while the audit table is unavailable would be worse than a gap in the log.
+pub async fn record_audit_event(app_state: &AppState, event: AuditEvent) {
+    let event_type = event.event_type;
+    if let Err(e) = app_state.audit_sink.record(event).await {
+        tracing::error!(
+            error = ?e,
+            event_type = event_type.as_str(),
+            "Failed to record audit event"
+        );
+    }
+}
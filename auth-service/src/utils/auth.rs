@@ -1,14 +1,41 @@
-use axum_extra::extract::cookie::{Cookie, SameSite};
+use std::marker::PhantomData;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap},
+};
+use axum_extra::extract::{
+    cookie::{Cookie, SameSite},
+    CookieJar,
+};
 use chrono::Utc;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Validation};
 use serde::{Deserialize, Serialize};
 
-use crate::{app_state::BannedTokenStoreType, domain::email::Email};
+use crate::{
+    app_state::{AppState, BannedTokenStoreType},
+    config::AuthConfig,
+    domain::{
+        email::Email, AuthAPIError, Permission, Role, TenantId, ADMIN_ROLE, DEFAULT_TENANT_ID,
+    },
+};
 
-use super::constants::{JWT_COOKIE_NAME, JWT_SECRET};
+use super::{
+    api_key::{authenticate_api_key, is_api_key},
+    constants::JWT_COOKIE_NAME,
+    tenant::CurrentTenant,
+};
 
-pub fn generate_auth_cookie(email: &Email) -> Result<Cookie<'static>, GenerateTokenError> {
-    let token = generate_auth_token(email)?;
+/// `scope` limits the token to those permissions, see [`grant_scope`], and
+/// then it has no roles; with `None` it has `roles` and all they allow.
+pub fn generate_auth_cookie(
+    config: &AuthConfig,
+    tenant: &TenantId,
+    email: &Email,
+    roles: &[Role],
+    scope: Option<&[Permission]>,
+) -> Result<Cookie<'static>, GenerateTokenError> {
+    let token = generate_auth_token(config, tenant, email, roles, scope)?;
     Ok(create_auth_cookie(token))
 }
 
@@ -28,13 +55,48 @@ pub enum GenerateTokenError {
     UnexpectedError,
 }
 
-pub const TOKEN_TTL_SECONDS: i64 = 600;
+fn generate_auth_token(
+    config: &AuthConfig,
+    tenant: &TenantId,
+    email: &Email,
+    roles: &[Role],
+    scope: Option<&[Permission]>,
+) -> Result<String, GenerateTokenError> {
+    let claims = new_claims(tenant, email, roles, scope, config.token_ttl_seconds)?;
+    create_token(config, &claims).map_err(GenerateTokenError::TokenError)
+}
 
-fn generate_auth_token(email: &Email) -> Result<String, GenerateTokenError> {
-    let delta = chrono::Duration::try_seconds(TOKEN_TTL_SECONDS)
-        .ok_or(GenerateTokenError::UnexpectedError)?;
+/// A token for `email` with their roles, for the admin `actor` to see what
+/// they see. It names the admin in its `act` claim, see [`Actor`].
+///
+/// Impersonation tokens are short-lived, and can't be renewed.
+pub fn generate_impersonation_token(
+    config: &AuthConfig,
+    tenant: &TenantId,
+    email: &Email,
+    roles: &[Role],
+    actor: &Email,
+) -> Result<String, GenerateTokenError> {
+    let ttl_seconds = config.impersonation_ttl_seconds;
+    let mut claims = new_claims(tenant, email, roles, None, ttl_seconds)?;
+    claims.act = Some(Actor {
+        sub: actor.as_ref().to_owned(),
+    });
+    create_token(config, &claims).map_err(GenerateTokenError::TokenError)
+}
 
-    let exp = Utc::now()
+fn new_claims(
+    tenant: &TenantId,
+    email: &Email,
+    roles: &[Role],
+    scope: Option<&[Permission]>,
+    ttl_seconds: i64,
+) -> Result<Claims, GenerateTokenError> {
+    let delta =
+        chrono::Duration::try_seconds(ttl_seconds).ok_or(GenerateTokenError::UnexpectedError)?;
+
+    let now = Utc::now();
+    let exp = now
         .checked_add_signed(delta)
         .ok_or(GenerateTokenError::UnexpectedError)?
         .timestamp();
@@ -45,13 +107,34 @@ fn generate_auth_token(email: &Email) -> Result<String, GenerateTokenError> {
 
     let sub = email.as_ref().to_owned();
 
-    let claims = Claims { sub, exp };
+    // A scoped token can't do more through its roles than its scope allows
+    let roles = match scope {
+        Some(_) => vec![],
+        None => roles.iter().map(|role| role.as_ref().to_owned()).collect(),
+    };
 
-    create_token(&claims).map_err(GenerateTokenError::TokenError)
+    let iat: usize = now
+        .timestamp()
+        .try_into()
+        .map_err(|_| GenerateTokenError::UnexpectedError)?;
+
+    Ok(Claims {
+        sub,
+        exp,
+        iat,
+        tenant: tenant.as_ref().to_owned(),
+        roles,
+        scope: scope.map(format_scope),
+        act: None,
+    })
 }
 
+/// Check `token` is valid for `tenant`: tokens issued for one tenant are
+/// refused by every other.
 pub async fn validate_token(
+    config: &AuthConfig,
     token: &str,
+    tenant: &TenantId,
     banned_token_store: BannedTokenStoreType,
 ) -> Result<Claims, jsonwebtoken::errors::Error> {
     match banned_token_store.read().await.contains_token(token).await {
@@ -69,19 +152,46 @@ pub async fn validate_token(
         }
     }
 
-    decode::<Claims>(
+    let claims = decode::<Claims>(
         token,
-        &DecodingKey::from_secret(JWT_SECRET.as_bytes()),
+        &DecodingKey::from_secret(config.jwt_secret.as_bytes()),
         &Validation::default(),
     )
-    .map(|data| data.claims)
+    .map(|data| data.claims)?;
+
+    if claims.tenant != tenant.as_ref() {
+        return Err(jsonwebtoken::errors::Error::from(
+            jsonwebtoken::errors::ErrorKind::InvalidToken,
+        ));
+    }
+
+    // `iat` only has whole seconds, so a token issued in the same second as
+    // a subject ban is refused too
+    let banned_until = banned_token_store
+        .read()
+        .await
+        .subject_banned_until(tenant, &claims.sub)
+        .await
+        .map_err(|_| {
+            jsonwebtoken::errors::Error::from(jsonwebtoken::errors::ErrorKind::InvalidToken)
+        })?;
+    if banned_until.is_some_and(|until| claims.iat as i64 <= until.timestamp()) {
+        return Err(jsonwebtoken::errors::Error::from(
+            jsonwebtoken::errors::ErrorKind::InvalidToken,
+        ));
+    }
+
+    Ok(claims)
 }
 
-fn create_token(claims: &Claims) -> Result<String, jsonwebtoken::errors::Error> {
+fn create_token(
+    config: &AuthConfig,
+    claims: &Claims,
+) -> Result<String, jsonwebtoken::errors::Error> {
     encode(
         &jsonwebtoken::Header::default(),
         &claims,
-        &EncodingKey::from_secret(JWT_SECRET.as_bytes()),
+        &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
     )
 }
 
@@ -89,6 +199,251 @@ fn create_token(claims: &Claims) -> Result<String, jsonwebtoken::errors::Error>
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
+    // Tokens issued before this was added count as issued at the epoch
+    #[serde(default)]
+    pub iat: usize,
+    // The tenant the token was issued for; tokens from before tenants belong
+    // to the default one
+    #[serde(default = "default_tenant")]
+    pub tenant: String,
+    // The user's roles when the token was issued, unless it was scoped. Role
+    // changes take effect at the next login.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    // The only permissions the credential has, space-separated as in OAuth,
+    // when it's limited to fewer than its roles allow. Always set for API keys.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    // Set when an admin is acting as `sub`, see `generate_impersonation_token`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+}
+
+/// Who is acting for the token's subject, as in the `act` claim of RFC 8693
+/// (OAuth 2.0 Token Exchange).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Actor {
+    pub sub: String,
+}
+
+fn default_tenant() -> String {
+    DEFAULT_TENANT_ID.to_owned()
+}
+
+impl Claims {
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    pub fn is_impersonated(&self) -> bool {
+        self.act.is_some()
+    }
+
+    /// Who is really making the request, for the audit log: the admin named
+    /// in `act` for impersonation tokens, otherwise the subject.
+    pub fn actor(&self) -> &str {
+        self.act.as_ref().map_or(&self.sub, |act| &act.sub)
+    }
+}
+
+/// Refuse (403) impersonation tokens on sensitive routes, e.g. ones that
+/// change credentials or delete accounts: an admin can see what a user sees,
+/// but not act as them there.
+pub fn forbid_impersonation(claims: &Claims) -> Result<(), AuthAPIError> {
+    if claims.is_impersonated() {
+        Err(AuthAPIError::Forbidden)
+    } else {
+        Ok(())
+    }
+}
+
+/// Permissions as a space-separated OAuth `scope`.
+pub fn format_scope(scopes: &[Permission]) -> String {
+    scopes
+        .iter()
+        .map(|scope| scope.as_ref())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The permissions in a space-separated OAuth `scope`, skipping any that
+/// aren't valid permission names.
+pub fn parse_scope(scope: &str) -> Vec<Permission> {
+    scope
+        .split_whitespace()
+        .filter_map(|scope| Permission::parse(scope.to_owned()).ok())
+        .collect()
+}
+
+/// The scope to issue a token for `roles` with, when the client asked for
+/// `requested`: just the requested permissions the roles allow. Asking for
+/// nothing gets an unscoped token, with everything they allow.
+pub async fn grant_scope(
+    state: &AppState,
+    roles: &[Role],
+    requested: Option<&str>,
+) -> Result<Option<Vec<Permission>>, AuthAPIError> {
+    let Some(requested) = requested else {
+        return Ok(None);
+    };
+
+    let permissions = state
+        .user_store
+        .read()
+        .await
+        .get_permissions(roles)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)?;
+
+    let mut scopes: Vec<Permission> = parse_scope(requested)
+        .into_iter()
+        .filter(|scope| permissions.contains(scope))
+        .collect();
+    scopes.sort();
+    scopes.dedup();
+    Ok(Some(scopes))
+}
+
+/// The permissions `claims` carry: their scope if they have one, and
+/// otherwise everything their roles allow.
+pub async fn granted_scopes(
+    state: &AppState,
+    claims: &Claims,
+) -> Result<Vec<Permission>, AuthAPIError> {
+    if let Some(scope) = &claims.scope {
+        return Ok(parse_scope(scope));
+    }
+
+    let roles: Vec<Role> = claims
+        .roles
+        .iter()
+        .filter_map(|role| Role::parse(role.clone()).ok())
+        .collect();
+    state
+        .user_store
+        .read()
+        .await
+        .get_permissions(&roles)
+        .await
+        .map_err(|_| AuthAPIError::UnexpectedError)
+}
+
+/// Refuse (403) `claims` that don't carry every one of `required`.
+pub async fn require_scopes(
+    state: &AppState,
+    claims: &Claims,
+    required: &[Permission],
+) -> Result<(), AuthAPIError> {
+    if required.is_empty() {
+        return Ok(());
+    }
+
+    let granted = granted_scopes(state, claims).await?;
+    if required.iter().all(|scope| granted.contains(scope)) {
+        Ok(())
+    } else {
+        Err(AuthAPIError::Forbidden)
+    }
+}
+
+/// The credential in an `Authorization: Bearer` header, if there is one.
+pub fn bearer_credential(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, credential) = value.split_once(' ')?;
+    scheme
+        .eq_ignore_ascii_case("bearer")
+        .then(|| credential.trim())
+        .filter(|credential| !credential.is_empty())
+}
+
+/// Check a credential from a bearer header or request body, which may be
+/// an API key or a token.
+pub async fn authenticate_credential(
+    state: &AppState,
+    tenant: &TenantId,
+    credential: &str,
+) -> Result<Claims, AuthAPIError> {
+    if is_api_key(credential) {
+        return authenticate_api_key(state, tenant, credential).await;
+    }
+
+    let config = &state.config.auth;
+    validate_token(config, credential, tenant, state.banned_token_store.clone())
+        .await
+        .map_err(|_| AuthAPIError::InvalidToken)
+}
+
+/// The claims of the request's credentials, issued for the request's
+/// tenant: an `Authorization: Bearer` API key or token if there is one,
+/// and otherwise the auth cookie.
+#[derive(Debug)]
+pub struct Authenticated(pub Claims);
+
+impl FromRequestParts<AppState> for Authenticated {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let tenant = CurrentTenant::from_request_parts(parts, state).await?;
+        if let Some(credential) = bearer_credential(&parts.headers) {
+            return authenticate_credential(state, tenant.id(), credential)
+                .await
+                .map(Authenticated);
+        }
+
+        let jar = CookieJar::from_headers(&parts.headers);
+        let token = jar
+            .get(JWT_COOKIE_NAME)
+            .ok_or(AuthAPIError::InvalidToken)?
+            .value();
+
+        let config = &state.config.auth;
+        validate_token(config, token, tenant.id(), state.banned_token_store.clone())
+            .await
+            .map(Authenticated)
+            .map_err(|_| AuthAPIError::InvalidToken)
+    }
+}
+
+/// A role a route can demand with [`RequireRole`].
+pub trait RequiredRole {
+    const ROLE: &'static str;
+}
+
+pub struct AdminRole;
+
+impl RequiredRole for AdminRole {
+    const ROLE: &'static str = ADMIN_ROLE;
+}
+
+/// Like [`Authenticated`], but also refuses tokens without the role `R`,
+/// e.g. `RequireRole<AdminRole>` for `admin`.
+#[derive(Debug)]
+pub struct RequireRole<R> {
+    pub claims: Claims,
+    role: PhantomData<R>,
+}
+
+impl<R: RequiredRole> FromRequestParts<AppState> for RequireRole<R> {
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Authenticated(claims) = Authenticated::from_request_parts(parts, state).await?;
+
+        if !claims.has_role(R::ROLE) {
+            return Err(AuthAPIError::Forbidden);
+        }
+
+        Ok(RequireRole {
+            claims,
+            role: PhantomData,
+        })
+    }
 }
 
 #[cfg(test)]
@@ -97,16 +452,26 @@ mod tests {
     use tokio::sync::RwLock;
 
     use crate::{
-        domain::BannedTokenStore,
-        services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore,
+        config::Config,
+        domain::{BannedTokenStore, Tenant},
+        services::{
+            argon2_password_hasher::Argon2PasswordHasher,
+            data_stores::{
+                hashmap_two_fa_code_store::HashmapTwoFACodeStore,
+                hashmap_user_store::HashmapUserStore,
+                hashset_banned_token_store::HashsetBannedTokenStore,
+            },
+            mock_email_client::MockEmailClient,
+        },
     };
 
     use super::*;
 
     #[tokio::test]
     async fn test_generate_auth_cookie() {
+        let tenant = TenantId::default();
         let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let cookie = generate_auth_cookie(&email).unwrap();
+        let cookie = generate_auth_cookie(&test_config(), &tenant, &email, &[], None).unwrap();
         assert_eq!(cookie.name(), JWT_COOKIE_NAME);
         assert_eq!(cookie.value().split('.').count(), 3);
         assert_eq!(cookie.path(), Some("/"));
@@ -127,17 +492,21 @@ mod tests {
 
     #[tokio::test]
     async fn test_generate_auth_token() {
+        let tenant = TenantId::default();
         let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let result = generate_auth_token(&email).unwrap();
+        let result = generate_auth_token(&test_config(), &tenant, &email, &[], None).unwrap();
         assert_eq!(result.split('.').count(), 3);
     }
 
     #[tokio::test]
     async fn test_validate_token_with_valid_token() {
+        let tenant = TenantId::default();
         let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let token = generate_auth_token(&email).unwrap();
+        let token = generate_auth_token(&test_config(), &tenant, &email, &[], None).unwrap();
         let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let result = validate_token(&token, banned_token_store).await.unwrap();
+        let result = validate_token(&test_config(), &token, &tenant, banned_token_store)
+            .await
+            .unwrap();
         assert_eq!(result.sub, "test@example.com");
 
         let exp = Utc::now()
@@ -148,22 +517,277 @@ mod tests {
         assert!(result.exp > exp as usize);
     }
 
+    #[tokio::test]
+    async fn test_validate_token_with_banned_subject() {
+        let tenant = TenantId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let token = generate_auth_token(&test_config(), &tenant, &email, &[], None).unwrap();
+        let mut hs = HashsetBannedTokenStore::default();
+        hs.ban_subject(&tenant, "test@example.com", Utc::now())
+            .await
+            .unwrap();
+        let banned_token_store = Arc::new(RwLock::new(hs));
+
+        let result =
+            validate_token(&test_config(), &token, &tenant, banned_token_store.clone()).await;
+        assert!(result.is_err());
+
+        let other = Email::parse("other@example.com".to_owned()).unwrap();
+        let token = generate_auth_token(&test_config(), &tenant, &other, &[], None).unwrap();
+        assert!(
+            validate_token(&test_config(), &token, &tenant, banned_token_store)
+                .await
+                .is_ok()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_for_another_tenant() {
+        let tenant = TenantId::default();
+        let other_tenant = TenantId::parse("acme".to_owned()).unwrap();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let token = generate_auth_token(&test_config(), &tenant, &email, &[], None).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+
+        let claims = validate_token(&test_config(), &token, &tenant, banned_token_store.clone())
+            .await
+            .unwrap();
+        assert_eq!(claims.tenant, "default");
+        assert!(
+            validate_token(&test_config(), &token, &other_tenant, banned_token_store)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_roles_are_embedded_in_token() {
+        let tenant = TenantId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let admin = Role::parse("admin".to_owned()).unwrap();
+        let token = generate_auth_token(&test_config(), &tenant, &email, &[admin], None).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+
+        let claims = validate_token(&test_config(), &token, &tenant, banned_token_store)
+            .await
+            .unwrap();
+        assert_eq!(claims.roles, vec!["admin".to_owned()]);
+        assert!(claims.has_role("admin"));
+        assert!(!claims.has_role("support"));
+    }
+
+    #[tokio::test]
+    async fn test_require_role() {
+        let tenant = TenantId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let admin = Role::parse("admin".to_owned()).unwrap();
+        let app_state = test_app_state();
+
+        let with_role = request_parts(
+            &generate_auth_token(&test_config(), &tenant, &email, &[admin], None).unwrap(),
+        );
+        let without_role = request_parts(
+            &generate_auth_token(&test_config(), &tenant, &email, &[], None).unwrap(),
+        );
+        let without_cookie = axum::http::Request::builder()
+            .extension(default_tenant_extension())
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0;
+
+        assert!(
+            RequireRole::<AdminRole>::from_request_parts(&mut { with_role }, &app_state)
+                .await
+                .is_ok()
+        );
+        assert!(matches!(
+            RequireRole::<AdminRole>::from_request_parts(&mut { without_role }, &app_state).await,
+            Err(AuthAPIError::Forbidden)
+        ));
+        assert!(matches!(
+            RequireRole::<AdminRole>::from_request_parts(&mut { without_cookie }, &app_state).await,
+            Err(AuthAPIError::InvalidToken)
+        ));
+    }
+
+    #[test]
+    fn test_bearer_credential() {
+        let headers = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+            headers
+        };
+
+        assert_eq!(bearer_credential(&headers("Bearer abc")), Some("abc"));
+        assert_eq!(bearer_credential(&headers("bearer  abc ")), Some("abc"));
+        assert_eq!(bearer_credential(&headers("Basic abc")), None);
+        assert_eq!(bearer_credential(&headers("Bearer ")), None);
+        assert_eq!(bearer_credential(&HeaderMap::new()), None);
+    }
+
+    #[tokio::test]
+    async fn test_authenticated_accepts_bearer_token() {
+        let tenant = TenantId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let token = generate_auth_token(&test_config(), &tenant, &email, &[], None).unwrap();
+        let app_state = test_app_state();
+
+        let mut parts = axum::http::Request::builder()
+            .header(header::AUTHORIZATION, format!("Bearer {}", token))
+            .extension(default_tenant_extension())
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0;
+        let Ok(Authenticated(claims)) =
+            Authenticated::from_request_parts(&mut parts, &app_state).await
+        else {
+            panic!("Bearer token was refused");
+        };
+        assert_eq!(claims.sub, "test@example.com");
+        assert_eq!(claims.scope, None);
+    }
+
+    #[test]
+    fn test_parse_and_format_scope() {
+        let scopes = parse_scope(" users:read  not-a:permission!  users:write ");
+        assert_eq!(format_scope(&scopes), "users:read users:write");
+        assert!(parse_scope("").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scoped_token_has_only_its_scope() {
+        let tenant = TenantId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let admin = Role::parse(ADMIN_ROLE.to_owned()).unwrap();
+        let scope = parse_scope("users:read");
+        let token =
+            generate_auth_token(&test_config(), &tenant, &email, &[admin], Some(&scope)).unwrap();
+        let app_state = test_app_state();
+
+        let claims = validate_token(
+            &test_config(),
+            &token,
+            &tenant,
+            app_state.banned_token_store.clone(),
+        )
+        .await
+        .unwrap();
+        assert!(claims.roles.is_empty());
+        assert_eq!(claims.scope.as_deref(), Some("users:read"));
+
+        assert!(require_scopes(&app_state, &claims, &scope).await.is_ok());
+        let write = parse_scope("users:write");
+        assert!(matches!(
+            require_scopes(&app_state, &claims, &write).await,
+            Err(AuthAPIError::Forbidden)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_impersonation_token_names_the_actor() {
+        let tenant = TenantId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let admin = Email::parse("admin@example.com".to_owned()).unwrap();
+        let token =
+            generate_impersonation_token(&test_config(), &tenant, &email, &[], &admin).unwrap();
+        let app_state = test_app_state();
+
+        let claims = validate_token(
+            &test_config(),
+            &token,
+            &tenant,
+            app_state.banned_token_store.clone(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(claims.sub, "test@example.com");
+        assert_eq!(
+            claims.act,
+            Some(Actor {
+                sub: "admin@example.com".to_owned()
+            })
+        );
+        assert_eq!(
+            claims.exp - claims.iat,
+            test_config().impersonation_ttl_seconds as usize
+        );
+        assert!(matches!(
+            forbid_impersonation(&claims),
+            Err(AuthAPIError::Forbidden)
+        ));
+    }
+
+    fn request_parts(token: &str) -> Parts {
+        axum::http::Request::builder()
+            .header("cookie", format!("{}={}", JWT_COOKIE_NAME, token))
+            .extension(default_tenant_extension())
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0
+    }
+
+    // What `resolve_tenant` adds for requests to the default tenant
+    fn default_tenant_extension() -> CurrentTenant {
+        CurrentTenant(Tenant::new(TenantId::default(), "Default"))
+    }
+
+    fn test_config() -> AuthConfig {
+        AuthConfig {
+            jwt_secret: "test-secret".to_owned(),
+            ..AuthConfig::default()
+        }
+    }
+
+    fn test_app_state() -> AppState {
+        let config = Config {
+            auth: test_config(),
+            ..Config::default()
+        };
+        AppState::new(
+            Arc::new(config),
+            Arc::new(RwLock::new(HashmapUserStore::default())),
+            Arc::new(RwLock::new(HashsetBannedTokenStore::default())),
+            Arc::new(RwLock::new(HashmapTwoFACodeStore::default())),
+            Arc::new(RwLock::new(MockEmailClient)),
+            Arc::new(Argon2PasswordHasher::default()),
+        )
+    }
+
     #[tokio::test]
     async fn test_validate_token_with_invalid_token() {
+        let tenant = TenantId::default();
         let token = "invalid_token".to_owned();
         let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
-        let result = validate_token(&token, banned_token_store).await;
+        let result = validate_token(&test_config(), &token, &tenant, banned_token_store).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_validate_token_signed_with_another_secret() {
+        let tenant = TenantId::default();
+        let email = Email::parse("test@example.com".to_owned()).unwrap();
+        let other_config = AuthConfig {
+            jwt_secret: "another-secret".to_owned(),
+            ..test_config()
+        };
+        let token = generate_auth_token(&other_config, &tenant, &email, &[], None).unwrap();
+        let banned_token_store = Arc::new(RwLock::new(HashsetBannedTokenStore::default()));
+        let result = validate_token(&test_config(), &token, &tenant, banned_token_store).await;
         assert!(result.is_err());
     }
 
     #[tokio::test]
     async fn test_validate_token_with_banned_token() {
+        let tenant = TenantId::default();
         let email = Email::parse("test@example.com".to_owned()).unwrap();
-        let token = generate_auth_token(&email).unwrap();
+        let token = generate_auth_token(&test_config(), &tenant, &email, &[], None).unwrap();
         let mut hs = HashsetBannedTokenStore::default();
         hs.add_token(token.clone()).await.unwrap();
         let banned_token_store = Arc::new(RwLock::new(hs));
-        let result = validate_token(&token, banned_token_store).await;
+        let result = validate_token(&test_config(), &token, &tenant, banned_token_store).await;
         assert!(result.is_err());
     }
 }
@@ -0,0 +1,102 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{request::Parts, HeaderMap},
+};
+
+use crate::app_state::AppState;
+
+/// The address of the client making the request.
+///
+/// Behind a reverse proxy (traefik in production) the socket peer is the proxy,
+/// so when `auth.trust_proxy_headers` is set the last `X-Forwarded-For` entry is used
+/// instead: it's the one appended by our proxy, earlier entries are client-supplied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl ClientIp {
+    fn resolve(headers: &HeaderMap, peer: Option<IpAddr>, trust_proxy_headers: bool) -> Self {
+        if trust_proxy_headers {
+            let forwarded = headers
+                .get_all("x-forwarded-for")
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .filter_map(|ip| ip.trim().parse::<IpAddr>().ok())
+                .next_back();
+
+            if forwarded.is_some() {
+                return ClientIp(forwarded);
+            }
+        }
+
+        ClientIp(peer)
+    }
+}
+
+impl fmt::Display for ClientIp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(ip) => write!(f, "{}", ip),
+            None => write!(f, "unknown"),
+        }
+    }
+}
+
+impl FromRequestParts<AppState> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        // Only present when served with `into_make_service_with_connect_info`
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+
+        let trust_proxy_headers = state.config.auth.trust_proxy_headers;
+        Ok(Self::resolve(&parts.headers, peer, trust_proxy_headers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn peer() -> Option<IpAddr> {
+        Some("10.0.0.1".parse().unwrap())
+    }
+
+    fn forwarded_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("1.1.1.1, 203.0.113.7"),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_peer_address_is_used_by_default() {
+        let ip = ClientIp::resolve(&forwarded_headers(), peer(), false);
+        assert_eq!(ip, ClientIp(peer()));
+    }
+
+    #[test]
+    fn test_last_forwarded_address_is_used_behind_proxy() {
+        let ip = ClientIp::resolve(&forwarded_headers(), peer(), true);
+        assert_eq!(ip, ClientIp(Some("203.0.113.7".parse().unwrap())));
+    }
+
+    #[test]
+    fn test_peer_address_is_used_without_forwarded_header() {
+        let ip = ClientIp::resolve(&HeaderMap::new(), peer(), true);
+        assert_eq!(ip, ClientIp(peer()));
+    }
+}
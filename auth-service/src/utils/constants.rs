@@ -1,66 +1,58 @@
-use dotenvy::dotenv;
-use lazy_static::lazy_static;
-use std::env as std_env;
-
-// Define a lazily evaluated static. lazy_static is needed because std_env::var is not a const function.
-lazy_static! {
-    pub static ref JWT_SECRET: String = set_token();
-    pub static ref DATABASE_URL: String = set_database_url();
-    pub static ref REDIS_HOSTNAME: String = set_redis_hostname();
-    pub static ref REDIS_PORT: String = set_redis_port();
-}
-
-fn set_database_url() -> String {
-    dotenv().ok(); // Load environment variables
-    let url = std_env::var(env::DATABASE_URL_ENV_VAR).expect("DATABASE_URL must be set.");
-    if url.is_empty() {
-        panic!("DATABASE_URL must not be empty.");
-    }
-    url
-}
-
-fn set_token() -> String {
-    dotenv().ok(); // Load environment variables
-    let secret = std_env::var(env::JWT_SECRET_ENV_VAR).expect("JWT_SECRET must be set.");
-    if secret.is_empty() {
-        panic!("JWT_SECRET must not be empty.");
-    }
-    secret
-}
-
-// New!
-fn set_redis_hostname() -> String {
-    dotenv().ok();
-    let hostname =
-        std_env::var(env::REDIS_HOSTNAME_ENV_VAR).unwrap_or(DEFAULT_REDIS_HOSTNAME.to_string());
-    hostname
-}
-
-fn set_redis_port() -> String {
-    dotenv().ok();
-    let port = std_env::var(env::REDIS_PORT_ENV_VAR).unwrap_or(DEFAULT_REDIS_PORT.to_string());
-    port
-}
-
 pub mod env {
+    pub const CONFIG_FILE_ENV_VAR: &str = "CONFIG_FILE";
+    pub const SERVER_ADDRESS_ENV_VAR: &str = "SERVER_ADDRESS";
+    pub const DRAIN_TIMEOUT_SECONDS_ENV_VAR: &str = "DRAIN_TIMEOUT_SECONDS";
+    pub const PRE_DRAIN_DELAY_SECONDS_ENV_VAR: &str = "PRE_DRAIN_DELAY_SECONDS";
     pub const JWT_SECRET_ENV_VAR: &str = "JWT_SECRET";
     pub const DATABASE_URL_ENV_VAR: &str = "DATABASE_URL";
     pub const REDIS_HOSTNAME_ENV_VAR: &str = "REDIS_HOSTNAME";
     pub const REDIS_PORT_ENV_VAR: &str = "REDIS_PORT";
+    pub const DATABASE_MAX_CONNECTIONS_ENV_VAR: &str = "DATABASE_MAX_CONNECTIONS";
+    pub const TOKEN_TTL_SECONDS_ENV_VAR: &str = "TOKEN_TTL_SECONDS";
+    pub const IMPERSONATION_TTL_SECONDS_ENV_VAR: &str = "IMPERSONATION_TTL_SECONDS";
+    pub const TWO_FA_CODE_TTL_MINUTES_ENV_VAR: &str = "TWO_FA_CODE_TTL_MINUTES";
+    pub const OTEL_EXPORTER_OTLP_ENDPOINT_ENV_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+    pub const OTEL_SERVICE_NAME_ENV_VAR: &str = "OTEL_SERVICE_NAME";
+    pub const ARGON2_MEMORY_COST_ENV_VAR: &str = "ARGON2_MEMORY_COST";
+    pub const ARGON2_TIME_COST_ENV_VAR: &str = "ARGON2_TIME_COST";
+    pub const ARGON2_PARALLELISM_ENV_VAR: &str = "ARGON2_PARALLELISM";
+    pub const PASSWORD_PEPPER_ENV_VAR: &str = "PASSWORD_PEPPER";
+    pub const EMAIL_LOWERCASE_LOCAL_PART_ENV_VAR: &str = "EMAIL_LOWERCASE_LOCAL_PART";
+    pub const TRUST_PROXY_HEADERS_ENV_VAR: &str = "TRUST_PROXY_HEADERS";
+    pub const LOCKOUT_BACKOFF_THRESHOLD_ENV_VAR: &str = "LOCKOUT_BACKOFF_THRESHOLD";
+    pub const LOCKOUT_THRESHOLD_ENV_VAR: &str = "LOCKOUT_THRESHOLD";
+    pub const LOCKOUT_DURATION_SECONDS_ENV_VAR: &str = "LOCKOUT_DURATION_SECONDS";
+    pub const ADMIN_API_TOKEN_ENV_VAR: &str = "ADMIN_API_TOKEN";
+    pub const AUDIT_CHECKPOINT_SIGNING_KEY_ENV_VAR: &str = "AUDIT_CHECKPOINT_SIGNING_KEY";
+    pub const AUDIT_CHECKPOINT_INTERVAL_SECONDS_ENV_VAR: &str = "AUDIT_CHECKPOINT_INTERVAL_SECONDS";
+    pub const WEBHOOK_DISPATCH_INTERVAL_SECONDS_ENV_VAR: &str = "WEBHOOK_DISPATCH_INTERVAL_SECONDS";
+    pub const ENUMERATION_RESISTANT_SIGNUP_ENV_VAR: &str = "ENUMERATION_RESISTANT_SIGNUP";
+    pub const RATE_LIMIT_LOGIN_PER_IP_ENV_VAR: &str = "RATE_LIMIT_LOGIN_PER_IP";
+    pub const RATE_LIMIT_LOGIN_PER_EMAIL_ENV_VAR: &str = "RATE_LIMIT_LOGIN_PER_EMAIL";
+    pub const RATE_LIMIT_SIGNUP_PER_IP_ENV_VAR: &str = "RATE_LIMIT_SIGNUP_PER_IP";
+    pub const RATE_LIMIT_SIGNUP_PER_EMAIL_ENV_VAR: &str = "RATE_LIMIT_SIGNUP_PER_EMAIL";
+    pub const RATE_LIMIT_VERIFY_2FA_PER_IP_ENV_VAR: &str = "RATE_LIMIT_VERIFY_2FA_PER_IP";
+    pub const RATE_LIMIT_VERIFY_2FA_PER_EMAIL_ENV_VAR: &str = "RATE_LIMIT_VERIFY_2FA_PER_EMAIL";
+    pub const SMTP_HOST_ENV_VAR: &str = "SMTP_HOST";
+    pub const SMTP_PORT_ENV_VAR: &str = "SMTP_PORT";
+    pub const SMTP_TLS_ENV_VAR: &str = "SMTP_TLS";
+    pub const SMTP_USERNAME_ENV_VAR: &str = "SMTP_USERNAME";
+    pub const SMTP_PASSWORD_ENV_VAR: &str = "SMTP_PASSWORD";
+    pub const SMTP_SENDER_ENV_VAR: &str = "SMTP_SENDER";
+    pub const SMTP_TIMEOUT_SECONDS_ENV_VAR: &str = "SMTP_TIMEOUT_SECONDS";
+    pub const SMTP_MAX_CONNECTIONS_ENV_VAR: &str = "SMTP_MAX_CONNECTIONS";
+    pub const EMAIL_OUTBOX_INTERVAL_MILLISECONDS_ENV_VAR: &str =
+        "EMAIL_OUTBOX_INTERVAL_MILLISECONDS";
+    pub const EMAIL_TEMPLATE_DIR_ENV_VAR: &str = "EMAIL_TEMPLATE_DIR";
+    pub const PRODUCT_NAME_ENV_VAR: &str = "PRODUCT_NAME";
+    pub const SMS_PROVIDER_URL_ENV_VAR: &str = "SMS_PROVIDER_URL";
+    pub const SMS_PROVIDER_TOKEN_ENV_VAR: &str = "SMS_PROVIDER_TOKEN";
+    pub const SMS_SENDER_ENV_VAR: &str = "SMS_SENDER";
+    pub const SMS_TIMEOUT_SECONDS_ENV_VAR: &str = "SMS_TIMEOUT_SECONDS";
+    pub const RATE_LIMIT_PHONE_VERIFICATION_PER_IP_ENV_VAR: &str =
+        "RATE_LIMIT_PHONE_VERIFICATION_PER_IP";
+    pub const RATE_LIMIT_PHONE_VERIFICATION_PER_EMAIL_ENV_VAR: &str =
+        "RATE_LIMIT_PHONE_VERIFICATION_PER_EMAIL";
 }
 
 pub const JWT_COOKIE_NAME: &str = "jwt";
-pub const DEFAULT_REDIS_HOSTNAME: &str = "localhost";
-pub const DEFAULT_REDIS_PORT: &str = "6379";
-
-pub mod prod {
-    pub const APP_ADDRESS: &str = "0.0.0.0:3000";
-    pub const REDIS_HOSTNAME: &str = "redis";
-    pub const REDIS_PORT: &str = "6379";
-}
-
-pub mod dev {
-    pub const APP_ADDRESS: &str = "0.0.0.0:3000";
-    pub const REDIS_HOSTNAME: &str = "localhost";
-    pub const REDIS_PORT: &str = "6379";
-}
@@ -0,0 +1,28 @@
+use crate::{
+    app_state::AppState,
+    domain::{Email, OutboxEmail},
+};
+
+use super::email_templates::EmailTemplate;
+
+/// Render `template` and queue it for `recipient`. Sending happens later, in
+/// the outbox worker, so a mail server that is down doesn't fail the request.
+///
+/// Queuing again with the same `idempotency_key` does nothing.
+#[tracing::instrument(skip_all)]
+pub async fn queue_email(
+    app_state: &AppState,
+    recipient: &Email,
+    template: EmailTemplate,
+    idempotency_key: String,
+) -> Result<(), String> {
+    let message = app_state.email_templates.render(&template)?;
+
+    app_state
+        .email_outbox_store
+        .write()
+        .await
+        .enqueue(OutboxEmail::new(idempotency_key, recipient, message))
+        .await
+        .map_err(|e| format!("Failed to queue email: {:?}", e))
+}
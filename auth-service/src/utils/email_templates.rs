@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use minijinja::{Environment, Value};
+
+use crate::config::EmailConfig;
+use crate::domain::EmailMessage;
+
+pub const DEFAULT_PRODUCT_NAME: &str = "Auth Service";
+
+macro_rules! built_in {
+    ($name:literal) => {
+        (
+            $name,
+            include_str!(concat!("../../templates/email/", $name)),
+        )
+    };
+}
+
+// Every template that can be overridden, with the copy compiled into the
+// binary. `layout.html` is extended by the other HTML templates.
+const BUILT_IN_TEMPLATES: &[(&str, &str)] = &[
+    built_in!("layout.html"),
+    built_in!("two_fa_code.subject.txt"),
+    built_in!("two_fa_code.txt"),
+    built_in!("two_fa_code.html"),
+    built_in!("welcome.subject.txt"),
+    built_in!("welcome.txt"),
+    built_in!("welcome.html"),
+    built_in!("signup_attempt.subject.txt"),
+    built_in!("signup_attempt.txt"),
+    built_in!("signup_attempt.html"),
+    built_in!("password_reset.subject.txt"),
+    built_in!("password_reset.txt"),
+    built_in!("password_reset.html"),
+    built_in!("account_locked.subject.txt"),
+    built_in!("account_locked.txt"),
+    built_in!("account_locked.html"),
+];
+
+/// An email we send, with the variables its templates can use. Every
+/// template can also use `product_name`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EmailTemplate {
+    TwoFACode {
+        code: String,
+        expires_in_minutes: u64,
+        ip: Option<IpAddr>,
+    },
+    // Enumeration-resistant signup made an account, which `code` confirms
+    Welcome {
+        code: String,
+        expires_at: DateTime<Utc>,
+    },
+    // Someone signed up with an address that already has an account
+    SignupAttempt {
+        ip: Option<IpAddr>,
+    },
+    PasswordReset {
+        code: String,
+        expires_at: DateTime<Utc>,
+    },
+    AccountLocked {
+        until: DateTime<Utc>,
+        ip: Option<IpAddr>,
+    },
+}
+
+impl EmailTemplate {
+    fn name(&self) -> &'static str {
+        match self {
+            EmailTemplate::TwoFACode { .. } => "two_fa_code",
+            EmailTemplate::Welcome { .. } => "welcome",
+            EmailTemplate::SignupAttempt { .. } => "signup_attempt",
+            EmailTemplate::PasswordReset { .. } => "password_reset",
+            EmailTemplate::AccountLocked { .. } => "account_locked",
+        }
+    }
+
+    fn variables(&self) -> BTreeMap<&'static str, Value> {
+        let mut variables = BTreeMap::new();
+        match self {
+            EmailTemplate::TwoFACode {
+                code,
+                expires_in_minutes,
+                ip,
+            } => {
+                variables.insert("code", Value::from(code.as_str()));
+                variables.insert("expires_in_minutes", Value::from(*expires_in_minutes));
+                variables.insert("ip", ip_value(ip));
+            }
+            EmailTemplate::SignupAttempt { ip } => {
+                variables.insert("ip", ip_value(ip));
+            }
+            EmailTemplate::Welcome { code, expires_at }
+            | EmailTemplate::PasswordReset { code, expires_at } => {
+                variables.insert("code", Value::from(code.as_str()));
+                variables.insert("expires_at", time_value(expires_at));
+            }
+            EmailTemplate::AccountLocked { until, ip } => {
+                variables.insert("until", time_value(until));
+                variables.insert("ip", ip_value(ip));
+            }
+        }
+        variables
+    }
+}
+
+fn ip_value(ip: &Option<IpAddr>) -> Value {
+    ip.map(|ip| Value::from(ip.to_string())).unwrap_or_default()
+}
+
+fn time_value(time: &DateTime<Utc>) -> Value {
+    Value::from(time.format("%Y-%m-%d %H:%M UTC").to_string())
+}
+
+/// The subject, text and HTML templates of every email. HTML templates
+/// escape their variables, text templates don't.
+pub struct EmailTemplates {
+    env: Environment<'static>,
+    product_name: String,
+}
+
+impl Default for EmailTemplates {
+    fn default() -> Self {
+        Self::built_in(DEFAULT_PRODUCT_NAME)
+    }
+}
+
+impl EmailTemplates {
+    pub fn built_in(product_name: &str) -> Self {
+        Self::load(product_name, |_| Ok(None)).expect("Built-in email templates are invalid")
+    }
+
+    /// The templates in `email.template_dir`, if it's set, otherwise the
+    /// built-in ones.
+    pub fn from_config(config: &EmailConfig) -> Result<Self, String> {
+        match &config.template_dir {
+            Some(dir) => Self::from_dir(Path::new(dir), &config.product_name),
+            None => Ok(Self::built_in(&config.product_name)),
+        }
+    }
+
+    /// The templates in `dir`, named like the built-in ones (e.g.
+    /// `two_fa_code.html`), falling back to the built-in ones for any file
+    /// that's missing. Invalid templates are an error here rather than when
+    /// the email is sent.
+    pub fn from_dir(dir: &Path, product_name: &str) -> Result<Self, String> {
+        if !dir.is_dir() {
+            return Err(format!("{} is not a directory", dir.display()));
+        }
+        Self::load(product_name, |name| {
+            let path = dir.join(name);
+            if !path.exists() {
+                return Ok(None);
+            }
+            std::fs::read_to_string(&path)
+                .map(Some)
+                .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+        })
+    }
+
+    fn load(
+        product_name: &str,
+        override_for: impl Fn(&str) -> Result<Option<String>, String>,
+    ) -> Result<Self, String> {
+        let mut env = Environment::new();
+        env.set_trim_blocks(true);
+
+        for (name, built_in) in BUILT_IN_TEMPLATES {
+            let source = override_for(name)?.unwrap_or_else(|| built_in.to_string());
+            env.add_template_owned(*name, source)
+                .map_err(|e| format!("Invalid email template {}: {}", name, e))?;
+        }
+
+        Ok(Self {
+            env,
+            product_name: product_name.to_owned(),
+        })
+    }
+
+    // Texts and calls name the product too, though they aren't templated
+    pub fn product_name(&self) -> &str {
+        &self.product_name
+    }
+
+    pub fn render(&self, template: &EmailTemplate) -> Result<EmailMessage, String> {
+        let mut variables = template.variables();
+        variables.insert("product_name", Value::from(self.product_name.as_str()));
+
+        let name = template.name();
+        let render = |suffix: &str| {
+            let template_name = format!("{}.{}", name, suffix);
+            self.env
+                .get_template(&template_name)
+                .and_then(|template| template.render(&variables))
+                .map_err(|e| format!("Failed to render {}: {}", template_name, e))
+        };
+
+        Ok(EmailMessage {
+            // A subject is a single line, however the template ends
+            subject: render("subject.txt")?.trim().to_owned(),
+            text: render("txt")?,
+            html: Some(render("html")?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_fa_code() -> EmailTemplate {
+        EmailTemplate::TwoFACode {
+            code: "123456".to_owned(),
+            expires_in_minutes: 10,
+            ip: "203.0.113.7".parse().ok(),
+        }
+    }
+
+    fn welcome() -> EmailTemplate {
+        EmailTemplate::Welcome {
+            code: "abcdef".to_owned(),
+            expires_at: Utc::now(),
+        }
+    }
+
+    fn template_dir(files: &[(&str, &str)]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("email-templates-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        for (name, content) in files {
+            std::fs::write(dir.join(name), content).unwrap();
+        }
+        dir
+    }
+
+    #[test]
+    fn test_built_in_templates_render_text_and_html() {
+        let templates = EmailTemplates::built_in("Let's Get Rusty");
+
+        for template in [
+            two_fa_code(),
+            welcome(),
+            EmailTemplate::SignupAttempt { ip: None },
+            EmailTemplate::PasswordReset {
+                code: "abcdef".to_owned(),
+                expires_at: Utc::now(),
+            },
+            EmailTemplate::AccountLocked {
+                until: Utc::now(),
+                ip: None,
+            },
+        ] {
+            let message = templates.render(&template).unwrap();
+            assert!(!message.subject.is_empty());
+            assert!(!message.subject.contains('\n'));
+            assert!(!message.text.is_empty());
+            assert!(message.html.unwrap().contains("Let&#x27;s Get Rusty"));
+        }
+
+        let message = templates.render(&two_fa_code()).unwrap();
+        assert_eq!(message.subject, "Your Let's Get Rusty login code");
+        assert!(message.text.contains("123456"));
+        assert!(message.text.contains("10 minutes"));
+        assert!(message.text.contains("203.0.113.7"));
+        assert!(message.html.unwrap().contains("123456"));
+    }
+
+    #[test]
+    fn test_templates_can_be_overridden_from_a_directory() {
+        let dir = template_dir(&[
+            ("two_fa_code.subject.txt", "Code for {{ product_name }}\n"),
+            ("two_fa_code.html", "<b>{{ code }}</b>"),
+        ]);
+        let templates = EmailTemplates::from_dir(&dir, "<Acme>").unwrap();
+
+        let message = templates.render(&two_fa_code()).unwrap();
+        assert_eq!(message.subject, "Code for <Acme>");
+        assert_eq!(message.html.as_deref(), Some("<b>123456</b>"));
+        // Files that aren't in the directory are the built-in ones
+        assert!(message.text.starts_with("Your login code is 123456"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_html_templates_escape_variables() {
+        let dir = template_dir(&[("welcome.html", "{{ product_name }}")]);
+        let templates = EmailTemplates::from_dir(&dir, "<Acme>").unwrap();
+
+        let message = templates.render(&welcome()).unwrap();
+        assert_eq!(message.html.as_deref(), Some("&lt;Acme&gt;"));
+        assert!(message.text.contains("<Acme>"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_templates_are_refused_up_front() {
+        let dir = template_dir(&[("welcome.txt", "{% if %}")]);
+        assert!(EmailTemplates::from_dir(&dir, DEFAULT_PRODUCT_NAME).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(EmailTemplates::from_dir(&dir, DEFAULT_PRODUCT_NAME).is_err());
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use redis::Connection;
+use serde::Serialize;
+use sqlx::{migrate::Migrate, PgPool};
+use tokio::{sync::RwLock, time::timeout};
+
+use crate::MIGRATOR;
+
+// Well within the interval load balancers probe at
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Up,
+    Down,
+}
+
+#[derive(Serialize, Debug)]
+pub struct DependencyStatus {
+    pub status: Status,
+    // Kept vague, as readiness is public; the details are logged
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// The dependencies `/health/ready` checks. There are none by default, as
+/// the in-memory stores can't become unavailable.
+#[derive(Clone, Default)]
+pub struct HealthChecks {
+    db_pool: Option<PgPool>,
+    redis_conn: Option<Arc<RwLock<Connection>>>,
+}
+
+impl HealthChecks {
+    // Also checks every migration has been applied to the database
+    pub fn with_postgres(mut self, db_pool: PgPool) -> Self {
+        self.db_pool = Some(db_pool);
+        self
+    }
+
+    // The connection the Redis stores share, which doesn't reconnect by itself
+    pub fn with_redis(mut self, redis_conn: Arc<RwLock<Connection>>) -> Self {
+        self.redis_conn = Some(redis_conn);
+        self
+    }
+
+    /// Checks every dependency at once, each given `CHECK_TIMEOUT`.
+    pub async fn check(&self) -> BTreeMap<&'static str, DependencyStatus> {
+        let postgres = async {
+            let db_pool = self.db_pool.as_ref()?;
+            let (postgres, migrations) = tokio::join!(
+                within_timeout(ping_postgres(db_pool)),
+                within_timeout(check_migrations(db_pool)),
+            );
+            Some([("postgres", postgres), ("migrations", migrations)])
+        };
+        let redis = async {
+            let redis_conn = self.redis_conn.as_ref()?;
+            Some((
+                "redis",
+                within_timeout(ping_redis(redis_conn.clone())).await,
+            ))
+        };
+        let (postgres, redis) = tokio::join!(postgres, redis);
+
+        postgres.into_iter().flatten().chain(redis).collect()
+    }
+}
+
+async fn within_timeout(check: impl Future<Output = Result<(), String>>) -> DependencyStatus {
+    let (status, error) = match timeout(CHECK_TIMEOUT, check).await {
+        Ok(Ok(())) => (Status::Up, None),
+        Ok(Err(e)) => (Status::Down, Some(e)),
+        Err(_) => (Status::Down, Some("timed out".to_owned())),
+    };
+    DependencyStatus { status, error }
+}
+
+async fn ping_postgres(db_pool: &PgPool) -> Result<(), String> {
+    sqlx::query("SELECT 1")
+        .execute(db_pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| {
+            tracing::warn!(error = %e, "Postgres health check failed");
+            "unreachable".to_owned()
+        })
+}
+
+// Migrations are run on startup, so any pending means another instance has
+// newer ones than this one, or they failed
+async fn check_migrations(db_pool: &PgPool) -> Result<(), String> {
+    let applied = async {
+        let mut conn = db_pool.acquire().await?;
+        conn.list_applied_migrations()
+            .await
+            .map_err(sqlx::Error::from)
+    };
+    let applied: HashSet<i64> = applied
+        .await
+        .map_err(|e| {
+            tracing::warn!(error = %e, "Failed to list applied migrations");
+            "unreachable".to_owned()
+        })?
+        .into_iter()
+        .map(|migration| migration.version)
+        .collect();
+
+    let pending = MIGRATOR
+        .iter()
+        .filter(|migration| !migration.migration_type.is_down_migration())
+        .filter(|migration| !applied.contains(&migration.version))
+        .count();
+    match pending {
+        0 => Ok(()),
+        _ => Err(format!("{} pending", pending)),
+    }
+}
+
+async fn ping_redis(redis_conn: Arc<RwLock<Connection>>) -> Result<(), String> {
+    let mut conn = redis_conn.write_owned().await;
+    // The connection blocks, so it's used off the async worker threads
+    let pong =
+        tokio::task::spawn_blocking(move || redis::cmd("PING").query::<String>(&mut *conn)).await;
+
+    match pong {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => {
+            tracing::warn!(error = %e, "Redis health check failed");
+            Err("unreachable".to_owned())
+        }
+        Err(_) => Err("unreachable".to_owned()),
+    }
+}
@@ -0,0 +1,210 @@
+use std::future::Future;
+use std::time::Instant;
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+use prometheus::{
+    Error, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, Opts, Registry, TextEncoder,
+};
+
+use crate::app_state::AppState;
+
+// Argon2 is tuned to take tens to hundreds of milliseconds
+const PASSWORD_HASH_BUCKETS: [f64; 9] = [0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+// Redis calls take well under a millisecond, Postgres ones a few
+const STORE_CALL_BUCKETS: [f64; 12] = [
+    0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 1.0,
+];
+
+/// Which `AuthAPIError` a response was made from, so it can be counted.
+#[derive(Clone, Copy, Debug)]
+pub struct ErrorName(pub &'static str);
+
+/// Counters and latency histograms, served at /metrics in the Prometheus
+/// text format. Label values are always from a fixed set, never user input.
+pub struct Metrics {
+    registry: Registry,
+    logins: IntCounterVec,
+    signups: IntCounterVec,
+    two_fa_codes: IntCounterVec,
+    banned_token_hits: IntCounter,
+    errors: IntCounterVec,
+    request_duration: HistogramVec,
+    password_hash_duration: HistogramVec,
+    store_call_duration: HistogramVec,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let logins = IntCounterVec::new(
+            Opts::new("auth_logins_total", "Login attempts by outcome"),
+            &["outcome"],
+        )
+        .unwrap();
+        let signups = IntCounterVec::new(
+            Opts::new("auth_signups_total", "Signups by outcome"),
+            &["outcome"],
+        )
+        .unwrap();
+        let two_fa_codes = IntCounterVec::new(
+            Opts::new(
+                "auth_two_fa_codes_total",
+                "2FA codes sent, and attempts to verify them",
+            ),
+            &["event"],
+        )
+        .unwrap();
+        let banned_token_hits = IntCounter::new(
+            "auth_banned_token_hits_total",
+            "Tokens refused because they were logged out",
+        )
+        .unwrap();
+        let errors = IntCounterVec::new(
+            Opts::new("auth_errors_total", "Error responses by error"),
+            &["error"],
+        )
+        .unwrap();
+        let request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "auth_http_request_duration_seconds",
+                "Time to handle a request, by route",
+            ),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let password_hash_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "auth_password_hash_duration_seconds",
+                "Time to hash or verify a password",
+            )
+            .buckets(PASSWORD_HASH_BUCKETS.to_vec()),
+            &["operation"],
+        )
+        .unwrap();
+        let store_call_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "auth_store_call_duration_seconds",
+                "Time taken by store calls, by store and method",
+            )
+            .buckets(STORE_CALL_BUCKETS.to_vec()),
+            &["store", "operation"],
+        )
+        .unwrap();
+
+        // Registering only fails for duplicate names, which these don't have
+        registry.register(Box::new(logins.clone())).unwrap();
+        registry.register(Box::new(signups.clone())).unwrap();
+        registry.register(Box::new(two_fa_codes.clone())).unwrap();
+        registry
+            .register(Box::new(banned_token_hits.clone()))
+            .unwrap();
+        registry.register(Box::new(errors.clone())).unwrap();
+        registry
+            .register(Box::new(request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(password_hash_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(store_call_duration.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            logins,
+            signups,
+            two_fa_codes,
+            banned_token_hits,
+            errors,
+            request_duration,
+            password_hash_duration,
+            store_call_duration,
+        }
+    }
+
+    /// Every metric, in the Prometheus text format.
+    pub fn render(&self) -> Result<String, Error> {
+        TextEncoder::new().encode_to_string(&self.registry.gather())
+    }
+
+    pub fn record_login(&self, outcome: &str) {
+        self.logins.with_label_values(&[outcome]).inc();
+    }
+
+    pub fn record_signup(&self, outcome: &str) {
+        self.signups.with_label_values(&[outcome]).inc();
+    }
+
+    // "sent", "verified" or "failed"
+    pub fn record_two_fa_code(&self, event: &str) {
+        self.two_fa_codes.with_label_values(&[event]).inc();
+    }
+
+    pub fn record_banned_token_hit(&self) {
+        self.banned_token_hits.inc();
+    }
+
+    pub fn record_error(&self, error: ErrorName) {
+        self.errors.with_label_values(&[error.0]).inc();
+    }
+
+    pub async fn time_password_hash<F: Future>(&self, operation: &str, call: F) -> F::Output {
+        let start = Instant::now();
+        let output = call.await;
+        self.password_hash_duration
+            .with_label_values(&[operation])
+            .observe(start.elapsed().as_secs_f64());
+        output
+    }
+
+    pub async fn time_store_call<F: Future>(
+        &self,
+        store: &str,
+        operation: &str,
+        call: F,
+    ) -> F::Output {
+        let start = Instant::now();
+        let output = call.await;
+        self.store_call_duration
+            .with_label_values(&[store, operation])
+            .observe(start.elapsed().as_secs_f64());
+        output
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Times every routed request, and counts the errors responses were made
+/// from. Added as a route layer, so requests for static files and unknown
+/// paths, which could be anything, aren't given a `route` of their own.
+pub async fn track_requests(
+    State(state): State<AppState>,
+    matched_path: MatchedPath,
+    request: Request,
+    next: Next,
+) -> Response {
+    let start = Instant::now();
+    let method = request.method().clone();
+    let response = next.run(request).await;
+
+    let status = response.status();
+    state
+        .metrics
+        .request_duration
+        .with_label_values(&[method.as_str(), matched_path.as_str(), status.as_str()])
+        .observe(start.elapsed().as_secs_f64());
+    if let Some(error) = response.extensions().get::<ErrorName>() {
+        state.metrics.record_error(*error);
+    }
+
+    response
+}
@@ -1,2 +1,18 @@
+pub mod api_key;
+pub mod audit;
 pub mod auth;
+pub mod client_ip;
 pub mod constants;
+pub mod email_outbox;
+pub mod email_templates;
+pub mod health;
+pub mod metrics;
+pub mod password_reset;
+pub mod rate_limit;
+pub mod shutdown;
+pub mod sms;
+pub mod telemetry;
+pub mod tenant;
+pub mod user_agent;
+pub mod webhook_signature;
+pub mod webhooks;
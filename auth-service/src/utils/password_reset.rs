@@ -0,0 +1,33 @@
+use chrono::Duration;
+use rand::{rng, Rng};
+use sha2::{Digest, Sha256};
+
+/// How long a password reset link stays usable.
+pub const PASSWORD_RESET_TTL: Duration = Duration::hours(1);
+
+/// A new random reset token, to be sent to the user and never stored as is.
+pub fn generate_password_reset_token() -> String {
+    hex::encode(rng().random::<[u8; 32]>())
+}
+
+/// What the user store keeps instead of the token, so a leaked database
+/// can't be used to reset passwords.
+pub fn hash_password_reset_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_are_unique_and_hashed() {
+        let token = generate_password_reset_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_password_reset_token());
+
+        let hash = hash_password_reset_token(&token);
+        assert_ne!(hash, token);
+        assert_eq!(hash, hash_password_reset_token(&token));
+    }
+}
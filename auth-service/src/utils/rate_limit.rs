@@ -0,0 +1,130 @@
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Email, RateLimitDecision, RateLimitPolicy},
+};
+
+use super::client_ip::ClientIp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitedRoute {
+    Login,
+    Signup,
+    Verify2FA,
+    PhoneVerification,
+}
+
+impl RateLimitedRoute {
+    fn name(&self) -> &'static str {
+        match self {
+            RateLimitedRoute::Login => "login",
+            RateLimitedRoute::Signup => "signup",
+            RateLimitedRoute::Verify2FA => "verify_2fa",
+            RateLimitedRoute::PhoneVerification => "phone_verification",
+        }
+    }
+}
+
+// Requests are counted both per client IP (one client trying many accounts)
+// and per target email (many clients trying one account).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RouteRateLimits {
+    pub per_ip: RateLimitPolicy,
+    pub per_email: RateLimitPolicy,
+}
+
+/// The `[rate_limits]` config section. Each limit is written like `30/60`
+/// for 30 requests per 60 seconds.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    pub login_per_ip: RateLimitPolicy,
+    pub login_per_email: RateLimitPolicy,
+    pub signup_per_ip: RateLimitPolicy,
+    pub signup_per_email: RateLimitPolicy,
+    pub verify_2fa_per_ip: RateLimitPolicy,
+    pub verify_2fa_per_email: RateLimitPolicy,
+    // Each attempt sends a text, so this is about cost as much as guessing
+    pub phone_verification_per_ip: RateLimitPolicy,
+    pub phone_verification_per_email: RateLimitPolicy,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        let per_minute = |max_requests| RateLimitPolicy::new(max_requests, Duration::from_secs(60));
+        let per_hour = |max_requests| RateLimitPolicy::new(max_requests, Duration::from_secs(3600));
+
+        Self {
+            login_per_ip: per_minute(30),
+            login_per_email: per_minute(10),
+            signup_per_ip: per_hour(20),
+            signup_per_email: per_hour(5),
+            verify_2fa_per_ip: per_minute(30),
+            verify_2fa_per_email: per_minute(10),
+            phone_verification_per_ip: per_hour(20),
+            phone_verification_per_email: per_hour(5),
+        }
+    }
+}
+
+impl RateLimits {
+    pub fn for_route(&self, route: RateLimitedRoute) -> RouteRateLimits {
+        let (per_ip, per_email) = match route {
+            RateLimitedRoute::Login => (self.login_per_ip, self.login_per_email),
+            RateLimitedRoute::Signup => (self.signup_per_ip, self.signup_per_email),
+            RateLimitedRoute::Verify2FA => (self.verify_2fa_per_ip, self.verify_2fa_per_email),
+            RateLimitedRoute::PhoneVerification => (
+                self.phone_verification_per_ip,
+                self.phone_verification_per_email,
+            ),
+        };
+        RouteRateLimits { per_ip, per_email }
+    }
+}
+
+/// Return `AuthAPIError::TooManyRequests` if `client_ip` is over the route's per-IP limit.
+pub async fn enforce_ip_rate_limit(
+    app_state: &AppState,
+    route: RateLimitedRoute,
+    client_ip: &ClientIp,
+) -> Result<(), AuthAPIError> {
+    let policy = app_state.config.rate_limits.for_route(route).per_ip;
+    let key = format!("{}:ip:{}", route.name(), client_ip);
+    enforce(app_state, &key, &policy).await
+}
+
+/// Return `AuthAPIError::TooManyRequests` if `email` is over the route's per-account limit.
+pub async fn enforce_email_rate_limit(
+    app_state: &AppState,
+    route: RateLimitedRoute,
+    email: &Email,
+) -> Result<(), AuthAPIError> {
+    let policy = app_state.config.rate_limits.for_route(route).per_email;
+    let key = format!("{}:email:{}", route.name(), email.as_ref().to_lowercase());
+    enforce(app_state, &key, &policy).await
+}
+
+async fn enforce(
+    app_state: &AppState,
+    key: &str,
+    policy: &RateLimitPolicy,
+) -> Result<(), AuthAPIError> {
+    let decision = app_state
+        .rate_limiter
+        .write()
+        .await
+        .check(key, policy)
+        .await;
+
+    match decision {
+        Ok(RateLimitDecision::Limited { retry_after }) => {
+            Err(AuthAPIError::TooManyRequests(retry_after))
+        }
+        // The limiters already fall back to in-memory counters, so an error here
+        // is unexpected; don't lock everyone out because of it.
+        Ok(RateLimitDecision::Allowed) | Err(_) => Ok(()),
+    }
+}
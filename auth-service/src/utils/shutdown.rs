@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Tells whatever's watching it to stop. Clones share the one signal, so
+/// triggering any of them stops everything watching the others.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    sender: Arc<watch::Sender<bool>>,
+}
+
+impl ShutdownHandle {
+    pub fn new() -> Self {
+        Self {
+            sender: Arc::new(watch::Sender::new(false)),
+        }
+    }
+
+    pub fn shutdown(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.sender.borrow()
+    }
+
+    /// Resolves once shutdown has been triggered, straight away if it
+    /// already has been.
+    pub async fn wait(&self) {
+        let mut receiver = self.sender.subscribe();
+        // Only fails once the sender is dropped, and `self` holds it
+        let _ = receiver.wait_for(|shutting_down| *shutting_down).await;
+    }
+}
+
+impl Default for ShutdownHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Triggers `handle` on SIGINT (Ctrl+C) or, on Unix, SIGTERM, which is what
+/// `docker stop` sends.
+pub async fn shutdown_on_signal(handle: ShutdownHandle) {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!(error = %e, "Failed to listen for SIGINT");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => tracing::info!("Received SIGINT, shutting down"),
+        _ = terminate => tracing::info!("Received SIGTERM, shutting down"),
+    }
+    handle.shutdown();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_clones_share_the_signal() {
+        let handle = ShutdownHandle::new();
+        let watcher = handle.clone();
+        assert!(!watcher.is_shutting_down());
+
+        let waiting = tokio::spawn(async move { watcher.wait().await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!waiting.is_finished());
+
+        handle.shutdown();
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .expect("Still waiting after shutdown")
+            .unwrap();
+        assert!(handle.is_shutting_down());
+
+        // Later waits don't miss it
+        tokio::time::timeout(Duration::from_secs(1), handle.wait())
+            .await
+            .expect("Waited after shutdown");
+    }
+}
@@ -0,0 +1,86 @@
+use chrono::Duration;
+use rand::{rng, Rng};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    app_state::AppState,
+    domain::{PhoneNumber, SmsClientError, TwoFAChannel},
+};
+
+/// How long a code texted to verify a phone number stays usable.
+pub const PHONE_VERIFICATION_TTL: Duration = Duration::minutes(10);
+
+/// A new random six digit code, to be texted to the user.
+pub fn generate_sms_code() -> String {
+    rng().random_range(100_000..=999_999u32).to_string()
+}
+
+/// What the user store keeps instead of a phone verification code.
+pub fn hash_sms_code(code: &str) -> String {
+    hex::encode(Sha256::digest(code.as_bytes()))
+}
+
+/// Text `code` to `phone`, or call and read it out, depending on `channel`.
+pub async fn send_sms_code(
+    app_state: &AppState,
+    phone: &PhoneNumber,
+    channel: TwoFAChannel,
+    code: &str,
+    expires_in_minutes: i64,
+) -> Result<(), SmsClientError> {
+    let product_name = app_state.email_templates.product_name();
+    let sms_client = app_state.sms_client.read().await;
+
+    match channel {
+        TwoFAChannel::Sms => {
+            let text = format!(
+                "{} is your {} code. It expires in {} minutes.",
+                code, product_name, expires_in_minutes
+            );
+            sms_client.send_sms(phone, &text).await
+        }
+        TwoFAChannel::Voice => {
+            let text = format!(
+                "Your {} code is {}. Once again, your code is {}.",
+                product_name,
+                spoken_digits(code),
+                spoken_digits(code)
+            );
+            sms_client.send_voice(phone, &text).await
+        }
+        TwoFAChannel::Email => Err(SmsClientError::InvalidMessage(
+            "email is not a phone channel".to_owned(),
+        )),
+    }
+}
+
+// "123456" is read out as a number unless the digits are spaced apart
+fn spoken_digits(code: &str) -> String {
+    code.chars()
+        .map(String::from)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_six_digits_and_hashed() {
+        for _ in 0..100 {
+            let code = generate_sms_code();
+            assert_eq!(code.len(), 6);
+            assert!(code.chars().all(|c| c.is_ascii_digit()));
+        }
+
+        let hash = hash_sms_code("123456");
+        assert_ne!(hash, "123456");
+        assert_eq!(hash, hash_sms_code("123456"));
+    }
+
+    #[test]
+    fn test_digits_are_spaced_for_voice() {
+        assert_eq!(spoken_digits("123456"), "1, 2, 3, 4, 5, 6");
+    }
+}
@@ -0,0 +1,341 @@
+use std::fmt;
+
+use axum::http::Request;
+use chrono::{SecondsFormat, Utc};
+use opentelemetry::{propagation::TextMapPropagator, trace::TracerProvider as _};
+use opentelemetry_http::HeaderExtractor;
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{propagation::TraceContextPropagator, trace::SdkTracerProvider, Resource};
+use serde_json::{Map, Value};
+use tower_http::request_id::RequestId;
+use tracing::{
+    field::{Field, Visit},
+    span::Record,
+    Event, Metadata, Span, Subscriber,
+};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{
+    field::RecordFields,
+    filter::{filter_fn, FilterFn},
+    fmt::{format::Writer, FmtContext, FormatEvent, FormatFields, FormattedFields},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+    EnvFilter, Layer,
+};
+
+use crate::config::TelemetryConfig;
+
+// Postgres notices, e.g. "relation already exists, skipping", are just noise
+const DEFAULT_LOG_FILTER: &str = "info,sqlx::postgres::notice=warn";
+
+/// The instrumentation scope spans are exported under.
+pub const TRACER_NAME: &str = "auth-service";
+
+/// What secrets are logged as.
+pub const REDACTED: &str = "[redacted]";
+
+// Fields with any of these words in their name are never logged, so
+// `password`, `new_password`, `api_key` and `two_fa_code` are all redacted
+const SECRET_FIELD_WORDS: [&str; 8] = [
+    "authorization",
+    "code",
+    "cookie",
+    "key",
+    "password",
+    "pepper",
+    "secret",
+    "token",
+];
+
+pub fn is_secret_field(name: &str) -> bool {
+    name.split(['_', '.', '-'])
+        .any(|word| SECRET_FIELD_WORDS.contains(&word.to_ascii_lowercase().as_str()))
+}
+
+/// Log JSON lines to stdout, at the levels `RUST_LOG` asks for, and export
+/// spans to the OTLP collector in `config`, if any. Call once, at startup, and
+/// shut the returned provider down on exit so the last spans are sent.
+pub fn init_tracing(
+    config: &TelemetryConfig,
+) -> Result<Option<SdkTracerProvider>, ExporterBuildError> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_LOG_FILTER));
+    let provider = match &config.otlp_endpoint {
+        Some(endpoint) => Some(tracer_provider(endpoint, &config.service_name)?),
+        None => None,
+    };
+    let otel_layer = provider.as_ref().map(|provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(TRACER_NAME))
+            .with_filter(without_secret_fields())
+    });
+
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .fmt_fields(JsonFields)
+                .event_format(JsonFormat),
+        )
+        .with(otel_layer)
+        .init();
+
+    Ok(provider)
+}
+
+/// Batches spans and sends them to the OTLP/HTTP collector at `endpoint`,
+/// e.g. "http://localhost:4318".
+pub fn tracer_provider(
+    endpoint: &str,
+    service_name: &str,
+) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()?;
+
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(
+            Resource::builder()
+                .with_service_name(service_name.to_owned())
+                .build(),
+        )
+        .build())
+}
+
+/// Exported spans and events can't be redacted like log lines, so the ones
+/// with secret fields are left out instead.
+pub fn without_secret_fields() -> FilterFn<fn(&Metadata<'_>) -> bool> {
+    filter_fn(|metadata| {
+        !metadata
+            .fields()
+            .iter()
+            .any(|field| is_secret_field(field.name()))
+    })
+}
+
+/// The span a request is handled in, with the ID from its `X-Request-Id`
+/// header. Only the path is recorded, as query strings can carry tokens.
+///
+/// When the caller sent a W3C `traceparent` header, the span continues its
+/// trace, so a request can be followed from app-service into this one.
+pub fn make_request_span<B>(request: &Request<B>) -> Span {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .and_then(|id| id.header_value().to_str().ok())
+        .unwrap_or_default();
+
+    let span = tracing::info_span!(
+        "request",
+        request_id,
+        method = %request.method(),
+        path = request.uri().path(),
+    );
+    let parent = TraceContextPropagator::new().extract(&HeaderExtractor(request.headers()));
+    // Only fails when spans aren't being exported
+    let _ = span.set_parent(parent);
+    span
+}
+
+/// One JSON object per line, with the event's `fields` and the `spans` it
+/// happened in, outermost first. Secret fields are redacted in both.
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let mut fields = JsonVisitor::default();
+        event.record(&mut fields);
+
+        // Span fields were already formatted, and redacted, by `JsonFields`
+        let spans: Vec<Value> = ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| {
+                let mut object = Map::new();
+                object.insert("name".to_owned(), span.name().into());
+                if let Some(formatted) = span.extensions().get::<FormattedFields<N>>() {
+                    if let Ok(fields) = serde_json::from_str::<Map<_, _>>(&formatted.fields) {
+                        object.extend(fields);
+                    }
+                }
+                Value::Object(object)
+            })
+            .collect();
+
+        let metadata = event.metadata();
+        let line = serde_json::json!({
+            "timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            "level": metadata.level().as_str(),
+            "target": metadata.target(),
+            "fields": fields.0,
+            "spans": spans,
+        });
+        writeln!(writer, "{}", line)
+    }
+}
+
+/// Formats span fields as a JSON object, redacting secrets.
+pub struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        fields.record(&mut visitor);
+        write!(writer, "{}", Value::Object(visitor.0))
+    }
+
+    // Fields recorded after the span was created are merged into the object
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &Record<'_>,
+    ) -> fmt::Result {
+        let mut visitor = JsonVisitor(serde_json::from_str(&current.fields).unwrap_or_default());
+        fields.record(&mut visitor);
+        current.fields = Value::Object(visitor.0).to_string();
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct JsonVisitor(Map<String, Value>);
+
+impl JsonVisitor {
+    fn insert(&mut self, field: &Field, value: Value) {
+        let value = match is_secret_field(field.name()) {
+            true => Value::from(REDACTED),
+            false => value,
+        };
+        self.0.insert(field.name().to_owned(), value);
+    }
+}
+
+impl Visit for JsonVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.insert(field, Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.insert(field, Value::from(format!("{:?}", value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // The JSON lines logged while running `f`
+    fn log_lines(f: impl FnOnce()) -> Vec<Value> {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .fmt_fields(JsonFields)
+            .event_format(JsonFormat)
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, f);
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_secret_fields() {
+        for name in [
+            "password",
+            "new_password",
+            "api_key",
+            "two_fa_code",
+            "jwt.token",
+        ] {
+            assert!(is_secret_field(name), "{}", name);
+        }
+        for name in ["email", "status", "request_id", "passwords_match"] {
+            assert!(!is_secret_field(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_events_are_logged_as_json_with_secrets_redacted() {
+        let lines = log_lines(|| {
+            let span = tracing::info_span!("request", request_id = "abc", token = "t0k3n");
+            let _entered = span.enter();
+            let inner = tracing::info_span!("get_user", found = tracing::field::Empty);
+            let _entered = inner.enter();
+            inner.record("found", true);
+            tracing::warn!(
+                email = "alice@example.com",
+                password = "hunter2",
+                "Login failed"
+            );
+        });
+
+        assert_eq!(lines.len(), 1);
+        let line = &lines[0];
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["fields"]["message"], "Login failed");
+        assert_eq!(line["fields"]["email"], "alice@example.com");
+        assert_eq!(line["fields"]["password"], REDACTED);
+        assert_eq!(
+            line["spans"],
+            serde_json::json!([
+                { "name": "request", "request_id": "abc", "token": REDACTED },
+                { "name": "get_user", "found": true },
+            ])
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{header, request::Parts, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    app_state::AppState,
+    domain::{AuthAPIError, Tenant, TenantId, TenantStoreError},
+};
+
+/// Routes under `/t/{tenant}/...` are served for that tenant, e.g.
+/// `/t/acme/login` is `/login` for `acme`.
+pub const TENANT_PATH_PREFIX: &str = "/t/";
+
+/// The tenant a request is for, resolved by [`resolve_tenant`].
+#[derive(Debug, Clone)]
+pub struct CurrentTenant(pub Tenant);
+
+impl CurrentTenant {
+    pub fn id(&self) -> &TenantId {
+        &self.0.id
+    }
+}
+
+impl<S> FromRequestParts<S> for CurrentTenant
+where
+    S: Send + Sync,
+{
+    type Rejection = AuthAPIError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<CurrentTenant>()
+            .cloned()
+            .ok_or(AuthAPIError::UnexpectedError)
+    }
+}
+
+/// Work out which tenant a request is for and make it available as
+/// [`CurrentTenant`]: a `/t/{tenant}` path prefix wins, then a host mapped
+/// to a tenant, and otherwise the default tenant.
+///
+/// Runs before routing, since it strips the path prefix.
+pub async fn resolve_tenant(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let tenant = match split_tenant_path(request.uri()) {
+        Some((tenant_id, uri)) => {
+            *request.uri_mut() = uri;
+            match TenantId::parse(tenant_id) {
+                Ok(id) => state.tenant_store.read().await.get_tenant(&id).await,
+                Err(_) => Err(TenantStoreError::TenantNotFound),
+            }
+        }
+        None => {
+            let host = request
+                .headers()
+                .get(header::HOST)
+                .and_then(|host| host.to_str().ok())
+                .map(str::to_owned);
+            tenant_for_host(&state, host.as_deref()).await
+        }
+    };
+
+    match tenant {
+        Ok(tenant) => {
+            request.extensions_mut().insert(CurrentTenant(tenant));
+            next.run(request).await
+        }
+        Err(TenantStoreError::TenantNotFound) => AuthAPIError::TenantNotFound.into_response(),
+        Err(_) => AuthAPIError::UnexpectedError.into_response(),
+    }
+}
+
+async fn tenant_for_host(state: &AppState, host: Option<&str>) -> Result<Tenant, TenantStoreError> {
+    let tenant_store = state.tenant_store.read().await;
+
+    if let Some(host) = host {
+        match tenant_store.get_tenant_by_host(host).await {
+            Err(TenantStoreError::TenantNotFound) => {}
+            result => return result,
+        }
+    }
+
+    tenant_store.get_tenant(&TenantId::default()).await
+}
+
+// The tenant named by a `/t/{tenant}` prefix and the URI without it
+fn split_tenant_path(uri: &Uri) -> Option<(String, Uri)> {
+    let rest = uri.path().strip_prefix(TENANT_PATH_PREFIX)?;
+    let (tenant, path) = match rest.find('/') {
+        Some(index) => rest.split_at(index),
+        None => (rest, "/"),
+    };
+
+    let path_and_query = match uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_owned(),
+    };
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(path_and_query.parse().ok()?);
+
+    Some((tenant.to_owned(), Uri::from_parts(parts).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_tenant_path() {
+        let uri: Uri = "/t/acme/admin/users?limit=2".parse().unwrap();
+        let (tenant, uri) = split_tenant_path(&uri).unwrap();
+        assert_eq!(tenant, "acme");
+        assert_eq!(uri, "/admin/users?limit=2");
+
+        let uri: Uri = "/t/acme".parse().unwrap();
+        assert_eq!(split_tenant_path(&uri).unwrap().1, "/");
+
+        let uri: Uri = "/login".parse().unwrap();
+        assert!(split_tenant_path(&uri).is_none());
+    }
+}
@@ -0,0 +1,36 @@
+use std::convert::Infallible;
+
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+
+// Longer values are cut off, it's only kept for the audit log
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// The client's `User-Agent` header, if it sent a readable one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserAgent(pub Option<String>);
+
+impl UserAgent {
+    pub fn as_deref(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
+
+impl<S> FromRequestParts<S> for UserAgent
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let user_agent = parts
+            .headers
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_LEN).collect());
+
+        Ok(UserAgent(user_agent))
+    }
+}
@@ -0,0 +1,25 @@
+use crate::{
+    app_state::AppState,
+    domain::{Email, TenantId, WebhookEvent, WebhookEventType},
+};
+
+/// Queue `event_type` for every subscription in `tenant` that wants it. Delivery
+/// happens later, in the dispatcher.
+///
+/// Like audit events, a failure to queue is logged but doesn't fail the request.
+pub async fn publish_webhook_event(
+    app_state: &AppState,
+    tenant: &TenantId,
+    event_type: WebhookEventType,
+    email: &Email,
+) {
+    let event = WebhookEvent::new(tenant, event_type, email);
+    let mut webhook_store = app_state.webhook_store.write().await;
+    if let Err(e) = webhook_store.enqueue_event(&event).await {
+        tracing::error!(
+            error = ?e,
+            event_type = event_type.as_str(),
+            "Failed to queue webhook event"
+        );
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::{Arc, Mutex};
+
+use auth_service::domain::{
+    AuditEventType, Email, PhoneNumber, SmsClient, SmsClientError, TenantId,
+};
+use auth_service::TwoFactorAuthResponse;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::helpers::{setup_user_for_login_with_password_no_2fa, TestApp};
+
+// Keeps every text and call instead of sending it
+#[derive(Default)]
+struct RecordingSmsClient {
+    sent: Mutex<Vec<(&'static str, String, String)>>,
+}
+
+impl RecordingSmsClient {
+    fn take(&self) -> Vec<(&'static str, String, String)> {
+        std::mem::take(&mut *self.sent.lock().unwrap())
+    }
+}
+
+#[async_trait::async_trait]
+impl SmsClient for RecordingSmsClient {
+    async fn send_sms(&self, recipient: &PhoneNumber, text: &str) -> Result<(), SmsClientError> {
+        let sent = ("sms", recipient.as_ref().to_owned(), text.to_owned());
+        self.sent.lock().unwrap().push(sent);
+        Ok(())
+    }
+
+    async fn send_voice(&self, recipient: &PhoneNumber, text: &str) -> Result<(), SmsClientError> {
+        let sent = ("voice", recipient.as_ref().to_owned(), text.to_owned());
+        self.sent.lock().unwrap().push(sent);
+        Ok(())
+    }
+}
+
+async fn app_with_sms_client() -> (TestApp, Arc<RwLock<RecordingSmsClient>>) {
+    let sms_client = Arc::new(RwLock::new(RecordingSmsClient::default()));
+    let app_sms_client = sms_client.clone();
+    let app = TestApp::new_with(Uuid::new_v4().to_string(), |state| {
+        state.with_sms_client(app_sms_client)
+    })
+    .await;
+    (app, sms_client)
+}
+
+// Sign up an admin and log in as them, leaving their auth cookie in the
+// app's cookie jar. Being an admin lets the test turn on their 2FA.
+async fn log_in_as_admin(app: &TestApp) -> (String, String) {
+    let (email, password) = setup_user_for_login_with_password_no_2fa(app).await;
+
+    let role = serde_json::json!({ "email": email, "role": "admin" });
+    let response = app.post_admin_role("assign", &role).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    (email, password)
+}
+
+#[tokio::test]
+async fn should_send_2fa_codes_by_sms_to_a_verified_phone() {
+    let (mut app, sms_client) = app_with_sms_client().await;
+    let (email, password) = log_in_as_admin(&app).await;
+
+    // No phone number yet
+    let body = serde_json::json!({ "channel": "sms" });
+    let response = app.put_account_2fa_channel(&body).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    let body = serde_json::json!({ "phone": "+44 7700 900123" });
+    let response = app.post_account_phone("", &body).await;
+    assert_eq!(response.status().as_u16(), 202);
+    let sent = sms_client.read().await.take();
+    assert_eq!(sent.len(), 1);
+    let (kind, phone, text) = &sent[0];
+    assert_eq!((*kind, phone.as_str()), ("sms", "+447700900123"));
+    let code = text[..6].to_owned();
+
+    // A wrong guess uses up the code
+    let response = app
+        .post_account_phone("/verify", &serde_json::json!({ "code": "000000" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+    let response = app
+        .post_account_phone("/verify", &serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_account_phone("", &body).await;
+    assert_eq!(response.status().as_u16(), 202);
+    let code = sms_client.read().await.take()[0].2[..6].to_owned();
+    let response = app
+        .post_account_phone("/verify", &serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let verified: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(verified["phone"], "+447700900123");
+
+    let response = app
+        .put_account_2fa_channel(&serde_json::json!({ "channel": "sms" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .post_admin_user_action(&email, "2fa", &serde_json::json!({ "requires2FA": true }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let body: TwoFactorAuthResponse = response.json().await.unwrap();
+
+    // The code is texted rather than emailed
+    let (_, code) = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&TenantId::default(), &Email::parse(email.clone()).unwrap())
+        .await
+        .unwrap();
+    let sent = sms_client.read().await.take();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].0, "sms");
+    assert!(sent[0].2.contains(code.as_ref()));
+    let key = format!("two_fa_code:{}", body.login_attempt_id);
+    assert!(app
+        .email_outbox_store
+        .read()
+        .await
+        .get_email(&key)
+        .await
+        .is_err());
+
+    let events = app.audit_sink.events();
+    assert!(events
+        .iter()
+        .any(|e| e.event_type == AuditEventType::PhoneVerified));
+    let sent_event = events
+        .iter()
+        .rev()
+        .find(|e| e.event_type == AuditEventType::TwoFASent)
+        .unwrap();
+    assert_eq!(sent_event.detail.as_deref(), Some("sms"));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_read_out_2fa_codes_by_voice_call() {
+    let (mut app, sms_client) = app_with_sms_client().await;
+    let (email, password) = log_in_as_admin(&app).await;
+
+    // Landlines can be verified with a call
+    let body = serde_json::json!({ "phone": "+14155550123", "channel": "voice" });
+    let response = app.post_account_phone("", &body).await;
+    assert_eq!(response.status().as_u16(), 202);
+    let sent = sms_client.read().await.take();
+    assert_eq!(sent[0].0, "voice");
+    let code: String = sent[0]
+        .2
+        .chars()
+        .filter(char::is_ascii_digit)
+        .take(6)
+        .collect();
+    let response = app
+        .post_account_phone("/verify", &serde_json::json!({ "code": code }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .put_account_2fa_channel(&serde_json::json!({ "channel": "voice" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .post_admin_user_action(&email, "2fa", &serde_json::json!({ "requires2FA": true }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    let (_, code) = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&TenantId::default(), &Email::parse(email).unwrap())
+        .await
+        .unwrap();
+    let sent = sms_client.read().await.take();
+    assert_eq!(sent.len(), 1);
+    assert_eq!((sent[0].0, sent[0].1.as_str()), ("voice", "+14155550123"));
+    let spoken: String = sent[0].2.chars().filter(char::is_ascii_digit).collect();
+    assert_eq!(spoken, code.as_ref().repeat(2));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_invalid_phone_numbers_and_channels() {
+    let (mut app, sms_client) = app_with_sms_client().await;
+
+    // Only for signed-in users
+    let body = serde_json::json!({ "phone": "+447700900123" });
+    let response = app.post_account_phone("", &body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    log_in_as_admin(&app).await;
+
+    let test_cases = [
+        serde_json::json!({ "phone": "07700 900123" }),
+        serde_json::json!({ "phone": "+44" }),
+    ];
+    for body in test_cases {
+        let response = app.post_account_phone("", &body).await;
+        assert_eq!(response.status().as_u16(), 400, "{}", body);
+    }
+
+    let test_cases = [
+        serde_json::json!({ "phone": "+447700900123", "channel": "email" }),
+        serde_json::json!({ "phone": "+447700900123", "channel": "pigeon" }),
+    ];
+    for body in test_cases {
+        let response = app.post_account_phone("", &body).await;
+        assert_eq!(response.status().as_u16(), 400, "{}", body);
+    }
+    assert!(sms_client.read().await.take().is_empty());
+
+    let response = app
+        .put_account_2fa_channel(&serde_json::json!({ "channel": "pigeon" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 400);
+    let response = app
+        .put_account_2fa_channel(&serde_json::json!({ "channel": "email" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,528 @@
+use crate::helpers::{
+    create_admin, get_random_email, setup_user_for_login_with_password_no_2fa, TestApp,
+};
+use auth_service::domain::AuditEventType;
+use auth_service::JWT_COOKIE_NAME;
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+// Sign up a user, make them an admin and log in as them, leaving their auth
+// cookie in the app's cookie jar
+async fn log_in_as_admin(app: &TestApp) -> String {
+    let (email, password) = setup_user_for_login_with_password_no_2fa(app).await;
+
+    let role = serde_json::json!({ "email": email, "role": "admin" });
+    assert_eq!(
+        app.post_admin_role("assign", &role).await.status().as_u16(),
+        200
+    );
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    email
+}
+
+async fn login_and_get_token(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    auth_cookie.value().to_owned()
+}
+
+#[tokio::test]
+async fn should_list_search_and_paginate_users() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let admin = log_in_as_admin(&app).await;
+    for _ in 0..2 {
+        setup_user_for_login_with_password_no_2fa(&app).await;
+    }
+
+    let response = app.get_admin_users("?limit=2").await;
+    assert_eq!(response.status().as_u16(), 200);
+    let page: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(page["total"], 3);
+    assert_eq!(page["users"].as_array().unwrap().len(), 2);
+
+    let page: serde_json::Value = app
+        .get_admin_users("?offset=2&limit=2")
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["users"].as_array().unwrap().len(), 1);
+
+    let search = &admin[..8];
+    let page: serde_json::Value = app
+        .get_admin_users(&format!("?search={}", search.to_uppercase()))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(page["total"], 1);
+    assert_eq!(page["users"][0]["email"], admin.as_str());
+
+    let user: serde_json::Value = app
+        .get_admin_users(&format!("/{}", admin))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(user["email"], admin.as_str());
+    assert_eq!(user["roles"], serde_json::json!(["admin"]));
+    assert_eq!(user["disabled"], false);
+
+    let response = app
+        .get_admin_users(&format!("/{}", get_random_email()))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_403_for_non_admins_and_401_without_a_session() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+
+    assert_eq!(app.get_admin_users("").await.status().as_u16(), 401);
+
+    let (email, _) = setup_user_for_login_with_password_no_2fa(&app).await;
+    login_and_get_token(&app, &email).await;
+    assert_eq!(app.get_admin_users("").await.status().as_u16(), 403);
+    assert_eq!(app.delete_admin_user(&email).await.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_disable_and_enable_users() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, password) = setup_user_for_login_with_password_no_2fa(&app).await;
+    let token = login_and_get_token(&app, &email).await;
+    let admin = log_in_as_admin(&app).await;
+
+    let response = app
+        .post_admin_user_action(&email, "disable", &serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Their existing session ends along with future logins
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let login = serde_json::json!({ "email": email, "password": password });
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 403);
+
+    // A wrong password still gets the usual answer
+    let wrong_login = serde_json::json!({ "email": email, "password": "wrongpassword" });
+    assert_eq!(app.post_login(&wrong_login).await.status().as_u16(), 401);
+
+    // Failed logins leave the admin's cookie in place
+    let response = app
+        .post_admin_user_action(&email, "enable", &serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let events = app.audit_sink.events();
+    let disabled = events
+        .iter()
+        .find(|event| event.event_type == AuditEventType::UserDisabled)
+        .expect("No user disabled event");
+    assert_eq!(disabled.actor.as_deref(), Some(admin.as_str()));
+    assert_eq!(disabled.subject.as_deref(), Some(email.as_str()));
+    assert!(events
+        .iter()
+        .any(|event| event.event_type == AuditEventType::UserEnabled));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_revoke_sessions_and_set_2fa() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, _) = setup_user_for_login_with_password_no_2fa(&app).await;
+    let token = login_and_get_token(&app, &email).await;
+    log_in_as_admin(&app).await;
+
+    let response = app
+        .post_admin_user_action(&email, "revoke-sessions", &serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_admin_user_action(&email, "2fa", &serde_json::json!({ "requires2FA": true }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let user: serde_json::Value = app
+        .get_admin_users(&format!("/{}", email))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(user["requires2FA"], true);
+
+    let response = app
+        .post_admin_user_action(&email, "password-reset", &serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let response = app
+        .post_admin_user_action(
+            &get_random_email(),
+            "revoke-sessions",
+            &serde_json::json!({}),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_password_resets_with_a_wrong_token() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, password) = setup_user_for_login_with_password_no_2fa(&app).await;
+    log_in_as_admin(&app).await;
+
+    let response = app
+        .post_admin_user_action(&email, "password-reset", &serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let response = app
+        .post_reset_password(&serde_json::json!({
+            "email": email,
+            "token": "not-the-token",
+            "password": "new-password",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The old password keeps working
+    let login = serde_json::json!({ "email": email, "password": password });
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_hold_password_resets_to_the_tenant_minimum() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, _) = setup_user_for_login_with_password_no_2fa(&app).await;
+    let (_, admin) = create_admin(&app).await;
+
+    let settings = serde_json::json!({ "min_password_length": 12 });
+    let response = app
+        .put_admin_tenant_settings("default", &settings, &admin)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_admin_user_action(&email, "password-reset", &serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 202);
+    let token = password_reset_token(&app, &email).await;
+
+    let mut reset = serde_json::json!({
+        "email": email,
+        "token": token,
+        "password": "password456",
+    });
+    let response = app.post_reset_password(&reset).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    // The token is still good for a long enough password
+    reset["password"] = "long password 456".into();
+    let response = app.post_reset_password(&reset).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_refuse_account_changes_for_members_of_other_tenants() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, _) = setup_user_for_login_with_password_no_2fa(&app).await;
+    let (_, admin) = create_admin(&app).await;
+
+    let tenant = serde_json::json!({ "id": "acme", "name": "Acme" });
+    let response = app.post_admin_tenant("", &tenant, &admin).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let member = serde_json::json!({ "email": email });
+    let response = app
+        .post_admin_tenant("/acme/members", &member, &admin)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // They would reach into acme, which isn't this admin's to manage
+    for (action, body) in [
+        ("disable", serde_json::json!({})),
+        ("enable", serde_json::json!({})),
+        ("2fa", serde_json::json!({ "requires2FA": true })),
+    ] {
+        let response = app.post_admin_user_action(&email, action, &body).await;
+        assert_eq!(response.status().as_u16(), 403, "{}", action);
+    }
+
+    // Changes scoped to this tenant are still allowed
+    let response = app
+        .post_admin_user_action(&email, "revoke-sessions", &serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_delete_users() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, password) = setup_user_for_login_with_password_no_2fa(&app).await;
+    let admin = log_in_as_admin(&app).await;
+
+    assert_eq!(app.delete_admin_user(&email).await.status().as_u16(), 204);
+    assert_eq!(app.delete_admin_user(&email).await.status().as_u16(), 404);
+
+    let login = serde_json::json!({ "email": email, "password": password });
+    assert_eq!(app.post_login(&login).await.status().as_u16(), 401);
+
+    let deleted = app
+        .audit_sink
+        .events()
+        .into_iter()
+        .find(|event| event.event_type == AuditEventType::UserDeleted)
+        .expect("No user deleted event");
+    assert_eq!(deleted.actor.as_deref(), Some(admin.as_str()));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_impersonate_users_with_an_actor_claim() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, _) = setup_user_for_login_with_password_no_2fa(&app).await;
+    let admin = log_in_as_admin(&app).await;
+
+    let response = app
+        .post_admin_user_action(&email, "impersonate", &serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["expires_in"], 300);
+    let token = body["token"].as_str().unwrap().to_owned();
+
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let verified: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(verified["sub"], email.as_str());
+    assert_eq!(verified["impersonated"], true);
+    assert_eq!(verified["act"]["sub"], admin.as_str());
+
+    // Sensitive routes refuse it
+    let response = app
+        .http_client
+        .post(format!("{}/api-keys", &app.address))
+        .bearer_auth(&token)
+        .json(&serde_json::json!({ "name": "ci" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    let events = app.audit_sink.events();
+    let event = events
+        .iter()
+        .find(|event| event.event_type == AuditEventType::Impersonation)
+        .expect("No impersonation audit event");
+    assert_eq!(event.subject.as_deref(), Some(email.as_str()));
+    assert_eq!(event.actor.as_deref(), Some(admin.as_str()));
+
+    // Ordinary tokens aren't flagged
+    let token = login_and_get_token(&app, &email).await;
+    let verified: serde_json::Value = app
+        .post_verify_token(&serde_json::json!({ "token": token }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(verified["impersonated"], false);
+    assert!(verified.get("act").is_none());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_refuse_sensitive_admin_actions_while_impersonating() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, _) = setup_user_for_login_with_password_no_2fa(&app).await;
+    let other_admin = log_in_as_admin(&app).await;
+    log_in_as_admin(&app).await;
+
+    // Impersonating another admin gives their roles, but not on sensitive routes
+    let response = app
+        .post_admin_user_action(&other_admin, "impersonate", &serde_json::json!({}))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let token = body["token"].as_str().unwrap().to_owned();
+
+    for (path, method) in [
+        ("/password-reset", reqwest::Method::POST),
+        ("/impersonate", reqwest::Method::POST),
+        ("", reqwest::Method::DELETE),
+    ] {
+        let response = app
+            .http_client
+            .request(
+                method,
+                format!("{}/admin/users/{}{}", &app.address, email, path),
+            )
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 403, "Failed for {:?}", path);
+    }
+
+    // Nor can it change roles, webhooks or tenants
+    let role = serde_json::json!({ "email": email, "role": "admin" });
+    let webhook = serde_json::json!({ "url": "https://example.com/hook", "event_types": [] });
+    let tenant = serde_json::json!({ "id": "acme", "name": "Acme" });
+    let member = serde_json::json!({ "email": email });
+    for (method, path, body) in [
+        (
+            reqwest::Method::POST,
+            "/admin/unlock".to_owned(),
+            Some(&member),
+        ),
+        (
+            reqwest::Method::POST,
+            "/admin/roles/assign".to_owned(),
+            Some(&role),
+        ),
+        (
+            reqwest::Method::POST,
+            "/admin/roles/revoke".to_owned(),
+            Some(&role),
+        ),
+        (
+            reqwest::Method::POST,
+            "/admin/webhooks".to_owned(),
+            Some(&webhook),
+        ),
+        (
+            reqwest::Method::DELETE,
+            format!("/admin/webhooks/{}", Uuid::new_v4()),
+            None,
+        ),
+        (
+            reqwest::Method::POST,
+            "/admin/tenants".to_owned(),
+            Some(&tenant),
+        ),
+        (
+            reqwest::Method::PUT,
+            "/admin/tenants/default/settings".to_owned(),
+            Some(&serde_json::json!({})),
+        ),
+        (
+            reqwest::Method::POST,
+            "/admin/tenants/default/members".to_owned(),
+            Some(&member),
+        ),
+        (
+            reqwest::Method::DELETE,
+            format!("/admin/tenants/default/members/{}", email),
+            None,
+        ),
+    ] {
+        let mut request = app
+            .http_client
+            .request(method, format!("{}{}", &app.address, path))
+            .bearer_auth(&token);
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        let response = request.send().await.unwrap();
+        assert_eq!(response.status().as_u16(), 403, "Failed for {:?}", path);
+    }
+
+    // Reading is still allowed
+    let response = app
+        .http_client
+        .get(format!("{}/admin/users/{}", &app.address, email))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_audit_the_admin_behind_an_impersonation_token() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, _) = setup_user_for_login_with_password_no_2fa(&app).await;
+    let admin = log_in_as_admin(&app).await;
+
+    let response = app
+        .post_admin_user_action(&email, "impersonate", &serde_json::json!({}))
+        .await;
+    let body: serde_json::Value = response.json().await.unwrap();
+    let token = body["token"].as_str().unwrap().to_owned();
+
+    // Ending the impersonation session is recorded as the admin's doing
+    let response = reqwest::Client::new()
+        .post(format!("{}/logout", &app.address))
+        .header("Cookie", format!("{}={}", JWT_COOKIE_NAME, token))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let events = app.audit_sink.events();
+    let event = events
+        .iter()
+        .find(|event| event.event_type == AuditEventType::Logout)
+        .expect("No logout audit event");
+    assert_eq!(event.subject.as_deref(), Some(email.as_str()));
+    assert_eq!(event.actor.as_deref(), Some(admin.as_str()));
+
+    app.clean_up().await;
+}
+
+// The token in the password reset email sent to `email`
+async fn password_reset_token(app: &TestApp, email: &str) -> String {
+    let emails = app
+        .email_outbox_store
+        .write()
+        .await
+        .claim_due(Utc::now(), Duration::minutes(1), 100)
+        .await
+        .unwrap();
+    emails
+        .iter()
+        .filter(|e| e.recipient.as_ref() == email)
+        .flat_map(|e| e.message.text.lines())
+        .find(|line| line.len() == 64 && line.chars().all(|c| c.is_ascii_hexdigit()))
+        .expect("No password reset email")
+        .to_owned()
+}
@@ -0,0 +1,182 @@
+use crate::helpers::{setup_user_for_login_with_password_no_2fa, TestApp};
+use auth_service::domain::AuditEventType;
+use uuid::Uuid;
+
+// Sign up a user and log in as them, optionally making them an admin first,
+// leaving their auth cookie in the app's cookie jar
+async fn log_in(app: &TestApp, admin: bool) -> String {
+    let (email, password) = setup_user_for_login_with_password_no_2fa(app).await;
+
+    if admin {
+        let role = serde_json::json!({ "email": email, "role": "admin" });
+        let response = app.post_admin_role("assign", &role).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    email
+}
+
+async fn create_key(app: &TestApp, body: serde_json::Value) -> serde_json::Value {
+    let response = app.post_api_key(&body).await;
+    assert_eq!(response.status().as_u16(), 201);
+    response.json().await.unwrap()
+}
+
+async fn verify_with_key(app: &TestApp, key: &str, body: serde_json::Value) -> u16 {
+    app.http_client
+        .post(format!("{}/verify_token", &app.address))
+        .bearer_auth(key)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn should_create_list_and_revoke_api_keys() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let email = log_in(&app, true).await;
+
+    let created = create_key(
+        &app,
+        serde_json::json!({ "name": " ci ", "scopes": ["users:read"], "expires_in_days": 30 }),
+    )
+    .await;
+    let key = created["key"].as_str().unwrap().to_owned();
+    assert!(key.starts_with("ak_"));
+    assert_eq!(created["name"], "ci");
+    assert_eq!(created["scopes"], serde_json::json!(["users:read"]));
+    assert!(created["last_used_at"].is_null());
+
+    // The key acts only within its scopes, and has no roles
+    let body = serde_json::json!({ "required_permission": "users:read" });
+    assert_eq!(verify_with_key(&app, &key, body).await, 200);
+    let body = serde_json::json!({ "required_permission": "users:write" });
+    assert_eq!(verify_with_key(&app, &key, body).await, 403);
+    let body = serde_json::json!({ "required_role": "admin" });
+    assert_eq!(verify_with_key(&app, &key, body).await, 403);
+
+    // No body is needed with a bearer credential
+    let response = app
+        .http_client
+        .post(format!("{}/verify_token", &app.address))
+        .bearer_auth(&key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The key is never shown again
+    let response = app.get_api_keys().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let keys: Vec<serde_json::Value> = response.json().await.unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0]["id"], created["id"]);
+    assert!(keys[0].get("key").is_none());
+    assert!(keys[0]["last_used_at"].is_string());
+
+    let id = created["id"].as_str().unwrap();
+    let response = app.delete_api_key(id).await;
+    assert_eq!(response.status().as_u16(), 204);
+    assert_eq!(
+        verify_with_key(&app, &key, serde_json::json!({})).await,
+        401
+    );
+
+    let response = app.delete_api_key(id).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let events = app.audit_sink.events();
+    for event_type in [AuditEventType::ApiKeyCreated, AuditEventType::ApiKeyRevoked] {
+        assert!(events.iter().any(|event| event.event_type == event_type
+            && event.subject.as_deref() == Some(email.as_str())
+            && event.detail.as_deref() == Some(id)));
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_invalid_api_keys() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+
+    let response = app.post_api_key(&serde_json::json!({ "name": "ci" })).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    log_in(&app, false).await;
+
+    let test_cases = [
+        serde_json::json!({ "name": " " }),
+        serde_json::json!({ "name": "ci", "expires_in_days": 0 }),
+        serde_json::json!({ "name": "ci", "expires_in_days": 366 }),
+        serde_json::json!({ "name": "ci", "scopes": ["not a permission"] }),
+    ];
+    for body in test_cases {
+        let response = app.post_api_key(&body).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            body
+        );
+    }
+
+    // Keys can't have permissions their owner doesn't
+    let body = serde_json::json!({ "name": "ci", "scopes": ["users:read"] });
+    let response = app.post_api_key(&body).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_create_api_keys_with_an_api_key() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    log_in(&app, false).await;
+    let created = create_key(&app, serde_json::json!({ "name": "ci" })).await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/api-keys", &app.address))
+        .bearer_auth(created["key"].as_str().unwrap())
+        .json(&serde_json::json!({ "name": "another" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_refuse_api_keys_of_disabled_users() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let email = log_in(&app, false).await;
+    let created = create_key(&app, serde_json::json!({ "name": "ci" })).await;
+    let key = created["key"].as_str().unwrap();
+    assert_eq!(verify_with_key(&app, key, serde_json::json!({})).await, 200);
+
+    log_in(&app, true).await;
+    let response = app
+        .post_admin_user_action(&email, "disable", &serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    assert_eq!(verify_with_key(&app, key, serde_json::json!({})).await, 401);
+
+    // Enabling the account again doesn't bring old keys back
+    let response = app
+        .post_admin_user_action(&email, "enable", &serde_json::json!({}))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(verify_with_key(&app, key, serde_json::json!({})).await, 401);
+
+    app.clean_up().await;
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use auth_service::domain::{
+    Email, EmailClient, EmailClientError, EmailMessage, OutboxEmail, OutboxEmailStatus,
+    RetryPolicy, TenantId,
+};
+use auth_service::services::email_outbox_worker::EmailOutboxWorker;
+use auth_service::services::mock_email_client::MockEmailClient;
+use auth_service::TwoFactorAuthResponse;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::helpers::{setup_user_for_login_with_password_and_2fa, TestApp};
+
+// A mail server that is down
+struct UnavailableEmailClient;
+
+#[async_trait::async_trait]
+impl EmailClient for UnavailableEmailClient {
+    async fn send_email(
+        &self,
+        _recipient: &Email,
+        _message: &EmailMessage,
+    ) -> Result<(), EmailClientError> {
+        Err(EmailClientError::Unavailable(
+            "connection refused".to_owned(),
+        ))
+    }
+}
+
+#[tokio::test]
+async fn should_log_in_with_2fa_while_the_mail_server_is_down() {
+    let mut app = TestApp::new_with(Uuid::new_v4().to_string(), |mut state| {
+        state.email_client = Arc::new(RwLock::new(UnavailableEmailClient));
+        state
+    })
+    .await;
+    let (email, password) = setup_user_for_login_with_password_and_2fa(&app).await;
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 206);
+    let body: TwoFactorAuthResponse = response.json().await.unwrap();
+
+    // The code is queued, not sent
+    let (_, code) = app
+        .two_fa_code_store
+        .read()
+        .await
+        .get_code(&TenantId::default(), &Email::parse(email.clone()).unwrap())
+        .await
+        .unwrap();
+    let key = format!("two_fa_code:{}", body.login_attempt_id);
+    let queued = app
+        .email_outbox_store
+        .read()
+        .await
+        .get_email(&key)
+        .await
+        .unwrap();
+    assert_eq!(queued.status, OutboxEmailStatus::Pending);
+    assert_eq!(queued.recipient.as_ref(), email);
+    assert!(queued.message.text.contains(code.as_ref()));
+    assert!(queued.message.html.unwrap().contains(code.as_ref()));
+
+    let retry_policy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: chrono::Duration::zero(),
+        max_delay: chrono::Duration::zero(),
+    };
+    let worker = EmailOutboxWorker::new(
+        app.email_outbox_store.clone(),
+        Arc::new(RwLock::new(UnavailableEmailClient)),
+    )
+    .with_retry_policy(retry_policy.clone());
+    assert_eq!(worker.send_due().await, Ok(1));
+
+    let queued = app
+        .email_outbox_store
+        .read()
+        .await
+        .get_email(&key)
+        .await
+        .unwrap();
+    assert_eq!(queued.status, OutboxEmailStatus::Pending);
+    assert_eq!(queued.attempts, 1);
+    assert!(queued.last_error.unwrap().contains("connection refused"));
+
+    // Once the mail server is back the email goes out, and only its subject is kept
+    let worker = EmailOutboxWorker::new(
+        app.email_outbox_store.clone(),
+        Arc::new(RwLock::new(MockEmailClient)),
+    )
+    .with_retry_policy(retry_policy);
+    assert_eq!(worker.send_due().await, Ok(1));
+    assert_eq!(worker.send_due().await, Ok(0));
+
+    let sent = app
+        .email_outbox_store
+        .read()
+        .await
+        .get_email(&key)
+        .await
+        .unwrap();
+    assert_eq!(sent.status, OutboxEmailStatus::Sent);
+    assert_eq!(sent.attempts, 2);
+    assert!(!sent.message.subject.is_empty());
+    assert!(sent.message.text.is_empty());
+    assert!(sent.message.html.is_none());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_queue_an_email_once_per_idempotency_key() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let recipient = Email::parse("test@example.com".to_owned()).unwrap();
+
+    for text in ["first", "second"] {
+        let message = EmailMessage {
+            subject: "Subject".to_owned(),
+            text: text.to_owned(),
+            html: None,
+        };
+        app.email_outbox_store
+            .write()
+            .await
+            .enqueue(OutboxEmail::new("same-key".to_owned(), &recipient, message))
+            .await
+            .unwrap();
+    }
+
+    let queued = app
+        .email_outbox_store
+        .read()
+        .await
+        .get_email("same-key")
+        .await
+        .unwrap();
+    assert_eq!(queued.message.text, "first");
+
+    let worker = EmailOutboxWorker::new(
+        app.email_outbox_store.clone(),
+        Arc::new(RwLock::new(MockEmailClient)),
+    );
+    assert_eq!(worker.send_due().await, Ok(1));
+    assert_eq!(worker.send_due().await, Ok(0));
+
+    app.clean_up().await;
+}
@@ -13,51 +13,116 @@ use sqlx::Connection;
 use std::str::FromStr;
 
 // use auth_service::services::data_stores::hashmap_user_store::HashmapUserStore;
+use auth_service::domain::LockoutPolicy;
+use auth_service::services::argon2_password_hasher::Argon2PasswordHasher;
+use auth_service::services::data_stores::postgres_api_key_store::PostgresApiKeyStore;
+use auth_service::services::data_stores::postgres_email_outbox_store::PostgresEmailOutboxStore;
+use auth_service::services::data_stores::postgres_tenant_store::PostgresTenantStore;
 use auth_service::services::data_stores::postgres_user_store::PostgresUserStore;
+use auth_service::services::data_stores::postgres_webhook_store::PostgresWebhookStore;
 
 use auth_service::get_postgres_pool;
 use auth_service::get_redis_client;
-use auth_service::utils::constants::{DATABASE_URL, REDIS_HOSTNAME};
+use auth_service::Config;
 use sqlx::{postgres::PgConnectOptions, postgres::PgPoolOptions, Executor, PgConnection, PgPool};
 
 use auth_service::services::data_stores::redis_two_fa_code_store::RedisTwoFACodeStore;
 // use auth_service::services::data_stores::hashset_banned_token_store::HashsetBannedTokenStore;
 use auth_service::services::data_stores::redis_banned_token_store::RedisBannedTokenStore;
+use auth_service::services::data_stores::vec_audit_sink::VecAuditSink;
 use auth_service::services::mock_email_client::MockEmailClient;
+use auth_service::utils::health::HealthChecks;
+use auth_service::utils::shutdown::ShutdownHandle;
+use tokio::task::JoinHandle;
 
-use auth_service::app_state::{AppState, BannedTokenStoreType, TwoFACodeStoreType};
+use auth_service::app_state::{
+    AppState, BannedTokenStoreType, EmailOutboxStoreType, TwoFACodeStoreType, WebhookStoreType,
+};
 
 use reqwest;
 
+pub const ADMIN_API_TOKEN: &str = "test-admin-token";
+
 pub struct TestApp {
     pub address: String,
     pub cookie_jar: Arc<Jar>,
     pub banned_token_store: BannedTokenStoreType,
     pub two_fa_code_store: TwoFACodeStoreType,
+    pub audit_sink: Arc<VecAuditSink>,
+    pub webhook_store: WebhookStoreType,
+    pub email_outbox_store: EmailOutboxStoreType,
     pub http_client: reqwest::Client,
     pub db_name: String,
     pub clean_up_called: bool,
+    pub shutdown: ShutdownHandle,
+    pub server: JoinHandle<Result<(), std::io::Error>>,
 }
 
 impl TestApp {
     pub async fn new(db_name: String) -> Self {
-        let db_pool = Self::configure_postgresql(&db_name).await;
-        let user_store = Arc::new(RwLock::new(PostgresUserStore::new(db_pool)));
-        let redis_conn = get_redis_client(REDIS_HOSTNAME.to_owned())
+        Self::new_with(db_name, |app_state| app_state).await
+    }
+
+    // Like `new`, but lets a test adjust the app state before the app is built
+    pub async fn new_with(db_name: String, configure: impl FnOnce(AppState) -> AppState) -> Self {
+        let mut config = Config::load().expect("Invalid configuration");
+        // Listen on any free port
+        config.server.address = "0.0.0.0:0".to_owned();
+        // Nothing balances load onto test servers, so they start draining straight away
+        config.server.pre_drain_delay_seconds = 0;
+        let db_pool = Self::configure_postgresql(&config, &db_name).await;
+        let password_hasher = Arc::new(Argon2PasswordHasher::default());
+        // No backoff between failed logins so lockout tests don't have to wait
+        let lockout_policy = LockoutPolicy {
+            backoff_threshold: 3,
+            backoff_base: chrono::Duration::zero(),
+            lock_threshold: 5,
+            lock_duration: chrono::Duration::minutes(15),
+        };
+        let user_store = Arc::new(RwLock::new(
+            PostgresUserStore::new(db_pool.clone(), password_hasher.clone())
+                .with_lockout_policy(lockout_policy),
+        ));
+        let redis_conn = get_redis_client(&config.redis)
             .expect("Failed to get Redis client")
             .get_connection()
             .expect("Failed to get Redis connection");
         let redis_conn = Arc::new(RwLock::new(redis_conn));
-        let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(redis_conn.clone())));
-        let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(redis_conn.clone())));
+        let banned_token_store = Arc::new(RwLock::new(RedisBannedTokenStore::new(
+            redis_conn.clone(),
+            config.auth.token_ttl_seconds as u64,
+        )));
+        let two_fa_code_store = Arc::new(RwLock::new(RedisTwoFACodeStore::new(
+            redis_conn.clone(),
+            config.auth.two_fa_code_ttl_minutes * 60,
+        )));
         let email_client = Arc::new(RwLock::new(MockEmailClient::default()));
+        let audit_sink = Arc::new(VecAuditSink::default());
+        let webhook_store: WebhookStoreType =
+            Arc::new(RwLock::new(PostgresWebhookStore::new(db_pool.clone())));
+        let tenant_store = Arc::new(RwLock::new(PostgresTenantStore::new(db_pool.clone())));
+        let email_outbox_store: EmailOutboxStoreType =
+            Arc::new(RwLock::new(PostgresEmailOutboxStore::new(db_pool.clone())));
+        let api_key_store = Arc::new(RwLock::new(PostgresApiKeyStore::new(db_pool.clone())));
+        let health_checks = HealthChecks::default()
+            .with_postgres(db_pool)
+            .with_redis(redis_conn);
         let app_state = AppState::new(
+            Arc::new(config),
             user_store,
             banned_token_store.clone(),
             two_fa_code_store.clone(),
             email_client.clone(),
-        );
-        let app = Application::build(app_state, "0.0.0.0:0")
+            password_hasher,
+        )
+        .with_audit_sink(audit_sink.clone())
+        .with_webhook_store(webhook_store.clone())
+        .with_tenant_store(tenant_store)
+        .with_api_key_store(api_key_store)
+        .with_email_outbox_store(email_outbox_store.clone())
+        .with_admin_api_token(Some(ADMIN_API_TOKEN.to_owned()))
+        .with_health_checks(health_checks);
+        let app = Application::build(configure(app_state))
             .await
             .expect("Failed to build application");
 
@@ -65,30 +130,41 @@ impl TestApp {
 
         // Run the auth service in a separate async task
         // to avoid blocking the main test thread.
-        #[allow(clippy::let_underscore_future)]
-        let _ = tokio::spawn(app.run());
-
-        // create a reqwest http client instance
-        let http_client = reqwest::Client::new();
+        let shutdown = app.shutdown_handle();
+        let server = tokio::spawn(app.run());
+
+        // create a reqwest http client instance that keeps the auth cookie between requests
+        let cookie_jar = Arc::new(Jar::default());
+        let http_client = reqwest::Client::builder()
+            .cookie_provider(cookie_jar.clone())
+            .build()
+            .expect("Failed to build HTTP client");
         // Create new TestApp instance with the address and http_client
         TestApp {
             address,
-            cookie_jar: Arc::new(Jar::default()),
+            cookie_jar,
             banned_token_store: banned_token_store.clone(),
             two_fa_code_store: two_fa_code_store.clone(),
+            audit_sink,
+            webhook_store,
+            email_outbox_store,
             http_client,
             db_name,
             clean_up_called: false,
+            shutdown,
+            server,
         }
     }
 
-    pub async fn configure_postgresql(db_name: &str) -> PgPool {
+    pub async fn configure_postgresql(config: &Config, db_name: &str) -> PgPool {
         // Remove any existing database name from the URL to get the base connection string
 
-        let postgresql_conn_url = DATABASE_URL
+        let postgresql_conn_url = config
+            .database
+            .url
             .rsplit_once('/')
             .map(|(base, _)| base.to_string())
-            .unwrap_or_else(|| DATABASE_URL.to_owned());
+            .unwrap_or_else(|| config.database.url.clone());
 
         // We are creating a new database for each test case, and we need to ensure each database has a unique name!
         let db_name = Uuid::new_v4().to_string();
@@ -98,7 +174,9 @@ impl TestApp {
         let postgresql_conn_url_with_db = format!("{}/{}", postgresql_conn_url, db_name);
 
         // Create a new connection pool and return it
-        get_postgres_pool(&postgresql_conn_url_with_db)
+        let mut database = config.database.clone();
+        database.url = postgresql_conn_url_with_db;
+        get_postgres_pool(&database)
             .await
             .expect("Failed to create Postgres connection pool!")
     }
@@ -139,6 +217,23 @@ impl TestApp {
             .expect("Failed to get root")
     }
 
+    pub async fn get_health(&self, probe: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/health/{}", &self.address, probe))
+            .send()
+            .await
+            .expect("Failed to get health")
+    }
+
+    pub async fn get_metrics(&self, admin_token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/metrics", &self.address))
+            .header("X-Admin-Token", admin_token)
+            .send()
+            .await
+            .expect("Failed to get metrics")
+    }
+
     pub async fn post_signup<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -203,7 +298,241 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_admin_unlock<Body>(&self, body: &Body, admin_token: &str) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/unlock", &self.address))
+            .bearer_auth(admin_token)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // `action` is `assign` or `revoke`. Made with the admin API token, which
+    // is how the first admins are appointed
+    pub async fn post_admin_role<Body>(&self, action: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/roles/{}", &self.address, action))
+            .header("X-Admin-Token", ADMIN_API_TOKEN)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // The other /admin routes authenticate with an admin's auth token, see `create_admin`
+    pub async fn post_admin_webhook<Body>(
+        &self,
+        body: &Body,
+        admin_token: &str,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/webhooks", &self.address))
+            .bearer_auth(admin_token)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_admin_webhooks(&self, path: &str, admin_token: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/webhooks{}", &self.address, path))
+            .bearer_auth(admin_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_admin_webhook(&self, id: &str, admin_token: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/admin/webhooks/{}", &self.address, id))
+            .bearer_auth(admin_token)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // The /admin/users routes authenticate with the admin's auth cookie
+    pub async fn get_admin_users(&self, path: &str) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/admin/users{}", &self.address, path))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_user_action<Body>(
+        &self,
+        email: &str,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!(
+                "{}/admin/users/{}/{}",
+                &self.address, email, action
+            ))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_admin_user(&self, email: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/admin/users/{}", &self.address, email))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_confirm_signup<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/signup/confirm", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_reset_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/reset-password", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin_tenant<Body>(
+        &self,
+        path: &str,
+        body: &Body,
+        admin_token: &str,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/admin/tenants{}", &self.address, path))
+            .bearer_auth(admin_token)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_admin_tenant_settings<Body>(
+        &self,
+        id: &str,
+        body: &Body,
+        admin_token: &str,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .put(format!("{}/admin/tenants/{}/settings", &self.address, id))
+            .bearer_auth(admin_token)
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // The /api-keys routes authenticate with the auth cookie, like /admin/users
+    pub async fn post_api_key<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/api-keys", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn get_api_keys(&self) -> reqwest::Response {
+        self.http_client
+            .get(format!("{}/api-keys", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_api_key(&self, id: &str) -> reqwest::Response {
+        self.http_client
+            .delete(format!("{}/api-keys/{}", &self.address, id))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // The /account routes also authenticate with the auth cookie
+    pub async fn post_account_phone<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/account/phone{}", &self.address, path))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_account_2fa_channel<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .put(format!("{}/account/2fa-channel", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // POST `path` as `tenant`, e.g. `/login` goes to `/t/{tenant}/login`
+    pub async fn post_in_tenant<Body>(
+        &self,
+        tenant: &str,
+        path: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.http_client
+            .post(format!("{}/t/{}{}", &self.address, tenant, path))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub async fn clean_up(&mut self) {
+        self.shutdown.shutdown();
         delete_database(&self.db_name).await;
         self.clean_up_called = true;
     }
@@ -233,6 +562,29 @@ pub async fn setup_user_for_login_with_password_no_2fa(app: &TestApp) -> (String
     (random_email, good_password)
 }
 
+// Signs up a user, makes them an admin of the default tenant with the admin
+// API token and logs them in, returning their email and auth token
+pub async fn create_admin(app: &TestApp) -> (String, String) {
+    let (email, password) = setup_user_for_login_with_password_no_2fa(app).await;
+
+    let role = serde_json::json!({ "email": email, "role": "admin" });
+    let response = app.post_admin_role("assign", &role).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == auth_service::JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    (email, token)
+}
+
 pub async fn setup_user_for_login_with_password_and_2fa(app: &TestApp) -> (String, String) {
     let random_email = get_random_email();
     let good_password = "password123".to_string();
@@ -254,7 +606,8 @@ pub fn get_random_email() -> String {
 }
 
 pub async fn delete_database(db_name: &str) {
-    let postgresql_conn_url: String = DATABASE_URL.to_owned();
+    let config = Config::load().expect("Invalid configuration");
+    let postgresql_conn_url: String = config.database.url;
 
     let connection_options = PgConnectOptions::from_str(&postgresql_conn_url)
         .expect("Failed to parse PostgreSQL connection string");
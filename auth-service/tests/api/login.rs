@@ -1,10 +1,12 @@
 #![allow(unused_variables, unused_imports)]
 
 use crate::helpers::{
-    get_random_email, setup_user_for_login_with_password_and_2fa,
+    create_admin, get_random_email, setup_user_for_login_with_password_and_2fa,
     setup_user_for_login_with_password_no_2fa, TestApp,
 };
-use auth_service::{ErrorResponse, SignupResponse, TwoFactorAuthResponse, JWT_COOKIE_NAME};
+use auth_service::{
+    domain::AuditEventType, ErrorResponse, SignupResponse, TwoFactorAuthResponse, JWT_COOKIE_NAME,
+};
 use uuid::Uuid;
 
 #[tokio::test]
@@ -228,3 +230,97 @@ async fn should_return_206_if_valid_credentials_and_2fa_enabled() {
     
     app.clean_up().await;
 }
+
+#[tokio::test]
+async fn should_return_429_with_retry_after_if_too_many_attempts() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+
+    // No such account, so the account lockout never kicks in before the rate limit
+    let random_email = get_random_email();
+
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "wrong_password",
+    });
+
+    let mut response = app.post_login(&login_body).await;
+    for _ in 0..20 {
+        if response.status().as_u16() == 429 {
+            break;
+        }
+        assert_eq!(response.status().as_u16(), 401);
+        response = app.post_login(&login_body).await;
+    }
+
+    assert_eq!(response.status().as_u16(), 429);
+
+    let retry_after: u64 = response
+        .headers()
+        .get("retry-after")
+        .expect("No Retry-After header")
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_423_after_repeated_failures_until_unlocked() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (admin_email, admin) = create_admin(&app).await;
+
+    let (email, password) = setup_user_for_login_with_password_no_2fa(&app).await;
+
+    let bad_login_body = serde_json::json!({
+        "email": email,
+        "password": "wrongpassword",
+    });
+
+    // The test app locks accounts on the 5th consecutive failure
+    for _ in 0..4 {
+        let response = app.post_login(&bad_login_body).await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let response = app.post_login(&bad_login_body).await;
+    assert_eq!(response.status().as_u16(), 423);
+    assert_eq!(
+        response
+            .json::<ErrorResponse>()
+            .await
+            .expect("Could not deserialize response body to ErrorResponse")
+            .error,
+        "Account locked".to_owned()
+    );
+
+    // The correct password doesn't help while the account is locked
+    let login_body = serde_json::json!({
+        "email": email,
+        "password": password,
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 423);
+
+    let unlock_body = serde_json::json!({ "email": email });
+
+    let response = app.post_admin_unlock(&unlock_body, "not-a-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.post_admin_unlock(&unlock_body, &admin).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let events = app.audit_sink.events();
+    let unlocked = events
+        .iter()
+        .find(|e| e.event_type == AuditEventType::AccountUnlocked)
+        .expect("No account unlocked event");
+    assert_eq!(unlocked.subject.as_deref(), Some(email.as_str()));
+    assert_eq!(unlocked.actor.as_deref(), Some(admin_email.as_str()));
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
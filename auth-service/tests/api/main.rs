@@ -1,8 +1,18 @@
+mod account;
+mod admin_users;
+mod api_keys;
 mod droplet_integration;
+mod email_outbox;
+mod health;
 mod helpers;
 mod login;
 mod logout;
+mod metrics;
 mod root;
+mod shutdown;
 mod signup;
+mod telemetry;
+mod tenants;
 mod verify_2fa;
 mod verify_token;
+mod webhooks;
@@ -0,0 +1,58 @@
+use uuid::Uuid;
+
+use crate::helpers::{get_random_email, TestApp, ADMIN_API_TOKEN};
+
+#[tokio::test]
+async fn should_count_login_outcomes_and_errors() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+
+    let signup_body = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let login_body = serde_json::json!({
+        "email": signup_body["email"],
+        "password": "wrong-password",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app.get_metrics(ADMIN_API_TOKEN).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["content-type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain"));
+
+    let body = response.text().await.unwrap();
+    assert!(body.contains("auth_signups_total{outcome=\"created\"} 1"));
+    assert!(body.contains("auth_logins_total{outcome=\"incorrect_credentials\"} 1"));
+    assert!(body.contains("auth_errors_total{error=\"incorrect_credentials\"} 1"));
+    assert!(body.contains(
+        "auth_http_request_duration_seconds_count{method=\"POST\",route=\"/login\",status=\"401\"} 1"
+    ));
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_401_without_the_admin_api_token() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+
+    let response = app.get_metrics("wrong-token").await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .http_client
+        .get(format!("{}/metrics", &app.address))
+        .send()
+        .await
+        .expect("Failed to get metrics");
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
@@ -1,3 +1,5 @@
+use uuid::Uuid;
+
 use crate::helpers::TestApp;
 
 #[tokio::test]
@@ -14,3 +16,27 @@ async fn root_returns_auth_ui() {
     
     app.clean_up().await;
 }
+
+#[tokio::test]
+async fn should_return_a_request_id() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+
+    let response = app.get_root().await;
+    let request_id = response.headers().get("x-request-id").unwrap();
+    assert!(Uuid::parse_str(request_id.to_str().unwrap()).is_ok());
+
+    // The caller's own ID is kept, so a request can be followed across services
+    let response = app
+        .http_client
+        .get(format!("{}/", &app.address))
+        .header("x-request-id", "app-service-1234")
+        .send()
+        .await
+        .expect("Failed to get root");
+    assert_eq!(
+        response.headers().get("x-request-id").unwrap(),
+        "app-service-1234"
+    );
+
+    app.clean_up().await;
+}
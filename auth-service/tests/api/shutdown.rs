@@ -0,0 +1,142 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use auth_service::{domain::BannedTokenStore, routes::ready, Config};
+use axum::{extract::State, response::IntoResponse};
+use tokio::{sync::OwnedRwLockWriteGuard, task::JoinHandle};
+use uuid::Uuid;
+
+use crate::helpers::{setup_user_for_login_with_password_no_2fa, TestApp};
+
+// Logs a user in, then starts logging them out while the test holds the
+// banned token store, so the request stays in flight until it's let go
+async fn start_blocked_logout(
+    app: &TestApp,
+) -> (
+    OwnedRwLockWriteGuard<dyn BannedTokenStore + Send + Sync>,
+    JoinHandle<reqwest::Result<reqwest::Response>>,
+) {
+    let (email, password) = setup_user_for_login_with_password_no_2fa(app).await;
+    let login_body = serde_json::json!({ "email": email, "password": password });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let banned_token_store = app.banned_token_store.clone().write_owned().await;
+    let logout = tokio::spawn(
+        app.http_client
+            .post(format!("{}/logout", &app.address))
+            .send(),
+    );
+    // Long enough for the request to reach the store
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    (banned_token_store, logout)
+}
+
+#[tokio::test]
+async fn should_finish_requests_in_flight_before_stopping() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (banned_token_store, logout) = start_blocked_logout(&app).await;
+
+    app.shutdown.shutdown();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(!app.server.is_finished());
+
+    // New connections are refused while the request drains
+    let response = reqwest::Client::new()
+        .get(format!("{}/health/live", &app.address))
+        .send()
+        .await;
+    assert!(response.is_err());
+
+    drop(banned_token_store);
+    let response = logout.await.unwrap().expect("Logout was dropped");
+    assert_eq!(response.status().as_u16(), 200);
+
+    tokio::time::timeout(Duration::from_secs(5), &mut app.server)
+        .await
+        .expect("Server still running after requests drained")
+        .unwrap()
+        .expect("Server failed");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_stop_after_the_drain_timeout() {
+    let mut app = TestApp::new_with(Uuid::new_v4().to_string(), |mut app_state| {
+        let mut config = Config::clone(&app_state.config);
+        config.server.drain_timeout_seconds = 1;
+        app_state.config = Arc::new(config);
+        app_state
+    })
+    .await;
+    let (banned_token_store, _logout) = start_blocked_logout(&app).await;
+
+    app.shutdown.shutdown();
+    tokio::time::timeout(Duration::from_secs(5), &mut app.server)
+        .await
+        .expect("Server still running after the drain timeout")
+        .unwrap()
+        .expect("Server failed");
+
+    drop(banned_token_store);
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_fail_readiness_once_shutting_down() {
+    let mut app_state = None;
+    let mut app = TestApp::new_with(Uuid::new_v4().to_string(), |state| {
+        app_state = Some(state.clone());
+        state
+    })
+    .await;
+    let app_state = app_state.unwrap();
+
+    let response = ready(State(app_state.clone())).await.into_response();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Checked by the handler directly, as the server stops taking requests
+    app.shutdown.shutdown();
+    let response = ready(State(app_state)).await.into_response();
+    assert_eq!(response.status().as_u16(), 503);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_serving_through_the_pre_drain_delay() {
+    let mut app = TestApp::new_with(Uuid::new_v4().to_string(), |mut app_state| {
+        let mut config = Config::clone(&app_state.config);
+        config.server.pre_drain_delay_seconds = 1;
+        app_state.config = Arc::new(config);
+        app_state
+    })
+    .await;
+
+    app.shutdown.shutdown();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    // Load balancers see readiness fail while requests are still served
+    let response = reqwest::Client::new()
+        .get(format!("{}/health/ready", &app.address))
+        .send()
+        .await
+        .expect("Readiness wasn't served during the pre-drain delay");
+    assert_eq!(response.status().as_u16(), 503);
+    let response = reqwest::Client::new()
+        .get(format!("{}/health/live", &app.address))
+        .send()
+        .await
+        .expect("Liveness wasn't served during the pre-drain delay");
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(!app.server.is_finished());
+
+    tokio::time::timeout(Duration::from_secs(5), &mut app.server)
+        .await
+        .expect("Server still running after the pre-drain delay")
+        .unwrap()
+        .expect("Server failed");
+
+    app.clean_up().await;
+}
@@ -4,6 +4,8 @@
 use crate::helpers::{get_random_email, TestApp};
 use auth_service::{ErrorResponse, SignupResponse};
 use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use std::sync::Arc;
 use uuid::Uuid;
 
 #[tokio::test]
@@ -224,3 +226,182 @@ async fn should_return_400_dup_if_invalid_input() {
     
     app.clean_up().await;
 }
+
+#[tokio::test]
+async fn should_return_409_if_email_differs_only_by_case() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let signup_body = serde_json::json!({
+        "email": format!("  {}  ", random_email.to_uppercase()),
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_202_for_new_and_existing_emails_if_enumeration_resistant() {
+    let mut app = TestApp::new_with(Uuid::new_v4().to_string(), |mut app_state| {
+        let mut config = (*app_state.config).clone();
+        config.auth.enumeration_resistant_signup = true;
+        app_state.config = Arc::new(config);
+        app_state
+    })
+    .await;
+
+    let random_email = get_random_email();
+
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+
+    let first = app.post_signup(&signup_body).await;
+    assert_eq!(first.status().as_u16(), 202);
+    let first = first
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse");
+
+    // The account can't be used until the address is confirmed, so logging
+    // in doesn't show whether the email was registered before
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let token = signup_confirmation_token(&app, &random_email).await;
+
+    let response = app
+        .post_confirm_signup(&serde_json::json!({
+            "email": random_email,
+            "token": "not-the-token",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let response = app
+        .post_confirm_signup(&serde_json::json!({
+            "email": random_email,
+            "token": token,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Signing up with the registered email only tells the owner, by email
+    let second = app.post_signup(&signup_body).await;
+    assert_eq!(second.status().as_u16(), 202);
+    let second = second
+        .json::<SignupResponse>()
+        .await
+        .expect("Could not deserialize response body to SignupResponse");
+    assert_eq!(first, second);
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_not_reveal_locked_accounts_if_enumeration_resistant() {
+    let mut app = TestApp::new_with(Uuid::new_v4().to_string(), |mut app_state| {
+        let mut config = (*app_state.config).clone();
+        config.auth.enumeration_resistant_signup = true;
+        app_state.config = Arc::new(config);
+        app_state
+    })
+    .await;
+
+    let random_email = get_random_email();
+    let signup_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+        "requires2FA": false
+    });
+    let response = app.post_signup(&signup_body).await;
+    assert_eq!(response.status().as_u16(), 202);
+    let token = signup_confirmation_token(&app, &random_email).await;
+    let response = app
+        .post_confirm_signup(&serde_json::json!({ "email": random_email, "token": token }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The test app locks accounts on the 5th consecutive failure, but a
+    // registered email gets the same answers as an unknown one throughout
+    for email in [random_email.clone(), get_random_email()] {
+        let bad_login_body = serde_json::json!({
+            "email": email,
+            "password": "wrongpassword",
+        });
+        for _ in 0..6 {
+            let response = app.post_login(&bad_login_body).await;
+            assert_eq!(response.status().as_u16(), 401);
+        }
+    }
+
+    // Even the right password, while only the owner is told of the lock
+    let login_body = serde_json::json!({
+        "email": random_email,
+        "password": "password123",
+    });
+    let response = app.post_login(&login_body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let emails = app
+        .email_outbox_store
+        .write()
+        .await
+        .claim_due(Utc::now(), Duration::minutes(1), 100)
+        .await
+        .unwrap();
+    assert!(emails
+        .iter()
+        .any(|e| e.recipient.as_ref() == random_email && e.message.subject.contains("locked")));
+
+    app.clean_up().await;
+}
+
+// The code in the welcome email sent to `email`
+async fn signup_confirmation_token(app: &TestApp, email: &str) -> String {
+    let emails = app
+        .email_outbox_store
+        .write()
+        .await
+        .claim_due(Utc::now(), Duration::minutes(1), 100)
+        .await
+        .unwrap();
+    let welcome = emails
+        .iter()
+        .find(|e| e.recipient.as_ref() == email && e.message.subject.starts_with("Welcome"))
+        .expect("No welcome email");
+
+    // The code is on a line of its own
+    welcome
+        .message
+        .text
+        .lines()
+        .find(|line| line.len() == 64 && line.chars().all(|c| c.is_ascii_hexdigit()))
+        .expect("No code in the welcome email")
+        .to_owned()
+}
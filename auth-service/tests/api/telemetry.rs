@@ -0,0 +1,117 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use auth_service::utils::telemetry::{tracer_provider, without_secret_fields, TRACER_NAME};
+use axum::{body::Bytes, extract::State, routing::post, Router};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_proto::tonic::{
+    collector::trace::v1::ExportTraceServiceRequest, common::v1::any_value, trace::v1::Span,
+};
+use prost::Message;
+use tracing_subscriber::{layer::SubscriberExt, Layer};
+use uuid::Uuid;
+
+use crate::helpers::TestApp;
+
+type Exports = Arc<Mutex<Vec<ExportTraceServiceRequest>>>;
+
+// Stands in for an OTLP/HTTP collector, keeping every export it receives
+async fn start_collector() -> (String, Exports) {
+    let exports = Exports::default();
+    let router = Router::new()
+        .route(
+            "/v1/traces",
+            post(|State(exports): State<Exports>, body: Bytes| async move {
+                let export = ExportTraceServiceRequest::decode(body).expect("Invalid OTLP export");
+                exports.lock().unwrap().push(export);
+            }),
+        )
+        .with_state(exports.clone());
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+    (address, exports)
+}
+
+// The exported request spans, with the service name they were exported under
+fn request_spans(exports: &Exports) -> Vec<(String, Span)> {
+    let mut spans = Vec::new();
+    for export in exports.lock().unwrap().iter() {
+        for resource_spans in &export.resource_spans {
+            let service_name = resource_spans
+                .resource
+                .iter()
+                .flat_map(|resource| &resource.attributes)
+                .find(|attribute| attribute.key == "service.name")
+                .and_then(|attribute| attribute.value.as_ref()?.value.clone());
+            let service_name = match service_name {
+                Some(any_value::Value::StringValue(name)) => name,
+                _ => String::new(),
+            };
+            for scope_spans in &resource_spans.scope_spans {
+                for span in &scope_spans.spans {
+                    if span.name == "request" {
+                        spans.push((service_name.clone(), span.clone()));
+                    }
+                }
+            }
+        }
+    }
+    spans
+}
+
+#[tokio::test]
+async fn should_continue_the_callers_trace() {
+    let (collector, exports) = start_collector().await;
+    let provider = tracer_provider(&collector, "auth-service").unwrap();
+    // The app runs on this thread too, so it's traced by this subscriber
+    let subscriber = tracing_subscriber::registry().with(
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(TRACER_NAME))
+            .with_filter(without_secret_fields()),
+    );
+    let _subscriber = tracing::subscriber::set_default(subscriber);
+
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+
+    // As sent by app-service when it verifies a token
+    let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+    let parent_span_id = "00f067aa0ba902b7";
+    let response = app
+        .http_client
+        .post(format!("{}/verify_token", &app.address))
+        .header(
+            "traceparent",
+            format!("00-{}-{}-01", trace_id, parent_span_id),
+        )
+        .json(&serde_json::json!({ "token": "invalid" }))
+        .send()
+        .await
+        .expect("Failed to verify token");
+    assert_eq!(response.status().as_u16(), 401);
+
+    // The span ends once the response is sent, and is exported from a
+    // background thread, which has to reach the collector on this one
+    let mut spans = Vec::new();
+    for _ in 0..50 {
+        let provider = provider.clone();
+        tokio::task::spawn_blocking(move || provider.force_flush())
+            .await
+            .unwrap()
+            .expect("Failed to export spans");
+        spans = request_spans(&exports);
+        if !spans.is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    assert_eq!(spans.len(), 1);
+    let (service_name, span) = &spans[0];
+    assert_eq!(service_name, "auth-service");
+    assert_eq!(hex::encode(&span.trace_id), trace_id);
+    assert_eq!(hex::encode(&span.parent_span_id), parent_span_id);
+
+    app.clean_up().await;
+}
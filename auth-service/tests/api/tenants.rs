@@ -0,0 +1,343 @@
+use crate::helpers::{
+    create_admin, get_random_email, setup_user_for_login_with_password_no_2fa, TestApp,
+};
+use auth_service::{domain::AuditEventType, ErrorResponse, JWT_COOKIE_NAME};
+use uuid::Uuid;
+
+async fn create_tenant(app: &TestApp, admin: &str, body: serde_json::Value) {
+    let response = app.post_admin_tenant("", &body, admin).await;
+    assert_eq!(response.status().as_u16(), 201);
+}
+
+// Sign up in `tenant` and log in there, returning the email and auth token
+async fn sign_up_and_log_in(app: &TestApp, tenant: &str) -> (String, String) {
+    let email = get_random_email();
+    let credentials = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+
+    let response = app.post_in_tenant(tenant, "/signup", &credentials).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let token = log_in(app, tenant, &email).await;
+    (email, token)
+}
+
+// Log in to `tenant` as a user signed up by `sign_up_and_log_in`, returning
+// their auth token
+async fn log_in(app: &TestApp, tenant: &str, email: &str) -> String {
+    let credentials = serde_json::json!({ "email": email, "password": "password123" });
+    let response = app.post_in_tenant(tenant, "/login", &credentials).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+    token
+}
+
+#[tokio::test]
+async fn should_create_and_list_tenants() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (_, admin) = create_admin(&app).await;
+
+    let body = serde_json::json!({
+        "id": "acme",
+        "name": "Acme",
+        "hosts": ["Auth.Acme.Example:8443"],
+        "min_password_length": 12,
+    });
+    let response = app.post_admin_tenant("", &body, &admin).await;
+    assert_eq!(response.status().as_u16(), 201);
+    let tenant: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(tenant["hosts"], serde_json::json!(["auth.acme.example"]));
+    assert_eq!(tenant["min_password_length"], 12);
+    assert_eq!(tenant["requires2FA"], false);
+
+    let response = app.post_admin_tenant("", &body, &admin).await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    let response = app
+        .http_client
+        .get(format!("{}/admin/tenants", &app.address))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    let tenants: Vec<serde_json::Value> = response.json().await.unwrap();
+    let ids: Vec<&str> = tenants
+        .iter()
+        .map(|tenant| tenant["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, vec!["acme", "default"]);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_reject_invalid_tenants() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (_, admin) = create_admin(&app).await;
+
+    let test_cases = [
+        serde_json::json!({ "id": "Acme Corp", "name": "Acme" }),
+        serde_json::json!({ "id": "acme", "name": " " }),
+        serde_json::json!({ "id": "acme", "name": "Acme", "min_password_length": 4 }),
+    ];
+    for body in test_cases {
+        let response = app.post_admin_tenant("", &body, &admin).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "Failed for input: {:?}",
+            body
+        );
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_keep_users_and_tokens_to_their_tenant() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (_, admin) = create_admin(&app).await;
+    create_tenant(
+        &app,
+        &admin,
+        serde_json::json!({ "id": "acme", "name": "Acme" }),
+    )
+    .await;
+
+    let (email, token) = sign_up_and_log_in(&app, "acme").await;
+
+    // Not a member of the default tenant
+    let credentials = serde_json::json!({ "email": email, "password": "password123" });
+    let response = app.post_login(&credentials).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    let body = serde_json::json!({ "token": token });
+    let response = app.post_in_tenant("acme", "/verify_token", &body).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_verify_token(&body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    // Once they're a member, they can log in there too, with the same password
+    let response = app
+        .post_admin_tenant(
+            "/default/members",
+            &serde_json::json!({ "email": email }),
+            &admin,
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app.post_login(&credentials).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Signing up again anywhere is refused, the identity already exists
+    let signup = serde_json::json!({
+        "email": email,
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_signup(&signup).await;
+    assert_eq!(response.status().as_u16(), 409);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_end_sessions_when_membership_is_removed() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (_, admin) = create_admin(&app).await;
+    create_tenant(
+        &app,
+        &admin,
+        serde_json::json!({ "id": "acme", "name": "Acme" }),
+    )
+    .await;
+    let (email, token) = sign_up_and_log_in(&app, "acme").await;
+
+    let response = app
+        .http_client
+        .delete(format!(
+            "{}/admin/tenants/acme/members/{}",
+            &app.address, email
+        ))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+
+    let body = serde_json::json!({ "token": token });
+    let response = app.post_in_tenant("acme", "/verify_token", &body).await;
+    assert_eq!(response.status().as_u16(), 401);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_resolve_tenant_from_host() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (_, admin) = create_admin(&app).await;
+    create_tenant(
+        &app,
+        &admin,
+        serde_json::json!({ "id": "acme", "name": "Acme", "hosts": ["auth.acme.example"] }),
+    )
+    .await;
+    let (_, token) = sign_up_and_log_in(&app, "acme").await;
+
+    let response = app
+        .http_client
+        .post(format!("{}/verify_token", &app.address))
+        .header("Host", "AUTH.acme.example:3000")
+        .json(&serde_json::json!({ "token": token }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_apply_tenant_settings() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (_, admin) = create_admin(&app).await;
+    create_tenant(
+        &app,
+        &admin,
+        serde_json::json!({ "id": "acme", "name": "Acme", "min_password_length": 12 }),
+    )
+    .await;
+
+    let mut signup = serde_json::json!({
+        "email": get_random_email(),
+        "password": "password123",
+        "requires2FA": false,
+    });
+    let response = app.post_in_tenant("acme", "/signup", &signup).await;
+    assert_eq!(response.status().as_u16(), 400);
+
+    signup["password"] = "long password 123".into();
+    let response = app.post_in_tenant("acme", "/signup", &signup).await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    // Requiring 2FA applies to members who didn't ask for it
+    let settings = serde_json::json!({ "min_password_length": 12, "requires2FA": true });
+    let response = app
+        .put_admin_tenant_settings("acme", &settings, &admin)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app.post_in_tenant("acme", "/login", &signup).await;
+    assert_eq!(response.status().as_u16(), 206);
+
+    // Other tenants are unaffected
+    let (email, password) = setup_user_for_login_with_password_no_2fa(&app).await;
+    let response = app
+        .post_login(&serde_json::json!({ "email": email, "password": password }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_404_for_unknown_tenant() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (_, admin) = create_admin(&app).await;
+
+    let body = serde_json::json!({ "token": "token" });
+    for tenant in ["missing", "Not A Tenant"] {
+        let response = app.post_in_tenant(tenant, "/verify_token", &body).await;
+        assert_eq!(response.status().as_u16(), 404);
+        assert_eq!(
+            response.json::<ErrorResponse>().await.unwrap().error,
+            "Tenant not found"
+        );
+    }
+
+    let response = app
+        .put_admin_tenant_settings("missing", &serde_json::json!({}), &admin)
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_only_let_default_tenant_admins_manage_tenants() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (_, admin) = create_admin(&app).await;
+    create_tenant(
+        &app,
+        &admin,
+        serde_json::json!({ "id": "acme", "name": "Acme" }),
+    )
+    .await;
+
+    let body = serde_json::json!({ "id": "globex", "name": "Globex" });
+
+    // An admin of another tenant can't manage tenants, their own included
+    let (email, _) = sign_up_and_log_in(&app, "acme").await;
+    let response = app
+        .http_client
+        .post(format!("{}/t/acme/admin/roles/assign", &app.address))
+        .header("X-Admin-Token", crate::helpers::ADMIN_API_TOKEN)
+        .json(&serde_json::json!({ "email": email, "role": "admin" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    // Logging in again picks up the role
+    let token = log_in(&app, "acme", &email).await;
+    let response = app
+        .http_client
+        .post(format!("{}/t/acme/admin/tenants", &app.address))
+        .bearer_auth(&token)
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_audit_tenant_changes_with_the_admin() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, admin) = create_admin(&app).await;
+    create_tenant(
+        &app,
+        &admin,
+        serde_json::json!({ "id": "acme", "name": "Acme" }),
+    )
+    .await;
+    let settings = serde_json::json!({ "requires2FA": true });
+    let response = app
+        .put_admin_tenant_settings("acme", &settings, &admin)
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let events = app.audit_sink.events();
+    for event_type in [
+        AuditEventType::TenantCreated,
+        AuditEventType::TenantSettingsChanged,
+    ] {
+        let event = events
+            .iter()
+            .find(|event| event.event_type == event_type)
+            .unwrap_or_else(|| panic!("No {:?} event", event_type));
+        assert_eq!(event.actor.as_deref(), Some(email.as_str()));
+        assert_eq!(event.detail.as_deref(), Some("acme"));
+    }
+
+    app.clean_up().await;
+}
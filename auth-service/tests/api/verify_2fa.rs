@@ -1,4 +1,4 @@
-use auth_service::domain::{Email, LoginAttemptId, TwoFACode};
+use auth_service::domain::{Email, LoginAttemptId, TenantId, TwoFACode};
 use auth_service::utils::constants::JWT_COOKIE_NAME;
 use auth_service::{ErrorResponse, TwoFactorAuthResponse};
 use uuid::Uuid;
@@ -133,7 +133,10 @@ async fn should_return_401_if_incorrect_credentials() {
         .two_fa_code_store
         .read()
         .await
-        .get_code(&Email::parse(random_email.clone()).unwrap())
+        .get_code(
+            &TenantId::default(),
+            &Email::parse(random_email.clone()).unwrap(),
+        )
         .await
         .unwrap();
 
@@ -232,7 +235,10 @@ async fn should_return_401_if_old_code() {
         .two_fa_code_store
         .read()
         .await
-        .get_code(&Email::parse(random_email.clone()).unwrap())
+        .get_code(
+            &TenantId::default(),
+            &Email::parse(random_email.clone()).unwrap(),
+        )
         .await
         .unwrap();
 
@@ -294,7 +300,10 @@ async fn should_return_200_if_correct_code() {
         .two_fa_code_store
         .read()
         .await
-        .get_code(&Email::parse(random_email.clone()).unwrap())
+        .get_code(
+            &TenantId::default(),
+            &Email::parse(random_email.clone()).unwrap(),
+        )
         .await
         .unwrap();
 
@@ -357,7 +366,10 @@ async fn should_return_401_if_same_code_twice() {
         .two_fa_code_store
         .read()
         .await
-        .get_code(&Email::parse(random_email.clone()).unwrap())
+        .get_code(
+            &TenantId::default(),
+            &Email::parse(random_email.clone()).unwrap(),
+        )
         .await
         .unwrap();
 
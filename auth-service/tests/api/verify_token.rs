@@ -1,5 +1,6 @@
-use crate::helpers::{get_random_email, TestApp};
+use crate::helpers::{get_random_email, setup_user_for_login_with_password_no_2fa, TestApp};
 use auth_service::JWT_COOKIE_NAME;
+use uuid::Uuid;
 
 #[tokio::test]
 async fn should_return_200_valid_token_from_post_signup_and_post_login() {
@@ -122,3 +123,165 @@ async fn should_return_422_if_malformed_input() {
 
     assert_eq!(response.status().as_u16(), 200);
 }
+
+// Sign up and log in, returning the token from the auth cookie
+async fn login_and_get_token(app: &TestApp, email: &str) -> String {
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let auth_cookie = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found");
+    auth_cookie.value().to_owned()
+}
+
+#[tokio::test]
+async fn should_check_required_role_and_permission() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, _) = setup_user_for_login_with_password_no_2fa(&app).await;
+
+    let token = login_and_get_token(&app, &email).await;
+    for requirement in [
+        serde_json::json!({ "token": token, "required_role": "admin" }),
+        serde_json::json!({ "token": token, "required_permission": "users:write" }),
+    ] {
+        let response = app.post_verify_token(&requirement).await;
+        assert_eq!(response.status().as_u16(), 403);
+    }
+
+    let role = serde_json::json!({ "email": email, "role": "admin" });
+    assert_eq!(
+        app.post_admin_role("assign", &role).await.status().as_u16(),
+        200
+    );
+
+    // Roles are read when the token is issued
+    let token = login_and_get_token(&app, &email).await;
+    for (requirement, expected) in [
+        (
+            serde_json::json!({ "token": token, "required_role": "admin" }),
+            200,
+        ),
+        (
+            serde_json::json!({ "token": token, "required_permission": "users:write" }),
+            200,
+        ),
+        (
+            serde_json::json!({ "token": token, "required_role": "support" }),
+            403,
+        ),
+        (
+            serde_json::json!({ "token": token, "required_permission": "billing:read" }),
+            403,
+        ),
+    ] {
+        let response = app.post_verify_token(&requirement).await;
+        assert_eq!(response.status().as_u16(), expected, "{}", requirement);
+    }
+
+    assert_eq!(
+        app.post_admin_role("revoke", &role).await.status().as_u16(),
+        200
+    );
+    let token = login_and_get_token(&app, &email).await;
+    let response = app
+        .post_verify_token(&serde_json::json!({ "token": token, "required_role": "admin" }))
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_narrow_requested_scopes() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, password) = setup_user_for_login_with_password_no_2fa(&app).await;
+    let role = serde_json::json!({ "email": email, "role": "admin" });
+    assert_eq!(
+        app.post_admin_role("assign", &role).await.status().as_u16(),
+        200
+    );
+
+    // Scopes the user doesn't have are dropped rather than refused
+    let response = app
+        .post_login(&serde_json::json!({
+            "email": email,
+            "password": password,
+            "scope": "users:read billing:read",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let token = response
+        .cookies()
+        .find(|cookie| cookie.name() == JWT_COOKIE_NAME)
+        .expect("No auth cookie found")
+        .value()
+        .to_owned();
+
+    // A scoped token has no roles to do more with
+    for (requirement, expected) in [
+        (
+            serde_json::json!({ "required_scopes": ["users:read"] }),
+            200,
+        ),
+        (
+            serde_json::json!({ "required_scopes": ["users:write"] }),
+            403,
+        ),
+        (
+            serde_json::json!({ "required_scopes": ["users:read", "billing:read"] }),
+            403,
+        ),
+        (
+            serde_json::json!({ "required_permission": "users:write" }),
+            403,
+        ),
+        (serde_json::json!({ "required_role": "admin" }), 403),
+    ] {
+        let mut body = requirement.clone();
+        body["token"] = token.clone().into();
+        let response = app.post_verify_token(&body).await;
+        assert_eq!(response.status().as_u16(), expected, "{}", requirement);
+    }
+
+    // Unscoped tokens have everything their roles allow
+    let token = login_and_get_token(&app, &email).await;
+    let body = serde_json::json!({
+        "token": token,
+        "required_scopes": ["users:read", "users:write"],
+    });
+    let response = app.post_verify_token(&body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_404_when_assigning_unknown_role() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, _) = setup_user_for_login_with_password_no_2fa(&app).await;
+
+    let response = app
+        .post_admin_role(
+            "assign",
+            &serde_json::json!({ "email": email, "role": "superuser" }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = app
+        .post_admin_role(
+            "assign",
+            &serde_json::json!({ "email": get_random_email(), "role": "admin" }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    app.clean_up().await;
+}
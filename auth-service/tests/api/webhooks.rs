@@ -0,0 +1,213 @@
+use std::sync::{Arc, Mutex};
+
+use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+use chrono::Utc;
+
+use crate::helpers::{create_admin, get_random_email, TestApp};
+use auth_service::domain::{AuditEventType, RetryPolicy};
+use auth_service::services::webhook_dispatcher::WebhookDispatcher;
+use auth_service::utils::webhook_signature::verify_webhook_signature;
+use uuid::Uuid;
+
+type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+// A subscriber that records every request and answers with `status`
+async fn start_receiver(status: StatusCode) -> (Received, String) {
+    let received = Received::default();
+
+    let router = Router::new()
+        .route(
+            "/hook",
+            post(
+                move |State(received): State<Received>, headers: HeaderMap, body: String| async move {
+                    received.lock().unwrap().push((headers, body));
+                    status
+                },
+            ),
+        )
+        .with_state(received.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+    (received, url)
+}
+
+#[tokio::test]
+async fn should_deliver_signed_event_to_matching_subscription() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (_, admin) = create_admin(&app).await;
+    let (received, url) = start_receiver(StatusCode::OK).await;
+
+    let response = app
+        .post_admin_webhook(
+            &serde_json::json!({
+                "url": url,
+                "event_types": ["user.created"],
+            }),
+            &admin,
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let subscription: serde_json::Value = response.json().await.unwrap();
+    let secret = subscription["secret"].as_str().unwrap().to_owned();
+
+    let email = get_random_email();
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": email,
+            "password": "password123",
+            "requires2FA": true,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    // Only user.created was subscribed to, not user.2fa_enabled
+    let dispatcher = WebhookDispatcher::new(app.webhook_store.clone());
+    assert_eq!(dispatcher.dispatch_due().await, Ok(1));
+
+    let received = received.lock().unwrap().clone();
+    assert_eq!(received.len(), 1);
+    let (headers, body) = &received[0];
+    assert_eq!(headers["x-webhook-event"], "user.created");
+    assert!(verify_webhook_signature(
+        &secret,
+        headers["x-webhook-signature"].to_str().unwrap(),
+        body,
+        Utc::now().timestamp(),
+        300
+    ));
+    let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+    assert_eq!(payload["type"], "user.created");
+    assert_eq!(payload["data"]["email"], email.as_str());
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_dead_letter_deliveries_that_keep_failing() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (_, admin) = create_admin(&app).await;
+    let (received, url) = start_receiver(StatusCode::INTERNAL_SERVER_ERROR).await;
+
+    let response = app
+        .post_admin_webhook(&serde_json::json!({ "url": url }), &admin)
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let response = app
+        .post_signup(&serde_json::json!({
+            "email": get_random_email(),
+            "password": "password123",
+            "requires2FA": false,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+
+    let dispatcher =
+        WebhookDispatcher::new(app.webhook_store.clone()).with_retry_policy(RetryPolicy {
+            max_attempts: 2,
+            base_delay: chrono::Duration::zero(),
+            max_delay: chrono::Duration::zero(),
+        });
+    assert_eq!(dispatcher.dispatch_due().await, Ok(1));
+    assert_eq!(dispatcher.dispatch_due().await, Ok(1));
+    assert_eq!(dispatcher.dispatch_due().await, Ok(0));
+    assert_eq!(received.lock().unwrap().len(), 2);
+
+    let response = app.get_admin_webhooks("/dead-letters", &admin).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let dead_letters: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(dead_letters.as_array().unwrap().len(), 1);
+    assert_eq!(dead_letters[0]["event_type"], "user.created");
+    assert_eq!(dead_letters[0]["attempts"], 2);
+    assert_eq!(dead_letters[0]["last_error"], "HTTP 500");
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_list_and_delete_subscriptions() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (email, admin) = create_admin(&app).await;
+
+    let response = app
+        .post_admin_webhook(
+            &serde_json::json!({
+                "url": "https://siem.example.com/hooks/auth",
+                "event_types": ["user.locked"],
+            }),
+            &admin,
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 201);
+    let subscription: serde_json::Value = response.json().await.unwrap();
+    let id = subscription["id"].as_str().unwrap().to_owned();
+
+    let listed: serde_json::Value = app
+        .get_admin_webhooks("", &admin)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed[0]["id"], id.as_str());
+    assert_eq!(listed[0]["event_types"], serde_json::json!(["user.locked"]));
+    // The secret is only shown when the subscription is created
+    assert!(listed[0].get("secret").is_none());
+
+    assert_eq!(
+        app.delete_admin_webhook(&id, &admin)
+            .await
+            .status()
+            .as_u16(),
+        204
+    );
+    assert_eq!(
+        app.delete_admin_webhook(&id, &admin)
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+
+    let listed: serde_json::Value = app
+        .get_admin_webhooks("", &admin)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(listed.as_array().unwrap().is_empty());
+
+    let events = app.audit_sink.events();
+    for event_type in [
+        AuditEventType::WebhookCreated,
+        AuditEventType::WebhookDeleted,
+    ] {
+        let event = events
+            .iter()
+            .find(|event| event.event_type == event_type)
+            .unwrap_or_else(|| panic!("No {:?} event", event_type));
+        assert_eq!(event.actor.as_deref(), Some(email.as_str()));
+    }
+
+    app.clean_up().await;
+}
+
+#[tokio::test]
+async fn should_return_400_for_invalid_subscriptions() {
+    let mut app = TestApp::new(Uuid::new_v4().to_string()).await;
+    let (_, admin) = create_admin(&app).await;
+
+    let invalid = [
+        serde_json::json!({ "url": "not a url" }),
+        serde_json::json!({ "url": "ftp://example.com/hook" }),
+        serde_json::json!({ "url": "https://example.com/hook", "event_types": ["user.deleted"] }),
+    ];
+
+    for body in invalid {
+        let response = app.post_admin_webhook(&body, &admin).await;
+        assert_eq!(response.status().as_u16(), 400, "{}", body);
+    }
+
+    app.clean_up().await;
+}